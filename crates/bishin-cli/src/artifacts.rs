@@ -0,0 +1,199 @@
+//! Keeping the files that tests list with `@artifact`, such as logs, once
+//! they've run and before their temp dirs are removed.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bishin_collect::pattern::is_ignored;
+use bishin_collect::Pattern;
+use bishin_jobs::dir;
+
+/// The `@artifact` patterns of a test, compiled, adding a warning to
+/// `warnings` for and leaving out any that aren't valid.
+pub(crate) fn patterns(
+    name: &str,
+    artifacts: &[String],
+    warnings: &mut Vec<String>,
+) -> Vec<Pattern> {
+    artifacts
+        .iter()
+        .filter_map(|artifact| match Pattern::new(artifact) {
+            Ok(pattern) => Some(pattern),
+            Err(err) => {
+                warnings.push(format!("test {name} has an invalid @artifact: {err}"));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Copy what in `tmp_dir` matches `patterns` to `dest`, keeping their paths
+/// relative to `tmp_dir`, and return the copies.
+///
+/// Anything left in `dest` by an earlier run is removed first. Problems are
+/// only added to `warnings`, since the test has already run either way.
+pub(crate) fn collect(
+    name: &str,
+    tmp_dir: &Path,
+    patterns: &[Pattern],
+    dest: &Path,
+    warnings: &mut Vec<String>,
+) -> Vec<PathBuf> {
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+    if dest.exists() {
+        if let Err(err) = std::fs::remove_dir_all(dest) {
+            warnings.push(format!(
+                "failed to clear the artifacts of test {name} in {}: {err}",
+                dest.display()
+            ));
+        }
+    }
+    let mut matched = Vec::new();
+    if let Err(err) = find(tmp_dir, Path::new(""), patterns, &mut matched) {
+        warnings.push(format!(
+            "failed to look for the artifacts of test {name} in {}: {err}",
+            tmp_dir.display()
+        ));
+    }
+    for pattern in patterns.iter().filter(|pattern| !pattern.is_negated()) {
+        let found = matched
+            .iter()
+            .any(|(path, is_dir)| pattern.matches(path, *is_dir));
+        if !found {
+            warnings.push(format!(
+                "test {name} left no artifact matching \"{pattern}\""
+            ));
+        }
+    }
+    let mut copies = Vec::new();
+    for (path, _) in matched {
+        let copy = dest.join(&path);
+        match dir::copy(&tmp_dir.join(&path), &copy) {
+            Ok(()) => copies.push(copy),
+            Err(err) => warnings.push(format!(
+                "failed to copy artifact {} of test {name}: {err}",
+                path.display()
+            )),
+        }
+    }
+    copies
+}
+
+/// Add the paths under `dir`, which is `relative` in the temp dir, that
+/// `patterns` match to `matched`. A directory that matches is added whole
+/// rather than what's in it.
+fn find(
+    dir: &Path,
+    relative: &Path,
+    patterns: &[Pattern],
+    matched: &mut Vec<(PathBuf, bool)>,
+) -> io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+        if is_ignored(patterns, &path, is_dir) {
+            matched.push((path, is_dir));
+        } else if is_dir {
+            find(&entry.path(), &path, patterns, matched)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write_files, ScratchDir};
+
+    /// Where the artifacts of a test that left `files` went, what's there,
+    /// and the warnings about them.
+    fn collected(
+        artifacts: &[&str],
+        files: &[(&str, &str)],
+    ) -> (ScratchDir, Vec<PathBuf>, Vec<String>) {
+        let tmp_dir = scratch_dir();
+        write_files(&tmp_dir, files);
+        let dest = scratch_dir();
+        let artifacts: Vec<_> = artifacts.iter().map(|a| a.to_string()).collect();
+        let mut warnings = Vec::new();
+        let patterns = patterns("t", &artifacts, &mut warnings);
+        let copies = collect("t", &tmp_dir, &patterns, &dest, &mut warnings);
+        let relative = copies
+            .iter()
+            .map(|copy| copy.strip_prefix(&dest).unwrap().to_path_buf())
+            .collect();
+        (dest, relative, warnings)
+    }
+
+    #[test]
+    fn matching_files_are_copied_with_their_paths() {
+        let files = [
+            ("out/a.log", "a"),
+            ("out/b.txt", "b"),
+            ("deep/er/c.log", "c"),
+        ];
+        let (dest, copies, _) = collected(&["*.log"], &files);
+        assert_eq!(
+            copies,
+            [PathBuf::from("deep/er/c.log"), PathBuf::from("out/a.log")]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dest.join("out/a.log")).unwrap(),
+            "a"
+        );
+        assert!(!dest.join("out/b.txt").exists());
+    }
+
+    #[test]
+    fn matching_directories_are_copied_whole() {
+        let files = [("screens/1.png", "1"), ("screens/2/3.png", "3")];
+        let (dest, copies, _) = collected(&["screens/"], &files);
+        assert_eq!(copies, [PathBuf::from("screens")]);
+        assert!(dest.join("screens/2/3.png").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_in_artifacts_are_kept_as_links() {
+        let tmp_dir = scratch_dir();
+        write_files(&tmp_dir, &[("out/a.log", "a")]);
+        std::os::unix::fs::symlink("..", tmp_dir.join("out/up")).unwrap();
+        let dest = scratch_dir();
+        let mut warnings = Vec::new();
+        let patterns = patterns("t", &["out".to_string()], &mut warnings);
+        let copies = collect("t", &tmp_dir, &patterns, &dest, &mut warnings);
+        assert_eq!(warnings, Vec::<String>::new());
+        assert_eq!(copies, [dest.join("out")]);
+        assert!(dest.join("out/a.log").exists());
+        assert_eq!(
+            std::fs::read_link(dest.join("out/up")).unwrap(),
+            Path::new("..")
+        );
+    }
+
+    #[test]
+    fn negated_patterns_take_files_back_out() {
+        let files = [("a.log", ""), ("noisy.log", "")];
+        let (_, copies, warnings) = collected(&["*.log", "!noisy.log"], &files);
+        assert_eq!(warnings, Vec::<String>::new());
+        assert_eq!(copies, [PathBuf::from("a.log")]);
+    }
+
+    #[test]
+    fn missing_and_invalid_artifacts_only_warn() {
+        let (dest, copies, warnings) = collected(&["missing.log", "[", "a.log"], &[("a.log", "")]);
+        assert_eq!(copies, [PathBuf::from("a.log")]);
+        assert!(dest.join("a.log").exists());
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert!(warnings[0].starts_with("test t has an invalid @artifact: "));
+        assert_eq!(
+            warnings[1],
+            "test t left no artifact matching \"missing.log\""
+        );
+        assert_eq!(collected(&["*.log"], &[]).1, Vec::<PathBuf>::new());
+    }
+}
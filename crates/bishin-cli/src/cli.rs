@@ -0,0 +1,394 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bishin::report::{self, Report, ReportKind};
+use bishin_parser::duration::parse_duration;
+use bishin_parser::Shell;
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "bishin",
+    version,
+    about = "A totally rad way to run tests with shells"
+)]
+pub struct Cli {
+    /// Path to the config file [default: bishin.toml, or if there isn't one,
+    /// the [package.metadata.bishin] or [workspace.metadata.bishin] table of
+    /// the nearest Cargo.toml].
+    #[arg(short = 'f', long, global = true, value_hint = ValueHint::FilePath)]
+    pub config_file: Option<PathBuf>,
+
+    /// Read the config from the table at the dotted path TABLE of the config
+    /// file, e.g. tool.bishin, so it can live in a file shared with other
+    /// tools.
+    #[arg(long, global = true, value_name = "TABLE", requires = "config_file")]
+    pub config_key: Option<String>,
+
+    /// Apply the overrides from a `[profile.NAME]` section of the config.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Set the config's KEY to VALUE, over the config file and profile, e.g.
+    /// jobs=4 or snapshots.update=always. May be given more than once.
+    #[arg(long, global = true, value_name = "KEY=VALUE", value_parser = parse_set)]
+    pub set: Vec<(String, String)>,
+
+    /// Ignore keys in the config that bishin doesn't know about.
+    #[arg(long, global = true)]
+    pub allow_unknown_config_keys: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the test suite.
+    Run(Box<RunArgs>),
+    /// Write the test scripts to the work directory without running them.
+    Gen,
+    /// Write the scripts of the tests into one shell script that runs them
+    /// all, for machines without bishin.
+    Export(ExportArgs),
+    /// Write a config file with the default settings.
+    Init,
+    /// Show how a test did in the recorded runs.
+    History(HistoryArgs),
+    /// List the tests that flipped between passing and failing most often
+    /// in the recorded runs.
+    Flaky(FlakyArgs),
+    /// List the tests that would be collected, without running them.
+    List(ListArgs),
+    /// Print a digest of the test files and the files they read, which
+    /// changes whenever any of them does.
+    Fingerprint,
+    /// Run a shell snippet as if it were the body of a test.
+    Exec(ExecArgs),
+    /// Add a test that isn't written yet to its module's file, creating the
+    /// file if there isn't one, and print where it is.
+    New(NewArgs),
+    /// Render the JSON report of an earlier run another way.
+    Report(ReportArgs),
+    /// Print shell completions for bishin.
+    Completions(CompletionsArgs),
+    /// Print a JSON Schema of the config file.
+    #[command(hide = true, name = "config-schema")]
+    ConfigSchema,
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /// The shell to print completions for.
+    pub shell: CompletionShell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// The full name of the test, e.g. net::dns::resolves.
+    #[arg(value_name = "TEST")]
+    pub test: String,
+
+    /// How many of the most recent runs of it to show.
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub last: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct FlakyArgs {
+    /// How many of the most recent runs to look at.
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    pub last: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// List the test files instead, relative to the config's directory,
+    /// without parsing them.
+    #[arg(long)]
+    pub files: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// The JSON report to read, as written by `--report json=PATH`.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub from: PathBuf,
+
+    /// Write an HTML page of the results to PATH.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub html: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Where to write the script.
+    #[arg(short, long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub output: PathBuf,
+
+    /// Only export tests whose full names match one of these globs, as with
+    /// `bishin run`.
+    #[arg(value_name = "GLOB")]
+    pub tests: Vec<String>,
+
+    /// Only export tests whose names contain PATTERN. May be given more than
+    /// once.
+    #[arg(long, value_name = "PATTERN")]
+    pub filter: Vec<String>,
+
+    /// Don't export tests whose names contain PATTERN. May be given more
+    /// than once.
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Treat --filter and --exclude patterns as regular expressions.
+    #[arg(long)]
+    pub regex: bool,
+
+    /// Only export tests tagged TAG, overriding the config's default tags.
+    /// May be given more than once.
+    #[arg(long, value_name = "TAG")]
+    pub tag: Vec<String>,
+
+    /// Don't export tests tagged TAG, on top of the config's default
+    /// exclusions. May be given more than once.
+    #[arg(long, value_name = "TAG")]
+    pub exclude_tag: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExecArgs {
+    /// The shell to run the snippet with, instead of the one tests use.
+    #[arg(long)]
+    pub shell: Option<Shell>,
+
+    /// The snippet to run.
+    pub snippet: String,
+}
+
+#[derive(Debug, Args)]
+pub struct NewArgs {
+    /// The full name of the test, e.g. net::dns::resolves_ipv6.
+    pub name: String,
+
+    /// Open the test in $VISUAL or $EDITOR once it's added.
+    #[arg(long)]
+    pub edit: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct RunArgs {
+    /// Only run tests whose full names match one of these globs, where `*`
+    /// matches anything and `?` any one character, e.g. 'net::*'. They add
+    /// to any --filter patterns.
+    #[arg(value_name = "GLOB")]
+    pub tests: Vec<String>,
+
+    /// Only run tests whose names contain PATTERN. May be given more than
+    /// once to run the tests matching any of them.
+    #[arg(long, value_name = "PATTERN")]
+    pub filter: Vec<String>,
+
+    /// Don't run tests whose names contain PATTERN, even if they match a
+    /// --filter. May be given more than once.
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Treat --filter and --exclude patterns as regular expressions.
+    #[arg(long)]
+    pub regex: bool,
+
+    /// Only run tests tagged TAG, overriding the config's default tags. May
+    /// be given more than once.
+    #[arg(long, value_name = "TAG")]
+    pub tag: Vec<String>,
+
+    /// Don't run tests tagged TAG, on top of the config's default exclusions.
+    /// May be given more than once.
+    #[arg(long, value_name = "TAG")]
+    pub exclude_tag: Vec<String>,
+
+    /// List the tests that would run instead of running them.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// If another run is using the same work directory, wait for it to finish
+    /// instead of failing.
+    #[arg(long)]
+    pub wait: bool,
+
+    /// How many tests to run at once [default: one per CPU].
+    #[arg(short, long, value_name = "N", value_parser = parse_jobs)]
+    pub jobs: Option<usize>,
+
+    /// Succeed as long as at least this fraction of tests pass (0.0 to 1.0).
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub min_pass_rate: Option<f64>,
+
+    /// How many times to retry failed tests that don't set @retries.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(..=i64::from(bishin_config::MAX_RETRIES))
+    )]
+    pub retries: Option<u32>,
+
+    /// Stop starting tests after the first failure.
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Stop starting tests after N failures, or never if N is 0.
+    #[arg(long, value_name = "N")]
+    pub max_failures: Option<u32>,
+
+    /// Fail the run if any test was skipped.
+    #[arg(long)]
+    pub fail_on_skip: bool,
+
+    /// Run only the fastest tests that fit in DURATION between them, going
+    /// by how long the history says they take.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub within: Option<Duration>,
+
+    /// Run the tests in a random order. A run that fails prints how to
+    /// replay it.
+    #[arg(long)]
+    pub shuffle: bool,
+
+    /// The seed of the order that --shuffle runs the tests in, instead of a
+    /// random one. Implies --shuffle.
+    #[arg(long, value_name = "N")]
+    pub seed: Option<u64>,
+
+    /// Run the tests that a shuffled run ran, in the same order, from the
+    /// token it printed.
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        conflicts_with_all = ["tests", "filter", "exclude", "regex", "tag", "exclude_tag", "benches", "shuffle", "seed"]
+    )]
+    pub replay: Option<String>,
+
+    /// Run only the tests marked @bench, which are left out otherwise.
+    #[arg(long)]
+    pub benches: bool,
+
+    /// Fail the run if any test marked @xfail passed.
+    #[arg(long)]
+    pub strict_xfail: bool,
+
+    /// Record the output of @snapshot tests as their snapshots, whatever the
+    /// config's [snapshots] update says.
+    #[arg(long)]
+    pub update_snapshots: bool,
+
+    /// Check the syntax of every script before running any of them.
+    #[arg(long)]
+    pub syntax_check: bool,
+
+    /// Trace the tests and report which external commands they ran.
+    #[arg(long, conflicts_with_all = ["coverage", "coverage_lcov"])]
+    pub command_report: bool,
+
+    /// Trace the tests and report which lines of their bodies ran. Only
+    /// bash tests can be covered.
+    #[arg(long)]
+    pub coverage: bool,
+
+    /// Also write the coverage as an LCOV tracefile to PATH, for tools that
+    /// read them. Implies --coverage.
+    #[arg(long, value_name = "PATH")]
+    pub coverage_lcov: Option<PathBuf>,
+
+    /// Measure the CPU time and peak memory of each test and include them in
+    /// the results. Only done on Unix.
+    #[arg(long)]
+    pub resource_stats: bool,
+
+    /// Fail tests that leave processes running in the background once they
+    /// exit, rather than only warning about them. Only checked on Unix.
+    #[arg(long)]
+    pub strict_processes: bool,
+
+    /// Print the environment the test called NAME would run with, instead of
+    /// running anything. For a test run in several shells, NAME can end in
+    /// the shell, e.g. `net::dns[fish]`, or leave it off to print each.
+    #[arg(long, value_name = "NAME")]
+    pub print_env: Option<String>,
+
+    /// Show the results of the tests together by GROUP once they've all
+    /// run, instead of each as it finishes.
+    #[arg(long, value_name = "GROUP")]
+    pub group_by: Option<GroupBy>,
+
+    /// Report how long each phase of the run took.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "human"
+    )]
+    pub timings: Option<TimingsFormat>,
+
+    /// Write a report of KIND (junit, json, jsonl-events, html or markdown)
+    /// to PATH, on top of those in the config. May be given more than once.
+    #[arg(long, value_name = "KIND=PATH", value_parser = Report::parse)]
+    pub report: Vec<Report>,
+
+    /// Put PREFIX before the name of every test in the output and reports,
+    /// instead of the config's name-prefix.
+    #[arg(long, value_name = "PREFIX")]
+    pub name_prefix: Option<String>,
+
+    /// Don't write the reports of KIND that the config asks for. May be
+    /// given more than once.
+    #[arg(long, value_name = "KIND", value_parser = report::parse_kind)]
+    pub no_report: Vec<ReportKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// The test file each test is in, under its path.
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimingsFormat {
+    /// A table after the test results.
+    Human,
+    /// A single line JSON object of milliseconds by phase.
+    Json,
+}
+
+fn parse_set(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("{value:?} isn't of the form KEY=VALUE"))?;
+    Ok((key.trim().to_string(), value.to_string()))
+}
+
+fn parse_jobs(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(jobs) => Ok(jobs),
+        Err(_) => Err(format!("{value:?} is not a positive number")),
+    }
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value
+        .parse()
+        .map_err(|_| format!("{value:?} is not a number"))?;
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("{rate} is not between 0.0 and 1.0"))
+    }
+}
@@ -0,0 +1,476 @@
+//! Shell completions, written from the same clap definitions that parse the
+//! command line so that they can't fall behind it.
+//!
+//! The arguments that take test names complete them by running `bishin
+//! list`, so they come from the config in the directory being completed in.
+
+use std::process::ExitCode;
+
+use clap::{Arg, Command, CommandFactory, ValueHint};
+
+use crate::cli::{Cli, CompletionShell, CompletionsArgs};
+
+/// The value names of the positional arguments that take test names.
+const TEST_VALUE_NAMES: [&str; 2] = ["GLOB", "TEST"];
+
+pub fn completions(args: &CompletionsArgs) -> anyhow::Result<ExitCode> {
+    print!("{}", script(args.shell));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The completion script for `shell`.
+fn script(shell: CompletionShell) -> String {
+    let mut cli = Cli::command();
+    cli.build();
+    let model = Model::new(&cli);
+    match shell {
+        CompletionShell::Bash => bash(&model),
+        CompletionShell::Zsh => zsh(&model),
+        CompletionShell::Fish => fish(&model),
+    }
+}
+
+/// The parts of the command line that completions need.
+struct Model {
+    name: String,
+    options: Vec<Opt>,
+    subcommands: Vec<Subcommand>,
+}
+
+struct Subcommand {
+    name: String,
+    about: String,
+    options: Vec<Opt>,
+    /// Whether it takes test names as positional arguments.
+    tests: bool,
+}
+
+struct Opt {
+    long: Option<String>,
+    short: Option<char>,
+    help: String,
+    value: Option<Value>,
+}
+
+/// What an option's value completes to.
+enum Value {
+    Anything,
+    Files,
+    OneOf(Vec<String>),
+}
+
+impl Model {
+    fn new(cli: &Command) -> Self {
+        Self {
+            name: cli.get_name().to_string(),
+            options: options(cli),
+            subcommands: cli
+                .get_subcommands()
+                .filter(|subcommand| !subcommand.is_hide_set())
+                .map(|subcommand| Subcommand {
+                    name: subcommand.get_name().to_string(),
+                    about: first_line(subcommand.get_about().map(ToString::to_string)),
+                    options: options(subcommand),
+                    tests: subcommand.get_positionals().any(|arg| {
+                        arg.get_value_names()
+                            .is_some_and(|names| TEST_VALUE_NAMES.contains(&names[0].as_str()))
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn options(command: &Command) -> Vec<Opt> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .map(|arg| Opt {
+            long: arg.get_long().map(str::to_string),
+            short: arg.get_short(),
+            help: first_line(arg.get_help().map(ToString::to_string)),
+            value: arg.get_action().takes_values().then(|| value(arg)),
+        })
+        .collect()
+}
+
+fn value(arg: &Arg) -> Value {
+    let choices: Vec<_> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if !choices.is_empty() {
+        return Value::OneOf(choices);
+    }
+    match arg.get_value_hint() {
+        ValueHint::FilePath | ValueHint::AnyPath | ValueHint::DirPath => Value::Files,
+        _ => Value::Anything,
+    }
+}
+
+/// The first sentence of a help text, without its full stop.
+fn first_line(help: Option<String>) -> String {
+    let help = help.unwrap_or_default();
+    let line = help.lines().next().unwrap_or_default();
+    let mut end = line.len();
+    for (i, _) in line.match_indices(". ") {
+        if !line[..i].ends_with("e.g") && !line[..i].ends_with("i.e") {
+            end = i;
+            break;
+        }
+    }
+    line[..end].trim_end_matches('.').to_string()
+}
+
+impl Opt {
+    /// The ways the option can be spelled, e.g. `-f` and `--config-file`.
+    fn spellings(&self) -> Vec<String> {
+        self.short
+            .map(|short| format!("-{short}"))
+            .into_iter()
+            .chain(self.long.iter().map(|long| format!("--{long}")))
+            .collect()
+    }
+}
+
+fn bash(model: &Model) -> String {
+    let name = &model.name;
+    let words = |options: &[Opt]| -> String {
+        options
+            .iter()
+            .flat_map(Opt::spellings)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let values = |options: &[Opt], indent: &str| -> String {
+        let mut cases = String::new();
+        for option in options {
+            let Some(value) = &option.value else {
+                continue;
+            };
+            let reply = match value {
+                Value::Anything => "return".to_string(),
+                Value::Files => "COMPREPLY=($(compgen -f -- \"$cur\")); return".to_string(),
+                Value::OneOf(choices) => format!(
+                    "COMPREPLY=($(compgen -W '{}' -- \"$cur\")); return",
+                    choices.join(" ")
+                ),
+            };
+            cases.push_str(&format!(
+                "{indent}{}) {reply} ;;\n",
+                option.spellings().join("|")
+            ));
+        }
+        cases
+    };
+    let subcommands: Vec<_> = model
+        .subcommands
+        .iter()
+        .map(|sub| sub.name.as_str())
+        .collect();
+    let mut script = format!(
+        "# bash completion for {name}, from `{name} completions bash`.\n\
+         _{name}_tests() {{\n\
+         \x20   # Test names have colons in them, which bash splits words at.\n\
+         \x20   local word=\"${{COMP_LINE:0:COMP_POINT}}\"\n\
+         \x20   word=\"${{word##* }}\"\n\
+         \x20   local prefix=\"${{word%\"${{word##*:}}\"}}\"\n\
+         \x20   local tests\n\
+         \x20   tests=$({name} list 2>/dev/null) || return\n\
+         \x20   COMPREPLY=($(compgen -W \"$tests\" -- \"$word\"))\n\
+         \x20   COMPREPLY=(\"${{COMPREPLY[@]#\"$prefix\"}}\")\n\
+         }}\n\
+         \n\
+         _{name}() {{\n\
+         \x20   local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"\n\
+         \x20   local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n\
+         \x20   local subcommand=\"\" i\n\
+         \x20   for ((i = 1; i < COMP_CWORD; i++)); do\n\
+         \x20       case \"${{COMP_WORDS[i]}}\" in\n\
+         \x20           {}) subcommand=\"${{COMP_WORDS[i]}}\"; break ;;\n\
+         \x20       esac\n\
+         \x20   done\n\
+         \x20   case \"$subcommand\" in\n\
+         \x20       \"\")\n\
+         \x20           case \"$prev\" in\n\
+         {}\
+         \x20           esac\n\
+         \x20           COMPREPLY=($(compgen -W '{} {}' -- \"$cur\"))\n\
+         \x20           ;;\n",
+        subcommands.join("|"),
+        values(&model.options, "                "),
+        words(&model.options),
+        subcommands.join(" "),
+    );
+    for sub in &model.subcommands {
+        let positional = if sub.tests {
+            format!("_{name}_tests")
+        } else {
+            "COMPREPLY=()".to_string()
+        };
+        script.push_str(&format!(
+            "        {})\n\
+             \x20           case \"$prev\" in\n\
+             {}\
+             \x20           esac\n\
+             \x20           if [[ $cur == -* ]]; then\n\
+             \x20               COMPREPLY=($(compgen -W '{}' -- \"$cur\"))\n\
+             \x20           else\n\
+             \x20               {positional}\n\
+             \x20           fi\n\
+             \x20           ;;\n",
+            sub.name,
+            values(&sub.options, "                "),
+            words(&sub.options),
+        ));
+    }
+    script.push_str(&format!("    esac\n}}\n\ncomplete -F _{name} {name}\n"));
+    script
+}
+
+fn zsh(model: &Model) -> String {
+    let name = &model.name;
+    let specs = |options: &[Opt]| -> String {
+        options
+            .iter()
+            .map(|option| {
+                let spellings = option.spellings();
+                let names = match spellings.as_slice() {
+                    [one] => one.clone(),
+                    many => format!("'({})'{{{}}}", many.join(" "), many.join(",")),
+                };
+                let value = match &option.value {
+                    None => String::new(),
+                    Some(Value::Anything) => ": : ".to_string(),
+                    Some(Value::Files) => ": :_files".to_string(),
+                    Some(Value::OneOf(choices)) => format!(": :({})", choices.join(" ")),
+                };
+                format!(
+                    " \\\n            {names}'[{}]{value}'",
+                    zsh_escape(&option.help)
+                )
+            })
+            .collect()
+    };
+    let mut script = format!(
+        "#compdef {name}\n\
+         # zsh completion for {name}, from `{name} completions zsh`.\n\
+         \n\
+         _{name}_tests() {{\n\
+         \x20   local -a tests\n\
+         \x20   tests=(${{(f)\"$({name} list 2>/dev/null)\"}})\n\
+         \x20   compadd -a tests\n\
+         }}\n\
+         \n\
+         _{name}() {{\n\
+         \x20   local line state\n\
+         \x20   _arguments -C{} \\\n\
+         \x20       '1: :->command' \\\n\
+         \x20       '*:: :->args'\n\
+         \x20   case $state in\n\
+         \x20   command)\n\
+         \x20       local -a commands\n\
+         \x20       commands=(\n",
+        specs(&model.options).replace("\n            ", "\n        "),
+    );
+    for sub in &model.subcommands {
+        script.push_str(&format!(
+            "            '{}:{}'\n",
+            sub.name,
+            zsh_escape(&sub.about)
+        ));
+    }
+    script.push_str(
+        "        )\n\
+         \x20       _describe command commands\n\
+         \x20       ;;\n\
+         \x20   args)\n\
+         \x20       case $line[1] in\n",
+    );
+    for sub in &model.subcommands {
+        let positional = if sub.tests {
+            format!(" \\\n            '*: :_{name}_tests'")
+        } else {
+            String::new()
+        };
+        script.push_str(&format!(
+            "        {})\n\
+             \x20           _arguments{}{positional}\n\
+             \x20           ;;\n",
+            sub.name,
+            specs(&sub.options).replace("\n            ", "\n                "),
+        ));
+    }
+    script.push_str(&format!(
+        "        esac\n\
+         \x20       ;;\n\
+         \x20   esac\n\
+         }}\n\
+         \n\
+         _{name} \"$@\"\n"
+    ));
+    script
+}
+
+/// `text` escaped for a single-quoted `_arguments` spec or `_describe`
+/// entry.
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn fish(model: &Model) -> String {
+    let name = &model.name;
+    let option = |condition: Option<&str>, option: &Opt| -> String {
+        let mut line = format!("complete -c {name}");
+        if let Some(condition) = condition {
+            line.push_str(&format!(" -n '{condition}'"));
+        }
+        if let Some(short) = option.short {
+            line.push_str(&format!(" -s {short}"));
+        }
+        if let Some(long) = &option.long {
+            line.push_str(&format!(" -l {long}"));
+        }
+        match &option.value {
+            None => {}
+            Some(Value::Anything) => line.push_str(" -x"),
+            Some(Value::Files) => line.push_str(" -r -F"),
+            Some(Value::OneOf(choices)) => {
+                line.push_str(&format!(" -x -a '{}'", choices.join(" ")));
+            }
+        }
+        line.push_str(&format!(" -d '{}'\n", fish_escape(&option.help)));
+        line
+    };
+    let mut script = format!(
+        "# fish completion for {name}, from `{name} completions fish`.\n\
+         complete -c {name} -f\n"
+    );
+    for global in &model.options {
+        script.push_str(&option(None, global));
+    }
+    for sub in &model.subcommands {
+        script.push_str(&format!(
+            "complete -c {name} -n __fish_use_subcommand -a {} -d '{}'\n",
+            sub.name,
+            fish_escape(&sub.about)
+        ));
+    }
+    for sub in &model.subcommands {
+        let condition = format!("__fish_seen_subcommand_from {}", sub.name);
+        for opt in sub.options.iter().filter(|opt| {
+            // Globals are completed everywhere already.
+            !model
+                .options
+                .iter()
+                .any(|global| global.long == opt.long && global.short == opt.short)
+        }) {
+            script.push_str(&option(Some(&condition), opt));
+        }
+        if sub.tests {
+            script.push_str(&format!(
+                "complete -c {name} -n '{condition}' -a '({name} list 2>/dev/null)'\n"
+            ));
+        }
+    }
+    script
+}
+
+/// `text` escaped for a single-quoted fish string.
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHELLS: [CompletionShell; 3] = [
+        CompletionShell::Bash,
+        CompletionShell::Zsh,
+        CompletionShell::Fish,
+    ];
+
+    #[test]
+    fn every_shell_gets_a_script_with_every_visible_subcommand() {
+        for shell in SHELLS {
+            let script = script(shell);
+            assert!(script.starts_with('#'), "{shell:?}");
+            for subcommand in ["run", "list", "gen", "completions"] {
+                assert!(script.contains(subcommand), "{shell:?} {subcommand}");
+            }
+            assert!(!script.contains("config-schema"), "{shell:?}");
+            assert!(script.contains("bishin list 2>/dev/null"), "{shell:?}");
+        }
+    }
+
+    /// What the bash completion offers for `line`, with `bishin list`
+    /// listing `tests`.
+    fn bash_completes(line: &str, tests: &[&str]) -> Vec<String> {
+        let mut words: Vec<_> = line.split(' ').collect();
+        if words.last() == Some(&"") {
+            words.pop();
+            words.push("''");
+        }
+        let shell = format!(
+            "{}\nbishin() {{ printf '%s\\n' {}; }}\n\
+             COMP_LINE='{line}'; COMP_POINT=${{#COMP_LINE}}\n\
+             COMP_WORDS=({}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1))\n\
+             _bishin\nprintf '%s\\n' \"${{COMPREPLY[@]}}\"\n",
+            script(CompletionShell::Bash),
+            tests.join(" "),
+            words.join(" ")
+        );
+        let output = std::process::Command::new("bash")
+            .args(["-c", &shell])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn bash_completes_subcommands_options_and_test_names() {
+        assert_eq!(bash_completes("bishin ru", &[]), ["run"]);
+        assert!(bash_completes("bishin run --config-f", &[]).contains(&"--config-file".to_string()));
+        let tests = ["net::dns::a", "net::dns::b", "fs::copy"];
+        assert_eq!(bash_completes("bishin run f", &tests), ["fs::copy"]);
+        // Only what's after the last colon is replaced, as bash split the
+        // word there.
+        assert_eq!(bash_completes("bishin run net::dns:", &tests), [":a", ":b"]);
+        assert_eq!(bash_completes("bishin list ", &tests), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn help_is_cut_to_its_first_sentence() {
+        let first = |help: &str| first_line(Some(help.to_string()));
+        assert_eq!(first("Run the tests."), "Run the tests");
+        assert_eq!(first("Run them. Then report."), "Run them");
+        assert_eq!(
+            first("Read a table, e.g. tool.bishin. And more."),
+            "Read a table, e.g. tool.bishin"
+        );
+        assert_eq!(first("Line one\nline two"), "Line one");
+        assert_eq!(first_line(None), "");
+    }
+
+    #[test]
+    fn help_is_escaped_for_each_shell() {
+        assert_eq!(zsh_escape("it's [a]:b"), "it'\\''s \\[a\\]\\:b");
+        assert_eq!(fish_escape("it's a \\"), "it\\'s a \\\\");
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::Context;
+use bishin::resolve::{CliOverrides, EnvOverrides, Settings};
+use bishin::{unsupported_options, Redactor, WorkDirLock};
+use bishin_config::Config;
+use bishin_generate::generate_test;
+use bishin_jobs::{JobResult, Status};
+use bishin_parser::Test;
+
+use crate::cli::ExecArgs;
+use crate::cmd::run::print_output;
+
+/// The name the snippet runs under, as a test would.
+const EXEC_TEST: &str = "exec";
+
+/// Run a snippet through the same generation and execution as a test, and
+/// print everything about how it went.
+pub fn exec(config: &Config, args: &ExecArgs) -> anyhow::Result<ExitCode> {
+    let (script, result) = run_snippet(config, args)?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "script: {}", script.display())?;
+    print_output(&mut out, "stdout", &result.stdout)?;
+    print_output(&mut out, "stderr", &result.stderr)?;
+    writeln!(out, "{}", outcome(&result))?;
+    Ok(match result.status {
+        Status::Passed => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
+
+/// Generate the script for the snippet and run it, returning where the
+/// script is and its result.
+fn run_snippet(config: &Config, args: &ExecArgs) -> anyhow::Result<(PathBuf, JobResult)> {
+    let settings = Settings::resolve(
+        config,
+        &EnvOverrides::from_env(),
+        &CliOverrides::default(),
+        std::env::consts::OS,
+    )?;
+    let redactor = Redactor::new(&config.redact).context("invalid redact pattern")?;
+    let _lock = WorkDirLock::acquire(&config.resolved_work_dir(), false)?;
+    let mut options = settings.generate_options(config);
+    if let Some(shell) = args.shell {
+        options.shell = shell;
+    }
+    super::warn(&unsupported_options(&options));
+    let mut body = args.snippet.clone();
+    if !body.ends_with('\n') {
+        body.push('\n');
+    }
+    let test = Test {
+        name: EXEC_TEST.to_string(),
+        body,
+        ..Test::default()
+    };
+    // There's no test file, so it's as if there were one at the top of the
+    // test directory.
+    let test_job = generate_test(
+        &test,
+        EXEC_TEST.to_string(),
+        EXEC_TEST,
+        &config.resolved_test_dirs()[0].join(EXEC_TEST),
+        &config.resolved_work_dir(),
+        &options,
+    )?;
+    let mut result = test_job
+        .job
+        .run()
+        .unwrap_or_else(|err| JobResult::from_error(EXEC_TEST, &err));
+    test_job.job.remove_dirs();
+    redactor.redact_result(&mut result);
+    Ok((test_job.script, result))
+}
+
+/// How the snippet's run went, e.g. `failed with exit status 1 in 2.00ms`.
+fn outcome(result: &JobResult) -> String {
+    let outcome = match result.status {
+        Status::Passed => "passed".to_string(),
+        Status::Failed => "failed".to_string(),
+        Status::TimedOut => "timed out".to_string(),
+        Status::Error => "could not be run".to_string(),
+        Status::Skipped => match &result.skip_reason {
+            Some(reason) => format!("skipped, {reason}"),
+            None => "skipped".to_string(),
+        },
+        Status::XFail => "failed as expected".to_string(),
+        Status::XPass => "passed unexpectedly".to_string(),
+    };
+    match result.exit_code {
+        Some(code) => format!(
+            "{outcome} with exit status {code} in {:.2?}",
+            result.duration
+        ),
+        None => format!("{outcome} in {:.2?}", result.duration),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bishin_parser::Shell;
+
+    use super::*;
+    use crate::testing::project;
+
+    fn exec_args(shell: Option<Shell>, snippet: &str) -> ExecArgs {
+        ExecArgs {
+            shell,
+            snippet: snippet.to_string(),
+        }
+    }
+
+    #[test]
+    fn a_snippet_runs_as_a_test_would() {
+        let (config, _dir) = project("[env]\nGREETING = \"hi\"\n", &[("tests/.keep", "")]);
+        let snippet = "echo \"$GREETING from $BISHIN_TEST_NAME\"\necho oops >&2\nexit 3";
+        let (script, result) = run_snippet(&config, &exec_args(None, snippet)).unwrap();
+        assert_eq!(result.stdout, "hi from exec\n");
+        assert_eq!(result.stderr, "oops\n");
+        assert_eq!(result.status, Status::Failed);
+        assert_eq!(result.exit_code, Some(3));
+        assert_eq!(script, config.resolved_work_dir().join("exec.sh"));
+        let script = std::fs::read_to_string(script).unwrap();
+        assert!(script.starts_with("#!/usr/bin/env bash\n"), "{script}");
+    }
+
+    #[test]
+    fn the_shell_can_be_chosen() {
+        let (config, _dir) = project("", &[("tests/.keep", "")]);
+        let args = exec_args(Some(Shell::Sh), "echo ok");
+        let (script, result) = run_snippet(&config, &args).unwrap();
+        assert_eq!(result.status, Status::Passed);
+        let script = std::fs::read_to_string(script).unwrap();
+        assert!(script.starts_with("#!/usr/bin/env sh\n"), "{script}");
+    }
+
+    #[test]
+    fn the_outcome_has_the_exit_status() {
+        let result = |status, exit_code| JobResult {
+            status,
+            exit_code,
+            duration: Duration::from_millis(2),
+            skip_reason: Some("no network".to_string()),
+            ..JobResult::error(EXEC_TEST, "")
+        };
+        assert_eq!(
+            outcome(&result(Status::Passed, Some(0))),
+            "passed with exit status 0 in 2.00ms"
+        );
+        assert_eq!(
+            outcome(&result(Status::Skipped, Some(77))),
+            "skipped, no network with exit status 77 in 2.00ms"
+        );
+        assert_eq!(
+            outcome(&result(Status::TimedOut, None)),
+            "timed out in 2.00ms"
+        );
+    }
+}
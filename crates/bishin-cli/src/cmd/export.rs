@@ -0,0 +1,313 @@
+use std::fmt::Write as _;
+use std::process::ExitCode;
+
+use anyhow::Context;
+use bishin::resolve::{self, CliOverrides, EnvOverrides, Settings};
+use bishin::{Runner, TestFilter};
+use bishin_config::Config;
+use bishin_generate::TestJob;
+use bishin_jobs::{ExpectedExit, SKIP_EXIT_CODE, TERMINATE_GRACE, TMPDIR_ENV};
+
+use crate::cli::ExportArgs;
+
+/// The harness at the top of an exported script, which each test runs
+/// through. It gives each test a directory of its own, as a run would, holds
+/// it to its expected exit code, `@xfail` and timeout, and prints how it went.
+const HARNESS: &str = r#"passed=0
+failed=0
+skipped=0
+
+# bishin_run NAME EXPECTED XFAIL TIMEOUT FUNCTION: run a test and say how it
+# went. EXPECTED is the exit code it passes with, or "nonzero", XFAIL is
+# "xfail" if it's expected to fail, and TIMEOUT is how many seconds it may
+# take, or empty.
+bishin_run() {
+    name=$1
+    expected=$2
+    xfail=$3
+    limit=$4
+    shift 4
+    dir=$(mktemp -d) || exit 1
+    (cd "$dir" && BISHIN_TMPDIR_VAR=$dir && export BISHIN_TMPDIR_VAR && bishin_limit=$limit && "$@")
+    status=$?
+    rm -rf "$dir"
+    if [ -n "$limit" ] && { [ "$status" -eq 124 ] || [ "$status" -eq 137 ]; }; then
+        outcome=TIMEOUT
+    elif [ "$status" -eq SKIP_EXIT_CODE ] && [ "$expected" != SKIP_EXIT_CODE ]; then
+        outcome=skipped
+    elif [ "$status" = "$expected" ] || { [ "$expected" = nonzero ] && [ "$status" -ne 0 ]; }; then
+        outcome=ok
+    else
+        outcome="FAILED (exit code $status)"
+    fi
+    if [ -n "$xfail" ]; then
+        case $outcome in
+        ok) outcome=XPASS ;;
+        skipped) ;;
+        *) outcome=xfail ;;
+        esac
+    fi
+    case $outcome in
+    ok | xfail | XPASS) passed=$((passed + 1)) ;;
+    skipped) skipped=$((skipped + 1)) ;;
+    *) failed=$((failed + 1)) ;;
+    esac
+    echo "test $name ... $outcome"
+}
+
+# bishin_skip NAME: say that a test marked @skip was skipped.
+bishin_skip() {
+    skipped=$((skipped + 1))
+    echo "test $1 ... skipped"
+}
+
+# Run a test's command, stopping it once it's taken the seconds in
+# $bishin_limit, if there are any and a timeout command to do so.
+bishin_timeout() {
+    if [ -z "$bishin_limit" ]; then
+        "$@"
+    elif command -v timeout >/dev/null 2>&1; then
+        timeout -k TERMINATE_GRACE "$bishin_limit" "$@"
+    else
+        echo "warning: there's no timeout command to stop this test after ${bishin_limit}s" >&2
+        "$@"
+    fi
+}
+"#;
+
+/// Write the scripts of the selected tests into one script that runs them
+/// all without bishin.
+pub fn export(config: &Config, args: &ExportArgs) -> anyhow::Result<ExitCode> {
+    let settings = Settings::resolve(
+        config,
+        &EnvOverrides::from_env(),
+        &CliOverrides::default(),
+        std::env::consts::OS,
+    )?;
+    let (tags, _) = resolve::tags(&args.tag, &args.exclude_tag, &config.tags);
+    let filter = TestFilter {
+        tags,
+        ..TestFilter::new(&args.filter, &args.tests, &args.exclude, args.regex)?
+    };
+    let plan = Runner::with_settings(config.clone(), settings)
+        .filter(filter)
+        .plan()?;
+    super::warn(&plan.warnings);
+    if config.setup_script.is_some() || config.teardown_script.is_some() {
+        eprintln!("warning: the exported script doesn't run the setup or teardown script");
+    }
+    for test_job in &plan.tests {
+        if test_job.job.dir.is_some() || test_job.job.stdin.is_some() {
+            eprintln!(
+                "warning: the exported script doesn't copy the fixtures or stdin file of test {}",
+                test_job.id
+            );
+        }
+        if test_job.job.retry.is_some() {
+            eprintln!(
+                "warning: the exported script doesn't retry test {}",
+                test_job.id
+            );
+        }
+    }
+    let script = bundle(config, &plan.tests)?;
+    std::fs::write(&args.output, script)
+        .with_context(|| format!("failed to write {}", args.output.display()))?;
+    println!(
+        "exported {} test(s) to {}",
+        plan.tests.len(),
+        args.output.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// A POSIX shell script that runs each of `tests` in turn with the shell and
+/// environment it was generated for, and fails if any of them did.
+fn bundle(config: &Config, tests: &[TestJob]) -> anyhow::Result<String> {
+    let mut script = format!(
+        "#!/bin/sh\n# The tests of {}, exported by bishin to run without it.\n\n",
+        config.source()
+    );
+    script.push_str(
+        &HARNESS
+            .replace("BISHIN_TMPDIR_VAR", TMPDIR_ENV)
+            .replace("SKIP_EXIT_CODE", &SKIP_EXIT_CODE.to_string())
+            .replace("TERMINATE_GRACE", &TERMINATE_GRACE.as_secs().to_string()),
+    );
+    for (index, test_job) in tests.iter().enumerate() {
+        let contents = std::fs::read_to_string(&test_job.script)
+            .with_context(|| format!("failed to read {}", test_job.script.display()))?;
+        let shell = test_job.job.args.first().map_or("sh", String::as_str);
+        let mut envs: Vec<_> = test_job.job.envs.iter().collect();
+        envs.sort();
+        let _ = writeln!(
+            script,
+            "\n# {}\ntest_{index}() {{\n    bishin_timeout env \\",
+            test_job.id
+        );
+        for (key, value) in envs {
+            let _ = writeln!(script, "        {} \\", quote(&format!("{key}={value}")));
+        }
+        let _ = writeln!(
+            script,
+            "        {shell} -c {} {shell} \"$@\"\n}}",
+            quote(&contents)
+        );
+    }
+    script.push('\n');
+    for (index, test_job) in tests.iter().enumerate() {
+        let job = &test_job.job;
+        if job.skip.is_some() {
+            let _ = writeln!(script, "bishin_skip {}", quote(&test_job.id));
+            continue;
+        }
+        let expected = match job.expected_exit {
+            Some(ExpectedExit::Code(code)) => code.to_string(),
+            Some(ExpectedExit::NonZero) => "nonzero".to_string(),
+            None => "0".to_string(),
+        };
+        let xfail = if job.xfail.is_some() { "xfail" } else { "''" };
+        let timeout = job.timeout.map_or("''".to_string(), |timeout| {
+            timeout.as_secs_f64().to_string()
+        });
+        let _ = writeln!(
+            script,
+            "bishin_run {} {expected} {xfail} {timeout} test_{index}",
+            quote(&test_job.id)
+        );
+    }
+    script.push_str(concat!(
+        "\necho\n",
+        "echo \"$passed passed, $failed failed, $skipped skipped\"\n",
+        "[ \"$failed\" -eq 0 ]\n",
+    ));
+    Ok(script)
+}
+
+/// `value` in single quotes, for the shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::testing::project;
+
+    const SUITE: (&str, &str) = (
+        "tests/suite.b",
+        "@test passes {\n    echo \"it's $GREETING\" > \"$BISHIN_TMPDIR/out\"\n    grep -q hello out\n}\n@test fails {\n    exit 3\n}\n@test skips {\n    exit 77\n}\n",
+    );
+
+    /// The exported script of `config`'s tests, and where it was written.
+    fn exported(config: &Config) -> (String, std::path::PathBuf) {
+        let plan = Runner::new(config.clone()).plan().unwrap();
+        let script = bundle(config, &plan.tests).unwrap();
+        let path = config.dir().join("all_tests.sh");
+        std::fs::write(&path, &script).unwrap();
+        (script, path)
+    }
+
+    #[test]
+    fn each_test_gets_a_function_and_a_run() {
+        let (config, _dir) = project("[env]\nGREETING = \"hello\"\n", &[SUITE]);
+        let (script, _) = exported(&config);
+        assert!(script.starts_with("#!/bin/sh\n"), "{script}");
+        for (index, id) in ["suite::passes", "suite::fails", "suite::skips"]
+            .iter()
+            .enumerate()
+        {
+            assert!(
+                script.contains(&format!("\n# {id}\ntest_{index}() {{\n")),
+                "{script}"
+            );
+            assert!(
+                script.contains(&format!("bishin_run '{id}' 0 '' '' test_{index}\n")),
+                "{script}"
+            );
+        }
+        assert!(script.contains("        'GREETING=hello' \\\n"), "{script}");
+        assert!(!script.contains("BISHIN_TMPDIR_VAR"), "{script}");
+    }
+
+    #[test]
+    fn the_exported_script_runs_the_tests() {
+        let (config, _dir) = project("[env]\nGREETING = \"hello\"\n", &[SUITE]);
+        let (_, path) = exported(&config);
+        let output = Command::new("sh").arg(&path).output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(
+            stdout,
+            concat!(
+                "test suite::passes ... ok\n",
+                "test suite::fails ... FAILED (exit code 3)\n",
+                "test suite::skips ... skipped\n",
+                "\n",
+                "1 passed, 1 failed, 1 skipped\n",
+            )
+        );
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn the_exported_script_keeps_what_decorators_expect() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/decorated.b",
+                concat!(
+                    "@skip\n@test skipped {\n    exit 1\n}\n",
+                    "@exit(3)\n@test exits {\n    exit 3\n}\n",
+                    "@xfail\n@test known_bug {\n    exit 1\n}\n",
+                    "@xfail\n@test fixed_bug {\n    true\n}\n",
+                    "@timeout(100ms)\n@test slow {\n    sleep 10\n}\n",
+                ),
+            )],
+        );
+        let (script, path) = exported(&config);
+        assert!(
+            script.contains("bishin_skip 'decorated::skipped'\n"),
+            "{script}"
+        );
+        assert!(
+            script.contains("bishin_run 'decorated::slow' 0 '' 0.1 test_4\n"),
+            "{script}"
+        );
+        let output = Command::new("sh").arg(&path).output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(
+            stdout,
+            concat!(
+                "test decorated::skipped ... skipped\n",
+                "test decorated::exits ... ok\n",
+                "test decorated::known_bug ... xfail\n",
+                "test decorated::fixed_bug ... XPASS\n",
+                "test decorated::slow ... TIMEOUT\n",
+                "\n",
+                "3 passed, 1 failed, 1 skipped\n",
+            )
+        );
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn passing_tests_export_a_passing_script() {
+        let (config, _dir) = project("", &[("tests/ok.b", "@test a {\n    true\n}\n")]);
+        let (_, path) = exported(&config);
+        let output = Command::new("sh").arg(&path).output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    #[test]
+    fn quotes_survive_the_shell() {
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf %s {}", quote("a 'b' $c \\d")))
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, b"a 'b' $c \\d");
+    }
+}
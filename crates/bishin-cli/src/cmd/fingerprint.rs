@@ -0,0 +1,10 @@
+use std::process::ExitCode;
+
+use bishin::collect;
+use bishin_config::Config;
+
+pub fn fingerprint(config: &Config) -> anyhow::Result<ExitCode> {
+    let graph = collect(config)?;
+    println!("{}", graph.fingerprint()?);
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,54 @@
+use std::process::ExitCode;
+
+use bishin::resolve::{CliOverrides, EnvOverrides, Settings};
+use bishin::Runner;
+use bishin_config::Config;
+
+pub fn gen(config: &Config) -> anyhow::Result<ExitCode> {
+    let settings = Settings::resolve(
+        config,
+        &EnvOverrides::from_env(),
+        &CliOverrides::default(),
+        std::env::consts::OS,
+    )?;
+    let plan = Runner::with_settings(config.clone(), settings).plan()?;
+    super::warn(&plan.warnings);
+    for test_job in &plan.tests {
+        println!("{} -> {}", test_job.id, test_job.script.display());
+    }
+    println!(
+        "generated {} test script(s) in {}",
+        plan.tests.len(),
+        config.resolved_work_dir().display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::project;
+
+    #[test]
+    fn gen_writes_the_scripts_and_leaves_them() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/net/dns.b",
+                "@test resolves {\n    echo resolved\n}\n@test fails {\n    exit 1\n}\n",
+            )],
+        );
+        assert_eq!(gen(&config).unwrap(), ExitCode::SUCCESS);
+        let work_dir = config.resolved_work_dir();
+        for (stem, body) in [
+            ("net.dns.resolves", "echo resolved"),
+            ("net.dns.fails", "exit 1"),
+        ] {
+            let script = std::fs::read_to_string(work_dir.join(format!("{stem}.sh"))).unwrap();
+            assert!(script.starts_with("#!"), "{script}");
+            assert!(script.ends_with(&format!("{body}\n")), "{script}");
+        }
+        // Nothing ran, so there's no history of it.
+        assert!(!work_dir.join("history").exists());
+    }
+}
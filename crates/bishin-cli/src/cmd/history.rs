@@ -0,0 +1,202 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use std::time::{Duration, SystemTime};
+
+use bishin::report::Reporter;
+use bishin::timings::Timings;
+use bishin_config::Config;
+use bishin_jobs::{History, RunRecord, RunSummary, DEFAULT_HISTORY_RUNS};
+
+use crate::cli::{FlakyArgs, HistoryArgs};
+
+/// The file in the work directory that runs are recorded in.
+pub const HISTORY_FILE: &str = "history";
+
+fn history_path(config: &Config) -> PathBuf {
+    config.resolved_work_dir().join(HISTORY_FILE)
+}
+
+/// A reporter that adds the run that started at `started` to the history,
+/// warning rather than failing the run if it can't be.
+///
+/// It records the run as the reporters finish, while the work directory is
+/// still locked, so that runs finishing at once can't write over each other.
+pub(crate) struct Recorder {
+    pub config: Config,
+    pub started: SystemTime,
+}
+
+impl Reporter for Recorder {
+    fn finish(&mut self, summary: &RunSummary, _timings: &Timings) -> io::Result<()> {
+        if summary.results.is_empty() {
+            return Ok(());
+        }
+        let timestamp = self
+            .started
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let id = format!("{timestamp}-{}", std::process::id());
+        let run = RunRecord::new(id, timestamp, git_sha(self.config.dir()), summary);
+        let path = history_path(&self.config);
+        if let Err(err) = History::append(&path, &run, DEFAULT_HISTORY_RUNS) {
+            eprintln!(
+                "warning: failed to record the run in {}: {err}",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The runs recorded so far, warning and going without them if they can't
+/// be read.
+pub(crate) fn load(config: &Config) -> History {
+    let path = history_path(config);
+    History::load(&path).unwrap_or_else(|err| {
+        eprintln!(
+            "warning: failed to read the history in {}: {err}",
+            path.display()
+        );
+        History::default()
+    })
+}
+
+/// The commit checked out in the repository `dir` is in, if it's in one.
+fn git_sha(dir: &Path) -> Option<String> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "HEAD"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let sha = String::from_utf8(output.stdout).ok()?;
+    let sha = sha.trim();
+    (output.status.success() && !sha.is_empty()).then(|| sha.to_string())
+}
+
+pub fn history(config: &Config, args: &HistoryArgs) -> anyhow::Result<ExitCode> {
+    let history = History::load(&history_path(config))?;
+    let runs: Vec<_> = history.test(&args.test).collect();
+    let runs = &runs[runs.len().saturating_sub(args.last)..];
+    if runs.is_empty() {
+        println!("{} hasn't run in the recorded history", args.test);
+        return Ok(ExitCode::SUCCESS);
+    }
+    for (run, test) in runs {
+        let sha = run
+            .git_sha
+            .as_deref()
+            .map_or("-", |sha| &sha[..sha.len().min(10)]);
+        println!(
+            "{}  {sha:<10}  {:<9}  {:>10}",
+            utc(run.timestamp),
+            test.status.name(),
+            format!("{:.2?}", test.duration)
+        );
+    }
+    let passed = runs
+        .iter()
+        .filter(|(_, test)| test.status == bishin_jobs::Status::Passed)
+        .count();
+    println!("\npassed in {passed} of the last {} run(s)", runs.len());
+    if let Some(((_, latest), earlier)) =
+        runs.split_last().filter(|(_, earlier)| !earlier.is_empty())
+    {
+        let mean = earlier
+            .iter()
+            .map(|(_, test)| test.duration)
+            .sum::<Duration>()
+            / earlier.len() as u32;
+        let change = if mean.is_zero() {
+            String::new()
+        } else {
+            let change = (latest.duration.as_secs_f64() / mean.as_secs_f64() - 1.0) * 100.0;
+            format!(" ({change:+.0}%)")
+        };
+        println!(
+            "took {:.2?} in the latest run, against a mean of {mean:.2?} before{change}",
+            latest.duration
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn flaky(config: &Config, args: &FlakyArgs) -> anyhow::Result<ExitCode> {
+    let history = History::load(&history_path(config))?;
+    let runs = history.runs.len().min(args.last);
+    let flaky = history.flaky(args.last);
+    if flaky.is_empty() {
+        println!("no test flipped between passing and failing in the last {runs} run(s)");
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("{:>5}  {:>4}  test", "flips", "runs");
+    for flakiness in &flaky {
+        println!(
+            "{:>5}  {:>4}  {}",
+            flakiness.flips, flakiness.runs, flakiness.name
+        );
+    }
+    println!("\n{} flaky test(s) in the last {runs} run(s)", flaky.len());
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `timestamp`, seconds since the Unix epoch, as a UTC date and time.
+fn utc(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+    // Howard Hinnant's days_from_civil, the other way around.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        seconds / 3_600,
+        seconds % 3_600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bishin::Runner;
+
+    use super::*;
+    use crate::testing::project;
+
+    #[test]
+    fn runs_are_recorded_as_they_finish() {
+        let (config, _dir) = project("", &[("tests/t.b", "@test a {\n    true\n}\n")]);
+        let recorder = Recorder {
+            config: config.clone(),
+            started: SystemTime::UNIX_EPOCH + Duration::from_secs(60),
+        };
+        let runner = Runner::new(config.clone()).reporter(Box::new(recorder));
+        let plan = runner.plan().unwrap();
+        runner.execute(plan).unwrap();
+        let history = History::load(&history_path(&config)).unwrap();
+        assert_eq!(history.runs.len(), 1);
+        assert_eq!(history.runs[0].timestamp, 60);
+        assert_eq!(history.test("t::a").count(), 1);
+    }
+
+    #[test]
+    fn timestamps_are_shown_as_utc() {
+        assert_eq!(utc(0), "1970-01-01 00:00");
+        assert_eq!(utc(951_782_400), "2000-02-29 00:00");
+        assert_eq!(utc(1_760_400_000), "2025-10-14 00:00");
+        assert_eq!(utc(4_107_542_399), "2100-02-28 23:59");
+    }
+}
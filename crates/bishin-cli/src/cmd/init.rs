@@ -0,0 +1,13 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use bishin_config::Config;
+
+pub fn init(path: &Path) -> anyhow::Result<ExitCode> {
+    if path.exists() {
+        anyhow::bail!("{} already exists, not overwriting it", path.display());
+    }
+    Config::write_default(path)?;
+    println!("wrote the default config to {}", path.display());
+    Ok(ExitCode::SUCCESS)
+}
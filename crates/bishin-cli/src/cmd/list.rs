@@ -0,0 +1,92 @@
+use std::process::ExitCode;
+
+use bishin::collect;
+use bishin_config::Config;
+use bishin_generate::{parse_module, test_ids};
+
+use crate::cli::ListArgs;
+
+pub fn list(config: &Config, args: &ListArgs) -> anyhow::Result<ExitCode> {
+    for line in listing(config, args)? {
+        println!("{line}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The lines that `bishin list` prints: the ID of every test, one per shell
+/// for those run in several, or the path of every test file with `--files`.
+fn listing(config: &Config, args: &ListArgs) -> anyhow::Result<Vec<String>> {
+    let graph = collect(config)?;
+    let mut lines = Vec::new();
+    for module in graph.iter_leaf_modules() {
+        if args.files {
+            let path = module.file_path();
+            let path = path.strip_prefix(config.dir()).unwrap_or(path);
+            lines.push(path.display().to_string());
+            continue;
+        }
+        for test in parse_module(module)? {
+            lines.extend(test_ids(module, &test));
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{project, ScratchDir};
+
+    const TEST: &str = "@test a {\n    true\n}\n@test b {\n    true\n}\n";
+
+    fn nested() -> (Config, ScratchDir) {
+        project(
+            "",
+            &[
+                ("tests/top.b", TEST),
+                ("tests/net/dns.b", TEST),
+                ("tests/net/http/get.b", TEST),
+                ("tests/net/README.md", "not a test file"),
+                ("tests/broken.b", "@test {\n"),
+            ],
+        )
+    }
+
+    #[test]
+    fn files_are_listed_relative_to_the_config_without_parsing() {
+        let mut files = listing(&nested().0, &ListArgs { files: true }).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "tests/broken.b",
+                "tests/net/dns.b",
+                "tests/net/http/get.b",
+                "tests/top.b"
+            ]
+        );
+    }
+
+    #[test]
+    fn tests_are_listed_by_their_ids() {
+        let (config, _dir) = project("", &[("tests/top.b", TEST), ("tests/net/dns.b", TEST)]);
+        let mut tests = listing(&config, &ListArgs { files: false }).unwrap();
+        tests.sort();
+        assert_eq!(tests, ["net::dns::a", "net::dns::b", "top::a", "top::b"]);
+        // Listing the tests parses the files, which the file list doesn't.
+        assert!(listing(&nested().0, &ListArgs { files: false }).is_err());
+    }
+
+    #[test]
+    fn tests_in_several_shells_are_listed_once_per_shell() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/top.b",
+                "@shells(bash, sh)\n@test many {\n    true\n}\n@shells(sh)\n@test one {\n    true\n}\n",
+            )],
+        );
+        let tests = listing(&config, &ListArgs { files: false }).unwrap();
+        assert_eq!(tests, ["top::many[bash]", "top::many[sh]", "top::one"]);
+    }
+}
@@ -0,0 +1,18 @@
+pub mod completions;
+pub mod exec;
+pub mod export;
+pub mod fingerprint;
+pub mod gen;
+pub mod history;
+pub mod init;
+pub mod list;
+pub mod new;
+pub mod report;
+pub mod run;
+
+/// Print the warnings that the library leaves to its caller.
+fn warn(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+}
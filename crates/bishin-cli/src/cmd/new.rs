@@ -0,0 +1,243 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use anyhow::{bail, Context};
+use bishin::collect;
+use bishin_collect::namespace;
+use bishin_config::Config;
+use bishin_parser::parse_test_file;
+
+use crate::cli::NewArgs;
+
+/// What a new test does until it's written, which is to skip itself with a
+/// reason that says so.
+const STUB_BODY: &str = "    echo \"not written yet\" >&2\n    exit 77\n";
+
+pub fn new(config: &Config, args: &NewArgs) -> anyhow::Result<ExitCode> {
+    let (path, line) = add_stub(config, &args.name)?;
+    println!("{}:{line}", path.display());
+    if args.edit {
+        edit(&path, line)?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Add a stub of the test called `name` to its module's file, creating the
+/// file if need be, and return the file and the line the stub starts on.
+fn add_stub(config: &Config, name: &str) -> anyhow::Result<(PathBuf, usize)> {
+    let Some((module_name, test_name)) = name.rsplit_once("::") else {
+        bail!("{name:?} has no module, expected a name like net::dns::{name}");
+    };
+    if test_name.is_empty()
+        || !test_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        bail!("{test_name:?} isn't a test name, which is made of letters, digits and _");
+    }
+    let module_path: Vec<_> = module_name.split("::").collect();
+    if module_path.iter().any(|part| part.is_empty()) {
+        bail!("{module_name:?} isn't a module name");
+    }
+    let path = match find_module(config, module_name)? {
+        Some(path) => path,
+        None => new_module_path(config, &module_path)?,
+    };
+
+    let mut contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let tests = parse_test_file(&contents)
+        .with_context(|| format!("failed to parse test file {}", path.display()))?
+        .tests;
+    if tests.iter().any(|test| test.name == test_name) {
+        bail!("{} already has a test called {test_name}", path.display());
+    }
+    if !contents.is_empty() {
+        if !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        contents.push('\n');
+    }
+    let line = contents.matches('\n').count() + 1;
+    contents.push_str(&format!("@test {test_name} {{\n{STUB_BODY}}}\n"));
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {}", parent.display()))?;
+    }
+    std::fs::write(&path, contents)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok((path, line))
+}
+
+/// The test file that's already the module called `name`, if any.
+fn find_module(config: &Config, name: &str) -> anyhow::Result<Option<PathBuf>> {
+    let graph = collect(config)?;
+    let path = graph
+        .iter_leaf_modules()
+        .find(|module| module.name() == name)
+        .map(|module| module.file_path().to_path_buf());
+    Ok(path)
+}
+
+/// Where the test file of a module that doesn't exist yet goes, given its
+/// path.
+fn new_module_path(config: &Config, module_path: &[&str]) -> anyhow::Result<PathBuf> {
+    let test_dirs = config.resolved_test_dirs();
+    let (root, relative) = if config.test_dirs.is_empty() {
+        (&test_dirs[0], module_path)
+    } else {
+        // With several test directories, each is a module of its own.
+        let (first, rest) = module_path
+            .split_first()
+            .expect("module paths aren't empty");
+        let Some(root) = test_dirs
+            .iter()
+            .find(|dir| namespace(dir).as_deref() == Some(*first))
+        else {
+            bail!("there's no test directory for module {first}");
+        };
+        if rest.is_empty() {
+            bail!("module {first} is a test directory, not a test file");
+        }
+        (root, rest)
+    };
+    let extension = config
+        .file_extensions
+        .first()
+        .map_or(bishin_collect::FILE_EXTENSION, String::as_str);
+    let mut path = relative
+        .iter()
+        .fold(root.clone(), |path, part| path.join(part));
+    path.set_extension(extension);
+    Ok(path)
+}
+
+/// Open `path` at `line` in `$VISUAL` or `$EDITOR`, which most editors take
+/// as `+LINE PATH`.
+fn edit(path: &Path, line: usize) -> anyhow::Result<()> {
+    let Some(editor) = std::env::var_os("VISUAL")
+        .or_else(|| std::env::var_os("EDITOR"))
+        .filter(|editor| !editor.is_empty())
+    else {
+        bail!("neither $VISUAL nor $EDITOR is set, so there's no editor to open");
+    };
+    // The editor may come with arguments of its own, so the shell splits it.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg("exec $0 \"$@\"")
+        .arg(&editor)
+        .arg(format!("+{line}"))
+        .arg(path)
+        .status()
+        .with_context(|| format!("failed to run {}", editor.to_string_lossy()))?;
+    if !status.success() {
+        bail!("{} exited with {status}", editor.to_string_lossy());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::project;
+
+    #[test]
+    fn stubs_go_at_the_end_of_an_existing_file() {
+        let (config, _dir) = project("", &[("tests/net/dns.b", "@test resolves {\n    true\n}")]);
+        let (path, line) = add_stub(&config, "net::dns::resolves_ipv6").unwrap();
+        assert_eq!(path, config.dir().join("tests/net/dns.b"));
+        assert_eq!(line, 5);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents,
+            format!("@test resolves {{\n    true\n}}\n\n@test resolves_ipv6 {{\n{STUB_BODY}}}\n")
+        );
+        let tests = parse_test_file(&contents).unwrap().tests;
+        assert_eq!(tests[1].name, "resolves_ipv6");
+        assert_eq!(
+            contents.lines().nth(line - 1),
+            Some("@test resolves_ipv6 {")
+        );
+    }
+
+    #[test]
+    fn new_modules_get_a_file_and_its_directories() {
+        let (config, _dir) = project("", &[("tests/other.b", "@test a {\n    true\n}\n")]);
+        let (path, line) = add_stub(&config, "net::dns::resolves").unwrap();
+        assert_eq!(path, config.dir().join("tests/net/dns.b"));
+        assert_eq!(line, 1);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, format!("@test resolves {{\n{STUB_BODY}}}\n"));
+    }
+
+    #[test]
+    fn new_modules_take_the_first_file_extension() {
+        let (config, _dir) = project(
+            "file-extensions = [\"bishin\", \"b\"]\n",
+            &[("tests/other.b", "")],
+        );
+        let (path, _) = add_stub(&config, "net::resolves").unwrap();
+        assert_eq!(path, config.dir().join("tests/net.bishin"));
+    }
+
+    #[test]
+    fn with_several_test_dirs_the_module_names_the_dir() {
+        let (config, _dir) = project(
+            "test-dirs = [\"unit\", \"e2e\"]\n",
+            &[("unit/a.b", ""), ("e2e/a.b", "")],
+        );
+        let (path, _) = add_stub(&config, "e2e::login::works").unwrap();
+        assert_eq!(path, config.dir().join("e2e/login.b"));
+        let err = add_stub(&config, "smoke::login::works").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "there's no test directory for module smoke"
+        );
+        let err = add_stub(&config, "e2e::works").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "module e2e is a test directory, not a test file"
+        );
+    }
+
+    #[test]
+    fn tests_that_exist_are_not_added_again() {
+        let before = "@test resolves {\n    true\n}\n";
+        let (config, _dir) = project("", &[("tests/net.b", before)]);
+        let err = add_stub(&config, "net::resolves").unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("already has a test called resolves"),
+            "{err}"
+        );
+        assert_eq!(
+            std::fs::read_to_string(config.dir().join("tests/net.b")).unwrap(),
+            before
+        );
+    }
+
+    #[test]
+    fn names_need_a_module_and_a_valid_test_name() {
+        let (config, _dir) = project("", &[]);
+        for (name, message) in [
+            (
+                "resolves",
+                "\"resolves\" has no module, expected a name like net::dns::resolves",
+            ),
+            (
+                "net::",
+                "\"\" isn't a test name, which is made of letters, digits and _",
+            ),
+            (
+                "net::re-solves",
+                "\"re-solves\" isn't a test name, which is made of letters, digits and _",
+            ),
+            ("net::::resolves", "\"net::\" isn't a module name"),
+        ] {
+            assert_eq!(add_stub(&config, name).unwrap_err().to_string(), message);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use std::process::ExitCode;
+
+use anyhow::Context;
+use bishin::report;
+
+use crate::cli::ReportArgs;
+
+pub fn report(args: &ReportArgs) -> anyhow::Result<ExitCode> {
+    let contents = std::fs::read_to_string(&args.from)
+        .with_context(|| format!("failed to read {}", args.from.display()))?;
+    let (summary, duration) = report::read_summary(&contents)
+        .map_err(|err| anyhow::anyhow!("{} isn't a JSON report: {err}", args.from.display()))?;
+    std::fs::write(&args.html, report::render_html(&summary, duration))
+        .with_context(|| format!("failed to write {}", args.html.display()))?;
+    println!("wrote {}", args.html.display());
+    Ok(ExitCode::SUCCESS)
+}
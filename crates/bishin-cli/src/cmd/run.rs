@@ -0,0 +1,1006 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context};
+use bishin::replay::Replay;
+use bishin::report::{self, Prefixed, Reporter};
+use bishin::resolve::{self, CliOverrides, EnvOverrides, Settings, StopPolicy};
+use bishin::timings::Timings;
+use bishin::{NamePattern, Quarantine, RunOutcome, Runner, TagFilter, TestFilter};
+use bishin_config::{Config, SuccessOutput};
+use bishin_generate::coverage::Coverage;
+use bishin_generate::trace::command_counts;
+use bishin_generate::{syntax_check_job, TestJob};
+use bishin_jobs::{Executor, Job, JobResult, RunSummary, Status};
+
+use crate::cli::{GroupBy, RunArgs, TimingsFormat};
+use crate::cmd::history;
+
+/// The exit code of a run whose setup script failed, which is different from
+/// that of one whose tests failed.
+pub const SETUP_FAILED_EXIT_CODE: u8 = 3;
+
+pub fn run(config: &Config, args: &RunArgs, timings: Timings) -> anyhow::Result<ExitCode> {
+    let replayed;
+    let args = match &args.replay {
+        Some(token) => {
+            replayed = replay_args(args, Replay::from_token(token)?);
+            &replayed
+        }
+        None => args,
+    };
+    let seed = args.seed.or_else(|| args.shuffle.then(random_seed));
+    let settings = Settings::resolve(
+        config,
+        &EnvOverrides::from_env(),
+        &cli_overrides(args),
+        std::env::consts::OS,
+    )?;
+    let started = SystemTime::now();
+    let runner = Runner::with_settings(config.clone(), settings)
+        .command_report(args.command_report)
+        .coverage(args.coverage || args.coverage_lcov.is_some())
+        .resource_stats(args.resource_stats)
+        .strict_processes(args.strict_processes)
+        .wait(args.wait)
+        .strict_xfail(args.strict_xfail)
+        .fail_on_skip(args.fail_on_skip)
+        .history(history::load(config))
+        .within(args.within)
+        .shuffle(seed)
+        .timings(timings);
+    if let Some(name) = &args.print_env {
+        return print_env(runner, name);
+    }
+    let (tags, default_tags) = resolve::tags(&args.tag, &args.exclude_tag, &config.tags);
+    let filter = TestFilter {
+        tags,
+        benches: args.benches,
+        ..TestFilter::new(&args.filter, &args.tests, &args.exclude, args.regex)?
+    };
+    let mut runner = runner.filter(filter);
+    let mut plan = runner.plan()?;
+    // What's left in the plan's warnings would be the outcome's as well.
+    super::warn(&std::mem::take(&mut plan.warnings));
+    report_over_budget(&plan.over_budget);
+    if args.dry_run {
+        let out = &mut io::stdout().lock();
+        return dry_run(out, config, runner.settings(), &plan.tests, &default_tags);
+    }
+    if args.syntax_check && !check_syntax(&plan.tests, runner.settings().jobs.value) {
+        return Ok(ExitCode::FAILURE);
+    }
+    report_unquarantined(&plan.quarantine);
+
+    let name_prefix = args
+        .name_prefix
+        .as_deref()
+        .or(config.name_prefix.as_deref())
+        .filter(|prefix| !prefix.is_empty());
+    let files = match args.group_by {
+        Some(GroupBy::File) => Some(
+            plan.tests
+                .iter()
+                .map(|test_job| {
+                    let name = format!("{}{}", name_prefix.unwrap_or(""), test_job.id);
+                    let file = test_job.file.strip_prefix(config.dir());
+                    (name, file.unwrap_or(&test_job.file).to_path_buf())
+                })
+                .collect(),
+        ),
+        None => None,
+    };
+    let human: Box<dyn Reporter> = Box::new(HumanReporter {
+        out: Box::new(io::stdout()),
+        on_success: config.on_success_output,
+        passed: Vec::new(),
+        files,
+    });
+    for reporter in std::iter::once(human).chain(report::reporters(
+        &config.resolved_reports(),
+        &args.report,
+        &args.no_report,
+    )?) {
+        runner = runner.reporter(match name_prefix {
+            Some(prefix) => Box::new(Prefixed::new(prefix, reporter)),
+            None => reporter,
+        });
+    }
+    // The history keeps the names without the prefix.
+    runner = runner.reporter(Box::new(history::Recorder {
+        config: config.clone(),
+        started,
+    }));
+    let outcome = runner.execute(plan)?;
+    super::warn(&outcome.warnings);
+    let summary = &outcome.summary;
+
+    if outcome.setup_failed {
+        println!("\nthe setup script failed, so no tests were run");
+        return Ok(ExitCode::from(SETUP_FAILED_EXIT_CODE));
+    }
+
+    let passed = outcome.passed();
+    if args.command_report {
+        report_commands(outcome.trace.iter().map(String::as_str));
+    }
+    if let Some(coverage) = &outcome.coverage {
+        report_coverage(coverage);
+        if let Some(path) = &args.coverage_lcov {
+            std::fs::write(path, coverage.to_lcov())
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+    }
+    if outcome.skips_failed() {
+        report_skipped(summary);
+    }
+    if args.strict_xfail {
+        report_xpassed(summary);
+    }
+    report_quarantined(&outcome);
+    if !outcome.kept_dirs.is_empty() {
+        println!("\nkept the temp dirs of failed tests:");
+        for (name, dir) in &outcome.kept_dirs {
+            println!("    {name}: {}", dir.display());
+        }
+    }
+    if !outcome.artifacts.is_empty() {
+        println!("\nkept the artifacts of tests:");
+        for (name, path) in &outcome.artifacts {
+            println!("    {name}: {}", path.display());
+        }
+    }
+    if outcome.teardown_failed {
+        println!("\nthe teardown script failed");
+    }
+    if outcome.not_run > 0 {
+        println!(
+            "\nstopped early because of failures, {} test(s) were not run",
+            outcome.not_run
+        );
+    }
+    report_default_tags(&mut io::stdout(), &default_tags)?;
+    report_benchmarks(summary);
+    if let Some(seed) = seed.filter(|_| !passed) {
+        report_replay(args, seed);
+    }
+    report_summary(summary, outcome.min_pass_rate(), passed);
+    match args.timings {
+        Some(TimingsFormat::Human) => report_timings(&outcome.timings),
+        Some(TimingsFormat::Json) => println!("{}", outcome.timings.to_json()),
+        None => {}
+    }
+    Ok(if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// `args` with the tests and order of `replay` instead of their own.
+fn replay_args(args: &RunArgs, replay: Replay) -> RunArgs {
+    RunArgs {
+        tests: replay.tests,
+        filter: replay.filter,
+        exclude: replay.exclude,
+        regex: replay.regex,
+        tag: replay.tags,
+        exclude_tag: replay.exclude_tags,
+        benches: replay.benches,
+        seed: Some(replay.seed),
+        replay: None,
+        ..args.clone()
+    }
+}
+
+/// A seed for a shuffled run that wasn't given one, which only has to differ
+/// from run to run.
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    nanos ^ u64::from(std::process::id()).rotate_left(32)
+}
+
+fn report_replay(args: &RunArgs, seed: u64) {
+    let replay = Replay {
+        seed,
+        tests: args.tests.clone(),
+        filter: args.filter.clone(),
+        exclude: args.exclude.clone(),
+        regex: args.regex,
+        tags: args.tag.clone(),
+        exclude_tags: args.exclude_tag.clone(),
+        benches: args.benches,
+    };
+    println!(
+        "\nthe tests ran in a shuffled order, to run them in the same order again:\n    bishin run --replay {}",
+        replay.to_token()
+    );
+}
+
+/// The settings that `args` override.
+fn cli_overrides(args: &RunArgs) -> CliOverrides {
+    CliOverrides {
+        jobs: args.jobs,
+        min_pass_rate: args.min_pass_rate,
+        retries: args.retries,
+        stop: StopPolicy {
+            fail_fast: args.fail_fast.then_some(true),
+            max_failures: args.max_failures,
+        },
+        update_snapshots: args.update_snapshots,
+    }
+}
+
+/// Prints each result as its test finishes, or all of them by file once the
+/// last one does, and the result of the setup and teardown scripts.
+struct HumanReporter {
+    out: Box<dyn Write>,
+    on_success: SuccessOutput,
+    /// The passing tests whose output is shown once the last test finishes.
+    passed: Vec<JobResult>,
+    /// The file of each test by its name, if results are shown by file.
+    files: Option<HashMap<String, PathBuf>>,
+}
+
+impl HumanReporter {
+    fn show(&mut self, result: &JobResult) -> io::Result<()> {
+        report_result(&mut self.out, result)?;
+        if result.leaked_processes && result.status == Status::Passed {
+            eprintln!(
+                "warning: test {} left processes running in the background, which were killed; --strict-processes fails it",
+                result.name
+            );
+        }
+        if result.status == Status::Passed {
+            match self.on_success {
+                SuccessOutput::Hidden => {}
+                SuccessOutput::Summary => self.passed.push(result.clone()),
+                SuccessOutput::Full => {
+                    print_output(&mut self.out, "stdout", &result.stdout)?;
+                    print_output(&mut self.out, "stderr", &result.stderr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Show `results` under the files they're from, in order by path.
+    fn show_by_file(
+        &mut self,
+        files: &HashMap<String, PathBuf>,
+        results: &[JobResult],
+    ) -> io::Result<()> {
+        let mut by_file: BTreeMap<&Path, Vec<&JobResult>> = BTreeMap::new();
+        for result in results {
+            let file = files
+                .get(&result.name)
+                .map_or(Path::new(""), PathBuf::as_path);
+            by_file.entry(file).or_default().push(result);
+        }
+        for (file, results) in by_file {
+            let failed = results
+                .iter()
+                .filter(|result| result.status.is_failure())
+                .count();
+            writeln!(
+                self.out,
+                "\n{}: {} test(s), {failed} failed",
+                file.display(),
+                results.len()
+            )?;
+            for result in results {
+                self.show(result)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Reporter for HumanReporter {
+    fn result(&mut self, result: &JobResult) -> io::Result<()> {
+        if self.files.is_none() {
+            self.show(result)?;
+        }
+        Ok(())
+    }
+
+    fn hook(&mut self, result: &JobResult) -> io::Result<()> {
+        let status = match result.status {
+            Status::Passed => "ok",
+            _ => "FAILED",
+        };
+        writeln!(self.out, "{} ... {status}", result.name)?;
+        if result.status != Status::Passed {
+            print_output(&mut self.out, "stdout", &result.stdout)?;
+            print_output(&mut self.out, "stderr", &result.stderr)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &RunSummary, _timings: &Timings) -> io::Result<()> {
+        if let Some(files) = self.files.take() {
+            self.show_by_file(&files, &summary.results)?;
+        }
+        let passed: Vec<_> = self
+            .passed
+            .iter()
+            .filter(|result| !result.stdout.is_empty() || !result.stderr.is_empty())
+            .collect();
+        if !passed.is_empty() {
+            writeln!(self.out, "\noutput of passing tests:")?;
+        }
+        for result in passed {
+            writeln!(self.out, "\n{}:", result.name)?;
+            print_output(&mut self.out, "stdout", &result.stdout)?;
+            print_output(&mut self.out, "stderr", &result.stderr)?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Print the environment of the test called `name` as sorted `KEY=VALUE`
+/// lines.
+fn print_env(runner: Runner, name: &str) -> anyhow::Result<ExitCode> {
+    let filter = TestFilter {
+        include: vec![NamePattern::Substring(name.to_string())],
+        ..TestFilter::default()
+    };
+    let plan = runner.filter(filter).plan()?;
+    super::warn(&plan.warnings);
+    for line in env_lines(&plan.tests, name)? {
+        println!("{line}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The lines [`print_env`] prints for `name` out of `test_jobs`. A test run
+/// in several shells can be named with the shell, e.g. `net::dns[fish]`, or
+/// without it for the environment of each, under a `# <id>` line.
+fn env_lines(test_jobs: &[TestJob], name: &str) -> anyhow::Result<Vec<String>> {
+    let mut matching: Vec<&TestJob> = test_jobs
+        .iter()
+        .filter(|test_job| test_job.id == name)
+        .collect();
+    if matching.is_empty() {
+        let variant = format!("{name}[");
+        matching = test_jobs
+            .iter()
+            .filter(|test_job| {
+                test_job
+                    .id
+                    .strip_prefix(&variant)
+                    .is_some_and(|rest| rest.ends_with(']'))
+            })
+            .collect();
+    }
+    if matching.is_empty() {
+        bail!("no test is called {name}");
+    }
+    let headers = matching.len() > 1;
+    let mut lines = Vec::new();
+    for test_job in matching {
+        if headers {
+            lines.push(format!("# {}", test_job.id));
+        }
+        for (key, value) in test_job.job.environment() {
+            lines.push(format!("{key}={value}"));
+        }
+    }
+    Ok(lines)
+}
+
+/// Check the syntax of every script that can be checked, reporting any
+/// errors. Returns whether all of them were fine.
+fn check_syntax(test_jobs: &[TestJob], concurrency: usize) -> bool {
+    let jobs: Vec<Job> = test_jobs.iter().filter_map(syntax_check_job).collect();
+    let results = Executor::new(concurrency).run(&jobs, |_, _| {});
+    let failures: Vec<_> = results
+        .iter()
+        .filter(|result| result.status != Status::Passed)
+        .collect();
+    for result in &failures {
+        println!("syntax error in test {}:", result.name);
+        print!("{}", result.stderr);
+    }
+    if !failures.is_empty() {
+        println!(
+            "\n{} test(s) have syntax errors, not running any tests",
+            failures.len()
+        );
+    }
+    failures.is_empty()
+}
+
+fn report_result(out: &mut dyn Write, result: &JobResult) -> io::Result<()> {
+    let status = match result.status {
+        Status::Passed => "ok",
+        Status::Failed => "FAILED",
+        Status::TimedOut => "TIMEOUT",
+        Status::Error => "ERROR",
+        Status::Skipped => "skipped",
+        Status::XFail => "xfail",
+        Status::XPass => "XPASS",
+    };
+    // Like libtest's "ignored, reason".
+    let status = match result.skip_reason.as_ref().or(result.xfail_reason.as_ref()) {
+        Some(reason) => format!("{status}, {reason}"),
+        None => status.to_string(),
+    };
+    let mut notes = Vec::new();
+    if result.attempts > 1 {
+        notes.push(format!("after {} attempts", result.attempts));
+    }
+    if let Some(stats) = &result.bench {
+        notes.push(format!(
+            "median {:.2?} over {} runs",
+            stats.median(),
+            stats.times.len()
+        ));
+    }
+    if let Some(usage) = &result.usage {
+        notes.push(format!(
+            "cpu {:.2?}, max rss {}",
+            usage.cpu_time,
+            mebibytes(usage.max_rss)
+        ));
+    }
+    match notes.is_empty() {
+        true => writeln!(out, "test {} ... {status}", result.name)?,
+        false => writeln!(
+            out,
+            "test {} ... {status} ({})",
+            result.name,
+            notes.join(", ")
+        )?,
+    }
+    if result.status.is_failure() {
+        print_output(out, "stdout", &result.stdout)?;
+        print_output(out, "stderr", &result.stderr)?;
+    }
+    Ok(())
+}
+
+/// `bytes` in MiB, to one decimal place.
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+pub(crate) fn print_output(out: &mut dyn Write, stream: &str, output: &str) -> io::Result<()> {
+    if output.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "---- {stream} ----")?;
+    write!(out, "{output}")?;
+    if !output.ends_with('\n') {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn report_commands<'a>(trace: impl IntoIterator<Item = &'a str>) {
+    let mut counts: Vec<_> = command_counts(trace).into_iter().collect();
+    counts.sort_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+    });
+    println!("\ncommand usage:");
+    if counts.is_empty() {
+        println!("    (no external commands)");
+    }
+    for (command, count) in counts {
+        println!("{count:>8} {command}");
+    }
+}
+
+fn report_coverage(coverage: &Coverage) {
+    let percent = |covered: usize, total: usize| match total {
+        0 => 100.0,
+        total => covered as f64 * 100.0 / total as f64,
+    };
+    println!("\ncoverage of test bodies:");
+    let (mut all_covered, mut all_total) = (0, 0);
+    for (path, lines) in &coverage.files {
+        let (covered, total) = Coverage::counts(lines);
+        all_covered += covered;
+        all_total += total;
+        let missed: Vec<_> = lines
+            .iter()
+            .filter(|(_, hits)| **hits == 0)
+            .map(|(line, _)| *line)
+            .collect();
+        print!(
+            "    {}: {covered}/{total} lines ({:.1}%)",
+            path.display(),
+            percent(covered, total)
+        );
+        if missed.is_empty() {
+            println!();
+        } else {
+            println!(", not run: {}", line_ranges(&missed));
+        }
+    }
+    println!(
+        "    total: {all_covered}/{all_total} lines ({:.1}%)",
+        percent(all_covered, all_total)
+    );
+}
+
+/// Sorted line numbers as ranges, e.g. `3, 7-9`.
+fn line_ranges(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{start}-{end}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn report_over_budget(over_budget: &[String]) {
+    if !over_budget.is_empty() {
+        println!(
+            "left out {} test(s) that don't fit in the --within budget",
+            over_budget.len()
+        );
+    }
+}
+
+fn report_skipped(summary: &RunSummary) {
+    println!("\nskipped tests, which --fail-on-skip doesn't allow:");
+    for result in summary.skipped() {
+        match &result.skip_reason {
+            Some(reason) => println!("    {}: {reason}", result.name),
+            None => println!("    {}", result.name),
+        }
+    }
+}
+
+/// Warn about the `@flaky` tests whose failures count again.
+fn report_unquarantined(quarantine: &BTreeMap<String, Quarantine>) {
+    for (name, quarantine) in quarantine {
+        match quarantine {
+            Quarantine::Quarantined => {}
+            Quarantine::Promoted(runs) => eprintln!(
+                "warning: @flaky test {name} passed the last {runs} runs, so its failures count again; remove its @flaky if it's fixed"
+            ),
+            Quarantine::TooFlaky(percent) => eprintln!(
+                "warning: @flaky test {name} failed {percent}% of its recorded runs, more than it allows, so its failures count again"
+            ),
+        }
+    }
+}
+
+fn report_quarantined(outcome: &RunOutcome) {
+    let failed: Vec<_> = outcome
+        .summary
+        .results
+        .iter()
+        .filter(|result| result.status.is_failure() && outcome.is_quarantined(&result.name))
+        .collect();
+    if failed.is_empty() {
+        return;
+    }
+    println!("\nquarantined @flaky tests that failed, which don't fail the run:");
+    for result in failed {
+        println!("    {}", result.name);
+    }
+}
+
+fn report_xpassed(summary: &RunSummary) {
+    let xpassed: Vec<_> = summary
+        .results
+        .iter()
+        .filter(|result| result.status == Status::XPass)
+        .collect();
+    if xpassed.is_empty() {
+        return;
+    }
+    println!("\n@xfail tests that passed, which --strict-xfail doesn't allow:");
+    for result in xpassed {
+        println!("    {}", result.name);
+    }
+}
+
+/// Say which of the config's default tag filters applied, since they leave
+/// tests out without anything on the command line asking for it.
+fn report_default_tags(out: &mut impl Write, defaults: &TagFilter) -> io::Result<()> {
+    if defaults.is_empty() {
+        return Ok(());
+    }
+    let mut parts = Vec::new();
+    if !defaults.include.is_empty() {
+        parts.push(format!("only tests tagged {}", defaults.include.join(", ")));
+    }
+    if !defaults.exclude.is_empty() {
+        parts.push(format!("no tests tagged {}", defaults.exclude.join(", ")));
+    }
+    writeln!(
+        out,
+        "\nthe config's default tags chose {}, see --tag and --exclude-tag",
+        parts.join(" and ")
+    )
+}
+
+/// Say what each setting is and where it came from.
+fn report_settings(out: &mut impl Write, config: &Config, settings: &Settings) -> io::Result<()> {
+    writeln!(out, "\nsettings, with the config from {}:", config.source())?;
+    for (key, value, source) in settings.provenance() {
+        writeln!(out, "    {key:<20} {value:<10} ({source})")?;
+    }
+    Ok(())
+}
+
+/// List the tests that `--dry-run` would run, what chose them and the
+/// settings they'd run with.
+///
+/// A reader that stops early, such as `head`, only ends the listing early.
+fn dry_run(
+    out: &mut impl Write,
+    config: &Config,
+    settings: &Settings,
+    tests: &[TestJob],
+    default_tags: &TagFilter,
+) -> anyhow::Result<ExitCode> {
+    let listed = (|| {
+        for test_job in tests {
+            writeln!(out, "test {}", test_job.id)?;
+        }
+        report_default_tags(out, default_tags)?;
+        report_settings(out, config, settings)?;
+        writeln!(out, "\n{} test(s) would run", tests.len())?;
+        out.flush()
+    })();
+    match listed {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(ExitCode::SUCCESS),
+        listed => {
+            listed.context("failed to list the tests that would run")?;
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn report_summary(summary: &RunSummary, min_pass_rate: Option<f64>, passed: bool) {
+    let outcome = if passed { "ok" } else { "FAILED" };
+    let mut line = format!(
+        "\ntest result: {outcome}. {} passed; {} failed; {} timed out; {} errors; {} skipped; pass rate {:.1}%",
+        summary.count(Status::Passed),
+        summary.count(Status::Failed),
+        summary.count(Status::TimedOut),
+        summary.count(Status::Error),
+        summary.count(Status::Skipped),
+        summary.pass_rate() * 100.0,
+    );
+    if let Some(rate) = min_pass_rate {
+        line.push_str(&format!(" (minimum {:.1}%)", rate * 100.0));
+    }
+    // Most runs have no @xfail tests, so only mention them when there are.
+    let (xfailed, xpassed) = (summary.count(Status::XFail), summary.count(Status::XPass));
+    if xfailed + xpassed > 0 {
+        line.push_str(&format!("; {xfailed} xfailed; {xpassed} xpassed"));
+    }
+    println!("{line}");
+}
+
+fn report_benchmarks(summary: &RunSummary) {
+    let benchmarks: Vec<_> = summary
+        .results
+        .iter()
+        .filter_map(|result| Some((&result.name, result.bench.as_ref()?)))
+        .collect();
+    if benchmarks.is_empty() {
+        return;
+    }
+    let width = benchmarks
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+    println!("\nbenchmarks:");
+    // Durations ignore the width when formatted, so go through a string.
+    let cell = |duration: Duration| format!("{duration:.2?}");
+    println!(
+        "    {:<width$} {:>10} {:>10} {:>10} {:>10} {:>6}",
+        "", "min", "median", "mean", "p95", "runs"
+    );
+    for (name, stats) in benchmarks {
+        println!(
+            "    {name:<width$} {:>10} {:>10} {:>10} {:>10} {:>6}",
+            cell(stats.min()),
+            cell(stats.median()),
+            cell(stats.mean()),
+            cell(stats.p95()),
+            stats.times.len()
+        );
+    }
+}
+
+fn report_timings(timings: &Timings) {
+    println!("\ntimings:");
+    // Durations ignore the width when formatted, so go through a string.
+    let row = |name: &dyn std::fmt::Display, duration: Duration| {
+        println!("    {name:<12} {:>10}", format!("{duration:.2?}"));
+    };
+    for (phase, duration) in timings.iter() {
+        row(&phase, duration);
+    }
+    row(&"total", timings.total());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// What a reporter printed, kept after it's dropped.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn result(name: &str, status: Status, stdout: &str) -> JobResult {
+        JobResult {
+            status,
+            exit_code: Some(0),
+            stdout: stdout.to_string(),
+            ..JobResult::error(name, "")
+        }
+    }
+
+    /// What the human output of a run of a passing test that prints, a
+    /// passing one that doesn't and a failing one looks like with
+    /// `on_success`.
+    fn human_output(on_success: SuccessOutput) -> String {
+        let out = Captured::default();
+        let mut reporter = HumanReporter {
+            out: Box::new(out.clone()),
+            on_success,
+            passed: Vec::new(),
+            files: None,
+        };
+        let results = [
+            result("prints", Status::Passed, "hello\n"),
+            result("quiet", Status::Passed, ""),
+            result("fails", Status::Failed, "oops"),
+        ];
+        for result in &results {
+            reporter.result(result).unwrap();
+        }
+        let summary = RunSummary {
+            results: results.to_vec(),
+            ..RunSummary::default()
+        };
+        reporter.finish(&summary, &Timings::default()).unwrap();
+        let output = out.0.take();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn passing_output_is_hidden_by_default() {
+        assert_eq!(
+            human_output(SuccessOutput::Hidden),
+            "test prints ... ok\ntest quiet ... ok\ntest fails ... FAILED\n---- stdout ----\noops\n"
+        );
+    }
+
+    #[test]
+    fn passing_output_can_follow_the_last_test() {
+        assert_eq!(
+            human_output(SuccessOutput::Summary),
+            "test prints ... ok\ntest quiet ... ok\ntest fails ... FAILED\n---- stdout ----\noops\n\
+             \noutput of passing tests:\n\nprints:\n---- stdout ----\nhello\n"
+        );
+    }
+
+    #[test]
+    fn passing_output_can_follow_each_test() {
+        assert_eq!(
+            human_output(SuccessOutput::Full),
+            "test prints ... ok\n---- stdout ----\nhello\ntest quiet ... ok\ntest fails ... FAILED\n---- stdout ----\noops\n"
+        );
+    }
+
+    #[test]
+    fn snapshot_mismatches_show_their_diff() {
+        let out = Captured::default();
+        let mut reporter = HumanReporter {
+            out: Box::new(out.clone()),
+            on_success: SuccessOutput::Hidden,
+            passed: Vec::new(),
+            files: None,
+        };
+        let diff = bishin_jobs::unified_diff("one\ntwo\n", "one\nTWO\n", "snapshot", "stdout", 3);
+        let failed = JobResult {
+            stderr: format!("bishin: stdout doesn't match its snapshot\n{diff}"),
+            ..result("snap", Status::Failed, "one\nTWO\n")
+        };
+        reporter.result(&failed).unwrap();
+        assert_eq!(
+            String::from_utf8(out.0.take()).unwrap(),
+            "test snap ... FAILED\n---- stdout ----\none\nTWO\n---- stderr ----\n\
+             bishin: stdout doesn't match its snapshot\n\
+             --- snapshot\n+++ stdout\n@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n"
+        );
+    }
+
+    #[test]
+    fn skips_show_their_reason_if_they_have_one() {
+        let (config, _dir) = crate::testing::project(
+            "",
+            &[(
+                "tests/x.b",
+                "@skip(\"\")\n@test b {\n    true\n}\n@skip(\"later\")\n@test c {\n    true\n}\n",
+            )],
+        );
+        let mut results = Runner::new(config).run().unwrap().summary.results;
+        results.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut out = Vec::new();
+        for result in &results {
+            report_result(&mut out, result).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "test x::b ... skipped\ntest x::c ... skipped, later\n"
+        );
+    }
+
+    const DRY_RUN: [(&str, &str); 1] = [(
+        "tests/dry.b",
+        "@test a {\n    true\n}\n@test b {\n    true\n}\n",
+    )];
+
+    /// What `--dry-run` lists for [`DRY_RUN`] with `out`, and its exit code.
+    fn dry_run_to(out: &mut impl Write) -> anyhow::Result<ExitCode> {
+        let (config, _dir) = crate::testing::project("", &DRY_RUN);
+        let runner = Runner::new(config.clone());
+        let plan = runner.plan()?;
+        dry_run(
+            out,
+            &config,
+            runner.settings(),
+            &plan.tests,
+            &TagFilter::default(),
+        )
+    }
+
+    #[test]
+    fn dry_runs_list_the_tests_and_settings() {
+        let mut out = Vec::new();
+        assert_eq!(dry_run_to(&mut out).unwrap(), ExitCode::SUCCESS);
+        let listing = String::from_utf8(out).unwrap();
+        assert!(
+            listing.starts_with("test dry::a\ntest dry::b\n\nsettings, with the config from "),
+            "{listing}"
+        );
+        assert!(listing.ends_with("\n\n2 test(s) would run\n"), "{listing}");
+    }
+
+    /// A writer whose reader has gone, like a pipe into `head` that's done.
+    struct Closed(io::ErrorKind);
+
+    impl Write for Closed {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(self.0.into())
+        }
+    }
+
+    #[test]
+    fn dry_runs_stop_quietly_at_a_broken_pipe() {
+        let code = dry_run_to(&mut Closed(io::ErrorKind::BrokenPipe)).unwrap();
+        assert_eq!(code, ExitCode::SUCCESS);
+        let err = dry_run_to(&mut Closed(io::ErrorKind::PermissionDenied)).unwrap_err();
+        assert_eq!(err.to_string(), "failed to list the tests that would run");
+    }
+
+    #[test]
+    fn prefixed_names_reach_the_human_output() {
+        let out = Captured::default();
+        let human = HumanReporter {
+            out: Box::new(out.clone()),
+            on_success: SuccessOutput::Hidden,
+            passed: Vec::new(),
+            files: None,
+        };
+        let mut reporter = Prefixed::new("linux/", Box::new(human));
+        reporter
+            .result(&result("net::dns", Status::Passed, ""))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.0.take()).unwrap(),
+            "test linux/net::dns ... ok\n"
+        );
+    }
+
+    #[test]
+    fn results_can_be_grouped_by_file() {
+        let out = Captured::default();
+        let files = [
+            ("net::dns", "tests/net.b"),
+            ("net::http", "tests/net.b"),
+            ("db::migrate", "tests/db.b"),
+        ];
+        let mut reporter = HumanReporter {
+            out: Box::new(out.clone()),
+            on_success: SuccessOutput::Hidden,
+            passed: Vec::new(),
+            files: Some(
+                files
+                    .iter()
+                    .map(|(name, file)| (name.to_string(), PathBuf::from(file)))
+                    .collect(),
+            ),
+        };
+        let results = [
+            result("net::dns", Status::Passed, ""),
+            result("db::migrate", Status::Failed, "oops"),
+            result("net::http", Status::TimedOut, ""),
+        ];
+        for result in &results {
+            reporter.result(result).unwrap();
+        }
+        // Nothing shows until the files are complete.
+        assert!(out.0.borrow().is_empty());
+        let summary = RunSummary {
+            results: results.to_vec(),
+            ..RunSummary::default()
+        };
+        reporter.finish(&summary, &Timings::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out.0.take()).unwrap(),
+            "\ntests/db.b: 1 test(s), 1 failed\ntest db::migrate ... FAILED\n---- stdout ----\noops\n\
+             \ntests/net.b: 2 test(s), 1 failed\ntest net::dns ... ok\ntest net::http ... TIMEOUT\n"
+        );
+    }
+
+    #[test]
+    fn the_env_of_a_test_in_several_shells_is_printed_per_shell() {
+        let (config, _dir) = crate::testing::project(
+            "[env]\nGREETING = \"hi\"\n",
+            &[(
+                "tests/top.b",
+                "@shells(bash, sh)\n@test greets {\n    true\n}\n@test alone {\n    true\n}\n",
+            )],
+        );
+        let plan = Runner::new(config).plan().unwrap();
+        let lines = env_lines(&plan.tests, "top::greets").unwrap();
+        let headers: Vec<&String> = lines.iter().filter(|line| line.starts_with('#')).collect();
+        assert_eq!(headers, ["# top::greets[bash]", "# top::greets[sh]"]);
+        assert_eq!(
+            lines.iter().filter(|line| *line == "GREETING=hi").count(),
+            2
+        );
+        let lines = env_lines(&plan.tests, "top::greets[sh]").unwrap();
+        assert!(lines.contains(&"GREETING=hi".to_string()));
+        assert!(!lines.iter().any(|line| line.starts_with('#')));
+        let lines = env_lines(&plan.tests, "top::alone").unwrap();
+        assert!(!lines.iter().any(|line| line.starts_with('#')));
+        let error = env_lines(&plan.tests, "top::greet").unwrap_err();
+        assert_eq!(error.to_string(), "no test is called top::greet");
+    }
+}
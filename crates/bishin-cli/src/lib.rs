@@ -0,0 +1,52 @@
+//! Running bishin test suites from a program, as the `bishin` command does.
+//!
+//! A [`Runner`] takes a loaded [`Config`], and its builder methods override
+//! the settings there:
+//!
+//! ```
+//! use bishin::{Config, Runner, TestFilter};
+//!
+//! # let dir = std::env::temp_dir().join(format!("bishin-doc-run-{}", std::process::id()));
+//! # std::fs::create_dir_all(dir.join("tests/net"))?;
+//! # std::fs::write(dir.join("bishin.toml"), "")?;
+//! # std::fs::write(dir.join("tests/net/dns.b"), "@test resolves {\n    true\n}\n")?;
+//! # std::fs::write(dir.join("tests/fs.b"), "@test copies {\n    false\n}\n")?;
+//! let config = Config::load(&dir.join("bishin.toml"))?;
+//! let filter = TestFilter::new(&["net::".to_string()], &[], &[], false)?;
+//! let outcome = Runner::new(config).filter(filter).jobs(4).run()?;
+//! for result in &outcome.summary.results {
+//!     println!("{} {}", result.name, result.status.name());
+//! }
+//! assert_eq!(outcome.summary.results.len(), 1);
+//! assert!(outcome.passed());
+//! # std::fs::remove_dir_all(&dir)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! [`Runner::plan`] collects and generates the tests without running them,
+//! and [`Runner::execute`] runs such a plan.
+
+mod artifacts;
+mod lock;
+mod redact;
+mod regex;
+mod runner;
+#[cfg(test)]
+mod testing;
+
+pub mod replay;
+pub mod report;
+pub mod resolve;
+pub mod select;
+pub mod timings;
+
+pub use bishin_collect::ModuleGraph;
+pub use bishin_config::Config;
+pub use bishin_generate::TestJob;
+pub use bishin_jobs::{Job, JobResult, RunSummary, Status};
+pub use lock::WorkDirLock;
+pub use redact::Redactor;
+pub use report::Reporter;
+pub use runner::{collect, unsupported_options, Quarantine, RunOutcome, RunPlan, Runner};
+pub use select::{NamePattern, TagFilter, TestFilter};
+pub use timings::{Phase, Timings};
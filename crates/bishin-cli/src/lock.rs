@@ -0,0 +1,149 @@
+//! A lock on the work directory, so that two runs sharing it don't write
+//! over each other's scripts.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context};
+
+/// The file under the work directory that runs lock.
+const LOCK_FILE: &str = ".lock";
+
+/// Holds the lock on a work directory until it's dropped.
+///
+/// The lock is an advisory `flock` on a file, which the OS releases when the
+/// process exits however it exits, so a crashed run never leaves it behind.
+pub struct WorkDirLock {
+    _file: File,
+}
+
+impl WorkDirLock {
+    /// Lock `work_dir`, creating it if needed. If another run holds the lock,
+    /// either wait for it to finish or fail straight away.
+    pub fn acquire(work_dir: &Path, wait: bool) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(work_dir)
+            .with_context(|| format!("failed to create work directory {}", work_dir.display()))?;
+        let path = work_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("failed to open lock file {}", path.display()))?;
+        if !try_lock(&file).with_context(|| format!("failed to lock {}", path.display()))? {
+            let mut holder = String::new();
+            // The pid is only there to help find the other run.
+            let _ = file.read_to_string(&mut holder);
+            let holder = match holder.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {pid})"),
+            };
+            if !wait {
+                bail!(
+                    "another bishin run{holder} is in progress in {}",
+                    work_dir.display()
+                );
+            }
+            eprintln!(
+                "waiting for another bishin run{holder} in {} to finish",
+                work_dir.display()
+            );
+            lock(&file).with_context(|| format!("failed to lock {}", path.display()))?;
+        }
+        file.set_len(0)
+            .and_then(|()| file.rewind())
+            .and_then(|()| writeln!(file, "{}", std::process::id()))
+            .with_context(|| format!("failed to write lock file {}", path.display()))?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// Take the lock if nobody else has it, returning whether it was taken.
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Wait until the lock is free and take it.
+#[cfg(unix)]
+fn lock(file: &File) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::scratch_dir;
+
+    #[test]
+    fn a_second_run_fails_while_the_first_holds_the_lock() {
+        let scratch = scratch_dir();
+        let work_dir = scratch.join(".bishin");
+        let first = WorkDirLock::acquire(&work_dir, false).unwrap();
+        let err = WorkDirLock::acquire(&work_dir, false).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "another bishin run (pid {}) is in progress in {}",
+                std::process::id(),
+                work_dir.display()
+            )
+        );
+        drop(first);
+        WorkDirLock::acquire(&work_dir, false).unwrap();
+    }
+
+    #[test]
+    fn a_second_run_can_wait_for_the_first() {
+        let work_dir = scratch_dir();
+        let first = WorkDirLock::acquire(&work_dir, false).unwrap();
+        let (locked, waited) = mpsc::channel();
+        let second = {
+            let work_dir = work_dir.to_path_buf();
+            thread::spawn(move || {
+                let lock = WorkDirLock::acquire(&work_dir, true).unwrap();
+                locked.send(()).unwrap();
+                lock
+            })
+        };
+        assert!(waited.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(first);
+        waited.recv_timeout(Duration::from_secs(10)).unwrap();
+        drop(second.join().unwrap());
+    }
+}
@@ -0,0 +1,358 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use anyhow::bail;
+use bishin::resolve;
+use bishin::timings::{Phase, Timings};
+use bishin_config::{find_cargo_config, Config, LoadOptions, Severity, CONFIG_FILE};
+use clap::Parser;
+
+use crate::cli::{Cli, Command};
+
+mod cli;
+mod cmd;
+#[cfg(test)]
+mod testing;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match try_main(cli) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn try_main(cli: Cli) -> anyhow::Result<ExitCode> {
+    if let Command::Init = cli.command {
+        let path = cli.config_file.as_deref().unwrap_or(Path::new(CONFIG_FILE));
+        return cmd::init::init(path);
+    }
+    if let Command::Report(args) = &cli.command {
+        return cmd::report::report(args);
+    }
+    if let Command::Completions(args) = &cli.command {
+        return cmd::completions::completions(args);
+    }
+    if let Command::ConfigSchema = cli.command {
+        print!("{}", Config::json_schema());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut timings = Timings::default();
+    let config = timings.time(Phase::ConfigLoad, || load_config(&cli))?;
+    match cli.command {
+        Command::Run(args) => cmd::run::run(&config, &args, timings),
+        Command::Gen => cmd::gen::gen(&config),
+        Command::Export(args) => cmd::export::export(&config, &args),
+        Command::Exec(args) => cmd::exec::exec(&config, &args),
+        Command::New(args) => cmd::new::new(&config, &args),
+        Command::List(args) => cmd::list::list(&config, &args),
+        Command::Fingerprint => cmd::fingerprint::fingerprint(&config),
+        Command::History(args) => cmd::history::history(&config, &args),
+        Command::Flaky(args) => cmd::history::flaky(&config, &args),
+        Command::Init | Command::Report(_) | Command::Completions(_) | Command::ConfigSchema => {
+            unreachable!("doesn't load a config")
+        }
+    }
+}
+
+/// Load the config file, apply the selected profile and any `--set`s, and
+/// check the result.
+fn load_config(cli: &Cli) -> anyhow::Result<Config> {
+    let options = LoadOptions {
+        allow_unknown_keys: cli.allow_unknown_config_keys,
+        ..LoadOptions::default()
+    };
+    let mut config = match (&cli.config_file, &cli.config_key) {
+        (Some(path), Some(table)) => Config::load_table_with(path, table, &options)?,
+        (Some(path), None) => Config::load_with(path, &options)?,
+        (None, _) => load_default_config(&options)?,
+    };
+    let profile = resolve::profile(
+        cli.profile.clone(),
+        std::env::var(resolve::PROFILE_ENV).ok(),
+    );
+    if let Some(profile) = profile {
+        config = config.with_profile(&profile)?;
+    }
+    for (key, value) in &cli.set {
+        config = config.with_set(key, value)?;
+    }
+    check_config(&config)?;
+    Ok(config)
+}
+
+/// Load [`CONFIG_FILE`], or the config in the nearest Cargo manifest if
+/// there's no such file.
+fn load_default_config(options: &LoadOptions<'_>) -> anyhow::Result<Config> {
+    let path = Path::new(CONFIG_FILE);
+    let cargo = std::env::current_dir()
+        .ok()
+        .and_then(|dir| find_cargo_config(&dir));
+    let Some((manifest, table)) = cargo else {
+        return Ok(Config::load_with(path, options)?);
+    };
+    if !path.exists() {
+        return Ok(Config::load_table_with(&manifest, table, options)?);
+    }
+    eprintln!(
+        "warning: using {CONFIG_FILE} and ignoring [{table}] in {}",
+        manifest.display()
+    );
+    Ok(Config::load_with(path, options)?)
+}
+
+/// Print any problems with the config, failing if there are errors.
+fn check_config(config: &Config) -> anyhow::Result<()> {
+    let issues = config.validate();
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!("found {errors} problem(s) in {}", config.source());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write_files};
+
+    /// Run bishin with `args` and the config file at `config`.
+    fn bishin(config: &Path, args: &[&str]) -> anyhow::Result<ExitCode> {
+        let config = config.to_str().unwrap();
+        let cli = Cli::try_parse_from(["bishin", "-f", config].iter().chain(args))?;
+        try_main(cli)
+    }
+
+    /// The names of the scripts `bishin gen` writes for the project in `dir`.
+    fn generated(dir: &Path) -> Vec<String> {
+        assert_eq!(
+            bishin(&dir.join(CONFIG_FILE), &["gen"]).unwrap(),
+            ExitCode::SUCCESS
+        );
+        let mut scripts: Vec<_> = std::fs::read_dir(dir.join(".bishin"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".sh"))
+            .collect();
+        scripts.sort();
+        scripts
+    }
+
+    #[test]
+    fn ignore_patterns_and_files_prune_the_test_tree() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                (CONFIG_FILE, "ignore = [\"**/fixtures/**\", \"wip_*.b\"]\n"),
+                ("tests/net/dns.b", "@test resolves {\n    true\n}\n"),
+                ("tests/net/wip_http.b", "@test gets {\n    true\n}\n"),
+                ("tests/net/fixtures/data.b", "@test data {\n    true\n}\n"),
+                ("tests/fixtures/top.b", "@test top {\n    true\n}\n"),
+                ("tests/.bishinignore", "slow/\n"),
+                ("tests/slow/big.b", "@test big {\n    true\n}\n"),
+                ("tests/fast.b", "@test quick {\n    true\n}\n"),
+            ],
+        );
+        assert_eq!(generated(&dir), ["fast.quick.sh", "net.dns.resolves.sh"]);
+    }
+
+    #[test]
+    fn bad_ignore_patterns_stop_the_cli() {
+        let dir = scratch_dir();
+        write_files(&dir, &[(CONFIG_FILE, "ignore = [\"a\\\\\"]\n")]);
+        let err = bishin(&dir.join(CONFIG_FILE), &["gen"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("pattern \"a\\\\\" ends with an escape"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn only_the_configured_extensions_are_collected() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                (CONFIG_FILE, "file-extensions = [\"bishin\"]\n"),
+                ("tests/new.bishin", "@test a {\n    true\n}\n"),
+                ("tests/LOUD.BISHIN", "@test b {\n    true\n}\n"),
+                ("tests/old.b", "@test c {\n    true\n}\n"),
+            ],
+        );
+        assert_eq!(generated(&dir), ["LOUD.b.sh", "new.a.sh"]);
+    }
+
+    #[test]
+    fn skipped_tests_fail_the_run_under_fail_on_skip() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                (CONFIG_FILE, ""),
+                (
+                    "tests/skips.b",
+                    "@test runs {\n    true\n}\n@skip(\"no fish\")\n@test skipped {\n    true\n}\n",
+                ),
+            ],
+        );
+        let config = dir.join(CONFIG_FILE);
+        assert_eq!(bishin(&config, &["run"]).unwrap(), ExitCode::SUCCESS);
+        assert_eq!(
+            bishin(&config, &["run", "--fail-on-skip"]).unwrap(),
+            ExitCode::FAILURE
+        );
+        // So long as nothing is skipped, the flag changes nothing.
+        let only_runs = ["run", "--fail-on-skip", "--exclude", "skipped"];
+        assert_eq!(bishin(&config, &only_runs).unwrap(), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn a_failed_setup_has_its_own_exit_code() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                (CONFIG_FILE, "setup-script = \"setup.sh\"\n"),
+                ("setup.sh", "#!/bin/sh\nexit 1\n"),
+                ("tests/a.b", "@test a {\n    true\n}\n"),
+            ],
+        );
+        let setup = dir.join("setup.sh");
+        std::fs::set_permissions(setup, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            bishin(&dir.join(CONFIG_FILE), &["run"]).unwrap(),
+            ExitCode::from(cmd::run::SETUP_FAILED_EXIT_CODE)
+        );
+    }
+
+    #[test]
+    fn redacted_output_is_in_no_report() {
+        let dir = scratch_dir();
+        let reports = [
+            "junit.xml",
+            "report.json",
+            "events.jsonl",
+            "report.html",
+            "report.md",
+        ];
+        let paths = reports.map(|report| dir.join("reports").join(report).display().to_string());
+        let config = format!(
+            "redact = [\"tok_[a-z0-9]+\"]\n[reports]\njunit = {:?}\njson = {:?}\njsonl-events = {:?}\nhtml = {:?}\nmarkdown = {:?}\n",
+            paths[0], paths[1], paths[2], paths[3], paths[4],
+        );
+        write_files(
+            &dir,
+            &[
+                (CONFIG_FILE, config.as_str()),
+                (
+                    "tests/leaks.b",
+                    "@test leaks {\n    echo \"token tok_s3cret\"\n    echo \"tok_s3cret\" >&2\n    exit 1\n}\n",
+                ),
+            ],
+        );
+        assert_eq!(
+            bishin(&dir.join(CONFIG_FILE), &["run"]).unwrap(),
+            ExitCode::FAILURE
+        );
+        for path in paths {
+            let report = std::fs::read_to_string(&path).unwrap();
+            assert!(!report.contains("s3cret"), "{path}: {report}");
+        }
+        for report in ["junit.xml", "report.json", "events.jsonl", "report.html"] {
+            let report = std::fs::read_to_string(dir.join("reports").join(report)).unwrap();
+            assert!(report.contains("[REDACTED]"), "{report}");
+        }
+    }
+
+    #[test]
+    fn a_run_can_select_a_profile() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                (
+                    CONFIG_FILE,
+                    "[env]\nMODE = \"local\"\n[profiles.ci]\nfail-fast = true\n[profiles.ci.env]\nMODE = \"ci\"\n",
+                ),
+                ("tests/mode.b", "@test is_ci {\n    [ \"$MODE\" = ci ]\n}\n"),
+            ],
+        );
+        let config = dir.join(CONFIG_FILE);
+        assert_eq!(bishin(&config, &["run"]).unwrap(), ExitCode::FAILURE);
+        assert_eq!(
+            bishin(&config, &["--profile", "ci", "run"]).unwrap(),
+            ExitCode::SUCCESS
+        );
+        let err = bishin(&config, &["--profile", "cj", "run"]).unwrap_err();
+        assert!(format!("{err:#}").contains("ci"), "{err:#}");
+    }
+
+    #[test]
+    fn config_key_reads_the_config_from_a_table_of_the_file() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                (
+                    "project.toml",
+                    "[tool.other]\nkey = 1\n\n[tool.bishin]\ntest-dir = \"e2e\"\n",
+                ),
+                ("e2e/smoke.b", "@test boots {\n    true\n}\n"),
+                ("tests/unit.b", "@test adds {\n    true\n}\n"),
+            ],
+        );
+        let shared = dir.join("project.toml");
+        assert_eq!(
+            bishin(&shared, &["--config-key", "tool.bishin", "gen"]).unwrap(),
+            ExitCode::SUCCESS
+        );
+        assert!(dir.join(".bishin/smoke.boots.sh").exists());
+        assert!(!dir.join(".bishin/unit.adds.sh").exists());
+        let err = bishin(&shared, &["--config-key", "tool.bishni", "gen"]).unwrap_err();
+        assert!(err.to_string().contains("tool.bishni"), "{err}");
+        // Without a file to look in, there's nothing for the key to name.
+        assert!(Cli::try_parse_from(["bishin", "--config-key", "tool.bishin", "gen"]).is_err());
+    }
+
+    #[test]
+    fn run_takes_globs_of_the_tests_to_run() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                (CONFIG_FILE, ""),
+                (
+                    "tests/api.b",
+                    "@test get {\n    true\n}\n@test post {\n    true\n}\n",
+                ),
+                (
+                    "tests/db.b",
+                    "@test migrate_up {\n    true\n}\n@test broken {\n    false\n}\n",
+                ),
+            ],
+        );
+        let config = dir.join(CONFIG_FILE);
+        assert_eq!(bishin(&config, &["run"]).unwrap(), ExitCode::FAILURE);
+        assert_eq!(
+            bishin(&config, &["run", "api::*", "db::migrate*"]).unwrap(),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            bishin(&config, &["run", "api::*", "db::b?oken"]).unwrap(),
+            ExitCode::FAILURE
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Hiding secrets and unstable values in test output before it's shown or
+//! written to a report, with the config's `redact` patterns.
+
+use bishin_jobs::JobResult;
+
+use crate::regex::{Regex, RegexError};
+
+/// What each match of a pattern is replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[String]) -> Result<Self, RegexError> {
+        Ok(Self {
+            patterns: patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// `text` with every match of every pattern replaced, in the order the
+    /// patterns were given.
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in &self.patterns {
+            text = pattern.replace_all(&text, REDACTED);
+        }
+        text
+    }
+
+    /// Redact everything in `result` that came from what the job wrote.
+    pub fn redact_result(&self, result: &mut JobResult) {
+        if self.patterns.is_empty() {
+            return;
+        }
+        result.stdout = self.redact(&result.stdout);
+        result.stderr = self.redact(&result.stderr);
+        for reason in [&mut result.skip_reason, &mut result.xfail_reason]
+            .into_iter()
+            .flatten()
+        {
+            *reason = self.redact(reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bishin_jobs::Status;
+
+    use super::*;
+
+    fn redactor(patterns: &[&str]) -> Redactor {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        Redactor::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn every_pattern_is_redacted_in_order() {
+        let both = redactor(&[r"tok_[a-z0-9]+", r"\d{4}-\d\d-\d\d"]);
+        assert_eq!(
+            both.redact("token tok_abc123 on 2024-01-31, again tok_x\n"),
+            "token [REDACTED] on [REDACTED], again [REDACTED]\n"
+        );
+        // A later pattern sees what the earlier ones left.
+        let chained = redactor(&["secret", r"\[REDACTED\]!"]);
+        assert_eq!(chained.redact("secret! secret"), "[REDACTED] [REDACTED]");
+        assert_eq!(Redactor::default().redact("secret"), "secret");
+        assert!(Redactor::new(&["(".to_string()]).is_err());
+    }
+
+    #[test]
+    fn long_output_is_redacted() {
+        let line = "line with tok_0123456789abcdef and more\n";
+        let output = line.repeat((1 << 20) / line.len() + 1);
+        assert!(output.len() > 1 << 20);
+        let redacted = redactor(&[r"tok_\w+", r"^x*$"]).redact(&output);
+        assert_eq!(
+            redacted,
+            "line with [REDACTED] and more\n".repeat((1 << 20) / line.len() + 1)
+        );
+    }
+
+    #[test]
+    fn everything_the_job_wrote_is_redacted() {
+        let mut result = JobResult {
+            status: Status::Skipped,
+            stdout: "out tok_1\n".to_string(),
+            stderr: "err tok_2\n".to_string(),
+            skip_reason: Some("no tok_3".to_string()),
+            xfail_reason: Some("tok_4".to_string()),
+            ..JobResult::error("tok_name", "")
+        };
+        redactor(&[r"tok_\d"]).redact_result(&mut result);
+        assert_eq!(result.stdout, "out [REDACTED]\n");
+        assert_eq!(result.stderr, "err [REDACTED]\n");
+        assert_eq!(result.skip_reason.as_deref(), Some("no [REDACTED]"));
+        assert_eq!(result.xfail_reason.as_deref(), Some("[REDACTED]"));
+        // The name is the test's, not something it wrote.
+        assert_eq!(result.name, "tok_name");
+    }
+}
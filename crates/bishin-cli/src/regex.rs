@@ -0,0 +1,612 @@
+//! A small regex engine for `--regex` filters and `redact` patterns.
+//!
+//! It supports the usual syntax for matching test names: literals, `.`,
+//! classes like `[a-z]` and `[^_]`, the escapes `\d`, `\w` and `\s` and their
+//! negations, groups with `(...)` or `(?:...)`, alternation with `|`, the
+//! quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}` and their lazy forms,
+//! and the anchors `^` and `$`.
+//!
+//! Patterns are compiled to a program that runs as a Pike VM, which steps
+//! through the text once, keeping every way the pattern could still match.
+//! That takes time in proportion to the length of the text times the size
+//! of the program, and a fixed amount of stack, since the output of a test
+//! that's redacted can be as long as it likes.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid regex {pattern:?}: {message}")]
+pub struct RegexError {
+    pattern: String,
+    message: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    source: String,
+    program: Vec<Inst>,
+}
+
+/// How many instructions a pattern may compile to, so that counted
+/// repetitions of counted repetitions can't take all the memory there is.
+const MAX_PROGRAM: usize = 100_000;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+/// An instruction of a compiled pattern.
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// Carry on at both, preferring a match through the first.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let error = |message| RegexError {
+            pattern: pattern.to_string(),
+            message,
+        };
+        let node = parser.alternation().map_err(error)?;
+        if parser.pos < parser.chars.len() {
+            return Err(error("unmatched closing parenthesis"));
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program).map_err(error)?;
+        program.push(Inst::Match);
+        Ok(Self {
+            source: pattern.to_string(),
+            program,
+        })
+    }
+
+    /// Whether the regex matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.find_at(&chars, 0).is_some()
+    }
+
+    /// `text` with every match, leftmost first and without overlaps, replaced
+    /// by `replacement`.
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut replaced = String::with_capacity(text.len());
+        let mut pos = 0;
+        while let Some((start, end)) = self.find_at(&chars, pos) {
+            replaced.extend(&chars[pos..start]);
+            replaced.push_str(replacement);
+            // An empty match would match again in the same place.
+            if end == start {
+                replaced.extend(chars.get(start));
+                pos = start + 1;
+            } else {
+                pos = end;
+            }
+            if pos > chars.len() {
+                return replaced;
+            }
+        }
+        replaced.extend(&chars[pos..]);
+        replaced
+    }
+
+    /// The start and end of the leftmost match that starts at or after
+    /// `from`, preferring what a backtracking engine would find first.
+    fn find_at(&self, text: &[char], from: usize) -> Option<(usize, usize)> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut found = None;
+        for pos in from..=text.len() {
+            // A match starting here comes after any that started earlier.
+            if found.is_none() {
+                self.add_thread(&mut current, (0, pos), pos, text);
+            }
+            if current.list.is_empty() && found.is_some() {
+                break;
+            }
+            for &(pc, start) in &current.list {
+                let matched = match &self.program[pc] {
+                    Inst::Char(c) => text.get(pos) == Some(c),
+                    Inst::Any => pos < text.len(),
+                    Inst::Class(class) => text.get(pos).is_some_and(|c| class.contains(*c)),
+                    Inst::Match => {
+                        // Whatever else there is to try is worse than this.
+                        found = Some((start, pos));
+                        break;
+                    }
+                    Inst::Start | Inst::End | Inst::Split(..) | Inst::Jump(_) => {
+                        unreachable!("threads only wait at instructions that read the text")
+                    }
+                };
+                if matched {
+                    self.add_thread(&mut next, (pc + 1, start), pos + 1, text);
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        found
+    }
+
+    /// Add a thread at `pc` that started at `start` to `threads`, following
+    /// the instructions that don't read the text from it, in the order of
+    /// their preference.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        (pc, start): (usize, usize),
+        pos: usize,
+        text: &[char],
+    ) {
+        let mut stack = std::mem::take(&mut threads.stack);
+        stack.push(pc);
+        while let Some(pc) = stack.pop() {
+            if std::mem::replace(&mut threads.seen[pc], true) {
+                continue;
+            }
+            threads.marked.push(pc);
+            match self.program[pc] {
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => stack.extend([second, first]),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == text.len() => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => threads.list.push((pc, start)),
+            }
+        }
+        threads.stack = stack;
+    }
+}
+
+/// The threads of a Pike VM at one position in the text, in the order of
+/// their preference, as the instruction each is at and where its match
+/// started.
+struct Threads {
+    list: Vec<(usize, usize)>,
+    seen: Vec<bool>,
+    marked: Vec<usize>,
+    /// Kept to save allocating it for each thread.
+    stack: Vec<usize>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self {
+            list: Vec::new(),
+            seen: vec![false; len],
+            marked: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        for pc in self.marked.drain(..) {
+            self.seen[pc] = false;
+        }
+    }
+}
+
+/// Add the instructions that match `node` to `program`.
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), &'static str> {
+    if program.len() > MAX_PROGRAM {
+        return Err("pattern is too large");
+    }
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alt(branches) => {
+            let mut jumps = Vec::new();
+            for (index, branch) in branches.iter().enumerate() {
+                if index == branches.len() - 1 {
+                    compile(branch, program)?;
+                    break;
+                }
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(branch, program)?;
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                let next = program.len();
+                program[split] = Inst::Split(split + 1, next);
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            let split = |body, end| match greedy {
+                true => Inst::Split(body, end),
+                false => Inst::Split(end, body),
+            };
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let start = program.len();
+                    program.push(Inst::Jump(0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(start));
+                    program[start] = split(start + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Jump(0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for start in splits {
+                        program[start] = split(start + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+type ParseResult<T> = Result<T, &'static str>;
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let eaten = self.peek() == Some(c);
+        if eaten {
+            self.pos += 1;
+        }
+        eaten
+    }
+
+    fn next(&mut self) -> ParseResult<char> {
+        let c = self.peek().ok_or("unexpected end of pattern")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn alternation(&mut self) -> ParseResult<Node> {
+        let mut branches = vec![self.concat()?];
+        while self.eat('|') {
+            branches.push(self.concat()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().expect("there is one branch"),
+            _ => Node::Alt(branches),
+        })
+    }
+
+    fn concat(&mut self) -> ParseResult<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> ParseResult<Node> {
+        Ok(match self.next()? {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("only (?:...) groups are supported");
+                }
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    return Err("unclosed group");
+                }
+                node
+            }
+            '[' => Node::Class(self.class()?),
+            '\\' => match self.escape()? {
+                ClassItem::Range(c, _) => Node::Char(c),
+                item => Node::Class(Class {
+                    negated: false,
+                    items: vec![item],
+                }),
+            },
+            '*' | '+' | '?' | '{' => return Err("quantifier with nothing to repeat"),
+            c => Node::Char(c),
+        })
+    }
+
+    fn escape(&mut self) -> ParseResult<ClassItem> {
+        Ok(
+            match self.next().map_err(|_| "pattern ends with an escape")? {
+                'd' => ClassItem::Digit(false),
+                'D' => ClassItem::Digit(true),
+                'w' => ClassItem::Word(false),
+                'W' => ClassItem::Word(true),
+                's' => ClassItem::Space(false),
+                'S' => ClassItem::Space(true),
+                'n' => ClassItem::Range('\n', '\n'),
+                't' => ClassItem::Range('\t', '\t'),
+                c if c.is_ascii_alphanumeric() => return Err("unknown escape"),
+                c => ClassItem::Range(c, c),
+            },
+        )
+    }
+
+    fn class(&mut self) -> ParseResult<Class> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next().map_err(|_| "unclosed character class")?;
+            let item = match c {
+                ']' if !first => break,
+                // `[[:alpha:]]` would otherwise be a class of `[:alph` and then
+                // a `]`, and quietly match something other than it means.
+                '[' if self.peek() == Some(':') => return Err("[:...:] classes aren't supported"),
+                '\\' => self.escape()?,
+                c => ClassItem::Range(c, c),
+            };
+            first = false;
+            let item = match item {
+                ClassItem::Range(start, _)
+                    if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') =>
+                {
+                    self.pos += 1;
+                    let end = match self.next().map_err(|_| "unclosed character class")? {
+                        '\\' => match self.escape()? {
+                            ClassItem::Range(end, _) => end,
+                            _ => return Err("invalid class range"),
+                        },
+                        end => end,
+                    };
+                    if end < start {
+                        return Err("invalid class range");
+                    }
+                    ClassItem::Range(start, end)
+                }
+                item => item,
+            };
+            items.push(item);
+        }
+        Ok(Class { negated, items })
+    }
+
+    fn quantified(&mut self, atom: Node) -> ParseResult<Node> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => return self.counted(atom),
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy: !self.eat('?'),
+        })
+    }
+
+    fn counted(&mut self, atom: Node) -> ParseResult<Node> {
+        self.pos += 1;
+        let min = self.number()?.ok_or("expected a number in {...}")?;
+        let max = if self.eat(',') {
+            self.number()?
+        } else {
+            Some(min)
+        };
+        if !self.eat('}') {
+            return Err("unclosed {...} quantifier");
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err("invalid {...} quantifier");
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy: !self.eat('?'),
+        })
+    }
+
+    fn number(&mut self) -> ParseResult<Option<usize>> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().map(Some).map_err(|_| "number is too large")
+    }
+}
+
+impl Class {
+    fn contains(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match *item {
+            ClassItem::Range(start, end) => (start..=end).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        });
+        found != self.negated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    fn replaced(pattern: &str, text: &str) -> String {
+        Regex::new(pattern).unwrap().replace_all(text, "<>")
+    }
+
+    fn error(pattern: &str) -> &'static str {
+        Regex::new(pattern).unwrap_err().message
+    }
+
+    #[test]
+    fn the_syntax_matches_what_it_says() {
+        let cases = [
+            ("dns", "net::dns::resolves", true),
+            ("^net::", "net::dns", true),
+            ("^dns", "net::dns", false),
+            ("resolves$", "net::dns::resolves", true),
+            ("^$", "", true),
+            ("a.c", "abc", true),
+            ("a.c", "ac", false),
+            ("[a-c]x", "bx", true),
+            ("[^a-c]x", "bx", false),
+            ("[-_]", "a-b", true),
+            ("[a-]", "-", true),
+            ("[]]", "]", true),
+            (r"\d+", "retry7", true),
+            (r"\D", "123", false),
+            (r"\w", "::", false),
+            (r"\W", "a::b", true),
+            (r"\s", "a b", true),
+            (r"\S", " \t", false),
+            (r"a\.b", "a.b", true),
+            (r"a\.b", "axb", false),
+            (r"\n", "a\nb", true),
+            ("(?:ab)+$", "xabab", true),
+            ("^(ab)+$", "aba", false),
+            ("dns|http", "net::http", true),
+            ("^(dns|http)$", "https", false),
+            ("^a{3}$", "aaa", true),
+            ("^a{3}$", "aaaa", false),
+            ("^a{2,}$", "aaaaa", true),
+            ("^a{2,}$", "a", false),
+            ("^a{1,2}$", "aaa", false),
+            ("^a?b*c+$", "c", true),
+            ("^(a*)*b$", "aaab", true),
+            ("^(a|)+$", "aaa", true),
+            ("ü", "grüße", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(is_match(pattern, text), expected, "{pattern:?} on {text:?}");
+        }
+    }
+
+    #[test]
+    fn bad_patterns_say_what_is_wrong() {
+        assert_eq!(error("(a"), "unclosed group");
+        assert_eq!(error("a)"), "unmatched closing parenthesis");
+        assert_eq!(error("(?=a)"), "only (?:...) groups are supported");
+        assert_eq!(error("[a"), "unclosed character class");
+        assert_eq!(error("[z-a]"), "invalid class range");
+        assert_eq!(error("[[:alpha:]]"), "[:...:] classes aren't supported");
+        assert_eq!(error("*a"), "quantifier with nothing to repeat");
+        assert_eq!(error("a{2"), "unclosed {...} quantifier");
+        assert_eq!(error("a{3,2}"), "invalid {...} quantifier");
+        assert_eq!(error("a{x}"), "expected a number in {...}");
+        assert_eq!(error(r"a\"), "pattern ends with an escape");
+        assert_eq!(error(r"\q"), "unknown escape");
+        assert_eq!(error("(a{1000}){1000}"), "pattern is too large");
+        assert_eq!(
+            Regex::new("(a").unwrap_err().to_string(),
+            "invalid regex \"(a\": unclosed group"
+        );
+    }
+
+    #[test]
+    fn matches_are_replaced_leftmost_first() {
+        assert_eq!(replaced("a+", "baaac aa"), "b<>c <>");
+        // The first alternative that matches wins, as when backtracking.
+        assert_eq!(replaced("a|ab", "ab"), "<>b");
+        assert_eq!(replaced("ab|a", "ab"), "<>");
+        assert_eq!(replaced("<.*>", "<a> <b>"), "<>");
+        assert_eq!(replaced("<.*?>", "<a> <b>"), "<> <>");
+        assert_eq!(replaced("a{2,3}?", "aaaaa"), "<><>a");
+        assert_eq!(replaced("x*", "ab"), "<>a<>b<>");
+        assert_eq!(replaced("^", "ab"), "<>ab");
+        assert_eq!(replaced("$", "ab"), "ab<>");
+        assert_eq!(replaced("z", "ab"), "ab");
+        assert_eq!(replaced("b", ""), "");
+        assert_eq!(replaced("", ""), "<>");
+    }
+
+    #[test]
+    fn long_texts_take_no_more_stack() {
+        let text = "a".repeat(1 << 20);
+        assert!(is_match("^a*$", &text));
+        assert!(is_match("^(a|b)+$", &text));
+        assert!(!is_match("^a*b$", &text));
+        assert_eq!(replaced("a+", &text), "<>");
+    }
+}
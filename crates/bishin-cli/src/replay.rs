@@ -0,0 +1,263 @@
+//! Tokens that replay a shuffled run: the seed of its order along with the
+//! patterns and tags that chose its tests, so that with the same tests and
+//! config, [`Replay::from_token`] gets back the same tests in the same order.
+//!
+//! ```
+//! use bishin::replay::Replay;
+//!
+//! let replay = Replay {
+//!     seed: 42,
+//!     tests: vec!["net::*".to_string()],
+//!     ..Replay::default()
+//! };
+//! assert_eq!(Replay::from_token(&replay.to_token())?, replay);
+//! # Ok::<(), bishin::replay::InvalidToken>(())
+//! ```
+
+/// What every token starts with, which changes along with what's in them.
+const TOKEN_PREFIX: &str = "r2.";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// How a shuffled run chose and ordered its tests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Replay {
+    pub seed: u64,
+    /// The globs that tests had to match.
+    pub tests: Vec<String>,
+    /// The `--filter` and `--exclude` patterns, and whether they were
+    /// regular expressions.
+    pub filter: Vec<String>,
+    pub exclude: Vec<String>,
+    pub regex: bool,
+    /// The `--tag` and `--exclude-tag` tags, on top of those of the config.
+    pub tags: Vec<String>,
+    pub exclude_tags: Vec<String>,
+    /// Whether it ran the benchmarks rather than the other tests.
+    pub benches: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "invalid replay token {0:?}, expected one printed by a shuffled run of this version of bishin"
+)]
+pub struct InvalidToken(pub String);
+
+impl Replay {
+    /// The replay as a token that can be given on the command line.
+    pub fn to_token(&self) -> String {
+        let mut fields = vec![format!("seed={}", self.seed)];
+        if self.regex {
+            fields.push("regex".to_string());
+        }
+        if self.benches {
+            fields.push("benches".to_string());
+        }
+        for (key, values) in [
+            ("test", &self.tests),
+            ("filter", &self.filter),
+            ("exclude", &self.exclude),
+            ("tag", &self.tags),
+            ("exclude-tag", &self.exclude_tags),
+        ] {
+            fields.extend(values.iter().map(|value| format!("{key}={value}")));
+        }
+        // Each field goes after its length, so that a value can hold
+        // anything at all.
+        let fields: String = fields
+            .iter()
+            .map(|field| format!("{}:{field}", field.len()))
+            .collect();
+        format!("{TOKEN_PREFIX}{}", encode(fields.as_bytes()))
+    }
+
+    pub fn from_token(token: &str) -> Result<Self, InvalidToken> {
+        let invalid = || InvalidToken(token.to_string());
+        let bytes = token
+            .strip_prefix(TOKEN_PREFIX)
+            .and_then(decode)
+            .ok_or_else(invalid)?;
+        let fields = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let mut replay = Self::default();
+        let mut seed = None;
+        let mut rest = fields.as_str();
+        while !rest.is_empty() {
+            let (len, after) = rest.split_once(':').ok_or_else(invalid)?;
+            let len: usize = len.parse().map_err(|_| invalid())?;
+            let field = after.get(..len).ok_or_else(invalid)?;
+            rest = &after[len..];
+            let (key, value) = field.split_once('=').unwrap_or((field, ""));
+            let list = match key {
+                "seed" => {
+                    seed = Some(value.parse().map_err(|_| invalid())?);
+                    continue;
+                }
+                "regex" => {
+                    replay.regex = true;
+                    continue;
+                }
+                "benches" => {
+                    replay.benches = true;
+                    continue;
+                }
+                "test" => &mut replay.tests,
+                "filter" => &mut replay.filter,
+                "exclude" => &mut replay.exclude,
+                "tag" => &mut replay.tags,
+                "exclude-tag" => &mut replay.exclude_tags,
+                _ => return Err(invalid()),
+            };
+            list.push(value.to_string());
+        }
+        replay.seed = seed.ok_or_else(invalid)?;
+        Ok(replay)
+    }
+}
+
+/// `bytes` in unpadded URL-safe base64, so that the token needs no quoting
+/// in a shell.
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..=chunk.len() {
+            encoded.push(BASE64[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut group = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = BASE64.iter().position(|b| b == c)? as u32;
+            group |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::project;
+    use crate::{Runner, TestFilter};
+
+    fn everything() -> Replay {
+        Replay {
+            seed: u64::MAX,
+            tests: vec!["net::*".to_string(), "db::*".to_string()],
+            filter: vec!["re(solve|try)".to_string()],
+            exclude: vec!["slow".to_string()],
+            regex: true,
+            tags: vec!["smoke".to_string()],
+            exclude_tags: vec!["flaky".to_string()],
+            benches: true,
+        }
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        for replay in [Replay::default(), everything()] {
+            let token = replay.to_token();
+            assert!(token.starts_with(TOKEN_PREFIX));
+            assert!(token[TOKEN_PREFIX.len()..]
+                .bytes()
+                .all(|b| BASE64.contains(&b)));
+            assert_eq!(Replay::from_token(&token), Ok(replay));
+        }
+    }
+
+    #[test]
+    fn values_can_hold_separators() {
+        let replay = Replay {
+            seed: 7,
+            tests: vec![
+                "a\nseed=1".to_string(),
+                "b=c".to_string(),
+                "3:d".to_string(),
+                String::new(),
+                "ünï\r\n".to_string(),
+            ],
+            ..Replay::default()
+        };
+        assert_eq!(Replay::from_token(&replay.to_token()), Ok(replay));
+    }
+
+    #[test]
+    fn invalid_tokens_are_refused() {
+        let token = |fields: &str| format!("{TOKEN_PREFIX}{}", encode(fields.as_bytes()));
+        for invalid in [
+            String::new(),
+            "r1.AAAA".to_string(),
+            format!("{TOKEN_PREFIX}not base64!"),
+            format!("{TOKEN_PREFIX}A"),
+            token(""),
+            token("9:seed=1"),
+            token("seed=1"),
+            token("6:seed=1\n"),
+            token("7:seed=-1"),
+            token("6:seed=17:color=1"),
+            token("6:test=a"),
+        ] {
+            let err = Replay::from_token(&invalid).unwrap_err();
+            assert_eq!(err, InvalidToken(invalid.clone()));
+        }
+    }
+
+    #[test]
+    fn base64_round_trips_every_length() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..bytes.len() {
+            let encoded = encode(&bytes[..len]);
+            assert_eq!(encoded.len(), (len * 4).div_ceil(3));
+            assert_eq!(decode(&encoded).as_deref(), Some(&bytes[..len]));
+        }
+    }
+
+    /// The order that `replay` runs the tests of a project in.
+    fn replayed_order(replay: &Replay) -> Vec<String> {
+        let tests: String = (0..20)
+            .map(|n| format!("@test t{n} {{\n    true\n}}\n"))
+            .collect();
+        let (config, _dir) = project("", &[("tests/net.b", &tests), ("tests/db.b", &tests)]);
+        let filter =
+            TestFilter::new(&replay.filter, &replay.tests, &replay.exclude, replay.regex).unwrap();
+        let plan = Runner::new(config)
+            .filter(filter)
+            .shuffle(Some(replay.seed))
+            .plan()
+            .unwrap();
+        plan.tests.into_iter().map(|test_job| test_job.id).collect()
+    }
+
+    #[test]
+    fn a_replayed_token_runs_the_same_tests_in_the_same_order() {
+        let replay = Replay {
+            seed: 1234,
+            tests: vec!["net::*".to_string()],
+            exclude: vec!["net::t0".to_string()],
+            ..Replay::default()
+        };
+        let order = replayed_order(&replay);
+        assert_eq!(order.len(), 19);
+        assert!(order.iter().all(|id| id.starts_with("net::")));
+        let replayed = Replay::from_token(&replay.to_token()).unwrap();
+        assert_eq!(replayed_order(&replayed), order);
+        let reseeded = Replay {
+            seed: 4321,
+            ..replay
+        };
+        assert_ne!(replayed_order(&reseeded), order);
+    }
+}
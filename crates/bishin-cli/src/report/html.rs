@@ -0,0 +1,365 @@
+//! A single HTML page with the summary, a table of every result that can be
+//! filtered by name and status, the output of each test that failed, and how
+//! each module did. The styles and script are inline, so the page opens on
+//! its own, e.g. from a CI system's artifacts.
+//!
+//! Everything a test printed is escaped, and appears only as text in `<pre>`
+//! elements, never inside the script.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use bishin_jobs::{JobResult, RunSummary, Status};
+
+use super::{module_name, seconds, Reporter};
+use crate::timings::Timings;
+
+pub struct HtmlReporter<W> {
+    out: W,
+}
+
+impl<W: Write> HtmlReporter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Reporter for HtmlReporter<W> {
+    fn finish(&mut self, summary: &RunSummary, timings: &Timings) -> io::Result<()> {
+        self.out
+            .write_all(render(summary, timings.total()).as_bytes())?;
+        self.out.flush()
+    }
+}
+
+const STYLE: &str = r##"
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+.meta { color: #666; margin-top: 0; }
+.counts span { margin-right: 1em; }
+table { border-collapse: collapse; margin: 1em 0 2em; min-width: 40em; }
+th, td { text-align: left; padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; vertical-align: top; }
+td.number, th.number { text-align: right; }
+.badge { display: inline-block; padding: 0.1em 0.5em; border-radius: 0.3em; font-size: 0.85em; color: #fff; background: #888; }
+.passed { background: #2e7d32; }
+.failed, .timed-out, .error, .xpass { background: #c62828; }
+.skipped, .xfail { background: #9e9e9e; }
+.counts .badge { font-size: 1em; }
+pre { background: #f5f5f5; padding: 0.8em; overflow-x: auto; white-space: pre-wrap; word-break: break-all; max-width: 80em; }
+summary { cursor: pointer; }
+.filters { margin-top: 1em; }
+.filters input { width: 20em; }
+"##;
+
+const SCRIPT: &str = r##"
+const filter = document.getElementById("filter");
+const status = document.getElementById("status");
+function apply() {
+  const text = filter.value.toLowerCase();
+  for (const row of document.querySelectorAll("#tests tbody tr")) {
+    const name = row.dataset.name.toLowerCase();
+    row.hidden = !name.includes(text) || (status.value !== "" && row.dataset.status !== status.value);
+  }
+}
+filter.addEventListener("input", apply);
+status.addEventListener("change", apply);
+"##;
+
+/// The page for `summary`, of a run that took `duration`.
+pub fn render(summary: &RunSummary, duration: Duration) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>bishin test report</title>\n");
+    writeln!(out, "<style>{STYLE}</style>\n</head>\n<body>").unwrap();
+    out.push_str("<h1>bishin test report</h1>\n");
+    write!(
+        out,
+        "<p class=\"meta\">bishin {}, {} test(s) in {:.2?}, pass rate {:.1}%",
+        env!("CARGO_PKG_VERSION"),
+        summary.results.len(),
+        duration,
+        summary.pass_rate() * 100.0
+    )
+    .unwrap();
+    if let Some(seed) = summary.seed {
+        write!(out, ", shuffled with --seed {seed}").unwrap();
+    }
+    out.push_str("</p>\n");
+    out.push_str("<p class=\"counts\">");
+    for status in Status::ALL {
+        let count = summary.count(status);
+        if count > 0 {
+            write!(out, "<span>{} {count}</span>", badge(status)).unwrap();
+        }
+    }
+    out.push_str("</p>\n");
+
+    modules(&mut out, &summary.results);
+
+    out.push_str("<h2>Tests</h2>\n<div class=\"filters\">\n");
+    out.push_str("<input id=\"filter\" type=\"search\" placeholder=\"filter by name\">\n");
+    out.push_str("<select id=\"status\">\n<option value=\"\">any status</option>\n");
+    for status in Status::ALL {
+        let name = status.name();
+        writeln!(out, "<option value=\"{name}\">{name}</option>").unwrap();
+    }
+    out.push_str("</select>\n</div>\n");
+    results_table(&mut out, "tests", &summary.results);
+    if !summary.hooks.is_empty() {
+        out.push_str("<h2>Setup and teardown</h2>\n");
+        results_table(&mut out, "hooks", &summary.hooks);
+    }
+    writeln!(out, "<script>{SCRIPT}</script>\n</body>\n</html>").unwrap();
+    out
+}
+
+/// A table of how the tests in each module did, by the name of the module.
+fn modules(out: &mut String, results: &[JobResult]) {
+    #[derive(Default)]
+    struct Rollup {
+        tests: usize,
+        passed: usize,
+        failed: usize,
+        duration: Duration,
+    }
+    let mut modules: BTreeMap<&str, Rollup> = BTreeMap::new();
+    for result in results {
+        let rollup = modules.entry(module_name(&result.name)).or_default();
+        rollup.tests += 1;
+        rollup.passed += usize::from(result.status == Status::Passed);
+        rollup.failed += usize::from(result.status.is_failure());
+        rollup.duration += result.duration;
+    }
+    out.push_str("<h2>Modules</h2>\n<table>\n<thead><tr><th>Module</th>");
+    out.push_str("<th class=\"number\">Tests</th><th class=\"number\">Passed</th>");
+    out.push_str(
+        "<th class=\"number\">Failed</th><th class=\"number\">Time</th></tr></thead>\n<tbody>\n",
+    );
+    for (module, rollup) in modules {
+        let module = match module {
+            "" => "(top level)".to_string(),
+            module => escape(module),
+        };
+        writeln!(
+            out,
+            "<tr><td>{module}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{:.3}s</td></tr>",
+            rollup.tests,
+            rollup.passed,
+            rollup.failed,
+            rollup.duration.as_secs_f64()
+        )
+        .unwrap();
+    }
+    out.push_str("</tbody>\n</table>\n");
+}
+
+/// A table of `results`, each of which can be expanded to show its output if
+/// it failed.
+fn results_table(out: &mut String, id: &str, results: &[JobResult]) {
+    writeln!(out, "<table id=\"{id}\">").unwrap();
+    out.push_str("<thead><tr><th>Name</th><th>Status</th>");
+    out.push_str(
+        "<th class=\"number\">Time</th><th class=\"number\">Attempts</th></tr></thead>\n<tbody>\n",
+    );
+    for result in results {
+        let name = escape(&result.name);
+        write!(
+            out,
+            "<tr data-name=\"{name}\" data-status=\"{}\"><td>",
+            result.status.name()
+        )
+        .unwrap();
+        if result.status.is_failure() || result.status == Status::XPass {
+            write!(out, "<details><summary>{name}</summary>").unwrap();
+            let exit = match (result.status, result.exit_code) {
+                (Status::TimedOut, _) => "timed out".to_string(),
+                (Status::Error, _) => "could not be run".to_string(),
+                (_, Some(code)) => format!("exited with status {code}"),
+                (_, None) => "killed by a signal".to_string(),
+            };
+            write!(out, "<p>{exit}</p>").unwrap();
+            output(out, "stdout", &result.stdout);
+            output(out, "stderr", &result.stderr);
+            out.push_str("</details>");
+        } else {
+            out.push_str(&name);
+        }
+        let reason = result.skip_reason.as_ref().or(result.xfail_reason.as_ref());
+        writeln!(
+            out,
+            "</td><td>{}{}</td><td class=\"number\">{:.3}s</td><td class=\"number\">{}</td></tr>",
+            badge(result.status),
+            reason.map_or(String::new(), |reason| format!(" {}", escape(reason))),
+            seconds(result),
+            result.attempts
+        )
+        .unwrap();
+    }
+    out.push_str("</tbody>\n</table>\n");
+}
+
+fn output(out: &mut String, stream: &str, output: &str) {
+    if output.is_empty() {
+        write!(out, "<p>no {stream}</p>").unwrap();
+    } else {
+        write!(out, "<p>{stream}:</p><pre>{}</pre>", escape(output)).unwrap();
+    }
+}
+
+fn badge(status: Status) -> String {
+    let name = status.name();
+    format!("<span class=\"badge {name}\">{name}</span>")
+}
+
+/// `text` escaped to appear in an element or a quoted attribute.
+///
+/// Control characters other than whitespace, like those of terminal colors,
+/// are shown as the symbols for them, and NUL, which HTML doesn't allow, as
+/// the replacement character.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\0' => escaped.push(char::REPLACEMENT_CHARACTER),
+            c if (c as u32) < 0x20 => escaped.push(char::from_u32(0x2400 + c as u32).unwrap()),
+            '\u{7f}' => escaped.push('\u{2421}'),
+            // The C1 controls aren't allowed in HTML either.
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, status: Status) -> JobResult {
+        JobResult {
+            status,
+            exit_code: Some(1),
+            duration: Duration::from_millis(250),
+            ..JobResult::error(name, "")
+        }
+    }
+
+    fn page(results: Vec<JobResult>, hooks: Vec<JobResult>) -> String {
+        render(
+            &RunSummary {
+                results,
+                hooks,
+                ..RunSummary::default()
+            },
+            Duration::from_secs(2),
+        )
+    }
+
+    #[test]
+    fn a_shuffled_run_shows_its_seed() {
+        let summary = RunSummary {
+            results: vec![result("a", Status::Passed)],
+            seed: Some(42),
+            ..RunSummary::default()
+        };
+        let html = render(&summary, Duration::from_secs(2));
+        assert!(
+            html.contains(", pass rate 100.0%, shuffled with --seed 42</p>\n"),
+            "{html}"
+        );
+        let html = page(vec![result("a", Status::Passed)], vec![]);
+        assert!(html.contains(", pass rate 100.0%</p>\n"), "{html}");
+    }
+
+    #[test]
+    fn escapes_markup_and_control_characters() {
+        assert_eq!(
+            escape("<a href=\"x\" title='y'>&</a>"),
+            "&lt;a href=&quot;x&quot; title=&#39;y&#39;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(escape("tab\tline\r\n"), "tab\tline\r\n");
+        assert_eq!(escape("\u{1b}[31mred\u{7}"), "\u{241b}[31mred\u{2407}");
+        assert_eq!(
+            escape("nul\0del\u{7f}c1\u{85}"),
+            "nul\u{fffd}del\u{2421}c1\u{fffd}"
+        );
+        assert_eq!(escape("ünïcödé ✓"), "ünïcödé ✓");
+    }
+
+    #[test]
+    fn hostile_output_stays_text() {
+        let hostile = JobResult {
+            stdout: "</pre></details></td></tr></table><script>alert(1)</script>".to_string(),
+            stderr: "\u{1b}[1;31merror\u{1b}[0m\0 <!-- {{ & }} -->".to_string(),
+            ..result("evil::x\"><img src=x onerror=alert(1)>", Status::Failed)
+        };
+        let html = page(vec![hostile], vec![]);
+        // The only script is the page's own.
+        assert_eq!(html.matches("<script").count(), 1, "{html}");
+        assert_eq!(html.matches("</script>").count(), 1, "{html}");
+        assert_eq!(html.matches("</pre>").count(), 2, "{html}");
+        assert_eq!(html.matches("<!--").count(), 0, "{html}");
+        assert!(!html.contains("<img"), "{html}");
+        assert!(!html.contains(['\u{1b}', '\0']), "{html}");
+        assert!(html.contains(
+            "<pre>&lt;/pre&gt;&lt;/details&gt;&lt;/td&gt;&lt;/tr&gt;&lt;/table&gt;&lt;script&gt;alert(1)&lt;/script&gt;</pre>"
+        ));
+        assert!(html.contains("data-name=\"evil::x&quot;&gt;&lt;img src=x onerror=alert(1)&gt;\""));
+    }
+
+    #[test]
+    fn only_failures_can_be_expanded() {
+        let html = page(
+            vec![
+                JobResult {
+                    stdout: "passed output".to_string(),
+                    ..result("a::passes", Status::Passed)
+                },
+                JobResult {
+                    stdout: "failed output".to_string(),
+                    ..result("a::fails", Status::Failed)
+                },
+                result("a::slow", Status::TimedOut),
+                result("b::xpass", Status::XPass),
+            ],
+            vec![],
+        );
+        assert_eq!(html.matches("<details>").count(), 3, "{html}");
+        assert!(!html.contains("passed output"));
+        assert!(html.contains(
+            "<p>exited with status 1</p><p>stdout:</p><pre>failed output</pre><p>no stderr</p>"
+        ));
+        assert!(html.contains("<summary>a::slow</summary><p>timed out</p>"));
+        assert!(!html.contains("Setup and teardown"));
+    }
+
+    #[test]
+    fn modules_are_rolled_up() {
+        let html = page(
+            vec![
+                result("net::dns::resolves", Status::Passed),
+                result("net::dns::ipv6", Status::Failed),
+                result("top", Status::Passed),
+            ],
+            vec![result("setup", Status::Passed)],
+        );
+        assert!(html.contains(
+            "<tr><td>net::dns</td><td class=\"number\">2</td><td class=\"number\">1</td><td class=\"number\">1</td><td class=\"number\">0.500s</td></tr>"
+        ), "{html}");
+        assert!(
+            html.contains("<tr><td>(top level)</td><td class=\"number\">1</td>"),
+            "{html}"
+        );
+        assert!(html.contains("<h2>Setup and teardown</h2>\n<table id=\"hooks\">"));
+        assert!(html.contains(&format!("bishin {}, 3 test(s)", env!("CARGO_PKG_VERSION"))));
+        assert!(html.contains("pass rate 66.7%"), "{html}");
+    }
+}
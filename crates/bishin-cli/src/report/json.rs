@@ -0,0 +1,257 @@
+//! A JSON document with the summary, every result, the results of the setup
+//! and teardown scripts, the skipped tests and what skipped them, including
+//! those that filters left out, the benchmarks, and the timings.
+//!
+//! ```json
+//! {"summary":{"passed":1,...},"tests":[{"name":"net::dns::resolves",...}],"hooks":[...],"skipped":[{"name":"net::dns::ipv6","by":"exit-code","reason":"no IPv6"}],"benchmarks":[...],"timings":{...}}
+//! ```
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use bishin_jobs::{BenchStats, JobResult, RunSummary, SkipReason, Status};
+
+use super::{seconds, Reporter};
+use crate::timings::Timings;
+
+pub struct JsonReporter<W> {
+    out: W,
+}
+
+impl<W: Write> JsonReporter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Reporter for JsonReporter<W> {
+    fn finish(&mut self, summary: &RunSummary, timings: &Timings) -> io::Result<()> {
+        let tests: Vec<_> = summary.results.iter().map(result_object).collect();
+        let hooks: Vec<_> = summary.hooks.iter().map(result_object).collect();
+        let skipped: Vec<_> = summary
+            .skipped()
+            .map(|result| {
+                let by = result
+                    .skipped_by
+                    .map_or("null".to_string(), |by| string(by.name()));
+                let reason = result
+                    .skip_reason
+                    .as_deref()
+                    .map_or("null".to_string(), string);
+                format!(
+                    "{{\"name\":{},\"by\":{by},\"reason\":{reason}}}",
+                    string(&result.name)
+                )
+            })
+            .chain(summary.filtered.iter().map(|name| {
+                format!(
+                    "{{\"name\":{},\"by\":{},\"reason\":null}}",
+                    string(name),
+                    string(SkipReason::Filter.name())
+                )
+            }))
+            .collect();
+        let benchmarks: Vec<_> = summary
+            .results
+            .iter()
+            .filter_map(|result| {
+                let stats = result.bench.as_ref()?;
+                Some(format!(
+                    "{{\"name\":{},{}}}",
+                    string(&result.name),
+                    bench_fields(stats)
+                ))
+            })
+            .collect();
+        writeln!(
+            self.out,
+            "{{\"summary\":{},\"tests\":[{}],\"hooks\":[{}],\"skipped\":[{}],\"benchmarks\":[{}],\"timings\":{}}}",
+            summary_object(summary),
+            tests.join(","),
+            hooks.join(","),
+            skipped.join(","),
+            benchmarks.join(","),
+            timings.to_json(),
+        )?;
+        self.out.flush()
+    }
+}
+
+/// The counts of each status and the pass rate, as a JSON object.
+pub(super) fn summary_object(summary: &RunSummary) -> String {
+    format!(
+        "{{\"passed\":{},\"failed\":{},\"timed-out\":{},\"errors\":{},\"skipped\":{},\"xfail\":{},\"xpass\":{},\"pass-rate\":{}}}",
+        summary.count(Status::Passed),
+        summary.count(Status::Failed),
+        summary.count(Status::TimedOut),
+        summary.count(Status::Error),
+        summary.count(Status::Skipped),
+        summary.count(Status::XFail),
+        summary.count(Status::XPass),
+        summary.pass_rate(),
+    )
+}
+
+/// Everything about a result, as a JSON object. Durations are in
+/// milliseconds, like the timings.
+pub(super) fn result_object(result: &JobResult) -> String {
+    format!("{{{}}}", result_fields(result))
+}
+
+/// The fields of [`result_object`], without the braces around them.
+pub(super) fn result_fields(result: &JobResult) -> String {
+    let exit_code = result
+        .exit_code
+        .map_or("null".to_string(), |code| code.to_string());
+    let skipped_by = result
+        .skipped_by
+        .map_or("null".to_string(), |by| string(by.name()));
+    let skip_reason = result
+        .skip_reason
+        .as_deref()
+        .map_or("null".to_string(), string);
+    let xfail_reason = result
+        .xfail_reason
+        .as_deref()
+        .map_or("null".to_string(), string);
+    let (cpu_time, max_rss) = match &result.usage {
+        Some(usage) => (
+            format!("{:.3}", usage.cpu_time.as_secs_f64() * 1000.0),
+            usage.max_rss.to_string(),
+        ),
+        None => ("null".to_string(), "null".to_string()),
+    };
+    let bench = result.bench.as_ref().map_or("null".to_string(), |stats| {
+        format!("{{{}}}", bench_fields(stats))
+    });
+    format!(
+        "\"name\":{},\"status\":\"{}\",\"exit-code\":{exit_code},\"duration\":{:.3},\"attempts\":{},\"skipped-by\":{skipped_by},\"skip-reason\":{skip_reason},\"xfail-reason\":{xfail_reason},\"cpu-time\":{cpu_time},\"max-rss\":{max_rss},\"bench\":{bench},\"leaked-processes\":{},\"stdout\":{},\"stderr\":{}",
+        string(&result.name),
+        result.status.name(),
+        seconds(result) * 1000.0,
+        result.attempts,
+        result.leaked_processes,
+        string(&result.stdout),
+        string(&result.stderr),
+    )
+}
+
+/// The statistics of a benchmark and the time of each of its runs, in
+/// milliseconds, without the braces around them.
+fn bench_fields(stats: &BenchStats) -> String {
+    let milliseconds = |duration: Duration| format!("{:.3}", duration.as_secs_f64() * 1000.0);
+    let times: Vec<_> = stats.times.iter().copied().map(milliseconds).collect();
+    format!(
+        "\"iterations\":{},\"min\":{},\"median\":{},\"mean\":{},\"p95\":{},\"max\":{},\"times\":[{}]",
+        stats.times.len(),
+        milliseconds(stats.min()),
+        milliseconds(stats.median()),
+        milliseconds(stats.mean()),
+        milliseconds(stats.p95()),
+        milliseconds(stats.max()),
+        times.join(","),
+    )
+}
+
+/// `value` as a JSON string.
+pub(super) fn string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use bishin_jobs::SkipReason;
+
+    use super::*;
+
+    #[test]
+    fn benchmarks_have_a_section_of_their_own() {
+        let bench = JobResult {
+            status: Status::Passed,
+            bench: Some(BenchStats {
+                times: vec![Duration::from_millis(2), Duration::from_millis(4)],
+            }),
+            ..JobResult::error("cli::startup", "")
+        };
+        let plain = JobResult {
+            status: Status::Passed,
+            ..JobResult::error("cli::help", "")
+        };
+        let summary = RunSummary {
+            results: vec![bench, plain],
+            ..RunSummary::default()
+        };
+        let mut out = Vec::new();
+        JsonReporter::new(&mut out)
+            .finish(&summary, &Timings::default())
+            .unwrap();
+        let document = String::from_utf8(out).unwrap();
+        assert!(
+            document.contains("\"benchmarks\":[{\"name\":\"cli::startup\",\"iterations\":2,\"min\":2.000,\"median\":3.000,\"mean\":3.000,\"p95\":4.000,\"max\":4.000,\"times\":[2.000,4.000]}]"),
+            "{document}"
+        );
+        assert!(document.contains("\"bench\":null"), "{document}");
+    }
+
+    #[test]
+    fn skipped_tests_are_listed_with_what_skipped_them() {
+        let skipped = |name: &str, by, reason: Option<&str>| JobResult {
+            status: Status::Skipped,
+            skipped_by: by,
+            skip_reason: reason.map(str::to_string),
+            ..JobResult::error(name, "")
+        };
+        let summary = RunSummary {
+            results: vec![
+                skipped("a", Some(SkipReason::Decorator), Some("not \"yet\"")),
+                JobResult {
+                    status: Status::Passed,
+                    ..JobResult::error("b", "")
+                },
+                skipped("c", Some(SkipReason::ExitCode), None),
+                skipped(
+                    "d",
+                    Some(SkipReason::MissingShell),
+                    Some("fish isn't installed"),
+                ),
+            ],
+            filtered: vec!["e".to_string()],
+            ..RunSummary::default()
+        };
+        let mut out = Vec::new();
+        JsonReporter::new(&mut out)
+            .finish(&summary, &Timings::default())
+            .unwrap();
+        let document = String::from_utf8(out).unwrap();
+        assert!(
+            document.contains("\"skipped\":[{\"name\":\"a\",\"by\":\"decorator\",\"reason\":\"not \\\"yet\\\"\"},{\"name\":\"c\",\"by\":\"exit-code\",\"reason\":null},{\"name\":\"d\",\"by\":\"missing-shell\",\"reason\":\"fish isn't installed\"},{\"name\":\"e\",\"by\":\"filter\",\"reason\":null}]"),
+            "{document}"
+        );
+        assert!(
+            document.contains("\"skipped-by\":\"exit-code\""),
+            "{document}"
+        );
+    }
+
+    #[test]
+    fn skip_reasons_have_names_that_read_back() {
+        for reason in SkipReason::ALL {
+            assert_eq!(SkipReason::from_name(reason.name()), Some(reason));
+        }
+        assert_eq!(SkipReason::from_name("platform"), None);
+    }
+}
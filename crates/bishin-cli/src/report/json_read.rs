@@ -0,0 +1,382 @@
+//! Reading a JSON report back into the results it was written from, so that
+//! `bishin report` can render it another way.
+
+use std::time::Duration;
+
+use bishin_jobs::{BenchStats, JobResult, ResourceUsage, RunSummary, SkipReason, Status};
+
+/// The summary in the JSON report `contents`, and how long the run took.
+pub fn read_summary(contents: &str) -> Result<(RunSummary, Duration), String> {
+    let mut parser = Parser {
+        chars: contents.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    let results = |key| -> Result<Vec<JobResult>, String> {
+        value
+            .get(key)
+            .and_then(Value::as_array)
+            .ok_or_else(|| format!("no {key:?} array"))?
+            .iter()
+            .map(result)
+            .collect()
+    };
+    // The tests that filters left out are only in the skipped array.
+    let filtered = value
+        .get("skipped")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter(|skipped| {
+            skipped.get("by").and_then(Value::as_str) == Some(SkipReason::Filter.name())
+        })
+        .filter_map(|skipped| Some(skipped.get("name")?.as_str()?.to_string()))
+        .collect();
+    let summary = RunSummary {
+        results: results("tests")?,
+        hooks: results("hooks")?,
+        filtered,
+        ..RunSummary::default()
+    };
+    let duration = match value.get("timings") {
+        Some(Value::Object(phases)) => phases
+            .iter()
+            .filter_map(|(_, milliseconds)| milliseconds.as_f64())
+            .map(|milliseconds| Duration::from_secs_f64(milliseconds.max(0.0) / 1000.0))
+            .sum(),
+        _ => Duration::ZERO,
+    };
+    Ok((summary, duration))
+}
+
+/// A result as written by `result_fields`.
+fn result(value: &Value) -> Result<JobResult, String> {
+    let string = |key| value.get(key).and_then(Value::as_str);
+    let number = |key| value.get(key).and_then(Value::as_f64);
+    let name = string("name").ok_or("a result has no name")?;
+    let status = string("status")
+        .and_then(Status::from_name)
+        .ok_or_else(|| format!("result {name} has no known status"))?;
+    let milliseconds = |value: f64| Duration::from_secs_f64(value.max(0.0) / 1000.0);
+    Ok(JobResult {
+        name: name.to_string(),
+        status,
+        exit_code: number("exit-code").map(|code| code as i32),
+        stdout: string("stdout").unwrap_or_default().to_string(),
+        stderr: string("stderr").unwrap_or_default().to_string(),
+        skipped_by: string("skipped-by").and_then(SkipReason::from_name),
+        skip_reason: string("skip-reason").map(str::to_string),
+        xfail_reason: string("xfail-reason").map(str::to_string),
+        duration: number("duration").map_or(Duration::ZERO, milliseconds),
+        attempts: number("attempts").map_or(1, |attempts| attempts as u32),
+        usage: number("cpu-time")
+            .zip(number("max-rss"))
+            .map(|(cpu_time, max_rss)| ResourceUsage {
+                cpu_time: milliseconds(cpu_time),
+                max_rss: max_rss as u64,
+            }),
+        bench: value
+            .get("bench")
+            .and_then(|bench| bench.get("times"))
+            .and_then(Value::as_array)
+            .map(|times| BenchStats {
+                times: times
+                    .iter()
+                    .filter_map(Value::as_f64)
+                    .map(milliseconds)
+                    .collect(),
+            }),
+        leaked_processes: matches!(value.get("leaked-processes"), Some(Value::Bool(true))),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn value(&mut self) -> Result<Value, String> {
+        self.whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        self.whitespace();
+        if self.eat('}') {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.whitespace();
+            if !self.eat(':') {
+                return Err(self.error("expected ':'"));
+            }
+            fields.push((key, self.value()?));
+            self.whitespace();
+            if self.eat('}') {
+                return Ok(Value::Object(fields));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut values = Vec::new();
+        self.whitespace();
+        if self.eat(']') {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            if self.eat(']') {
+                return Ok(Value::Array(values));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            let c = self
+                .next()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    value.push(c);
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, which may take a second one for a
+    /// surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        if self.peek() != Some('\\') || self.chars.get(self.pos + 1) != Some(&'u') {
+            return Ok(char::REPLACEMENT_CHARACTER);
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        let c = 0x10000 + ((high - 0xd800) << 10) + low.wrapping_sub(0xdc00);
+        Ok(char::from_u32(c)
+            .filter(|_| (0xdc00..0xe000).contains(&low))
+            .unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.iter().skip(self.pos).take(4).collect();
+        let value = u32::from_str_radix(&digits, 16)
+            .ok()
+            .filter(|_| digits.len() == 4)
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        let end = self.pos + keyword.len();
+        if self
+            .chars
+            .get(self.pos..end)
+            .is_some_and(|chars| chars.iter().copied().eq(keyword.chars()))
+        {
+            self.pos = end;
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        self.pos += usize::from(matched);
+        matched
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{message} at character {}", self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::json::JsonReporter;
+    use crate::report::Reporter;
+    use crate::timings::Timings;
+
+    #[test]
+    fn a_written_report_reads_back_the_same() {
+        let summary = RunSummary {
+            results: vec![
+                JobResult {
+                    status: Status::Failed,
+                    exit_code: Some(3),
+                    stdout: "\"quoted\" \\ back\nslash\t\u{1b}[0m ✓".to_string(),
+                    stderr: "</pre>".to_string(),
+                    duration: Duration::from_millis(1250),
+                    attempts: 2,
+                    usage: Some(ResourceUsage {
+                        cpu_time: Duration::from_millis(40),
+                        max_rss: 8192,
+                    }),
+                    ..JobResult::error("net::dns::resolves", "")
+                },
+                JobResult {
+                    status: Status::Skipped,
+                    exit_code: Some(bishin_jobs::SKIP_EXIT_CODE),
+                    skipped_by: Some(SkipReason::ExitCode),
+                    skip_reason: Some("no IPv6".to_string()),
+                    ..JobResult::error("net::dns::ipv6", "")
+                },
+                JobResult {
+                    status: Status::XFail,
+                    xfail_reason: Some("bug #12".to_string()),
+                    ..JobResult::error("known", "")
+                },
+            ],
+            hooks: vec![JobResult {
+                status: Status::Passed,
+                exit_code: Some(0),
+                ..JobResult::error("setup", "")
+            }],
+            filtered: vec!["net::http::gets".to_string()],
+            ..RunSummary::default()
+        };
+        let mut out = Vec::new();
+        JsonReporter::new(&mut out)
+            .finish(&summary, &Timings::default())
+            .unwrap();
+        let (read, _) = read_summary(std::str::from_utf8(&out).unwrap()).unwrap();
+        assert_eq!(read, summary);
+    }
+
+    #[test]
+    fn other_documents_are_errors() {
+        for contents in ["", "[]", "{\"tests\":[]}", "{\"tests\":[],\"hooks\":[]} x"] {
+            assert!(read_summary(contents).is_err(), "{contents:?}");
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! A JSON object per line for each thing that happens during a run, written
+//! as it happens so that other tools can follow along:
+//!
+//! ```json
+//! {"event":"start","tests":2}
+//! {"event":"hook","name":"setup-script","status":"passed",...}
+//! {"event":"result","name":"net::dns::resolves","status":"passed",...}
+//! {"event":"result","name":"net::dns::times_out","status":"timed-out",...}
+//! {"event":"finish","summary":{"passed":1,...},"timings":{...}}
+//! ```
+
+use std::io::{self, Write};
+
+use bishin_jobs::{JobResult, RunSummary};
+
+use super::json::{result_fields, summary_object};
+use super::Reporter;
+use crate::timings::Timings;
+
+pub struct JsonlReporter<W> {
+    out: W,
+}
+
+impl<W: Write> JsonlReporter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Reporter for JsonlReporter<W> {
+    fn start(&mut self, tests: usize) -> io::Result<()> {
+        writeln!(self.out, "{{\"event\":\"start\",\"tests\":{tests}}}")?;
+        self.out.flush()
+    }
+
+    fn result(&mut self, result: &JobResult) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{{\"event\":\"result\",{}}}",
+            result_fields(result)
+        )?;
+        self.out.flush()
+    }
+
+    fn hook(&mut self, result: &JobResult) -> io::Result<()> {
+        writeln!(self.out, "{{\"event\":\"hook\",{}}}", result_fields(result))?;
+        self.out.flush()
+    }
+
+    fn finish(&mut self, summary: &RunSummary, timings: &Timings) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{{\"event\":\"finish\",\"summary\":{},\"timings\":{}}}",
+            summary_object(summary),
+            timings.to_json(),
+        )?;
+        self.out.flush()
+    }
+}
@@ -0,0 +1,511 @@
+//! A JUnit XML report, which most CI systems know how to show.
+//!
+//! Each test file is a `<testsuite>`, named by its module path, and each test
+//! in it a `<testcase>`. The setup and teardown scripts are test cases in a
+//! suite of their own, called [`HOOKS_SUITE`].
+//!
+//! A skipped test's `<skipped>` has the [`SkipReason`](bishin_jobs::SkipReason)
+//! that skipped it as its `type`.
+//!
+//! Each test is added to the report as it finishes, in a suite of its own,
+//! so that a run that's killed part way through still leaves a valid report
+//! of the tests that finished. Only the closing tag is written over to do
+//! so, rather than the tests before it. Once the run finishes, the report is
+//! written again with a suite per module.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use bishin_jobs::{JobResult, RunSummary, Status};
+
+use super::{module_name, seconds, Reporter};
+use crate::timings::Timings;
+
+/// The name of the suite holding the setup and teardown scripts, which can't
+/// be the name of a module.
+const HOOKS_SUITE: &str = "bishin-hooks";
+
+const DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+const END: &str = "</testsuites>\n";
+
+pub struct JunitReporter {
+    path: PathBuf,
+    /// The report while the run is going, and where its closing tag starts.
+    file: Option<(File, u64)>,
+}
+
+impl JunitReporter {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            file: None,
+        }
+    }
+
+    /// Add `result` to the report in a suite called `suite`, in place of the
+    /// closing tag, and close it again after.
+    fn append(&mut self, suite: &str, result: &JobResult) -> io::Result<()> {
+        let Some((file, end)) = &mut self.file else {
+            return Ok(());
+        };
+        file.seek(SeekFrom::Start(*end))?;
+        let mut out = BufWriter::new(&*file);
+        test_suite(&mut out, suite, &[result])?;
+        out.flush()?;
+        drop(out);
+        *end = file.stream_position()?;
+        file.write_all(END.as_bytes())?;
+        file.set_len(*end + END.len() as u64)
+    }
+}
+
+impl Reporter for JunitReporter {
+    fn start(&mut self, _tests: usize) -> io::Result<()> {
+        let mut file = File::create(&self.path)?;
+        write!(file, "{DECLARATION}\n<testsuites name=\"bishin\">\n")?;
+        let end = file.stream_position()?;
+        file.write_all(END.as_bytes())?;
+        self.file = Some((file, end));
+        Ok(())
+    }
+
+    fn result(&mut self, result: &JobResult) -> io::Result<()> {
+        self.append(module_name(&result.name), result)
+    }
+
+    fn hook(&mut self, result: &JobResult) -> io::Result<()> {
+        self.append(HOOKS_SUITE, result)
+    }
+
+    /// Replace the report with the whole of it. It's written next to the
+    /// report and renamed over it, so the report is never left half written.
+    fn finish(&mut self, summary: &RunSummary, _timings: &Timings) -> io::Result<()> {
+        self.file = None;
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        document(
+            &mut BufWriter::new(File::create(&partial)?),
+            &summary.results,
+            &summary.hooks,
+        )?;
+        std::fs::rename(&partial, &self.path)
+    }
+}
+
+fn document(out: &mut impl Write, results: &[JobResult], hooks: &[JobResult]) -> io::Result<()> {
+    writeln!(out, "{DECLARATION}")?;
+    writeln!(
+        out,
+        r#"<testsuites name="bishin" {}>"#,
+        counts(results.iter().chain(hooks))
+    )?;
+    let mut suites = suites(results);
+    if !hooks.is_empty() {
+        suites.push((HOOKS_SUITE, hooks.iter().collect()));
+    }
+    for (suite, results) in suites {
+        test_suite(out, suite, &results)?;
+    }
+    write!(out, "{END}")?;
+    out.flush()
+}
+
+fn test_suite(out: &mut impl Write, suite: &str, results: &[&JobResult]) -> io::Result<()> {
+    writeln!(
+        out,
+        r#"  <testsuite name="{}" {}>"#,
+        escape(suite),
+        counts(results.iter().copied())
+    )?;
+    for result in results {
+        test_case(out, suite, result)?;
+    }
+    writeln!(out, "  </testsuite>")
+}
+
+fn test_case(out: &mut impl Write, suite: &str, result: &JobResult) -> io::Result<()> {
+    let name = result
+        .name
+        .strip_prefix(suite)
+        .and_then(|name| name.strip_prefix("::"))
+        .unwrap_or(&result.name);
+    writeln!(
+        out,
+        r#"    <testcase name="{}" classname="{}" time="{:.3}">"#,
+        escape(name),
+        escape(suite),
+        seconds(result)
+    )?;
+    if let Some(usage) = &result.usage {
+        writeln!(out, "      <properties>")?;
+        writeln!(
+            out,
+            r#"        <property name="cpu-time" value="{:.3}"/>"#,
+            usage.cpu_time.as_secs_f64()
+        )?;
+        writeln!(
+            out,
+            r#"        <property name="max-rss" value="{}"/>"#,
+            usage.max_rss
+        )?;
+        writeln!(out, "      </properties>")?;
+    }
+    let exit = match result.exit_code {
+        Some(code) => format!("exited with status {code}"),
+        None => "killed by a signal".to_string(),
+    };
+    match result.status {
+        Status::Passed => {}
+        Status::Failed => writeln!(out, r#"      <failure message="{}"/>"#, escape(&exit))?,
+        Status::TimedOut => writeln!(out, r#"      <failure message="timed out"/>"#)?,
+        Status::Error => writeln!(out, r#"      <error message="could not be run"/>"#)?,
+        Status::Skipped => {
+            let kind = result
+                .skipped_by
+                .map(|by| format!(r#" type="{}""#, by.name()))
+                .unwrap_or_default();
+            match &result.skip_reason {
+                Some(reason) => writeln!(
+                    out,
+                    r#"      <skipped{kind} message="{}"/>"#,
+                    escape(reason)
+                )?,
+                None => writeln!(out, "      <skipped{kind}/>")?,
+            }
+        }
+        // JUnit has no expected failures, and skipped is the closest to one
+        // that doesn't fail the build.
+        Status::XFail => {
+            let message = match &result.xfail_reason {
+                Some(reason) => format!("expected failure: {reason}"),
+                None => "expected failure".to_string(),
+            };
+            writeln!(out, r#"      <skipped message="{}"/>"#, escape(&message))?
+        }
+        Status::XPass => {}
+    }
+    if !result.stdout.is_empty() {
+        writeln!(
+            out,
+            "      <system-out>{}</system-out>",
+            escape(&result.stdout)
+        )?;
+    }
+    if !result.stderr.is_empty() {
+        writeln!(
+            out,
+            "      <system-err>{}</system-err>",
+            escape(&result.stderr)
+        )?;
+    }
+    writeln!(out, "    </testcase>")
+}
+
+/// The results grouped by the module they're in, in the order each module
+/// first appears.
+fn suites(results: &[JobResult]) -> Vec<(&str, Vec<&JobResult>)> {
+    let mut suites: Vec<(&str, Vec<&JobResult>)> = Vec::new();
+    for result in results {
+        let suite = module_name(&result.name);
+        match suites.iter_mut().find(|(name, _)| *name == suite) {
+            Some((_, results)) => results.push(result),
+            None => suites.push((suite, vec![result])),
+        }
+    }
+    suites
+}
+
+/// The attributes counting the results of a suite.
+fn counts<'a>(results: impl IntoIterator<Item = &'a JobResult>) -> String {
+    let (mut tests, mut failures, mut errors, mut skipped, mut time) = (0, 0, 0, 0, 0.0);
+    for result in results {
+        tests += 1;
+        time += seconds(result);
+        match result.status {
+            Status::Failed | Status::TimedOut => failures += 1,
+            Status::Error => errors += 1,
+            Status::Skipped | Status::XFail => skipped += 1,
+            Status::Passed | Status::XPass => {}
+        }
+    }
+    format!(
+        r#"tests="{tests}" failures="{failures}" errors="{errors}" skipped="{skipped}" time="{time:.3}""#
+    )
+}
+
+/// `text` with the characters that XML gives meaning to escaped, and those
+/// it doesn't allow at all dropped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bishin_jobs::SkipReason;
+
+    use super::*;
+    use crate::testing::scratch_dir;
+
+    fn result(name: &str, status: Status) -> JobResult {
+        JobResult {
+            status,
+            exit_code: Some(0),
+            duration: Duration::from_millis(1500),
+            ..JobResult::error(name, "")
+        }
+    }
+
+    fn rendered(results: &[JobResult], hooks: &[JobResult]) -> String {
+        let mut out = Vec::new();
+        document(&mut out, results, hooks).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escapes_what_xml_would_read_as_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">'&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape("tab\tline\r\n"), "tab\tline\r\n");
+        // Other control characters aren't allowed in XML at all.
+        assert_eq!(escape("bell\u{7}esc\u{1b}[31mred"), "bellesc[31mred");
+        assert_eq!(escape("ünïcödé ✓"), "ünïcödé ✓");
+    }
+
+    #[test]
+    fn a_suite_per_module_and_one_for_the_hooks() {
+        let failed = JobResult {
+            exit_code: Some(3),
+            stdout: "expected <1> & got \"2\"\n".to_string(),
+            stderr: "\u{1b}[31merror\u{1b}[0m\n".to_string(),
+            ..result("net::dns::resolves", Status::Failed)
+        };
+        let skipped = JobResult {
+            skipped_by: Some(SkipReason::Decorator),
+            skip_reason: Some("needs <ipv6>".to_string()),
+            ..result("net::http", Status::Skipped)
+        };
+        let results = [result("net::dns::works", Status::Passed), failed, skipped];
+        let hooks = [result("setup", Status::Passed)];
+        assert_eq!(
+            rendered(&results, &hooks),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="bishin" tests="4" failures="1" errors="0" skipped="1" time="6.000">
+  <testsuite name="net::dns" tests="2" failures="1" errors="0" skipped="0" time="3.000">
+    <testcase name="works" classname="net::dns" time="1.500">
+    </testcase>
+    <testcase name="resolves" classname="net::dns" time="1.500">
+      <failure message="exited with status 3"/>
+      <system-out>expected &lt;1&gt; &amp; got &quot;2&quot;
+</system-out>
+      <system-err>[31merror[0m
+</system-err>
+    </testcase>
+  </testsuite>
+  <testsuite name="net" tests="1" failures="0" errors="0" skipped="1" time="1.500">
+    <testcase name="http" classname="net" time="1.500">
+      <skipped type="decorator" message="needs &lt;ipv6&gt;"/>
+    </testcase>
+  </testsuite>
+  <testsuite name="bishin-hooks" tests="1" failures="0" errors="0" skipped="0" time="1.500">
+    <testcase name="setup" classname="bishin-hooks" time="1.500">
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn other_statuses() {
+        let xfail = JobResult {
+            xfail_reason: Some("bug #1".to_string()),
+            ..result("a", Status::XFail)
+        };
+        let signalled = JobResult {
+            exit_code: None,
+            ..result("b", Status::Failed)
+        };
+        let report = rendered(
+            &[
+                xfail,
+                signalled,
+                result("c", Status::TimedOut),
+                result("d", Status::Error),
+                result("e", Status::XPass),
+            ],
+            &[],
+        );
+        assert!(
+            report.contains(r#"<skipped message="expected failure: bug #1"/>"#),
+            "{report}"
+        );
+        assert!(
+            report.contains(r#"<failure message="killed by a signal"/>"#),
+            "{report}"
+        );
+        assert!(
+            report.contains(r#"<failure message="timed out"/>"#),
+            "{report}"
+        );
+        assert!(
+            report.contains(r#"<error message="could not be run"/>"#),
+            "{report}"
+        );
+        assert!(
+            report.contains(r#"tests="5" failures="2" errors="1" skipped="1""#),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn tests_are_added_to_the_report_as_they_finish() {
+        let scratch = scratch_dir();
+        let path = scratch.join("junit.xml");
+        let mut reporter = JunitReporter::new(&path);
+        reporter.start(2).unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert_well_formed(&report);
+        assert!(!report.contains("<testsuite "), "{report}");
+        reporter.result(&result("a::one", Status::Passed)).unwrap();
+        let first = std::fs::read_to_string(&path).unwrap();
+        assert_well_formed(&first);
+        reporter.result(&result("a::two", Status::Passed)).unwrap();
+        let second = std::fs::read_to_string(&path).unwrap();
+        assert_well_formed(&second);
+        // What was there before is left as it was, up to the closing tag.
+        assert!(second.starts_with(first.strip_suffix(END).unwrap()));
+        assert_eq!(
+            second.matches(r#"<testsuite name="a" tests="1""#).count(),
+            2
+        );
+
+        let summary = RunSummary {
+            results: vec![
+                result("a::one", Status::Passed),
+                result("a::two", Status::Passed),
+            ],
+            ..RunSummary::default()
+        };
+        reporter.finish(&summary, &Timings::default()).unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(report, rendered(&summary.results, &[]));
+        assert!(
+            report.contains(r#"<testsuite name="a" tests="2""#),
+            "{report}"
+        );
+        assert!(!path.with_extension("xml.partial").exists());
+    }
+
+    /// Check that `xml` is a declaration followed by one element whose tags
+    /// all close in order, with no stray `<` or `&` in text or attributes.
+    fn assert_well_formed(xml: &str) {
+        let body = xml
+            .strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")
+            .expect("a declaration");
+        let mut open = Vec::new();
+        let mut closed_root = false;
+        let mut rest = body;
+        while let Some(start) = rest.find('<') {
+            let text = &rest[..start];
+            assert!(
+                !closed_root || text.trim().is_empty(),
+                "after the root: {text:?}"
+            );
+            assert_entities(text);
+            let end = rest[start..].find('>').expect("an unclosed tag") + start;
+            let tag = &rest[start + 1..end];
+            assert!(!tag.contains('<'), "{tag:?}");
+            assert_entities(tag);
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name), "{xml}");
+                closed_root = open.is_empty();
+            } else {
+                assert!(!closed_root, "a second root in {xml}");
+                let name = tag.split([' ', '/']).next().unwrap();
+                if !tag.ends_with('/') {
+                    open.push(name);
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        assert!(open.is_empty() && closed_root, "{xml}");
+        assert!(rest.trim().is_empty());
+    }
+
+    fn assert_entities(text: &str) {
+        for (index, _) in text.match_indices('&') {
+            let entity = &text[index..];
+            assert!(
+                ["&lt;", "&gt;", "&amp;", "&quot;", "&apos;"]
+                    .iter()
+                    .any(|known| entity.starts_with(known)),
+                "{entity:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_run_stopped_part_way_leaves_a_valid_report() {
+        let scratch = scratch_dir();
+        let path = scratch.join("junit.xml");
+        let mut reporter = JunitReporter::new(&path);
+        reporter.start(3).unwrap();
+        reporter.hook(&result("setup", Status::Passed)).unwrap();
+        reporter.result(&result("a::one", Status::Passed)).unwrap();
+        let failed = JobResult {
+            stdout: "<tag> & \"quotes\"\n".to_string(),
+            ..result("a::two", Status::Failed)
+        };
+        reporter.result(&failed).unwrap();
+        // The run is killed here, before the third test or `finish`.
+        drop(reporter);
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert_well_formed(&report);
+        assert_eq!(report.matches("<testcase ").count(), 3, "{report}");
+        assert!(report.contains(r#"tests="1" failures="1""#), "{report}");
+        assert!(report.contains(r#"<testcase name="two" classname="a""#));
+        assert!(report.contains(r#"<testsuite name="bishin-hooks""#));
+    }
+
+    #[test]
+    fn measured_tests_have_their_usage_as_properties() {
+        let measured = JobResult {
+            usage: Some(bishin_jobs::ResourceUsage {
+                cpu_time: Duration::from_millis(1250),
+                max_rss: 4096,
+            }),
+            ..result("suite::busy", Status::Passed)
+        };
+        let xml = rendered(&[measured, result("suite::idle", Status::Passed)], &[]);
+        assert!(
+            xml.contains(concat!(
+                "      <properties>\n",
+                "        <property name=\"cpu-time\" value=\"1.250\"/>\n",
+                "        <property name=\"max-rss\" value=\"4096\"/>\n",
+                "      </properties>\n",
+            )),
+            "{xml}"
+        );
+        assert_eq!(xml.matches("<properties>").count(), 1, "{xml}");
+    }
+}
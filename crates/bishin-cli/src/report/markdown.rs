@@ -0,0 +1,335 @@
+//! A Markdown summary of a run, for pull request comments and the step
+//! summaries of GitHub Actions: the seed of its order if it was shuffled, a
+//! table of how each module did, the end of the output of each test that
+//! failed, and the tests that only passed after being retried.
+//!
+//! Names go in table cells and output in fenced code blocks, so both are
+//! escaped for where they end up, and a fence is always longer than any run
+//! of backticks in what it holds.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use bishin_jobs::{JobResult, RunSummary, Status};
+
+use super::{module_name, Reporter};
+use crate::timings::Timings;
+
+/// How many lines from the end of each stream a failure shows.
+const TAIL_LINES: usize = 30;
+
+pub struct MarkdownReporter<W> {
+    out: W,
+}
+
+impl<W: Write> MarkdownReporter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Reporter for MarkdownReporter<W> {
+    fn finish(&mut self, summary: &RunSummary, timings: &Timings) -> io::Result<()> {
+        self.out
+            .write_all(render(summary, timings.total()).as_bytes())?;
+        self.out.flush()
+    }
+}
+
+/// The summary of a run that took `duration`.
+fn render(summary: &RunSummary, duration: Duration) -> String {
+    let mut out = String::from("## bishin test results\n\n");
+    if summary.results.is_empty() {
+        out.push_str("No tests ran.\n");
+        return out;
+    }
+    let failed: Vec<_> = summary
+        .results
+        .iter()
+        .filter(|result| result.status.is_failure() || result.status == Status::XPass)
+        .collect();
+    let counts: Vec<_> = Status::ALL
+        .into_iter()
+        .map(|status| (status, summary.count(status)))
+        .filter(|(_, count)| *count > 0)
+        .map(|(status, count)| format!("{count} {}", status.name()))
+        .collect();
+    writeln!(
+        out,
+        "{} test(s) in {:.2?}: {}.\n",
+        summary.results.len(),
+        duration,
+        counts.join(", ")
+    )
+    .unwrap();
+    if let Some(seed) = summary.seed {
+        writeln!(out, "The tests ran in the order of `--seed {seed}`.\n").unwrap();
+    }
+
+    modules(&mut out, &summary.results);
+
+    if !failed.is_empty() {
+        out.push_str("\n### Failures\n");
+        for result in failed {
+            failure(&mut out, result);
+        }
+    }
+
+    let flaky: Vec<_> = summary
+        .results
+        .iter()
+        .filter(|result| result.status == Status::Passed && result.attempts > 1)
+        .collect();
+    if !flaky.is_empty() {
+        out.push_str("\n### Passed after retrying\n\n");
+        for result in flaky {
+            writeln!(
+                out,
+                "- {} (after {} attempts)",
+                code(&result.name),
+                result.attempts
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+
+/// A table of how the tests in each module did.
+fn modules(out: &mut String, results: &[JobResult]) {
+    #[derive(Default)]
+    struct Rollup {
+        passed: usize,
+        failed: usize,
+        skipped: usize,
+        duration: Duration,
+    }
+    let mut modules: BTreeMap<&str, Rollup> = BTreeMap::new();
+    for result in results {
+        let rollup = modules.entry(module_name(&result.name)).or_default();
+        rollup.passed += usize::from(result.status == Status::Passed);
+        rollup.failed += usize::from(result.status.is_failure());
+        rollup.skipped += usize::from(result.status == Status::Skipped);
+        rollup.duration += result.duration;
+    }
+    out.push_str("| Module | Passed | Failed | Skipped | Time |\n");
+    out.push_str("| --- | ---: | ---: | ---: | ---: |\n");
+    for (module, rollup) in modules {
+        let module = match module {
+            "" => "(top level)".to_string(),
+            module => cell(module),
+        };
+        writeln!(
+            out,
+            "| {module} | {} | {} | {} | {:.3}s |",
+            rollup.passed,
+            rollup.failed,
+            rollup.skipped,
+            rollup.duration.as_secs_f64()
+        )
+        .unwrap();
+    }
+}
+
+/// A collapsed section with the end of what a failed test printed.
+fn failure(out: &mut String, result: &JobResult) {
+    // GitHub doesn't render Markdown in a summary, so this is HTML.
+    writeln!(
+        out,
+        "\n<details>\n<summary><code>{}</code> {}</summary>\n",
+        html(&result.name),
+        result.status.name()
+    )
+    .unwrap();
+    for (stream, output) in [("stdout", &result.stdout), ("stderr", &result.stderr)] {
+        if output.is_empty() {
+            continue;
+        }
+        let lines: Vec<_> = output.lines().collect();
+        let tail = &lines[lines.len().saturating_sub(TAIL_LINES)..];
+        if tail.len() < lines.len() {
+            writeln!(out, "{stream}, the last {} lines:\n", tail.len()).unwrap();
+        } else {
+            writeln!(out, "{stream}:\n").unwrap();
+        }
+        let tail = tail.join("\n");
+        let fence = "`".repeat(longest_backtick_run(&tail).max(2) + 1);
+        writeln!(out, "{fence}\n{tail}\n{fence}\n").unwrap();
+    }
+    out.push_str("</details>\n");
+}
+
+/// `text` in a code span, which holds anything but backticks literally.
+fn code(text: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    // A code span starting or ending with a backtick needs a space between
+    // it and the delimiter, which is then dropped.
+    let pad = if text.starts_with('`') || text.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!("{ticks}{pad}{text}{pad}{ticks}")
+}
+
+/// `text` escaped for a table cell, where a `|` would end the cell.
+fn cell(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '|' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `text` escaped for HTML.
+fn html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, status: Status) -> JobResult {
+        JobResult {
+            status,
+            exit_code: Some(0),
+            duration: Duration::from_millis(500),
+            ..JobResult::error(name, "")
+        }
+    }
+
+    fn rendered(results: Vec<JobResult>) -> String {
+        let summary = RunSummary {
+            results,
+            ..RunSummary::default()
+        };
+        render(&summary, Duration::from_millis(1500))
+    }
+
+    #[test]
+    fn a_run_with_failures_and_retries() {
+        let md = rendered(vec![
+            result("net::dns::resolves", Status::Passed),
+            JobResult {
+                stdout: "looking up\nexample.com: ``` not found |".to_string(),
+                stderr: "<b>exit</b> & done".to_string(),
+                ..result("net::dns::a|b`c", Status::Failed)
+            },
+            result("net::dns::ipv6", Status::Skipped),
+            JobResult {
+                attempts: 3,
+                ..result("we|ird`mod_*::get", Status::Passed)
+            },
+            result("top", Status::TimedOut),
+        ]);
+        assert_eq!(
+            md,
+            r#"## bishin test results
+
+5 test(s) in 1.50s: 2 passed, 1 failed, 1 timed-out, 1 skipped.
+
+| Module | Passed | Failed | Skipped | Time |
+| --- | ---: | ---: | ---: | ---: |
+| (top level) | 0 | 1 | 0 | 0.500s |
+| net::dns | 1 | 1 | 1 | 1.500s |
+| we\|ird\`mod\_\* | 1 | 0 | 0 | 0.500s |
+
+### Failures
+
+<details>
+<summary><code>net::dns::a|b`c</code> failed</summary>
+
+stdout:
+
+````
+looking up
+example.com: ``` not found |
+````
+
+stderr:
+
+```
+<b>exit</b> & done
+```
+
+</details>
+
+<details>
+<summary><code>top</code> timed-out</summary>
+
+</details>
+
+### Passed after retrying
+
+- ``we|ird`mod_*::get`` (after 3 attempts)
+"#
+        );
+    }
+
+    #[test]
+    fn a_run_without_tests_or_failures() {
+        assert_eq!(
+            rendered(vec![]),
+            "## bishin test results\n\nNo tests ran.\n"
+        );
+        let md = rendered(vec![result("a::b", Status::Passed)]);
+        assert!(md.ends_with("| a | 1 | 0 | 0 | 0.500s |\n"), "{md}");
+        assert!(!md.contains("###"), "{md}");
+    }
+
+    #[test]
+    fn a_shuffled_run_gives_its_seed() {
+        let summary = RunSummary {
+            results: vec![result("a::b", Status::Passed)],
+            seed: Some(42),
+            ..RunSummary::default()
+        };
+        let md = render(&summary, Duration::from_millis(1500));
+        assert!(
+            md.contains(
+                "1 test(s) in 1.50s: 1 passed.\n\nThe tests ran in the order of `--seed 42`.\n\n| Module"
+            ),
+            "{md}"
+        );
+    }
+
+    #[test]
+    fn only_the_end_of_long_output_is_shown() {
+        let stdout: Vec<_> = (1..=100).map(|line| format!("line {line}")).collect();
+        let md = rendered(vec![JobResult {
+            stdout: stdout.join("\n"),
+            ..result("noisy", Status::Failed)
+        }]);
+        assert!(
+            md.contains("stdout, the last 30 lines:\n\n```\nline 71\n"),
+            "{md}"
+        );
+        assert!(md.contains("line 100\n```\n"), "{md}");
+        assert!(!md.contains("line 70\n"), "{md}");
+    }
+
+    #[test]
+    fn code_spans_hold_any_backticks() {
+        assert_eq!(code("plain"), "`plain`");
+        assert_eq!(code("a`b"), "``a`b``");
+        assert_eq!(code("`edge``"), "``` `edge`` ```");
+        assert_eq!(cell("a|b\\c"), "a\\|b\\\\c");
+        assert_eq!(longest_backtick_run("a ``` b `` c"), 3);
+        assert_eq!(longest_backtick_run(""), 0);
+    }
+}
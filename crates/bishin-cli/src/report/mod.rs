@@ -0,0 +1,408 @@
+//! Reports of a run written to files, for CI systems and other tools.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use bishin_config::ReportsConfig;
+use bishin_jobs::{JobResult, RunSummary};
+
+use crate::timings::Timings;
+
+mod html;
+mod json;
+mod json_read;
+mod jsonl;
+mod junit;
+mod markdown;
+
+pub use html::render as render_html;
+pub use json_read::read_summary;
+
+/// Something that writes a report as a run goes.
+pub trait Reporter {
+    /// Called once before any test runs, with how many will.
+    fn start(&mut self, _tests: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called with each result as its test finishes.
+    fn result(&mut self, _result: &JobResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called with the result of the setup or teardown script once it has
+    /// run.
+    fn hook(&mut self, _result: &JobResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once every test has finished.
+    fn finish(&mut self, summary: &RunSummary, timings: &Timings) -> io::Result<()>;
+}
+
+/// A reporter that sees every test's name with a prefix, such as which
+/// shard of a CI job ran it. The setup and teardown scripts keep their names.
+pub struct Prefixed {
+    prefix: String,
+    inner: Box<dyn Reporter>,
+}
+
+impl Prefixed {
+    pub fn new(prefix: &str, inner: Box<dyn Reporter>) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            inner,
+        }
+    }
+
+    fn prefixed(&self, result: &JobResult) -> JobResult {
+        JobResult {
+            name: format!("{}{}", self.prefix, result.name),
+            ..result.clone()
+        }
+    }
+}
+
+impl Reporter for Prefixed {
+    fn start(&mut self, tests: usize) -> io::Result<()> {
+        self.inner.start(tests)
+    }
+
+    fn result(&mut self, result: &JobResult) -> io::Result<()> {
+        let result = self.prefixed(result);
+        self.inner.result(&result)
+    }
+
+    fn hook(&mut self, result: &JobResult) -> io::Result<()> {
+        self.inner.hook(result)
+    }
+
+    fn finish(&mut self, summary: &RunSummary, timings: &Timings) -> io::Result<()> {
+        let summary = RunSummary {
+            results: summary
+                .results
+                .iter()
+                .map(|result| self.prefixed(result))
+                .collect(),
+            hooks: summary.hooks.clone(),
+            filtered: summary
+                .filtered
+                .iter()
+                .map(|name| format!("{}{name}", self.prefix))
+                .collect(),
+            seed: summary.seed,
+        };
+        self.inner.finish(&summary, timings)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportKind {
+    Junit,
+    Json,
+    JsonlEvents,
+    Html,
+    Markdown,
+}
+
+impl ReportKind {
+    pub const ALL: [ReportKind; 5] = [
+        ReportKind::Junit,
+        ReportKind::Json,
+        ReportKind::JsonlEvents,
+        ReportKind::Html,
+        ReportKind::Markdown,
+    ];
+
+    /// The name used for the report in the config and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            ReportKind::Junit => "junit",
+            ReportKind::Json => "json",
+            ReportKind::JsonlEvents => "jsonl-events",
+            ReportKind::Html => "html",
+            ReportKind::Markdown => "markdown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+/// A report to write, and where to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub kind: ReportKind,
+    pub path: PathBuf,
+}
+
+impl Report {
+    /// Parse a `--report KIND=PATH` argument.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (kind, path) = value
+            .split_once('=')
+            .ok_or_else(|| format!("{value:?} isn't of the form KIND=PATH"))?;
+        if path.is_empty() {
+            return Err(format!("no path given for the {kind} report"));
+        }
+        Ok(Self {
+            kind: parse_kind(kind)?,
+            path: PathBuf::from(path),
+        })
+    }
+
+    /// Create the file for the report, and its parent directories, and a
+    /// reporter that writes to it.
+    pub fn open(&self) -> anyhow::Result<Box<dyn Reporter>> {
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let file = File::create(&self.path)
+            .with_context(|| format!("failed to create {}", self.path.display()))?;
+        let out = BufWriter::new(file);
+        Ok(match self.kind {
+            ReportKind::Junit => Box::new(junit::JunitReporter::new(&self.path)),
+            ReportKind::Json => Box::new(json::JsonReporter::new(out)),
+            ReportKind::JsonlEvents => Box::new(jsonl::JsonlReporter::new(out)),
+            ReportKind::Html => Box::new(html::HtmlReporter::new(out)),
+            ReportKind::Markdown => Box::new(markdown::MarkdownReporter::new(out)),
+        })
+    }
+}
+
+/// Parse the KIND of `--no-report KIND`.
+pub fn parse_kind(name: &str) -> Result<ReportKind, String> {
+    ReportKind::from_name(name).ok_or_else(|| {
+        let names: Vec<_> = ReportKind::ALL.iter().map(|kind| kind.name()).collect();
+        format!(
+            "unknown report {name:?}, expected one of {}",
+            names.join(", ")
+        )
+    })
+}
+
+/// The reports a run writes: the configured ones, except those of the
+/// `disabled` kinds, followed by those `added` on the command line.
+///
+/// `config` is [`Config::resolved_reports`](bishin_config::Config::resolved_reports),
+/// so that its paths are relative to the config file.
+pub fn reports(config: &ReportsConfig, added: &[Report], disabled: &[ReportKind]) -> Vec<Report> {
+    let configured = config.iter().filter_map(|(name, path)| {
+        let kind = ReportKind::from_name(name).expect("config reports are all known kinds");
+        (!disabled.contains(&kind)).then(|| Report {
+            kind,
+            path: path.to_path_buf(),
+        })
+    });
+    configured.chain(added.iter().cloned()).collect()
+}
+
+/// The file GitHub Actions shows the Markdown of on a job's summary page,
+/// which a run appends a [`ReportKind::Markdown`] report to.
+pub const STEP_SUMMARY_ENV: &str = "GITHUB_STEP_SUMMARY";
+
+/// Open a reporter for each of the [`reports`] a run writes, and one that
+/// appends to the [`STEP_SUMMARY_ENV`] file if it's set and Markdown reports
+/// aren't disabled.
+pub fn reporters(
+    config: &ReportsConfig,
+    added: &[Report],
+    disabled: &[ReportKind],
+) -> anyhow::Result<Vec<Box<dyn Reporter>>> {
+    let mut reporters = reports(config, added, disabled)
+        .iter()
+        .map(Report::open)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let step_summary = std::env::var_os(STEP_SUMMARY_ENV).filter(|path| !path.is_empty());
+    if let Some(path) = step_summary.filter(|_| !disabled.contains(&ReportKind::Markdown)) {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", Path::new(&path).display()))?;
+        reporters.push(Box::new(markdown::MarkdownReporter::new(BufWriter::new(
+            file,
+        ))));
+    }
+    Ok(reporters)
+}
+
+/// The module a test is in by its full name, or `""` for one at the top
+/// level.
+fn module_name(name: &str) -> &str {
+    name.rsplit_once("::").map_or("", |(module, _)| module)
+}
+
+/// The time a test took in seconds, as reports give it.
+fn seconds(result: &JobResult) -> f64 {
+    result.duration.as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{project, scratch_dir};
+
+    fn report(kind: ReportKind, path: &str) -> Report {
+        Report {
+            kind,
+            path: PathBuf::from(path),
+        }
+    }
+
+    fn junit_and_json() -> ReportsConfig {
+        ReportsConfig {
+            junit: Some("reports/junit.xml".into()),
+            json: Some("reports/results.json".into()),
+            ..ReportsConfig::default()
+        }
+    }
+
+    #[test]
+    fn configured_reports_come_first() {
+        let added = [report(ReportKind::Html, "out.html")];
+        assert_eq!(
+            reports(&junit_and_json(), &added, &[]),
+            [
+                report(ReportKind::Junit, "reports/junit.xml"),
+                report(ReportKind::Json, "reports/results.json"),
+                report(ReportKind::Html, "out.html"),
+            ]
+        );
+        assert_eq!(reports(&ReportsConfig::default(), &[], &[]), []);
+    }
+
+    #[test]
+    fn configured_reports_are_relative_to_the_config_file() {
+        let (config, dir) = project("[reports]\njunit = \"out/junit.xml\"\n", &[]);
+        let added = [report(ReportKind::Html, "out.html")];
+        assert_eq!(
+            reports(&config.resolved_reports(), &added, &[]),
+            [
+                Report {
+                    kind: ReportKind::Junit,
+                    path: dir.join("out/junit.xml"),
+                },
+                // Those given on the command line are relative to the current
+                // directory.
+                report(ReportKind::Html, "out.html"),
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_reports_are_only_those_of_the_config() {
+        let added = [report(ReportKind::Junit, "other.xml")];
+        assert_eq!(
+            reports(&junit_and_json(), &added, &[ReportKind::Junit]),
+            [
+                report(ReportKind::Json, "reports/results.json"),
+                report(ReportKind::Junit, "other.xml"),
+            ]
+        );
+        let all = ReportKind::ALL;
+        assert_eq!(reports(&junit_and_json(), &[], &all), []);
+    }
+
+    #[test]
+    fn report_arguments() {
+        assert_eq!(
+            Report::parse("jsonl-events=a=b.jsonl"),
+            Ok(report(ReportKind::JsonlEvents, "a=b.jsonl"))
+        );
+        assert_eq!(
+            Report::parse("junit"),
+            Err("\"junit\" isn't of the form KIND=PATH".to_string())
+        );
+        assert_eq!(
+            Report::parse("junit="),
+            Err("no path given for the junit report".to_string())
+        );
+        assert_eq!(
+            Report::parse("tap=x"),
+            Err(
+                "unknown report \"tap\", expected one of junit, json, jsonl-events, html, markdown"
+                    .to_string()
+            )
+        );
+        for kind in ReportKind::ALL {
+            assert_eq!(parse_kind(kind.name()), Ok(kind));
+        }
+    }
+
+    #[test]
+    fn opening_a_report_creates_its_directories() {
+        let dir = scratch_dir();
+        for kind in ReportKind::ALL {
+            let path = dir.join(kind.name()).join("nested/report");
+            let mut reporter = Report {
+                kind,
+                path: path.clone(),
+            }
+            .open()
+            .unwrap();
+            reporter
+                .finish(&RunSummary::default(), &Timings::default())
+                .unwrap();
+            drop(reporter);
+            assert!(path.is_file(), "{}", path.display());
+        }
+        // A file where a directory should be.
+        let blocked = dir.join("file");
+        std::fs::write(&blocked, "").unwrap();
+        let report = Report {
+            kind: ReportKind::Json,
+            path: blocked.join("results.json"),
+        };
+        assert!(report.open().is_err());
+    }
+
+    #[test]
+    fn prefixed_names_reach_the_junit_report() {
+        let scratch = scratch_dir();
+        let path = scratch.join("junit.xml");
+        let junit = Report {
+            kind: ReportKind::Junit,
+            path: path.clone(),
+        }
+        .open()
+        .unwrap();
+        let mut reporter = Prefixed::new("shard-2::", junit);
+        let result = JobResult {
+            status: bishin_jobs::Status::Passed,
+            ..JobResult::error("net::dns::resolves", "")
+        };
+        let hook = JobResult {
+            status: bishin_jobs::Status::Passed,
+            ..JobResult::error("setup", "")
+        };
+        reporter.start(1).unwrap();
+        reporter.hook(&hook).unwrap();
+        reporter.result(&result).unwrap();
+        let summary = RunSummary {
+            results: vec![result],
+            hooks: vec![hook],
+            ..RunSummary::default()
+        };
+        reporter.finish(&summary, &Timings::default()).unwrap();
+        drop(reporter);
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            report.contains(r#"<testcase name="resolves" classname="shard-2::net::dns""#),
+            "{report}"
+        );
+        // The hooks are the run's own, whichever shard it is.
+        assert!(
+            report.contains(r#"<testcase name="setup" classname="bishin-hooks""#),
+            "{report}"
+        );
+    }
+}
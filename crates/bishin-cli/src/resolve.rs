@@ -0,0 +1,697 @@
+//! Resolution of settings that can come from a flag, the environment, or the
+//! config file, in that order of precedence.
+//!
+//! [`Settings`] does this for every setting at once, and remembers where each
+//! value came from so that a run can say why it's using it.
+
+use std::fmt;
+use std::thread::available_parallelism;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use bishin_config::{parse_jobs, Config, SnapshotUpdate, TagDefaults};
+use bishin_generate::{GenerateOptions, ScriptOptions, SnapshotOptions};
+use bishin_parser::duration::format_duration;
+use bishin_parser::Shell;
+
+use crate::select::TagFilter;
+
+/// Overrides the `jobs` config setting, accepting a number or `auto`.
+pub const JOBS_ENV: &str = "BISHIN_JOBS";
+
+/// Selects a config profile when `--profile` isn't given.
+pub const PROFILE_ENV: &str = "BISHIN_PROFILE";
+
+/// The config profile to apply, if any.
+pub fn profile(flag: Option<String>, env: Option<String>) -> Option<String> {
+    flag.or(env).filter(|profile| !profile.is_empty())
+}
+
+/// The tag filter for a run, and the part of it that's left of the config's
+/// defaults.
+///
+/// Giving `--tag` replaces the defaults altogether, since it says exactly
+/// which tests to run, so `--tag network` runs the network tests even if
+/// `default-exclude` has `network` or any of their other tags. Without it,
+/// `--exclude-tag` adds to `default-exclude`.
+pub fn tags(
+    include: &[String],
+    exclude: &[String],
+    defaults: &TagDefaults,
+) -> (TagFilter, TagFilter) {
+    let from_defaults = match include {
+        [] => TagFilter {
+            include: defaults.default_include.clone(),
+            exclude: defaults.default_exclude.clone(),
+        },
+        _ => TagFilter::default(),
+    };
+    let mut filter = from_defaults.clone();
+    filter.include.extend(include.iter().cloned());
+    for tag in exclude {
+        if !filter.exclude.contains(tag) {
+            filter.exclude.push(tag.clone());
+        }
+    }
+    (filter, from_defaults)
+}
+
+/// A source's say on when to stop a run early: whether to stop at the first
+/// failure, and how many failures to allow otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StopPolicy {
+    pub fail_fast: Option<bool>,
+    pub max_failures: Option<u32>,
+}
+
+/// How many failures to stop the run after, or `None` to run every test.
+///
+/// The flags are taken as a whole over the config, so `--max-failures 5`
+/// overrides `fail-fast = true` in the config. If one source sets both,
+/// fail-fast wins, since stopping at the first failure is what it asked for
+/// either way. A `max-failures` of 0 means there's no limit.
+fn max_failures(flags: StopPolicy, config: StopPolicy) -> Setting<Option<usize>> {
+    let (policy, source) = if flags != StopPolicy::default() {
+        let flag = match flags.fail_fast {
+            Some(true) => "--fail-fast",
+            _ => "--max-failures",
+        };
+        (flags, Source::Flag(flag))
+    } else if config != StopPolicy::default() {
+        (config, Source::Config)
+    } else {
+        return Setting::default_value(None);
+    };
+    let value = match policy {
+        StopPolicy {
+            fail_fast: Some(true),
+            ..
+        } => Some(1),
+        StopPolicy {
+            max_failures: Some(max),
+            ..
+        } if max > 0 => Some(max as usize),
+        _ => None,
+    };
+    Setting { value, source }
+}
+
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// Nothing set it.
+    Default,
+    /// The config file, or the profile applied to it.
+    Config,
+    /// The environment variable of this name.
+    Env(&'static str),
+    /// The command line flag of this name.
+    Flag(&'static str),
+    /// The [`Runner`](crate::Runner) method of this name, called by the
+    /// program running the tests.
+    Runner(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::Config => f.write_str("config"),
+            Source::Env(name) => write!(f, "${name}"),
+            Source::Flag(name) => f.write_str(name),
+            Source::Runner(name) => write!(f, "Runner::{name}"),
+        }
+    }
+}
+
+/// The effective value of a setting and where it came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    /// `value`, as set by the [`Runner`](crate::Runner) method `method`.
+    pub(crate) fn runner(value: T, method: &'static str) -> Self {
+        Self {
+            value,
+            source: Source::Runner(method),
+        }
+    }
+
+    fn default_value(value: T) -> Self {
+        Self {
+            value,
+            source: Source::Default,
+        }
+    }
+
+    /// The value of the first of `layers` that has one, or `default`.
+    fn layered(layers: impl IntoIterator<Item = (Option<T>, Source)>, default: T) -> Self {
+        layers
+            .into_iter()
+            .find_map(|(value, source)| {
+                Some(Self {
+                    value: value?,
+                    source,
+                })
+            })
+            .unwrap_or_else(|| Self::default_value(default))
+    }
+}
+
+impl<T> Setting<Option<T>> {
+    /// Like [`Setting::layered`], for settings that can be left unset.
+    fn optional(layers: impl IntoIterator<Item = (Option<T>, Source)>) -> Self {
+        let layers = layers
+            .into_iter()
+            .map(|(value, source)| (value.map(Some), source));
+        Self::layered(layers, None)
+    }
+}
+
+/// The settings that the environment can override.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvOverrides {
+    /// [`JOBS_ENV`], unparsed.
+    pub jobs: Option<String>,
+}
+
+impl EnvOverrides {
+    pub fn from_env() -> Self {
+        Self {
+            jobs: std::env::var(JOBS_ENV).ok(),
+        }
+    }
+}
+
+/// The settings that command line flags can override.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CliOverrides {
+    pub jobs: Option<usize>,
+    pub min_pass_rate: Option<f64>,
+    pub retries: Option<u32>,
+    pub stop: StopPolicy,
+    pub update_snapshots: bool,
+}
+
+/// The settings a run uses, after applying every source of overrides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// The number of tests to run at once, one per CPU unless something
+    /// sets it, or sets it to `auto`.
+    pub jobs: Setting<usize>,
+    pub timeout: Setting<Option<Duration>>,
+    pub min_pass_rate: Setting<Option<f64>>,
+    pub retries: Setting<Option<u32>>,
+    /// How many failures to stop the run after, or `None` to run every test.
+    pub max_failures: Setting<Option<usize>>,
+    pub keep_tmp_on_failure: Setting<bool>,
+    /// The shell that tests run with, for the operating system that bishin
+    /// is running on.
+    pub shell: Setting<Shell>,
+    pub snapshot_update: Setting<SnapshotUpdate>,
+}
+
+impl Settings {
+    /// Resolve every setting for a run on the operating system `os`, named
+    /// as in `std::env::consts::OS`.
+    pub fn resolve(
+        config: &Config,
+        env: &EnvOverrides,
+        cli: &CliOverrides,
+        os: &str,
+    ) -> anyhow::Result<Self> {
+        let env_jobs = match &env.jobs {
+            // `auto` is as good as a number here, it still overrides the
+            // config.
+            Some(jobs) => Some(
+                parse_jobs(jobs)
+                    .map_err(|err| anyhow!("{JOBS_ENV}: {err}"))?
+                    .unwrap_or_else(cpus),
+            ),
+            None => None,
+        };
+        Ok(Self {
+            jobs: Setting::layered(
+                [
+                    (cli.jobs, Source::Flag("--jobs")),
+                    (env_jobs, Source::Env(JOBS_ENV)),
+                    (config.jobs, Source::Config),
+                ],
+                cpus(),
+            ),
+            timeout: Setting::optional([(config.timeout, Source::Config)]),
+            min_pass_rate: Setting::optional([
+                (cli.min_pass_rate, Source::Flag("--min-pass-rate")),
+                (config.min_pass_rate, Source::Config),
+            ]),
+            retries: Setting::optional([
+                (cli.retries, Source::Flag("--retries")),
+                (config.retries, Source::Config),
+            ]),
+            max_failures: max_failures(
+                cli.stop,
+                StopPolicy {
+                    fail_fast: config.fail_fast,
+                    max_failures: config.max_failures,
+                },
+            ),
+            keep_tmp_on_failure: Setting::layered(
+                [(config.keep_tmp_on_failure.then_some(true), Source::Config)],
+                false,
+            ),
+            shell: Setting::layered(
+                [(config.platform_shell.get(os).copied(), Source::Config)],
+                Shell::default(),
+            ),
+            snapshot_update: Setting::layered(
+                [
+                    (
+                        cli.update_snapshots.then_some(SnapshotUpdate::Always),
+                        Source::Flag("--update-snapshots"),
+                    ),
+                    (config.snapshots.update, Source::Config),
+                ],
+                SnapshotUpdate::default(),
+            ),
+        })
+    }
+
+    /// The generation settings, with the environment of the tests from
+    /// `config`.
+    pub fn generate_options(&self, config: &Config) -> GenerateOptions {
+        GenerateOptions {
+            env: config.env.clone(),
+            clean_env: config
+                .env_policy
+                .clean
+                .then(|| config.env_policy.allow.clone()),
+            timeout: self.timeout.value,
+            retries: self.retries.value,
+            shell: self.shell.value,
+            script: ScriptOptions {
+                errexit: config.script.errexit,
+                nounset: config.script.nounset,
+                pipefail: config.script.pipefail,
+                xtrace: config.script.xtrace,
+            },
+            snapshots: Some(SnapshotOptions {
+                dir: config.resolved_snapshot_dir(),
+                update: match self.snapshot_update.value {
+                    SnapshotUpdate::Never => bishin_jobs::SnapshotUpdate::Never,
+                    SnapshotUpdate::Missing => bishin_jobs::SnapshotUpdate::Missing,
+                    SnapshotUpdate::Always => bishin_jobs::SnapshotUpdate::Always,
+                },
+            }),
+            ..GenerateOptions::default()
+        }
+    }
+
+    /// Each setting's config key, its value as shown to people, and where it
+    /// came from.
+    pub fn provenance(&self) -> Vec<(&'static str, String, Source)> {
+        let row = |key, value: String, source| (key, value, source);
+        let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+        vec![
+            row("jobs", self.jobs.value.to_string(), self.jobs.source),
+            row(
+                "timeout",
+                or_none(self.timeout.value.map(format_duration)),
+                self.timeout.source,
+            ),
+            row(
+                "min-pass-rate",
+                or_none(self.min_pass_rate.value.map(|rate| rate.to_string())),
+                self.min_pass_rate.source,
+            ),
+            row(
+                "retries",
+                self.retries.value.unwrap_or(0).to_string(),
+                self.retries.source,
+            ),
+            row(
+                "max-failures",
+                or_none(self.max_failures.value.map(|max| max.to_string())),
+                self.max_failures.source,
+            ),
+            row(
+                "keep-tmp-on-failure",
+                self.keep_tmp_on_failure.value.to_string(),
+                self.keep_tmp_on_failure.source,
+            ),
+            row("shell", self.shell.value.to_string(), self.shell.source),
+            row(
+                "snapshots.update",
+                self.snapshot_update.value.to_string(),
+                self.snapshot_update.source,
+            ),
+        ]
+    }
+}
+
+/// One job per CPU.
+fn cpus() -> usize {
+    available_parallelism().map_or(1, usize::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn resolve(config: &Config, env: Option<&str>, cli: &CliOverrides) -> Settings {
+        let env = EnvOverrides {
+            jobs: env.map(str::to_string),
+        };
+        Settings::resolve(config, &env, cli, "linux").unwrap()
+    }
+
+    fn with_jobs(jobs: Option<usize>) -> Config {
+        let mut config = Config::default();
+        config.jobs = jobs;
+        config
+    }
+
+    #[test]
+    fn jobs_flag_wins_over_env_and_config() {
+        let cli = CliOverrides {
+            jobs: Some(3),
+            ..CliOverrides::default()
+        };
+        let jobs = resolve(&with_jobs(Some(5)), Some("7"), &cli).jobs;
+        assert_eq!(jobs.value, 3);
+        assert_eq!(jobs.source, Source::Flag("--jobs"));
+    }
+
+    #[test]
+    fn jobs_env_wins_over_config() {
+        let cli = CliOverrides::default();
+        let jobs = resolve(&with_jobs(Some(5)), Some("7"), &cli).jobs;
+        assert_eq!(jobs.value, 7);
+        assert_eq!(jobs.source, Source::Env(JOBS_ENV));
+        let jobs = resolve(&with_jobs(Some(5)), Some("auto"), &cli).jobs;
+        assert_eq!(jobs.value, cpus());
+        assert_eq!(jobs.source, Source::Env(JOBS_ENV));
+    }
+
+    #[test]
+    fn jobs_come_from_config_then_the_cpus() {
+        let cli = CliOverrides::default();
+        let jobs = resolve(&with_jobs(Some(5)), None, &cli).jobs;
+        assert_eq!((jobs.value, jobs.source), (5, Source::Config));
+        let jobs = resolve(&with_jobs(None), None, &cli).jobs;
+        assert_eq!((jobs.value, jobs.source), (cpus(), Source::Default));
+    }
+
+    #[test]
+    fn invalid_jobs_env_is_an_error() {
+        for jobs in ["0", "lots"] {
+            let env = EnvOverrides {
+                jobs: Some(jobs.to_string()),
+            };
+            let err =
+                Settings::resolve(&Config::default(), &env, &CliOverrides::default(), "linux")
+                    .unwrap_err();
+            assert!(err.to_string().starts_with(JOBS_ENV), "{err}");
+        }
+    }
+
+    fn stop(fail_fast: Option<bool>, max_failures: Option<u32>) -> StopPolicy {
+        StopPolicy {
+            fail_fast,
+            max_failures,
+        }
+    }
+
+    #[test]
+    fn stop_policy_of_one_source() {
+        let cases = [
+            (stop(None, None), None),
+            (stop(Some(true), None), Some(1)),
+            (stop(Some(false), None), None),
+            (stop(None, Some(5)), Some(5)),
+            (stop(None, Some(0)), None),
+            // Fail-fast wins over max-failures from the same source.
+            (stop(Some(true), Some(5)), Some(1)),
+            (stop(Some(true), Some(0)), Some(1)),
+            (stop(Some(false), Some(5)), Some(5)),
+        ];
+        for (config, expected) in cases {
+            let setting = max_failures(StopPolicy::default(), config);
+            assert_eq!(setting.value, expected, "{config:?}");
+            let expected_source = if config == StopPolicy::default() {
+                Source::Default
+            } else {
+                Source::Config
+            };
+            assert_eq!(setting.source, expected_source, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn stop_flags_override_the_config_as_a_whole() {
+        let setting = max_failures(stop(None, Some(5)), stop(Some(true), None));
+        assert_eq!(setting.value, Some(5));
+        assert_eq!(setting.source, Source::Flag("--max-failures"));
+
+        let setting = max_failures(stop(Some(true), None), stop(None, Some(5)));
+        assert_eq!(setting.value, Some(1));
+        assert_eq!(setting.source, Source::Flag("--fail-fast"));
+
+        // `--max-failures 0` takes back the config's limit.
+        let setting = max_failures(stop(None, Some(0)), stop(None, Some(5)));
+        assert_eq!(setting.value, None);
+        assert_eq!(setting.source, Source::Flag("--max-failures"));
+    }
+
+    #[test]
+    fn stop_policy_of_the_config_is_resolved() {
+        let mut config = Config::default();
+        config.fail_fast = Some(true);
+        config.max_failures = Some(3);
+        let settings = resolve(&config, None, &CliOverrides::default());
+        assert_eq!(settings.max_failures.value, Some(1));
+        assert_eq!(settings.max_failures.source, Source::Config);
+    }
+
+    #[test]
+    fn retries_flag_wins_over_config() {
+        let mut config = Config::default();
+        config.retries = Some(2);
+        let retries = resolve(&config, None, &CliOverrides::default()).retries;
+        assert_eq!((retries.value, retries.source), (Some(2), Source::Config));
+        let cli = CliOverrides {
+            retries: Some(0),
+            ..CliOverrides::default()
+        };
+        let retries = resolve(&config, None, &cli).retries;
+        assert_eq!(
+            (retries.value, retries.source),
+            (Some(0), Source::Flag("--retries"))
+        );
+        let retries = resolve(&Config::default(), None, &CliOverrides::default()).retries;
+        assert_eq!((retries.value, retries.source), (None, Source::Default));
+    }
+
+    fn strings(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(ToString::to_string).collect()
+    }
+
+    fn filter(include: &[&str], exclude: &[&str]) -> TagFilter {
+        TagFilter {
+            include: strings(include),
+            exclude: strings(exclude),
+        }
+    }
+
+    fn network_and_slow() -> TagDefaults {
+        TagDefaults {
+            default_include: Vec::new(),
+            default_exclude: strings(&["network", "slow"]),
+        }
+    }
+
+    #[test]
+    fn default_tags_apply_without_flags() {
+        let (filtered, from_defaults) = tags(&[], &[], &network_and_slow());
+        assert_eq!(filtered, filter(&[], &["network", "slow"]));
+        assert_eq!(from_defaults, filtered);
+    }
+
+    #[test]
+    fn excluded_tags_add_to_the_defaults() {
+        let (filtered, from_defaults) = tags(&[], &strings(&["slow", "gpu"]), &network_and_slow());
+        assert_eq!(filtered, filter(&[], &["network", "slow", "gpu"]));
+        assert_eq!(from_defaults, filter(&[], &["network", "slow"]));
+    }
+
+    #[test]
+    fn included_tags_replace_the_defaults() {
+        let (filtered, from_defaults) = tags(&strings(&["network"]), &[], &network_and_slow());
+        assert_eq!(filtered, filter(&["network"], &[]));
+        assert!(from_defaults.is_empty());
+        // So a network test that's also slow runs.
+        assert!(filtered.matches(&strings(&["network", "slow"])));
+
+        let defaults = TagDefaults {
+            default_include: strings(&["unit"]),
+            default_exclude: Vec::new(),
+        };
+        let (filtered, _) = tags(&strings(&["e2e"]), &strings(&["flaky"]), &defaults);
+        assert_eq!(filtered, filter(&["e2e"], &["flaky"]));
+    }
+
+    #[test]
+    fn the_shell_is_the_one_for_the_os() {
+        let mut config = Config::default();
+        config.platform_shell = BTreeMap::from([
+            ("macos".to_string(), Shell::Zsh),
+            ("linux".to_string(), Shell::Bash),
+            ("freebsd".to_string(), Shell::Sh),
+        ]);
+        let shell = |os| {
+            Settings::resolve(
+                &config,
+                &EnvOverrides::default(),
+                &CliOverrides::default(),
+                os,
+            )
+            .unwrap()
+            .shell
+        };
+        assert_eq!(shell("macos").value, Shell::Zsh);
+        assert_eq!(shell("macos").source, Source::Config);
+        assert_eq!(shell("linux").value, Shell::Bash);
+        assert_eq!(shell("freebsd").value, Shell::Sh);
+        // Any that isn't listed gets the default.
+        assert_eq!(shell("openbsd").value, Shell::Bash);
+        assert_eq!(shell("openbsd").source, Source::Default);
+    }
+
+    #[test]
+    fn every_setting_has_a_default() {
+        let settings = resolve(&Config::default(), None, &CliOverrides::default());
+        let provenance: Vec<_> = settings
+            .provenance()
+            .into_iter()
+            .map(|(key, value, source)| (key, value, source.to_string()))
+            .collect();
+        let row = |key, value: &str| (key, value.to_string(), "default".to_string());
+        assert_eq!(
+            provenance,
+            [
+                row("jobs", &cpus().to_string()),
+                row("timeout", "none"),
+                row("min-pass-rate", "none"),
+                row("retries", "0"),
+                row("max-failures", "none"),
+                row("keep-tmp-on-failure", "false"),
+                row("shell", "bash"),
+                row("snapshots.update", "never"),
+            ]
+        );
+    }
+
+    /// A config that sets every setting that a config can.
+    fn configured() -> Config {
+        let mut config = Config::default();
+        config.jobs = Some(2);
+        config.timeout = Some(Duration::from_secs(90));
+        config.min_pass_rate = Some(0.5);
+        config.retries = Some(1);
+        config.max_failures = Some(4);
+        config.keep_tmp_on_failure = true;
+        config.platform_shell = BTreeMap::from([("linux".to_string(), Shell::Zsh)]);
+        config.snapshots.update = Some(SnapshotUpdate::Missing);
+        config
+    }
+
+    #[test]
+    fn every_setting_comes_from_the_config() {
+        let settings = resolve(&configured(), None, &CliOverrides::default());
+        let provenance = settings.provenance();
+        let row = |key, value: &str| (key, value.to_string(), Source::Config);
+        assert_eq!(
+            provenance,
+            [
+                row("jobs", "2"),
+                row("timeout", "90s"),
+                row("min-pass-rate", "0.5"),
+                row("retries", "1"),
+                row("max-failures", "4"),
+                row("keep-tmp-on-failure", "true"),
+                row("shell", "zsh"),
+                row("snapshots.update", "missing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_flag_wins_over_the_config() {
+        let cli = CliOverrides {
+            jobs: Some(8),
+            min_pass_rate: Some(0.75),
+            retries: Some(3),
+            stop: stop(Some(true), None),
+            update_snapshots: true,
+        };
+        let provenance: Vec<_> = resolve(&configured(), Some("6"), &cli)
+            .provenance()
+            .into_iter()
+            .filter(|(_, _, source)| *source != Source::Config)
+            .collect();
+        assert_eq!(
+            provenance,
+            [
+                ("jobs", "8".to_string(), Source::Flag("--jobs")),
+                (
+                    "min-pass-rate",
+                    "0.75".to_string(),
+                    Source::Flag("--min-pass-rate")
+                ),
+                ("retries", "3".to_string(), Source::Flag("--retries")),
+                ("max-failures", "1".to_string(), Source::Flag("--fail-fast")),
+                (
+                    "snapshots.update",
+                    "always".to_string(),
+                    Source::Flag("--update-snapshots")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sources_are_shown_as_where_to_change_them() {
+        let shown = [
+            Source::Default,
+            Source::Config,
+            Source::Env(JOBS_ENV),
+            Source::Flag("--jobs"),
+            Source::Runner("jobs"),
+        ]
+        .map(|source| source.to_string());
+        assert_eq!(
+            shown,
+            [
+                "default",
+                "config",
+                "$BISHIN_JOBS",
+                "--jobs",
+                "Runner::jobs"
+            ]
+        );
+    }
+
+    #[test]
+    fn the_profile_flag_wins_over_the_env() {
+        let some = |profile: &str| Some(profile.to_string());
+        assert_eq!(profile(some("ci"), some("local")), some("ci"));
+        assert_eq!(profile(None, some("local")), some("local"));
+        // An empty variable is as good as an unset one.
+        assert_eq!(profile(None, some("")), None);
+        assert_eq!(profile(None, None), None);
+    }
+}
@@ -0,0 +1,1771 @@
+//! Running a suite: collecting and generating the tests that a filter
+//! selects, then running them with the setup and teardown scripts around
+//! them and reporting each result as it comes.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use bishin_collect::{load_test_roots, load_tests_with, CollectOptions, ModuleGraph};
+use bishin_config::Config;
+use bishin_generate::coverage::{trace_path, Coverage};
+use bishin_generate::trace::split_trace;
+use bishin_generate::{
+    check_distinct_scripts, generate_module, parse_module, test_ids, GenerateOptions, TestJob,
+};
+use bishin_jobs::{Executor, History, Job, JobResult, RunSummary, Status};
+use bishin_parser::{Flaky, Shell};
+
+use crate::artifacts;
+use crate::lock::WorkDirLock;
+use crate::redact::Redactor;
+use crate::report::Reporter;
+use crate::resolve::{CliOverrides, EnvOverrides, Setting, Settings};
+use crate::select::TestFilter;
+use crate::timings::{Phase, Timings};
+
+/// Runs the tests of a config, with the settings that its builder methods
+/// override.
+pub struct Runner {
+    config: Config,
+    settings: Settings,
+    filter: TestFilter,
+    reporters: Vec<Box<dyn Reporter>>,
+    command_report: bool,
+    coverage: bool,
+    resource_stats: bool,
+    strict_processes: bool,
+    wait: bool,
+    strict_xfail: bool,
+    fail_on_skip: bool,
+    history: History,
+    within: Option<Duration>,
+    shuffle: Option<u64>,
+    timings: Timings,
+}
+
+/// The tests that a run would run, with their scripts written to the work
+/// directory. The work directory stays locked until the plan is dropped or
+/// run, so that another run can't write over the scripts in between.
+pub struct RunPlan {
+    pub tests: Vec<TestJob>,
+    /// Whether the failures of each test marked `@flaky` count, by name.
+    pub quarantine: BTreeMap<String, Quarantine>,
+    /// The tests that were left out to fit in [`Runner::within`]'s budget.
+    pub over_budget: Vec<String>,
+    /// The tests that the filter left out.
+    pub filtered: Vec<String>,
+    /// What's worth warning about, such as `[script]` options the shell
+    /// doesn't have, for the caller to show as it sees fit.
+    pub warnings: Vec<String>,
+    timings: Timings,
+    _lock: WorkDirLock,
+}
+
+/// How a run went.
+#[derive(Debug)]
+pub struct RunOutcome {
+    /// The results of the tests that ran and of the setup and teardown
+    /// scripts.
+    pub summary: RunSummary,
+    pub timings: Timings,
+    /// Whether the setup script failed, in which case no tests ran.
+    pub setup_failed: bool,
+    pub teardown_failed: bool,
+    /// How many tests weren't run because the run stopped early.
+    pub not_run: usize,
+    /// The temp directories kept for failed tests, by test name.
+    pub kept_dirs: Vec<(String, PathBuf)>,
+    /// The files and directories that tests listed with `@artifact`, copied
+    /// to the report directory, by test name.
+    pub artifacts: Vec<(String, PathBuf)>,
+    /// The lines of `set -x` output that the tests wrote, if
+    /// [`Runner::command_report`] asked for them.
+    pub trace: Vec<String>,
+    /// Which lines of the tests' bodies ran, if [`Runner::coverage`] asked
+    /// for it.
+    pub coverage: Option<Coverage>,
+    /// Whether the failures of each test marked `@flaky` count, by name.
+    pub quarantine: BTreeMap<String, Quarantine>,
+    /// What's worth warning about, such as artifacts that tests didn't
+    /// leave, after whatever is still in the plan's `warnings`.
+    pub warnings: Vec<String>,
+    min_pass_rate: Option<f64>,
+    strict_xfail: bool,
+    fail_on_skip: bool,
+}
+
+/// Whether the failures of a test marked `@flaky` count towards the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quarantine {
+    /// They don't.
+    Quarantined,
+    /// They do, since it passed this many recorded runs in a row, as many as
+    /// `flaky-promote-after` asks for.
+    Promoted(u32),
+    /// They do, since it failed in this percentage of its recorded runs,
+    /// more than its `@flaky` allows.
+    TooFlaky(u8),
+}
+
+impl Quarantine {
+    /// How `@flaky` test `name` stands, by how it did in `history`.
+    fn of(name: &str, flaky: &Flaky, history: &History, promote_after: Option<u32>) -> Self {
+        let passes: Vec<bool> = history
+            .test(name)
+            .filter_map(|(_, test)| match test.status {
+                Status::Passed => Some(true),
+                status if status.is_failure() => Some(false),
+                _ => None,
+            })
+            .collect();
+        if let Some(after) = promote_after {
+            let recent = passes
+                .len()
+                .checked_sub(after as usize)
+                .map(|start| &passes[start..]);
+            if recent.is_some_and(|recent| recent.iter().all(|passed| *passed)) {
+                return Quarantine::Promoted(after);
+            }
+        }
+        if let (Some(max), false) = (flaky.max_failure_percent, passes.is_empty()) {
+            let failures = passes.iter().filter(|passed| !**passed).count();
+            let percent = (failures * 100 / passes.len()) as u8;
+            if percent > max {
+                return Quarantine::TooFlaky(percent);
+            }
+        }
+        Quarantine::Quarantined
+    }
+}
+
+impl Runner {
+    /// A runner with the settings from `config` alone, which runs every test
+    /// and writes no reports.
+    pub fn new(config: Config) -> Self {
+        let settings = Settings::resolve(
+            &config,
+            &EnvOverrides::default(),
+            &CliOverrides::default(),
+            std::env::consts::OS,
+        )
+        .expect("only the environment's settings can be invalid");
+        Self::with_settings(config, settings)
+    }
+
+    /// A runner with `settings` already resolved from `config` and whatever
+    /// else overrides it.
+    pub fn with_settings(config: Config, settings: Settings) -> Self {
+        Self {
+            config,
+            settings,
+            filter: TestFilter::default(),
+            reporters: Vec::new(),
+            command_report: false,
+            coverage: false,
+            resource_stats: false,
+            strict_processes: false,
+            wait: false,
+            strict_xfail: false,
+            fail_on_skip: false,
+            history: History::default(),
+            within: None,
+            shuffle: None,
+            timings: Timings::default(),
+        }
+    }
+
+    /// Only run the tests that `filter` selects.
+    pub fn filter(mut self, filter: TestFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Run `jobs` tests at once.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.settings.jobs = Setting::runner(jobs.max(1), "jobs");
+        self
+    }
+
+    /// Time out tests that don't set `@timeout` after `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.settings.timeout = Setting::runner(Some(timeout), "timeout");
+        self
+    }
+
+    /// Retry failed tests that don't set `@retries` this many times.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.settings.retries = Setting::runner(Some(retries), "retries");
+        self
+    }
+
+    /// Stop starting tests after this many failures, or never if `None`.
+    pub fn max_failures(mut self, max_failures: Option<usize>) -> Self {
+        self.settings.max_failures = Setting::runner(max_failures, "max_failures");
+        self
+    }
+
+    /// Count the run as passed as long as at least this fraction of the
+    /// tests pass.
+    pub fn min_pass_rate(mut self, min_pass_rate: Option<f64>) -> Self {
+        self.settings.min_pass_rate = Setting::runner(min_pass_rate, "min_pass_rate");
+        self
+    }
+
+    /// Also give each result to `reporter`, after the reporters added
+    /// before it.
+    pub fn reporter(mut self, reporter: Box<dyn Reporter>) -> Self {
+        self.reporters.push(reporter);
+        self
+    }
+
+    /// Trace the tests, keeping the trace out of their stderr and in
+    /// [`RunOutcome::trace`].
+    pub fn command_report(mut self, command_report: bool) -> Self {
+        self.command_report = command_report;
+        self
+    }
+
+    /// Trace which lines of the tests' bodies run, for
+    /// [`RunOutcome::coverage`].
+    pub fn coverage(mut self, coverage: bool) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    /// Measure the CPU time and peak memory of each test.
+    pub fn resource_stats(mut self, resource_stats: bool) -> Self {
+        self.resource_stats = resource_stats;
+        self
+    }
+
+    /// Fail tests that leave processes running in their process group,
+    /// instead of only warning about them.
+    pub fn strict_processes(mut self, strict_processes: bool) -> Self {
+        self.strict_processes = strict_processes;
+        self
+    }
+
+    /// If another run holds the work directory, wait for it instead of
+    /// failing.
+    pub fn wait(mut self, wait: bool) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Fail the run if a test marked `@xfail` passed.
+    pub fn strict_xfail(mut self, strict_xfail: bool) -> Self {
+        self.strict_xfail = strict_xfail;
+        self
+    }
+
+    /// Fail the run if any test was skipped.
+    pub fn fail_on_skip(mut self, fail_on_skip: bool) -> Self {
+        self.fail_on_skip = fail_on_skip;
+        self
+    }
+
+    /// Decide whether `@flaky` tests stay quarantined by how they did in
+    /// `history`, see [`Quarantine`]. Without it they all do.
+    pub fn history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Only run the tests that the history says fit in `budget` between
+    /// them, the fastest first. Tests the history doesn't have a duration for
+    /// are left out, unless it has none at all, in which case every test
+    /// runs.
+    pub fn within(mut self, budget: Option<Duration>) -> Self {
+        self.within = budget;
+        self
+    }
+
+    /// Run the tests in an order shuffled by `seed`, which is the same
+    /// order for the same seed and tests.
+    pub fn shuffle(mut self, seed: Option<u64>) -> Self {
+        self.shuffle = seed;
+        self
+    }
+
+    /// Add the phases of the run to `timings`, e.g. after the time it took
+    /// to load the config.
+    pub fn timings(mut self, timings: Timings) -> Self {
+        self.timings = timings;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// How the scripts are generated, from the settings.
+    pub fn generate_options(&self) -> GenerateOptions {
+        GenerateOptions {
+            trace: self.command_report,
+            coverage: self
+                .coverage
+                .then(|| self.config.resolved_work_dir().join("coverage")),
+            resource_stats: self.resource_stats,
+            strict_processes: self.strict_processes,
+            ..self.settings.generate_options(&self.config)
+        }
+    }
+
+    /// Lock the work directory, and collect and generate the tests that the
+    /// filter selects.
+    ///
+    /// ```
+    /// # let dir = std::env::temp_dir().join(format!("bishin-doc-plan-{}", std::process::id()));
+    /// # std::fs::create_dir_all(dir.join("tests"))?;
+    /// # std::fs::write(dir.join("bishin.toml"), "")?;
+    /// # std::fs::write(dir.join("tests/net.b"), "@test a {\n    true\n}\n@test b {\n    true\n}\n")?;
+    /// let runner = bishin::Runner::new(bishin::Config::load(&dir.join("bishin.toml"))?);
+    /// let plan = runner.plan()?;
+    /// let ids: Vec<_> = plan.tests.iter().map(|test| test.id.as_str()).collect();
+    /// assert_eq!(ids, ["net::a", "net::b"]);
+    /// // Nothing has run yet, and the plan runs as it is.
+    /// let outcome = runner.execute(plan)?;
+    /// assert_eq!(outcome.summary.results.len(), 2);
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn plan(&self) -> anyhow::Result<RunPlan> {
+        let lock = WorkDirLock::acquire(&self.config.resolved_work_dir(), self.wait)?;
+        let mut timings = self.timings.clone();
+        let mut warnings = unsupported_options(&self.generate_options());
+        let (mut tests, filtered) = generate(
+            &self.config,
+            &self.generate_options(),
+            &self.filter,
+            &mut timings,
+        )?;
+        let mut over_budget = Vec::new();
+        match self.within {
+            Some(_) if self.history.runs.is_empty() => warnings.push(
+                "there's no history of how long tests take, so none are left out to fit in the time budget"
+                    .to_string(),
+            ),
+            Some(budget) => (tests, over_budget) = within_budget(tests, budget, &self.history),
+            None => {}
+        }
+        if let Some(seed) = self.shuffle {
+            shuffle(&mut tests, seed);
+        }
+        let quarantine = tests
+            .iter()
+            .filter_map(|test_job| {
+                let flaky = test_job.flaky.as_ref()?;
+                let quarantine = Quarantine::of(
+                    &test_job.id,
+                    flaky,
+                    &self.history,
+                    self.config.flaky_promote_after,
+                );
+                Some((test_job.id.clone(), quarantine))
+            })
+            .collect();
+        Ok(RunPlan {
+            tests,
+            quarantine,
+            over_budget,
+            filtered,
+            warnings,
+            timings,
+            _lock: lock,
+        })
+    }
+
+    /// Plan the run and run it.
+    pub fn run(self) -> anyhow::Result<RunOutcome> {
+        let plan = self.plan()?;
+        self.execute(plan)
+    }
+
+    /// Run the tests in `plan`, between the setup and teardown scripts.
+    pub fn execute(mut self, plan: RunPlan) -> anyhow::Result<RunOutcome> {
+        let RunPlan {
+            tests,
+            quarantine,
+            filtered,
+            mut warnings,
+            mut timings,
+            _lock,
+            ..
+        } = plan;
+        let config = &self.config;
+        let settings = &self.settings;
+        let patterns: Vec<_> = tests
+            .iter()
+            .map(|test_job| artifacts::patterns(&test_job.id, &test_job.artifacts, &mut warnings))
+            .collect();
+        let scripts: Vec<_> = tests
+            .iter()
+            .map(|test_job| test_job.script.clone())
+            .collect();
+        let jobs: Vec<Job> = tests.into_iter().map(|test_job| test_job.job).collect();
+        let report_dir = config.resolved_report_dir();
+        let redactor = Redactor::new(&config.redact).context("invalid redact pattern")?;
+        let reporters = &mut self.reporters;
+        for reporter in reporters.iter_mut() {
+            reporter.start(jobs.len())?;
+        }
+        // Reporters can't fail the run from inside the executor's callback,
+        // so the first error waits until it's done.
+        let mut report_error = None;
+        let command_report = self.command_report;
+        // Trace lines are taken out of stderr so that they don't drown out
+        // what the test itself wrote there.
+        let untrace = |mut result: JobResult| {
+            let mut trace = Vec::new();
+            if command_report {
+                (result.stderr, trace) = split_trace(&result.stderr);
+            }
+            redactor.redact_result(&mut result);
+            (result, trace)
+        };
+        let setup = run_hook(
+            config,
+            "setup-script",
+            config.resolved_setup_script().as_deref(),
+            &redactor,
+            reporters,
+            &mut report_error,
+        );
+        let setup_failed = setup
+            .as_ref()
+            .is_some_and(|result| result.status != Status::Passed);
+        let mut kept_dirs = Vec::new();
+        let mut collected = Vec::new();
+        let results = timings.time(Phase::Execution, || {
+            if setup_failed {
+                return Vec::new();
+            }
+            Executor::new(settings.jobs.value)
+                .max_failures(settings.max_failures.value)
+                .run(&jobs, |index, result| {
+                    let untraced = untrace(result.clone()).0;
+                    for reporter in reporters.iter_mut() {
+                        if let Err(err) = reporter.result(&untraced) {
+                            report_error.get_or_insert(err);
+                        }
+                    }
+                    let job = &jobs[index];
+                    if let Some(tmp_dir) = &job.tmp_dir {
+                        let dest = report_dir.join(job.name.replace("::", "/"));
+                        collected.extend(
+                            artifacts::collect(
+                                &job.name,
+                                tmp_dir,
+                                &patterns[index],
+                                &dest,
+                                &mut warnings,
+                            )
+                            .into_iter()
+                            .map(|copy| (job.name.clone(), copy)),
+                        );
+                    }
+                    let failed = result.status.is_failure();
+                    match &job.tmp_dir {
+                        Some(tmp_dir) if failed && settings.keep_tmp_on_failure.value => {
+                            kept_dirs.push((job.name.clone(), tmp_dir.clone()));
+                        }
+                        _ => job.remove_dirs(),
+                    }
+                })
+        });
+        let teardown = run_hook(
+            config,
+            "teardown-script",
+            config.resolved_teardown_script().as_deref(),
+            &redactor,
+            reporters,
+            &mut report_error,
+        );
+        let teardown_failed = teardown
+            .as_ref()
+            .is_some_and(|result| result.status != Status::Passed);
+        let mut summary = RunSummary {
+            hooks: setup.into_iter().chain(teardown).collect(),
+            filtered,
+            seed: self.shuffle,
+            ..RunSummary::default()
+        };
+        let mut trace = Vec::new();
+        for result in results {
+            let (result, job_trace) = untrace(result);
+            summary.results.push(result);
+            trace.extend(job_trace);
+        }
+
+        if let Some(err) = report_error {
+            return Err(anyhow::Error::new(err).context("failed to write a report"));
+        }
+        for reporter in reporters.iter_mut() {
+            reporter
+                .finish(&summary, &timings)
+                .context("failed to write a report")?;
+        }
+        let not_run = if setup_failed {
+            0
+        } else {
+            jobs.len() - summary.results.len()
+        };
+        Ok(RunOutcome {
+            summary,
+            timings,
+            setup_failed,
+            teardown_failed,
+            not_run,
+            kept_dirs,
+            artifacts: collected,
+            trace,
+            coverage: self
+                .generate_options()
+                .coverage
+                .map(|dir| measure_coverage(&dir, &scripts)),
+            quarantine,
+            warnings,
+            min_pass_rate: settings.min_pass_rate.value,
+            strict_xfail: self.strict_xfail,
+            fail_on_skip: self.fail_on_skip,
+        })
+    }
+}
+
+impl RunOutcome {
+    /// Whether the run failed only because a test was skipped, which
+    /// [`Runner::fail_on_skip`] doesn't allow.
+    pub fn skips_failed(&self) -> bool {
+        self.fail_on_skip && self.summary.skipped().next().is_some()
+    }
+
+    /// Whether the run as a whole succeeded.
+    ///
+    /// Without a minimum pass rate every test that wasn't skipped has to
+    /// pass, otherwise enough of them do. Quarantined tests are left out
+    /// either way, and the setup and teardown scripts have to pass.
+    pub fn passed(&self) -> bool {
+        if self.setup_failed || self.teardown_failed || self.skips_failed() {
+            return false;
+        }
+        let counted = RunSummary {
+            results: self
+                .summary
+                .results
+                .iter()
+                .filter(|result| !self.is_quarantined(&result.name))
+                .cloned()
+                .collect(),
+            ..RunSummary::default()
+        };
+        if self.strict_xfail && counted.count(Status::XPass) > 0 {
+            return false;
+        }
+        match self.min_pass_rate {
+            Some(rate) => counted.pass_rate() >= rate,
+            None => counted.results.iter().all(|r| !r.status.is_failure()),
+        }
+    }
+
+    /// Whether the failures of the test called `name` don't count.
+    pub fn is_quarantined(&self, name: &str) -> bool {
+        self.quarantine.get(name) == Some(&Quarantine::Quarantined)
+    }
+
+    pub fn min_pass_rate(&self) -> Option<f64> {
+        self.min_pass_rate
+    }
+}
+
+/// Run the setup or teardown script at `script`, if there is one, reporting
+/// its result as it would a test's.
+fn run_hook(
+    config: &Config,
+    name: &str,
+    script: Option<&Path>,
+    redactor: &Redactor,
+    reporters: &mut [Box<dyn Reporter>],
+    report_error: &mut Option<io::Error>,
+) -> Option<JobResult> {
+    let script = script?;
+    let job = Job {
+        name: name.to_string(),
+        // A relative path with no directory in it would be looked up in
+        // $PATH instead.
+        args: vec![std::path::absolute(script)
+            .unwrap_or_else(|_| script.to_path_buf())
+            .to_string_lossy()
+            .into_owned()],
+        envs: config.env.clone(),
+        // The hooks set things up for the tests, so they see everything.
+        clean_env: None,
+        timeout: None,
+        dir: None,
+        tmp_dir: None,
+        retry: None,
+        snapshot: None,
+        xfail: None,
+        skip: None,
+        expected_exit: None,
+        bench: None,
+        resource_stats: false,
+        stdin: None,
+        ssh: None,
+        resources: Vec::new(),
+        leak_check: None,
+        tty: false,
+        skip_if_missing: false,
+    };
+    let mut result = job
+        .run()
+        .unwrap_or_else(|err| JobResult::from_error(name, &err));
+    redactor.redact_result(&mut result);
+    for reporter in reporters {
+        if let Err(err) = reporter.hook(&result) {
+            report_error.get_or_insert(err);
+        }
+    }
+    Some(result)
+}
+
+/// Shuffle `items` with a Fisher-Yates shuffle driven by SplitMix64 from
+/// `seed`, which doesn't change with the platform or Rust version.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// The `tests` whose mean durations in `history` fit in `budget` between
+/// them, taking the fastest first, and the names of the rest.
+fn within_budget(
+    tests: Vec<TestJob>,
+    budget: Duration,
+    history: &History,
+) -> (Vec<TestJob>, Vec<String>) {
+    let mut expected: Vec<_> = tests
+        .iter()
+        .enumerate()
+        .filter_map(|(index, test_job)| Some((history.mean_duration(&test_job.id)?, index)))
+        .collect();
+    expected.sort();
+    let mut fits = vec![false; tests.len()];
+    let mut total = Duration::ZERO;
+    for (duration, index) in expected {
+        total += duration;
+        if total > budget {
+            break;
+        }
+        fits[index] = true;
+    }
+    let (selected, left_out): (Vec<_>, Vec<_>) =
+        tests.into_iter().zip(fits).partition(|(_, fits)| *fits);
+    (
+        selected.into_iter().map(|(test_job, _)| test_job).collect(),
+        left_out
+            .into_iter()
+            .map(|(test_job, _)| test_job.id)
+            .collect(),
+    )
+}
+
+/// The coverage of the tests whose scripts are at `scripts`, from the
+/// traces they left in `dir`. A test that didn't run has no trace, so none of
+/// its lines ran.
+fn measure_coverage(dir: &Path, scripts: &[PathBuf]) -> Coverage {
+    let mut coverage = Coverage::default();
+    for script in scripts {
+        let Ok(contents) = std::fs::read_to_string(script) else {
+            continue;
+        };
+        let trace = std::fs::read_to_string(trace_path(dir, script)).unwrap_or_default();
+        let name = script.file_name().unwrap_or_default().to_string_lossy();
+        coverage.add(&contents, &name, &trace);
+    }
+    coverage
+}
+
+/// Collect and parse the tests, writing scripts for those that `filter`
+/// selects to the work directory.
+fn generate(
+    config: &Config,
+    options: &GenerateOptions,
+    filter: &TestFilter,
+    timings: &mut Timings,
+) -> anyhow::Result<(Vec<TestJob>, Vec<String>)> {
+    let work_dir = config.resolved_work_dir();
+    std::fs::create_dir_all(&work_dir)
+        .with_context(|| format!("failed to create work directory {}", work_dir.display()))?;
+    if let Some(dir) = &options.coverage {
+        // Traces are appended to, so those of an earlier run have to go.
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)
+                .with_context(|| format!("failed to clear coverage traces in {}", dir.display()))?;
+        }
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create directory {}", dir.display()))?;
+    }
+    let graph = timings.time(Phase::Collection, || collect(config))?;
+    let mut test_jobs = Vec::new();
+    let mut filtered = Vec::new();
+    for module in graph.iter_leaf_modules() {
+        let mut tests = timings.time(Phase::Parsing, || parse_module(module))?;
+        tests.retain(|test| test.bench.is_some() == filter.benches);
+        tests.retain(|test| {
+            let ids = test_ids(module, test);
+            let selected = ids.iter().any(|id| filter.matches(id, &test.tags));
+            if !selected {
+                filtered.extend(ids);
+            }
+            selected
+        });
+        let mut jobs = timings.time(Phase::Generation, || {
+            generate_module(module, &tests, &work_dir, options)
+        })?;
+        // A test run in several shells is selected by the ID of each.
+        jobs.retain(|test_job| {
+            let selected = filter.matches(&test_job.id, &test_job.tags);
+            if !selected {
+                filtered.push(test_job.id.clone());
+            }
+            selected
+        });
+        test_jobs.extend(jobs);
+    }
+    check_distinct_scripts(&test_jobs)?;
+    Ok((test_jobs, filtered))
+}
+
+/// Collect the test files from the config's test directories.
+pub fn collect(config: &Config) -> anyhow::Result<ModuleGraph> {
+    let collect_options = CollectOptions {
+        extensions: config.file_extensions.clone(),
+        ignore: config.ignore.clone(),
+        respect_bishinignore: true,
+        respect_gitignore: config.respect_gitignore,
+    };
+    let test_dirs = config.resolved_test_dirs();
+    Ok(if config.test_dirs.is_empty() {
+        load_tests_with(&test_dirs[0], &collect_options)?
+    } else {
+        load_test_roots(&test_dirs, &collect_options)?
+    })
+}
+
+/// Warnings about the configured `[script]` options that the shell doesn't
+/// have, since the scripts leave them out, and about coverage it can't trace.
+pub fn unsupported_options(options: &GenerateOptions) -> Vec<String> {
+    let mut warnings: Vec<_> = options
+        .script
+        .unsupported(options.shell)
+        .into_iter()
+        .map(|option| {
+            format!(
+                "{} has no {option} option, so [script] {option} is ignored",
+                options.shell
+            )
+        })
+        .collect();
+    if options.coverage.is_some() && options.shell != Shell::Bash {
+        warnings.push(format!(
+            "{} can't trace the lines it runs to a file, so only bash tests are covered",
+            options.shell
+        ));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use bishin_jobs::SkipReason;
+
+    use super::*;
+    use crate::testing::{project, scratch_dir, write_files, ScratchDir};
+    use crate::NamePattern;
+
+    fn outcome(statuses: &[Status], min_pass_rate: Option<f64>) -> RunOutcome {
+        RunOutcome {
+            summary: RunSummary {
+                results: statuses
+                    .iter()
+                    .enumerate()
+                    .map(|(index, status)| JobResult {
+                        status: *status,
+                        ..JobResult::error(format!("t{index}"), "")
+                    })
+                    .collect(),
+                ..RunSummary::default()
+            },
+            timings: Timings::default(),
+            setup_failed: false,
+            teardown_failed: false,
+            not_run: 0,
+            kept_dirs: Vec::new(),
+            artifacts: Vec::new(),
+            trace: Vec::new(),
+            coverage: None,
+            quarantine: BTreeMap::new(),
+            warnings: Vec::new(),
+            min_pass_rate,
+            strict_xfail: false,
+            fail_on_skip: false,
+        }
+    }
+
+    const NINE_OF_TEN: [Status; 10] = [
+        Status::Passed,
+        Status::Passed,
+        Status::Passed,
+        Status::Passed,
+        Status::Passed,
+        Status::Passed,
+        Status::Passed,
+        Status::Passed,
+        Status::Passed,
+        Status::Failed,
+    ];
+
+    #[test]
+    fn any_failure_fails_without_a_minimum() {
+        assert!(!outcome(&NINE_OF_TEN, None).passed());
+        assert!(outcome(&[Status::Passed, Status::Skipped], None).passed());
+    }
+
+    #[test]
+    fn pass_rate_at_or_above_the_minimum_passes() {
+        assert!(outcome(&NINE_OF_TEN, Some(0.9)).passed());
+        assert!(outcome(&NINE_OF_TEN, Some(0.5)).passed());
+    }
+
+    #[test]
+    fn pass_rate_below_the_minimum_fails() {
+        assert!(!outcome(&NINE_OF_TEN, Some(0.95)).passed());
+        assert!(!outcome(&NINE_OF_TEN, Some(1.0)).passed());
+    }
+
+    #[test]
+    fn failed_setup_fails_whatever_the_pass_rate() {
+        let mut outcome = outcome(&[Status::Passed], Some(0.0));
+        outcome.setup_failed = true;
+        assert!(!outcome.passed());
+    }
+
+    #[test]
+    fn runs_time_each_phase() {
+        let mut timings = Timings::default();
+        let (config, _dir) = timings.time(Phase::ConfigLoad, || {
+            project("", &[("tests/phases.b", "@test a {\n    sleep 0.01\n}\n")])
+        });
+        let outcome = Runner::new(config).timings(timings).run().unwrap();
+        assert!(outcome.passed());
+        for (phase, duration) in outcome.timings.iter() {
+            assert!(duration > Duration::ZERO, "{phase} took no time");
+        }
+        assert!(outcome.timings.get(Phase::Execution) >= Duration::from_millis(10));
+        let json = outcome.timings.to_json();
+        for phase in Phase::ALL {
+            assert!(json.contains(&format!("\"{phase}\":")), "{json}");
+        }
+    }
+
+    #[test]
+    fn skips_only_fail_when_asked_to() {
+        let statuses = [Status::Passed, Status::Skipped];
+        assert!(outcome(&statuses, None).passed());
+        let mut strict = outcome(&statuses, None);
+        strict.fail_on_skip = true;
+        assert!(strict.skips_failed());
+        assert!(!strict.passed());
+        let mut no_skips = outcome(&[Status::Passed], None);
+        no_skips.fail_on_skip = true;
+        assert!(no_skips.passed());
+    }
+
+    /// The environment the test called `name` in `config` would run with.
+    fn environment(config: Config, name: &str) -> BTreeMap<String, String> {
+        let plan = Runner::new(config).plan().unwrap();
+        let test_job = plan
+            .tests
+            .iter()
+            .find(|test_job| test_job.id == name)
+            .unwrap();
+        test_job.job.environment()
+    }
+
+    #[test]
+    fn a_tests_environment_is_layered() {
+        let files = [("tests/env.b", "@env(SHARED=test)\n@test a {\n    true\n}\n")];
+        let (config, _dir) = project(
+            "[env]\nPATH = \"/config/bin\"\nSHARED = \"config\"\nONLY_CONFIG = \"1\"\n",
+            &files,
+        );
+        let inherited: BTreeMap<String, String> = std::env::vars().collect();
+        let environment = environment(config, "env::a");
+        assert_eq!(environment["PATH"], "/config/bin");
+        assert_eq!(environment["SHARED"], "test");
+        assert_eq!(environment["ONLY_CONFIG"], "1");
+        assert_eq!(environment["BISHIN_TEST_NAME"], "a");
+        assert!(environment.contains_key(bishin_jobs::TMPDIR_ENV));
+        for (key, value) in &inherited {
+            if !["PATH", "SHARED", "ONLY_CONFIG"].contains(&key.as_str()) {
+                assert_eq!(environment.get(key), Some(value), "{key}");
+            }
+        }
+    }
+
+    #[test]
+    fn a_clean_environment_only_inherits_what_is_allowed() {
+        let files = [("tests/env.b", "@test a {\n    true\n}\n")];
+        let (config, _dir) = project(
+            "[env]\nONLY_CONFIG = \"1\"\n[env-policy]\nclean = true\nallow = [\"PATH\"]\n",
+            &files,
+        );
+        let environment = environment(config, "env::a");
+        let mut expected = vec!["BISHIN_TEST_NAME", bishin_jobs::TMPDIR_ENV, "ONLY_CONFIG"];
+        if std::env::var_os("PATH").is_some() {
+            expected.push("PATH");
+        }
+        expected.sort();
+        assert_eq!(environment.keys().collect::<Vec<_>>(), expected);
+    }
+
+    /// The outcome of running `config`, and the temporary directory of each
+    /// test by name.
+    fn run_with_dirs(config: Config) -> (RunOutcome, BTreeMap<String, PathBuf>) {
+        let runner = Runner::new(config);
+        let plan = runner.plan().unwrap();
+        let tmp_dirs = plan
+            .tests
+            .iter()
+            .map(|test_job| (test_job.id.clone(), test_job.job.tmp_dir.clone().unwrap()))
+            .collect();
+        (runner.execute(plan).unwrap(), tmp_dirs)
+    }
+
+    const PASS_AND_FAIL: [(&str, &str); 1] = [(
+        "tests/tmp.b",
+        "@test passes {\n    touch \"$BISHIN_TMPDIR/file\"\n}\n@test fails {\n    touch \"$BISHIN_TMPDIR/file\"\n    exit 1\n}\n",
+    )];
+
+    #[test]
+    fn failed_tests_keep_their_tmp_dirs_when_asked_to() {
+        let (config, _dir) = project("keep-tmp-on-failure = true\n", &PASS_AND_FAIL);
+        let (outcome, tmp_dirs) = run_with_dirs(config);
+        assert_eq!(
+            outcome.kept_dirs,
+            [("tmp::fails".to_string(), tmp_dirs["tmp::fails"].clone())]
+        );
+        assert!(tmp_dirs["tmp::fails"].join("file").exists());
+        assert!(!tmp_dirs["tmp::passes"].exists());
+    }
+
+    #[test]
+    fn tmp_dirs_are_removed_by_default() {
+        let (config, _dir) = project("", &PASS_AND_FAIL);
+        let (outcome, tmp_dirs) = run_with_dirs(config);
+        assert!(outcome.kept_dirs.is_empty());
+        for tmp_dir in tmp_dirs.values() {
+            assert!(!tmp_dir.exists(), "{}", tmp_dir.display());
+        }
+    }
+
+    /// A project with `setup` and `teardown` as its hook scripts, which both
+    /// run in its directory, and a failing test if `fails` is set.
+    fn with_hooks(setup: &str, teardown: &str, fails: bool) -> (Config, ScratchDir) {
+        let test = if fails { "false" } else { "true" };
+        let (config, dir) = project(
+            "setup-script = \"setup.sh\"\nteardown-script = \"teardown.sh\"\n",
+            &[
+                (
+                    "setup.sh",
+                    &format!("#!/bin/sh\ncd \"$(dirname \"$0\")\"\n{setup}"),
+                ),
+                (
+                    "teardown.sh",
+                    &format!("#!/bin/sh\ncd \"$(dirname \"$0\")\"\n{teardown}"),
+                ),
+                ("tests/hooks.b", &format!("@test a {{\n    {test}\n}}\n")),
+            ],
+        );
+        for script in ["setup.sh", "teardown.sh"] {
+            let path = config.dir().join(script);
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        (config, dir)
+    }
+
+    const TOUCH_TORN_DOWN: &str = "echo tearing down\ntouch torn-down\n";
+
+    #[test]
+    fn a_failed_setup_runs_no_tests_but_still_tears_down() {
+        let (config, _dir) = with_hooks("echo not today >&2\nexit 2\n", TOUCH_TORN_DOWN, false);
+        let dir = config.dir().to_path_buf();
+        let outcome = Runner::new(config).run().unwrap();
+        assert!(outcome.setup_failed);
+        assert!(!outcome.teardown_failed);
+        assert!(!outcome.passed());
+        assert!(outcome.summary.results.is_empty());
+        assert!(dir.join("torn-down").exists());
+        let hooks: Vec<_> = outcome
+            .summary
+            .hooks
+            .iter()
+            .map(|hook| (hook.name.as_str(), hook.status, hook.exit_code))
+            .collect();
+        assert_eq!(
+            hooks,
+            [
+                ("setup-script", Status::Failed, Some(2)),
+                ("teardown-script", Status::Passed, Some(0)),
+            ]
+        );
+        assert_eq!(outcome.summary.hooks[0].stderr, "not today\n");
+        assert_eq!(outcome.summary.hooks[1].stdout, "tearing down\n");
+    }
+
+    #[test]
+    fn teardown_runs_after_failed_tests() {
+        let (config, _dir) = with_hooks("", TOUCH_TORN_DOWN, true);
+        let dir = config.dir().to_path_buf();
+        let outcome = Runner::new(config).run().unwrap();
+        assert!(!outcome.setup_failed);
+        assert_eq!(outcome.summary.results[0].status, Status::Failed);
+        assert!(dir.join("torn-down").exists());
+    }
+
+    #[test]
+    fn a_setup_script_can_leave_a_service_running() {
+        let (config, _dir) = with_hooks("sleep 8 &\necho \"$!\" > service.pid\n", "", false);
+        let dir = config.dir().to_path_buf();
+        let start = std::time::Instant::now();
+        let outcome = Runner::new(config).run().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(outcome.passed());
+        let pid = std::fs::read_to_string(dir.join("service.pid")).unwrap();
+        let pid: libc::pid_t = pid.trim().parse().unwrap();
+        assert_eq!(unsafe { libc::kill(pid, libc::SIGKILL) }, 0);
+    }
+
+    #[test]
+    fn unexpected_passes_only_fail_under_strict_xfail() {
+        let statuses = [Status::Passed, Status::XFail, Status::XPass];
+        assert!(outcome(&statuses, None).passed());
+        let mut strict = outcome(&statuses, None);
+        strict.strict_xfail = true;
+        assert!(!strict.passed());
+        let mut expected = outcome(&[Status::Passed, Status::XFail], None);
+        expected.strict_xfail = true;
+        assert!(expected.passed());
+    }
+
+    #[test]
+    fn xfail_tests_run_as_expected_failures() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/xfail.b",
+                "@xfail(\"bug #12\")\n@test fails {\n    false\n}\n@xfail\n@test passes {\n    true\n}\n",
+            )],
+        );
+        let outcome = Runner::new(config.clone()).run().unwrap();
+        let statuses: Vec<_> = outcome
+            .summary
+            .results
+            .iter()
+            .map(|result| (result.name.as_str(), result.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("xfail::fails", Status::XFail),
+                ("xfail::passes", Status::XPass)
+            ]
+        );
+        assert!(outcome.passed());
+        assert!(!Runner::new(config)
+            .strict_xfail(true)
+            .run()
+            .unwrap()
+            .passed());
+    }
+
+    #[test]
+    fn options_the_shell_lacks_are_warned_about() {
+        let options = |shell| GenerateOptions {
+            shell,
+            script: bishin_generate::ScriptOptions {
+                errexit: true,
+                nounset: false,
+                pipefail: true,
+                xtrace: false,
+            },
+            ..GenerateOptions::default()
+        };
+        assert!(unsupported_options(&options(Shell::Bash)).is_empty());
+        assert_eq!(
+            unsupported_options(&options(Shell::Fish)),
+            [
+                "fish has no errexit option, so [script] errexit is ignored",
+                "fish has no pipefail option, so [script] pipefail is ignored",
+            ]
+        );
+        let coverage = GenerateOptions {
+            coverage: Some(PathBuf::from("coverage")),
+            ..options(Shell::Sh)
+        };
+        assert_eq!(
+            unsupported_options(&coverage),
+            [
+                "sh has no pipefail option, so [script] pipefail is ignored",
+                "sh can't trace the lines it runs to a file, so only bash tests are covered",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_test_sees_the_environment_it_was_given() {
+        let scratch = crate::testing::scratch_dir();
+        let dump = scratch.join("env.txt");
+        let (config, _dir) = project(
+            &format!(
+                "[env]\nSHARED = \"config\"\nONLY_CONFIG = \"1\"\nDUMP = {:?}\n[env-policy]\nclean = true\nallow = [\"PATH\", \"SHARED\"]\n",
+                dump.display().to_string()
+            ),
+            &[(
+                "tests/env.b",
+                "@env(SHARED=test)\n@test dump {\n    env > \"$DUMP\"\n}\n",
+            )],
+        );
+        let outcome = Runner::new(config).run().unwrap();
+        assert!(outcome.passed(), "{:?}", outcome.summary.results);
+        let dumped = std::fs::read_to_string(dump).unwrap();
+        let environment: BTreeMap<_, _> = dumped
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .collect();
+        assert_eq!(environment["SHARED"], "test");
+        assert_eq!(environment["ONLY_CONFIG"], "1");
+        assert_eq!(environment["BISHIN_TEST_NAME"], "dump");
+        assert!(environment.contains_key("PATH"));
+        assert!(!environment.contains_key("HOME"), "{dumped}");
+    }
+
+    /// Run the `@snapshot` test of a project with `snapshots` as its
+    /// `[snapshots] update`, once it prints each of `outputs`, with
+    /// `--update-snapshots` if `flag` is set. Gives each run's status and the
+    /// snapshot after it, if there is one.
+    fn snapshot_runs(
+        snapshots: &str,
+        flag: bool,
+        outputs: &[&str],
+    ) -> Vec<(Status, Option<String>)> {
+        let scratch = crate::testing::scratch_dir();
+        let output_file = scratch.join("output.txt");
+        let (config, _dir) = project(
+            &format!(
+                "[env]\nOUTPUT = {:?}\n[snapshots]\ndir = \"snaps\"\nupdate = \"{snapshots}\"\n",
+                output_file.display().to_string()
+            ),
+            &[(
+                "tests/snap.b",
+                "@snapshot\n@test prints {\n    cat \"$OUTPUT\"\n}\n",
+            )],
+        );
+        let cli = CliOverrides {
+            update_snapshots: flag,
+            ..CliOverrides::default()
+        };
+        let settings = Settings::resolve(&config, &EnvOverrides::default(), &cli, "linux").unwrap();
+        let snapshot = config.dir().join("snaps/snap/prints.snap");
+        outputs
+            .iter()
+            .map(|output| {
+                std::fs::write(&output_file, output).unwrap();
+                let runner = Runner::with_settings(config.clone(), settings.clone());
+                let outcome = runner.run().unwrap();
+                (
+                    outcome.summary.results[0].status,
+                    std::fs::read_to_string(&snapshot).ok(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snapshots_are_only_recorded_when_the_policy_says() {
+        let recorded = |output: &str| Some(output.to_string());
+        assert_eq!(
+            snapshot_runs("never", false, &["a\n"]),
+            [(Status::Failed, None)]
+        );
+        assert_eq!(
+            snapshot_runs("missing", false, &["a\n", "a\n", "b\n"]),
+            [
+                (Status::Passed, recorded("a\n")),
+                (Status::Passed, recorded("a\n")),
+                (Status::Failed, recorded("a\n")),
+            ]
+        );
+        assert_eq!(
+            snapshot_runs("always", false, &["a\n", "b\n"]),
+            [
+                (Status::Passed, recorded("a\n")),
+                (Status::Passed, recorded("b\n")),
+            ]
+        );
+        // The flag records whatever the config says.
+        assert_eq!(
+            snapshot_runs("never", true, &["a\n", "b\n"]),
+            [
+                (Status::Passed, recorded("a\n")),
+                (Status::Passed, recorded("b\n")),
+            ]
+        );
+    }
+
+    const FLAKY: [(&str, &str); 1] = [(
+        "tests/flaky.b",
+        "@flaky\n@test sometimes {\n    false\n}\n@test always {\n    true\n}\n",
+    )];
+
+    #[test]
+    fn a_quarantined_flaky_test_failing_does_not_fail_the_run() {
+        let (config, _dir) = project("", &FLAKY);
+        let outcome = Runner::new(config).run().unwrap();
+        assert!(outcome.is_quarantined("flaky::sometimes"));
+        assert!(!outcome.is_quarantined("flaky::always"));
+        assert_eq!(outcome.summary.count(Status::Failed), 1);
+        assert!(outcome.passed());
+    }
+
+    /// A history of `flaky::sometimes` with `statuses`, oldest first.
+    fn history_of(statuses: &[Status]) -> History {
+        let runs = statuses
+            .iter()
+            .enumerate()
+            .map(|(index, status)| bishin_jobs::RunRecord {
+                id: index.to_string(),
+                timestamp: index as u64,
+                git_sha: None,
+                tests: vec![bishin_jobs::TestRecord {
+                    name: "flaky::sometimes".to_string(),
+                    status: *status,
+                    duration: Duration::from_millis(1),
+                }],
+            })
+            .collect();
+        History { runs }
+    }
+
+    #[test]
+    fn a_flaky_test_that_kept_passing_is_promoted() {
+        let (config, _dir) = project("flaky-promote-after = 2\n", &FLAKY);
+        let history = history_of(&[Status::Failed, Status::Passed, Status::Passed]);
+        let outcome = Runner::new(config).history(history).run().unwrap();
+        assert_eq!(
+            outcome.quarantine["flaky::sometimes"],
+            Quarantine::Promoted(2)
+        );
+        assert!(!outcome.passed());
+    }
+
+    #[test]
+    fn quarantine_follows_the_history() {
+        let quarantine = |percent: Option<u8>, statuses: &[Status], after: Option<u32>| {
+            let flaky = Flaky {
+                max_failure_percent: percent,
+            };
+            Quarantine::of("flaky::sometimes", &flaky, &history_of(statuses), after)
+        };
+        let (pass, fail, skip) = (Status::Passed, Status::Failed, Status::Skipped);
+        assert_eq!(quarantine(None, &[], Some(2)), Quarantine::Quarantined);
+        assert_eq!(quarantine(None, &[pass], Some(2)), Quarantine::Quarantined);
+        assert_eq!(
+            quarantine(None, &[fail, pass, pass], Some(2)),
+            Quarantine::Promoted(2)
+        );
+        // Skips say nothing either way.
+        assert_eq!(
+            quarantine(None, &[pass, skip, pass, skip], Some(2)),
+            Quarantine::Promoted(2)
+        );
+        assert_eq!(
+            quarantine(None, &[pass, fail], Some(2)),
+            Quarantine::Quarantined
+        );
+        assert_eq!(
+            quarantine(Some(20), &[pass, fail], None),
+            Quarantine::TooFlaky(50)
+        );
+        assert_eq!(
+            quarantine(Some(50), &[pass, fail], None),
+            Quarantine::Quarantined
+        );
+        assert_eq!(quarantine(Some(0), &[], None), Quarantine::Quarantined);
+    }
+
+    #[test]
+    fn benchmarks_only_run_when_asked_for() {
+        let files = [(
+            "tests/cli.b",
+            "@bench(iterations = 3, warmup = 1)\n@test startup {\n    true\n}\n@test help {\n    true\n}\n",
+        )];
+        let names = |benches: bool| {
+            let filter = TestFilter {
+                benches,
+                ..TestFilter::default()
+            };
+            let (config, _dir) = project("", &files);
+            let outcome = Runner::new(config).filter(filter).run().unwrap();
+            outcome
+                .summary
+                .results
+                .into_iter()
+                .map(|result| (result.name, result.bench.map(|stats| stats.times.len())))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(false), [("cli::help".to_string(), None)]);
+        assert_eq!(names(true), [("cli::startup".to_string(), Some(3))]);
+    }
+
+    #[test]
+    fn artifacts_are_collected_into_the_report_dir() {
+        let files = [(
+            "tests/art.b",
+            "@artifact(\"logs/*.log\")\n@artifact(\"screenshot.png\")\n@test fails {\n    cd \"$BISHIN_TMPDIR\" || exit 1\n    mkdir logs\n    echo started > logs/server.log\n    exit 1\n}\n",
+        )];
+        let (config, _dir) = project("", &files);
+        let dest = config.resolved_report_dir().join("art/fails");
+        write_files(&dest, &[("stale.log", "from an earlier run")]);
+        let (outcome, tmp_dirs) = run_with_dirs(config);
+        let copy = dest.join("logs/server.log");
+        assert_eq!(
+            outcome.artifacts,
+            [("art::fails".to_string(), copy.clone())]
+        );
+        assert_eq!(std::fs::read_to_string(copy).unwrap(), "started\n");
+        assert!(!dest.join("stale.log").exists());
+        // The missing screenshot only warns, and the temp dir goes as usual.
+        assert_eq!(
+            outcome.warnings,
+            ["test art::fails left no artifact matching \"screenshot.png\""]
+        );
+        assert!(!tmp_dirs["art::fails"].exists());
+    }
+
+    #[test]
+    fn coverage_reports_the_branch_a_test_took() {
+        let files = [(
+            "tests/cov.b",
+            "setup {\n    SETUP=1\n}\n@test branch {\n    if [ -n \"$SETUP\" ]; then\n        echo taken\n    else\n        echo skipped\n    fi\n}\n",
+        )];
+        let (config, _dir) = project("", &files);
+        let outcome = Runner::new(config).coverage(true).run().unwrap();
+        assert!(outcome.passed());
+        let coverage = outcome.coverage.unwrap();
+        let (source, lines) = coverage.files.iter().next().unwrap();
+        assert!(source.ends_with("tests/cov.b"), "{}", source.display());
+        // The setup block isn't the test's own, and neither `else` nor `fi`
+        // runs anything.
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|(line, hits)| (*line, *hits > 0))
+            .collect();
+        assert_eq!(lines, [(5, true), (6, true), (8, false)]);
+    }
+
+    const TIMED: [(&str, &str); 1] = [(
+        "tests/t.b",
+        "@test a {\n    true\n}\n@test b {\n    true\n}\n@test c {\n    true\n}\n@test d {\n    true\n}\n",
+    )];
+
+    /// A history of runs in which each test took the milliseconds given for
+    /// it in that run.
+    fn timed_history(runs: &[&[(&str, u64)]]) -> History {
+        let runs = runs
+            .iter()
+            .enumerate()
+            .map(|(index, tests)| bishin_jobs::RunRecord {
+                id: index.to_string(),
+                timestamp: index as u64,
+                git_sha: None,
+                tests: tests
+                    .iter()
+                    .map(|(name, milliseconds)| bishin_jobs::TestRecord {
+                        name: format!("t::{name}"),
+                        status: Status::Passed,
+                        duration: Duration::from_millis(*milliseconds),
+                    })
+                    .collect(),
+            })
+            .collect();
+        History { runs }
+    }
+
+    /// The tests planned within `budget` milliseconds, and those left out.
+    fn within(history: History, budget: u64) -> (Vec<String>, Vec<String>) {
+        let (config, _dir) = project("", &TIMED);
+        let plan = Runner::new(config)
+            .history(history)
+            .within(Some(Duration::from_millis(budget)))
+            .plan()
+            .unwrap();
+        let planned = plan.tests.iter().map(|test_job| test_job.id.clone());
+        (planned.collect(), plan.over_budget)
+    }
+
+    #[test]
+    fn the_fastest_tests_that_fit_the_budget_run() {
+        let history = timed_history(&[
+            &[("a", 100), ("b", 50), ("c", 200)],
+            &[("a", 100), ("b", 50), ("c", 200)],
+        ]);
+        // The fastest go first, but the tests run in their usual order.
+        assert_eq!(
+            within(history.clone(), 250),
+            (
+                vec!["t::a".to_string(), "t::b".to_string()],
+                vec!["t::c".to_string(), "t::d".to_string()]
+            )
+        );
+        assert_eq!(within(history.clone(), 350).0, ["t::a", "t::b", "t::c"]);
+        assert_eq!(within(history, 10).0, Vec::<String>::new());
+    }
+
+    #[test]
+    fn the_budget_goes_by_the_mean_duration() {
+        let history = timed_history(&[&[("a", 100), ("b", 10)], &[("a", 300), ("b", 10)]]);
+        assert_eq!(within(history.clone(), 199).0, ["t::b"]);
+        assert_eq!(within(history, 210).0, ["t::a", "t::b"]);
+    }
+
+    #[test]
+    fn without_history_every_test_runs() {
+        let (planned, over_budget) = within(History::default(), 1);
+        assert_eq!(planned, ["t::a", "t::b", "t::c", "t::d"]);
+        assert!(over_budget.is_empty());
+        // Which is left to the caller to warn about.
+        let (config, _dir) = project("", &TIMED);
+        let plan = Runner::new(config)
+            .within(Some(Duration::from_millis(1)))
+            .plan()
+            .unwrap();
+        assert_eq!(
+            plan.warnings,
+            ["there's no history of how long tests take, so none are left out to fit in the time budget"]
+        );
+    }
+
+    #[test]
+    fn tests_read_their_stdin_file() {
+        let files = [
+            (
+                "tests/io/read.b",
+                "@stdin_file(\"data/input.txt\")\n@test cats {\n    cat\n}\n@stdin_file(\"missing.txt\")\n@test missing {\n    cat\n}\n",
+            ),
+            ("tests/io/data/input.txt", "from the file\n"),
+        ];
+        let (config, _dir) = project("", &files);
+        let outcome = Runner::new(config).run().unwrap();
+        let results = &outcome.summary.results;
+        let cats = results.iter().find(|r| r.name == "io::read::cats").unwrap();
+        assert_eq!(cats.status, Status::Passed);
+        assert_eq!(cats.stdout, "from the file\n");
+        let missing = results
+            .iter()
+            .find(|r| r.name == "io::read::missing")
+            .unwrap();
+        assert_eq!(missing.status, Status::Error);
+        assert!(
+            missing
+                .stderr
+                .contains("io/missing.txt as the stdin of job io::read::missing"),
+            "{}",
+            missing.stderr
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn leaked_processes_only_fail_tests_with_strict_processes() {
+        let files = [(
+            "tests/bg.b",
+            "@test daemon {\n    sleep 30 >/dev/null 2>&1 &\n}\n",
+        )];
+        for strict in [false, true] {
+            let (config, _dir) = project("", &files);
+            let outcome = Runner::new(config).strict_processes(strict).run().unwrap();
+            let result = &outcome.summary.results[0];
+            assert!(result.leaked_processes);
+            assert_eq!(result.status == Status::Failed, strict);
+            assert_eq!(outcome.passed(), !strict);
+        }
+    }
+
+    #[test]
+    fn skipped_tests_say_what_skipped_them() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/skips.b",
+                "@skip(\"not yet\")\n@test marked {\n    false\n}\n@skip\n@test marked_without_reason {\n    false\n}\n@test skips_itself {\n    echo no docker >&2\n    exit 77\n}\n@test runs {\n    true\n}\n",
+            )],
+        );
+        let outcome = Runner::new(config).run().unwrap();
+        let skips: Vec<_> = outcome
+            .summary
+            .skipped()
+            .map(|result| {
+                (
+                    result.name.as_str(),
+                    result.skipped_by,
+                    result.skip_reason.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            skips,
+            [
+                (
+                    "skips::marked",
+                    Some(SkipReason::Decorator),
+                    Some("not yet")
+                ),
+                (
+                    "skips::marked_without_reason",
+                    Some(SkipReason::Decorator),
+                    None
+                ),
+                (
+                    "skips::skips_itself",
+                    Some(SkipReason::ExitCode),
+                    Some("no docker")
+                ),
+            ]
+        );
+        assert!(outcome.passed());
+    }
+
+    #[test]
+    fn tests_in_missing_shells_are_skipped_and_filtered_ones_listed() {
+        let bin = scratch_dir();
+        let bash = std::env::split_paths(&std::env::var_os("PATH").unwrap())
+            .map(|dir| dir.join("bash"))
+            .find(|bash| bash.is_file())
+            .unwrap();
+        std::os::unix::fs::symlink(bash, bin.join("bash")).unwrap();
+        let (config, _dir) = project(
+            &format!("[env]\nPATH = \"{}\"\n", bin.display()),
+            &[(
+                "tests/shells.b",
+                "@test runs {\n    true\n}\n@shells(zsh)\n@test in_zsh {\n    true\n}\n@test left_out {\n    true\n}\n",
+            )],
+        );
+        let filter = TestFilter::new(&[], &[], &["left_out".to_string()], false).unwrap();
+        let outcome = Runner::new(config).filter(filter).run().unwrap();
+        let skips: Vec<_> = outcome
+            .summary
+            .skipped()
+            .map(|result| {
+                (
+                    result.name.as_str(),
+                    result.skipped_by,
+                    result.skip_reason.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            skips,
+            [(
+                "shells::in_zsh",
+                Some(SkipReason::MissingShell),
+                Some("zsh isn't installed")
+            )]
+        );
+        assert_eq!(outcome.summary.filtered, ["shells::left_out"]);
+        assert_eq!(outcome.summary.count(Status::Passed), 1);
+    }
+
+    #[test]
+    fn tests_marked_tty_see_a_terminal() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/tty.b",
+                "@tty\n@test sees_one {\n    [ -t 1 ]\n}\n@test sees_none {\n    [ -t 1 ]\n}\n",
+            )],
+        );
+        let outcome = Runner::new(config).run().unwrap();
+        let statuses: Vec<_> = outcome
+            .summary
+            .results
+            .iter()
+            .map(|result| (result.name.as_str(), result.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("tty::sees_one", Status::Passed),
+                ("tty::sees_none", Status::Failed)
+            ]
+        );
+    }
+
+    #[test]
+    fn env_decorators_reach_the_test() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/env.b",
+                "@env(MSG=\"hello world\", URL=a=b)\n@test a {\n    echo \"$MSG|$URL\"\n}\n",
+            )],
+        );
+        let outcome = Runner::new(config).run().unwrap();
+        assert_eq!(outcome.summary.results[0].stdout, "hello world|a=b\n");
+    }
+
+    #[test]
+    fn runs_generate_into_a_new_work_dir_and_sum_up() {
+        let (config, _dir) = project(
+            "work-dir = \"build/bishin\"\n",
+            &[(
+                "tests/sums.b",
+                "@test passes {\n    true\n}\n@test fails {\n    false\n}\n",
+            )],
+        );
+        let work_dir = config.resolved_work_dir();
+        assert!(!work_dir.exists());
+        let outcome = Runner::new(config).run().unwrap();
+        assert!(work_dir.is_dir());
+        let summary = &outcome.summary;
+        assert_eq!(
+            (summary.count(Status::Passed), summary.count(Status::Failed)),
+            (1, 1)
+        );
+        assert!(!outcome.passed());
+    }
+
+    #[test]
+    fn a_missing_test_dir_is_an_error_naming_it() {
+        let (config, _dir) = project("test-dir = \"checks\"\n", &[]);
+        let err = Runner::new(config.clone()).run().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "test directory {} doesn't exist",
+                config.dir().join("checks").display()
+            )
+        );
+    }
+
+    #[test]
+    fn teardowns_run_after_failed_bodies() {
+        let (config, _dir) = project(
+            "",
+            &[(
+                "tests/fixtures.b",
+                "setup {\n    echo setting up\n}\nteardown {\n    echo tearing down\n}\n@test fails {\n    echo body\n    exit 3\n    echo unreachable\n}\n@test passes {\n    true\n}\n",
+            )],
+        );
+        let outcome = Runner::new(config).run().unwrap();
+        let results = &outcome.summary.results;
+        assert_eq!(results[0].status, Status::Failed);
+        assert_eq!(results[0].exit_code, Some(3));
+        assert_eq!(results[0].stdout, "setting up\nbody\ntearing down\n");
+        assert_eq!(results[1].stdout, "setting up\ntearing down\n");
+    }
+
+    #[test]
+    fn tests_past_their_timeout_time_out() {
+        let (config, _dir) = project(
+            "timeout = \"200ms\"\n",
+            &[(
+                "tests/slow.b",
+                "@test by_default {\n    sleep 10\n}\n@timeout(5s)\n@test own {\n    sleep 0.5\n}\n",
+            )],
+        );
+        let outcome = Runner::new(config).run().unwrap();
+        let statuses: Vec<_> = outcome
+            .summary
+            .results
+            .iter()
+            .map(|result| result.status)
+            .collect();
+        assert_eq!(statuses, [Status::TimedOut, Status::Passed]);
+        assert!(!outcome.passed());
+    }
+
+    #[test]
+    fn tests_are_collected_by_the_configured_extensions() {
+        let (config, _dir) = project(
+            "file-extensions = [\"bishin\"]\n",
+            &[
+                ("tests/new.bishin", "@test a {\n    true\n}\n"),
+                ("tests/old.b", "@test a {\n    true\n}\n"),
+            ],
+        );
+        let outcome = Runner::new(config).run().unwrap();
+        let names: Vec<_> = outcome
+            .summary
+            .results
+            .iter()
+            .map(|result| result.name.as_str())
+            .collect();
+        assert_eq!(names, ["new::a"]);
+    }
+
+    #[test]
+    fn tests_in_several_shells_are_selected_by_each_id() {
+        let files = [(
+            "tests/top.b",
+            "@shells(bash, sh)\n@test many {\n    true\n}\n",
+        )];
+        let ids = |pattern: NamePattern| {
+            let filter = TestFilter {
+                include: vec![pattern],
+                ..TestFilter::default()
+            };
+            let (config, _dir) = project("", &files);
+            let plan = Runner::new(config).filter(filter).plan().unwrap();
+            plan.tests
+                .into_iter()
+                .map(|test_job| test_job.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(NamePattern::Exact("top::many[sh]".to_string())),
+            ["top::many[sh]"]
+        );
+        assert_eq!(
+            ids(NamePattern::Substring("top::many".to_string())),
+            ["top::many[bash]", "top::many[sh]"]
+        );
+        assert!(ids(NamePattern::Exact("top::many".to_string())).is_empty());
+    }
+
+    #[test]
+    fn the_summary_has_the_seed_of_a_shuffled_run() {
+        let (config, _dir) = project("", &[("tests/t.b", "@test a {\n    true\n}\n")]);
+        let outcome = Runner::new(config.clone()).shuffle(Some(7)).run().unwrap();
+        assert_eq!(outcome.summary.seed, Some(7));
+        assert_eq!(Runner::new(config).run().unwrap().summary.seed, None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Choosing which tests to run by their names and tags.
+
+use crate::regex::{Regex, RegexError};
+
+/// Something to look for in a test's name, e.g. `net::dns::resolves`.
+#[derive(Debug, Clone)]
+pub enum NamePattern {
+    Substring(String),
+    Regex(Regex),
+    /// A glob that the whole name must match, where `*` matches any run of
+    /// characters, `::` included, and `?` any one character.
+    Glob(String),
+    /// The whole name, as libtest's `--exact` takes its filters.
+    Exact(String),
+}
+
+impl NamePattern {
+    pub fn new(pattern: &str, regex: bool) -> Result<Self, RegexError> {
+        if regex {
+            Regex::new(pattern).map(NamePattern::Regex)
+        } else {
+            Ok(NamePattern::Substring(pattern.to_string()))
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Substring(substring) => name.contains(substring.as_str()),
+            NamePattern::Regex(regex) => regex.is_match(name),
+            NamePattern::Glob(glob) => glob_matches(glob, name),
+            NamePattern::Exact(exact) => name == exact,
+        }
+    }
+}
+
+/// Whether `name` matches all of `glob`.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut g, mut n) = (0, 0);
+    // Where to carry on from if what follows the last `*` stops matching:
+    // the `*`, and the character of the name it would match up to next.
+    let mut star = None;
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, n));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_g, star_n)) => {
+                    g = star_g + 1;
+                    n = star_n + 1;
+                    star = Some((star_g, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// Which tests to run by their `@tags`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagFilter {
+    /// Run only the tests with at least one of these, unless empty.
+    pub include: Vec<String>,
+    /// Leave out the tests with any of these.
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, tags: &[String]) -> bool {
+        let included = self.include.is_empty() || tags.iter().any(|tag| self.include.contains(tag));
+        included && !tags.iter().any(|tag| self.exclude.contains(tag))
+    }
+}
+
+/// Which tests to run. With no patterns at all every test runs.
+#[derive(Debug, Clone, Default)]
+pub struct TestFilter {
+    /// Run only the tests whose names match at least one of these, unless
+    /// empty.
+    pub include: Vec<NamePattern>,
+    /// Then only those with the right tags.
+    pub tags: TagFilter,
+    /// Then leave out the tests whose names match any of these.
+    pub exclude: Vec<NamePattern>,
+    /// Run the `@bench` benchmarks instead of the other tests.
+    pub benches: bool,
+}
+
+impl TestFilter {
+    /// A filter running the tests whose names match any of the `include`
+    /// patterns or `globs`, leaving out those matching an `exclude` pattern.
+    pub fn new(
+        include: &[String],
+        globs: &[String],
+        exclude: &[String],
+        regex: bool,
+    ) -> Result<Self, RegexError> {
+        let patterns = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| NamePattern::new(pattern, regex))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            include: patterns(include)?
+                .into_iter()
+                .chain(globs.iter().cloned().map(NamePattern::Glob))
+                .collect(),
+            tags: TagFilter::default(),
+            exclude: patterns(exclude)?,
+            benches: false,
+        })
+    }
+
+    pub fn matches(&self, name: &str, tags: &[String]) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(name));
+        included
+            && self.tags.matches(tags)
+            && !self.exclude.iter().any(|pattern| pattern.matches(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 4] = ["net::slow_dns", "net::dns", "fs::slow_copy", "fs::copy"];
+
+    /// The names of [`NAMES`] that `filter` selects, none of them tagged.
+    fn selected(filter: &TestFilter) -> Vec<&'static str> {
+        NAMES
+            .into_iter()
+            .filter(|name| filter.matches(name, &[]))
+            .collect()
+    }
+
+    fn strings(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn excludes_without_includes_leave_out_from_everything() {
+        let filter = TestFilter::new(&[], &[], &strings(&["slow_"]), false).unwrap();
+        assert_eq!(selected(&filter), ["net::dns", "fs::copy"]);
+    }
+
+    #[test]
+    fn excludes_apply_after_includes() {
+        let filter =
+            TestFilter::new(&strings(&["net::"]), &[], &strings(&["slow_"]), false).unwrap();
+        assert_eq!(selected(&filter), ["net::dns"]);
+        let filter =
+            TestFilter::new(&strings(&["slow_"]), &[], &strings(&["slow_"]), false).unwrap();
+        assert_eq!(selected(&filter), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn excludes_can_be_regexes() {
+        let filter = TestFilter::new(&[], &[], &strings(&["^fs::|dns$"]), true).unwrap();
+        assert_eq!(selected(&filter), [] as [&str; 0]);
+        let filter = TestFilter::new(&[], &[], &strings(&["::copy$"]), true).unwrap();
+        assert_eq!(
+            selected(&filter),
+            ["net::slow_dns", "net::dns", "fs::slow_copy"]
+        );
+        assert!(TestFilter::new(&[], &[], &strings(&["("]), true).is_err());
+    }
+
+    #[test]
+    fn excludes_apply_after_tags() {
+        let mut filter = TestFilter::new(&[], &[], &strings(&["slow_"]), false).unwrap();
+        filter.tags.include = strings(&["net"]);
+        let tagged = strings(&["net"]);
+        assert!(filter.matches("net::dns", &tagged));
+        assert!(!filter.matches("net::slow_dns", &tagged));
+        assert!(!filter.matches("fs::copy", &[]));
+    }
+
+    #[test]
+    fn globs_match_whole_names() {
+        for (glob, name, matches) in [
+            ("net::dns", "net::dns", true),
+            ("net::dns", "net::dns2", false),
+            ("net::*", "net::dns::resolves", true),
+            ("*dns*", "net::dns::resolves", true),
+            ("*::copy", "fs::slow_copy", false),
+            ("fs::?copy", "fs::_copy", true),
+            ("fs::?copy", "fs::copy", false),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("**", "", true),
+            ("?", "é", true),
+            ("", "", true),
+            ("", "x", false),
+        ] {
+            assert_eq!(glob_matches(glob, name), matches, "{glob} {name}");
+        }
+    }
+
+    #[test]
+    fn a_single_glob_selects_what_it_matches() {
+        let filter = TestFilter::new(&[], &strings(&["net::*"]), &[], false).unwrap();
+        assert_eq!(selected(&filter), ["net::slow_dns", "net::dns"]);
+        let filter = TestFilter::new(&[], &strings(&["*::slow_*"]), &[], false).unwrap();
+        assert_eq!(selected(&filter), ["net::slow_dns", "fs::slow_copy"]);
+    }
+
+    #[test]
+    fn globs_and_filters_add_up() {
+        let filter =
+            TestFilter::new(&[], &strings(&["net::dns", "fs::*copy"]), &[], false).unwrap();
+        assert_eq!(selected(&filter), ["net::dns", "fs::slow_copy", "fs::copy"]);
+        let filter = TestFilter::new(
+            &strings(&["slow_dns"]),
+            &strings(&["fs::copy"]),
+            &strings(&["slow_"]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(selected(&filter), ["fs::copy"]);
+        // Globs aren't regexes, even when the filters are.
+        let filter = TestFilter::new(&[], &strings(&["net::.*"]), &[], true).unwrap();
+        assert_eq!(selected(&filter), [] as [&str; 0]);
+    }
+}
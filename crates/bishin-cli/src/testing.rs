@@ -0,0 +1,17 @@
+//! Helpers for the tests of the library and the command alike.
+
+// Each of them uses only some of these.
+#![allow(dead_code)]
+
+use bishin_config::{Config, CONFIG_FILE};
+pub(crate) use bishin_testing::{scratch_dir, write_files, ScratchDir};
+
+/// The config of a new project with `config` in its config file and each of
+/// `files` written relative to it, and the directory it's in, which goes
+/// when that's dropped.
+pub(crate) fn project(config: &str, files: &[(&str, &str)]) -> (Config, ScratchDir) {
+    let dir = scratch_dir();
+    write_files(&dir, &[(CONFIG_FILE, config)]);
+    write_files(&dir, files);
+    (Config::load(&dir.join(CONFIG_FILE)).unwrap(), dir)
+}
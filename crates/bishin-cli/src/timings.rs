@@ -0,0 +1,106 @@
+//! How long each phase of a run took.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    ConfigLoad,
+    Collection,
+    Parsing,
+    Generation,
+    Execution,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::ConfigLoad,
+        Phase::Collection,
+        Phase::Parsing,
+        Phase::Generation,
+        Phase::Execution,
+    ];
+
+    /// The name used for the phase in reports, e.g. `config-load`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::ConfigLoad => "config-load",
+            Phase::Collection => "collection",
+            Phase::Parsing => "parsing",
+            Phase::Generation => "generation",
+            Phase::Execution => "execution",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// The time spent in each phase so far. Phases that run more than once, like
+/// parsing each file, add up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+    durations: [Duration; Phase::ALL.len()],
+}
+
+impl Timings {
+    /// Run `f`, counting the time it takes towards `phase`.
+    pub fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = f();
+        self.durations[phase as usize] += start.elapsed();
+        value
+    }
+
+    pub fn get(&self, phase: Phase) -> Duration {
+        self.durations[phase as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Phase, Duration)> + '_ {
+        Phase::ALL.into_iter().map(|phase| (phase, self.get(phase)))
+    }
+
+    pub fn total(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+
+    /// The timings as a JSON object of milliseconds by phase name.
+    pub fn to_json(&self) -> String {
+        let phases: Vec<_> = self
+            .iter()
+            .map(|(phase, duration)| format!("\"{phase}\":{:.3}", duration.as_secs_f64() * 1000.0))
+            .collect();
+        format!("{{{}}}", phases.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_that_run_again_add_up() {
+        let mut timings = Timings::default();
+        for _ in 0..2 {
+            timings.time(Phase::Parsing, || {
+                std::thread::sleep(Duration::from_millis(5))
+            });
+        }
+        assert!(timings.get(Phase::Parsing) >= Duration::from_millis(10));
+        assert_eq!(timings.get(Phase::Collection), Duration::ZERO);
+        assert_eq!(timings.total(), timings.get(Phase::Parsing));
+    }
+
+    #[test]
+    fn json_has_every_phase_in_milliseconds() {
+        let mut timings = Timings::default();
+        timings.durations[Phase::Execution as usize] = Duration::from_micros(1500);
+        assert_eq!(
+            timings.to_json(),
+            "{\"config-load\":0.000,\"collection\":0.000,\"parsing\":0.000,\"generation\":0.000,\"execution\":1.500}"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Running a suite through the library, the way a program embedding bishin
+//! would.
+
+use std::path::Path;
+use std::time::Duration;
+
+use bishin::report::{Report, ReportKind};
+use bishin::{Config, Runner, Status, TestFilter};
+use bishin_testing::{scratch_dir, write_files, ScratchDir};
+
+/// A new project in a directory of its own, with an empty config and each
+/// of `files` written relative to it, which goes when that's dropped.
+fn project(files: &[(&str, &str)]) -> ScratchDir {
+    let dir = scratch_dir();
+    write_files(&dir, &[("bishin.toml", "")]);
+    write_files(&dir, files);
+    dir
+}
+
+fn load(dir: &Path) -> Config {
+    Config::load(&dir.join("bishin.toml")).unwrap()
+}
+
+const SUITE: &[(&str, &str)] = &[
+    ("tests/net/dns.b", "@test resolves {\n    true\n}\n"),
+    ("tests/net/http.b", "@test gets {\n    echo got it\n}\n"),
+    ("tests/slow.b", "@test sleeps {\n    sleep 10\n}\n"),
+    ("tests/broken.b", "@test fails {\n    exit 3\n}\n"),
+];
+
+#[test]
+fn a_suite_runs_from_start_to_finish() {
+    let dir = project(SUITE);
+    let report = dir.join("reports/results.json");
+    let json = Report {
+        kind: ReportKind::Json,
+        path: report.clone(),
+    };
+    let outcome = Runner::new(load(&dir))
+        .jobs(4)
+        .timeout(Duration::from_millis(500))
+        .reporter(json.open().unwrap())
+        .run()
+        .unwrap();
+    let mut statuses: Vec<_> = outcome
+        .summary
+        .results
+        .iter()
+        .map(|result| (result.name.as_str(), result.status))
+        .collect();
+    statuses.sort_by_key(|(name, _)| *name);
+    assert_eq!(
+        statuses,
+        [
+            ("broken::fails", Status::Failed),
+            ("net::dns::resolves", Status::Passed),
+            ("net::http::gets", Status::Passed),
+            ("slow::sleeps", Status::TimedOut),
+        ]
+    );
+    assert!(!outcome.passed());
+    let gets = outcome
+        .summary
+        .results
+        .iter()
+        .find(|result| result.name == "net::http::gets")
+        .unwrap();
+    assert_eq!(gets.stdout, "got it\n");
+    let written = std::fs::read_to_string(&report).unwrap();
+    let (read, _) = bishin::report::read_summary(&written).unwrap();
+    assert_eq!(read.results.len(), 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_filtered_plan_runs_only_what_it_selected() {
+    let dir = project(SUITE);
+    let filter = TestFilter::new(&["net::".to_string()], &[], &[], false).unwrap();
+    let runner = Runner::new(load(&dir)).filter(filter);
+    let plan = runner.plan().unwrap();
+    let mut ids: Vec<_> = plan.tests.iter().map(|test| test.id.clone()).collect();
+    ids.sort();
+    assert_eq!(ids, ["net::dns::resolves", "net::http::gets"]);
+    let outcome = runner.execute(plan).unwrap();
+    assert_eq!(outcome.summary.results.len(), 2);
+    assert!(outcome.passed());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn overrides_replace_the_config() {
+    let dir = project(&[
+        ("bishin.toml", "min-pass-rate = 0.5\n"),
+        ("tests/bad.b", "@test fails {\n    false\n}\n"),
+        ("tests/ok.b", "@test ok {\n    true\n}\n"),
+    ]);
+    let outcome = Runner::new(load(&dir)).run().unwrap();
+    assert_eq!(outcome.min_pass_rate(), Some(0.5));
+    assert!(outcome.passed());
+    let outcome = Runner::new(load(&dir)).min_pass_rate(None).run().unwrap();
+    assert!(!outcome.passed());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
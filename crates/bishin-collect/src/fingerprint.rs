@@ -0,0 +1,235 @@
+//! A digest of a suite's test files and the files they read, so that CI can
+//! tell cheaply whether the suite changed.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use bishin_parser::parse_test_file;
+
+use crate::{Error, ModuleGraph};
+
+/// Starts every fingerprint, so that changing what goes into one changes
+/// all of them.
+const DOMAIN: &[u8] = b"bishin-fingerprint-2\0";
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// A digest of the names and contents of the test files in a
+/// [`ModuleGraph`] and of the files they read, which is the same on every
+/// platform and run for as long as they are. It's 128-bit FNV-1a, which is
+/// meant for telling changes apart rather than for standing up to someone
+/// making two suites collide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(u128);
+
+impl Fingerprint {
+    pub fn as_u128(self) -> u128 {
+        self.0
+    }
+}
+
+/// Formats as 32 lowercase hex digits.
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+struct Fnv(u128);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u128::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Write `bytes` after their length, so that where one field ends and
+    /// the next starts is part of the digest.
+    fn write_field(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+impl ModuleGraph {
+    /// The [`Fingerprint`] of the test files and the files their tests
+    /// read, reading each of them.
+    ///
+    /// Files are taken in the order of their module names, so moving a file
+    /// changes the fingerprint but walking the roots in another order
+    /// doesn't. The helpers that a file's tests `@include`, and the files
+    /// holding their bodies and stdin, are taken with it by the path they're
+    /// written as, whether they exist or not.
+    pub fn fingerprint(&self) -> Result<Fingerprint, Error> {
+        let mut modules: Vec<_> = self.iter_leaf_modules().collect();
+        modules.sort_by(|a, b| {
+            a.path()
+                .cmp(b.path())
+                .then_with(|| a.file_path().cmp(b.file_path()))
+        });
+        let mut hasher = Fnv(FNV_OFFSET_BASIS);
+        hasher.write(DOMAIN);
+        hasher.write(&(modules.len() as u64).to_le_bytes());
+        for module in modules {
+            let path = module.file_path();
+            let contents = std::fs::read(path).map_err(|source| Error::Read {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            hasher.write_field(module.name().as_bytes());
+            hasher.write_field(&contents);
+            let dependencies = dependencies(&contents);
+            hasher.write(&(dependencies.len() as u64).to_le_bytes());
+            let base = path.parent().unwrap_or(Path::new(""));
+            for dependency in dependencies {
+                hasher.write_field(dependency.as_bytes());
+                match read(&base.join(&dependency))? {
+                    Some(contents) => {
+                        hasher.write(&[1]);
+                        hasher.write_field(&contents);
+                    }
+                    None => hasher.write(&[0]),
+                }
+            }
+        }
+        Ok(Fingerprint(hasher.0))
+    }
+}
+
+/// The files that the tests in a test file read, relative to it: their
+/// includes, body files and stdin files.
+///
+/// A file that doesn't parse has none, since it can't run until it's
+/// changed anyway.
+fn dependencies(contents: &[u8]) -> BTreeSet<String> {
+    let Some(file) = std::str::from_utf8(contents)
+        .ok()
+        .and_then(|contents| parse_test_file(contents).ok())
+    else {
+        return BTreeSet::new();
+    };
+    file.tests
+        .into_iter()
+        .flat_map(|test| {
+            test.includes
+                .into_iter()
+                .chain(test.body_file)
+                .chain(test.stdin_file)
+        })
+        .collect()
+}
+
+/// The contents of the file at `path`, or `None` if there isn't one.
+fn read(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(Error::Read {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_tests;
+    use crate::testing::{scratch_dir, write_files, ScratchDir};
+
+    const SUITE: [(&str, &str); 5] = [
+        (
+            "tests/net.b",
+            "@include(\"_helpers.b\")\n@stdin_file(\"input.txt\")\n@test a {\n    true\n}\n",
+        ),
+        ("tests/disk.b", "@test b = \"scripts/b.sh\"\n"),
+        ("tests/_helpers.b", "helper() { true; }\n"),
+        ("tests/input.txt", "some input\n"),
+        ("tests/scripts/b.sh", "true\n"),
+    ];
+
+    /// The fingerprint of the suite in `dir`.
+    fn fingerprint(dir: &Path) -> Fingerprint {
+        load_tests(&dir.join("tests"))
+            .unwrap()
+            .fingerprint()
+            .unwrap()
+    }
+
+    /// A copy of [`SUITE`] in a directory of its own, and its fingerprint.
+    fn suite() -> (ScratchDir, Fingerprint) {
+        let dir = scratch_dir();
+        write_files(&dir, &SUITE);
+        let fingerprint = fingerprint(&dir);
+        (dir, fingerprint)
+    }
+
+    #[test]
+    fn the_same_suite_has_the_same_fingerprint() {
+        let (dir, first) = suite();
+        assert_eq!(fingerprint(&dir), first);
+        // Wherever it is.
+        assert_eq!(suite().1, first);
+        assert_eq!(first.to_string().len(), 32);
+        assert_eq!(first.to_string(), format!("{:032x}", first.as_u128()));
+    }
+
+    #[test]
+    fn changing_a_test_file_changes_the_fingerprint() {
+        let (dir, before) = suite();
+        write_files(&dir, &[("tests/disk.b", "@test b = \"scripts/b.sh\"\n\n")]);
+        assert_ne!(fingerprint(&dir), before);
+    }
+
+    #[test]
+    fn renaming_a_test_file_changes_the_fingerprint() {
+        let (dir, before) = suite();
+        std::fs::rename(dir.join("tests/disk.b"), dir.join("tests/disks.b")).unwrap();
+        assert_ne!(fingerprint(&dir), before);
+    }
+
+    #[test]
+    fn changing_what_the_tests_read_changes_the_fingerprint() {
+        for (path, contents) in [
+            ("tests/_helpers.b", "helper() { false; }\n"),
+            ("tests/input.txt", "other input\n"),
+            ("tests/scripts/b.sh", "false\n"),
+        ] {
+            let (dir, before) = suite();
+            write_files(&dir, &[(path, contents)]);
+            assert_ne!(fingerprint(&dir), before, "{path}");
+        }
+    }
+
+    #[test]
+    fn a_missing_dependency_is_part_of_the_fingerprint() {
+        let (dir, before) = suite();
+        std::fs::remove_file(dir.join("tests/input.txt")).unwrap();
+        let missing = fingerprint(&dir);
+        assert_ne!(missing, before);
+        // An empty file isn't the same as none.
+        write_files(&dir, &[("tests/input.txt", "")]);
+        assert_ne!(fingerprint(&dir), missing);
+    }
+
+    #[test]
+    fn files_nothing_reads_are_not_part_of_the_fingerprint() {
+        let (dir, before) = suite();
+        write_files(&dir, &[("tests/notes.txt", "unrelated\n")]);
+        assert_eq!(fingerprint(&dir), before);
+    }
+
+    #[test]
+    fn files_that_do_not_parse_are_fingerprinted_by_their_contents() {
+        let dir = scratch_dir();
+        write_files(&dir, &[("tests/broken.b", "@test {\n")]);
+        let before = fingerprint(&dir);
+        write_files(&dir, &[("tests/broken.b", "@test {\n\n")]);
+        assert_ne!(fingerprint(&dir), before);
+    }
+}
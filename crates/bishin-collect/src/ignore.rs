@@ -0,0 +1,222 @@
+//! Patterns kept in files in the test directories themselves, which leave
+//! files out of the directory they're in and those under it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::pattern::Pattern;
+use crate::{CollectOptions, Error};
+
+/// The file of patterns that only bishin reads.
+pub const BISHINIGNORE_FILE: &str = ".bishinignore";
+
+/// The file of patterns that git reads too.
+pub const GITIGNORE_FILE: &str = ".gitignore";
+
+/// The patterns of the ignore files in each directory under a root, read
+/// as the directories are first needed.
+pub(crate) struct IgnoreFiles<'a> {
+    root: &'a Path,
+    names: Vec<&'static str>,
+    patterns: HashMap<PathBuf, Vec<Pattern>>,
+}
+
+impl<'a> IgnoreFiles<'a> {
+    pub(crate) fn new(root: &'a Path, options: &CollectOptions) -> Self {
+        let names = [
+            (options.respect_gitignore, GITIGNORE_FILE),
+            (options.respect_bishinignore, BISHINIGNORE_FILE),
+        ]
+        .into_iter()
+        .filter_map(|(respected, name)| respected.then_some(name))
+        .collect();
+        Self {
+            root,
+            names,
+            patterns: HashMap::new(),
+        }
+    }
+
+    /// Whether the ignore files above `path`, relative to the root, leave it
+    /// out or take it back in, or `None` if none of them say.
+    ///
+    /// The files of directories further down take precedence, and within
+    /// a directory `.bishinignore` takes precedence over `.gitignore`.
+    pub(crate) fn is_ignored(&mut self, path: &Path, is_dir: bool) -> Result<Option<bool>, Error> {
+        if self.names.is_empty() {
+            return Ok(None);
+        }
+        for dir in path.ancestors().skip(1) {
+            let relative = path.strip_prefix(dir).expect("ancestors are prefixes");
+            let matched = self
+                .patterns(dir)?
+                .iter()
+                .rev()
+                .find(|pattern| pattern.matches(relative, is_dir));
+            if let Some(pattern) = matched {
+                return Ok(Some(!pattern.is_negated()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn patterns(&mut self, dir: &Path) -> Result<&[Pattern], Error> {
+        if !self.patterns.contains_key(dir) {
+            let mut patterns = Vec::new();
+            for name in &self.names {
+                patterns.extend(read(&self.root.join(dir).join(name))?);
+            }
+            self.patterns.insert(dir.to_path_buf(), patterns);
+        }
+        Ok(&self.patterns[dir])
+    }
+}
+
+/// The patterns in the ignore file at `path`, or none if there isn't one.
+///
+/// Blank lines and those starting with `#` are left out, as are spaces at
+/// the end of a line.
+fn read(path: &Path) -> Result<Vec<Pattern>, Error> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(Error::Read {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, source)| {
+            Pattern::new(source).map_err(|source| Error::IgnoreFile {
+                path: path.to_path_buf(),
+                line,
+                source,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write_files};
+
+    fn options(respect_gitignore: bool, respect_bishinignore: bool) -> CollectOptions {
+        CollectOptions {
+            respect_gitignore,
+            respect_bishinignore,
+            ..CollectOptions::default()
+        }
+    }
+
+    #[test]
+    fn ignore_files_say_nothing_unless_a_pattern_matches() {
+        let root = scratch_dir();
+        write_files(&root, &[(BISHINIGNORE_FILE, "*.tmp\n")]);
+        let mut ignore_files = IgnoreFiles::new(&root, &options(false, true));
+        assert_eq!(
+            ignore_files.is_ignored(Path::new("a.tmp"), false).unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            ignore_files
+                .is_ignored(Path::new("sub/b.tmp"), false)
+                .unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            ignore_files.is_ignored(Path::new("a.b"), false).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn only_the_files_asked_for_are_read() {
+        let root = scratch_dir();
+        write_files(
+            &root,
+            &[
+                (GITIGNORE_FILE, "git.b\n"),
+                (BISHINIGNORE_FILE, "bishin.b\n"),
+            ],
+        );
+        let is_ignored = |options: &CollectOptions, path: &str| {
+            IgnoreFiles::new(&root, options)
+                .is_ignored(Path::new(path), false)
+                .unwrap()
+        };
+        assert_eq!(is_ignored(&options(false, false), "git.b"), None);
+        assert_eq!(is_ignored(&options(false, false), "bishin.b"), None);
+        assert_eq!(is_ignored(&options(true, false), "git.b"), Some(true));
+        assert_eq!(is_ignored(&options(true, false), "bishin.b"), None);
+        assert_eq!(is_ignored(&options(false, true), "bishin.b"), Some(true));
+        assert_eq!(is_ignored(&options(false, true), "git.b"), None);
+    }
+
+    #[test]
+    fn deeper_files_and_bishinignore_take_precedence() {
+        let root = scratch_dir();
+        write_files(
+            &root,
+            &[
+                (GITIGNORE_FILE, "*.b\n"),
+                (BISHINIGNORE_FILE, "!keep.b\n"),
+                ("sub/.bishinignore", "keep.b\n!sub_keep.b\n"),
+            ],
+        );
+        let mut ignore_files = IgnoreFiles::new(&root, &options(true, true));
+        let mut is_ignored = |path: &str| ignore_files.is_ignored(Path::new(path), false).unwrap();
+        assert_eq!(is_ignored("other.b"), Some(true));
+        assert_eq!(is_ignored("keep.b"), Some(false));
+        assert_eq!(is_ignored("sub/keep.b"), Some(true));
+        assert_eq!(is_ignored("sub/sub_keep.b"), Some(false));
+        // The file of the directory above still applies below it.
+        assert_eq!(is_ignored("sub/other.b"), Some(true));
+    }
+
+    #[test]
+    fn patterns_in_a_subdirectory_are_relative_to_it() {
+        let root = scratch_dir();
+        write_files(&root, &[("net/.bishinignore", "/fixtures/\n")]);
+        let mut ignore_files = IgnoreFiles::new(&root, &options(false, true));
+        let mut is_ignored =
+            |path: &str, is_dir| ignore_files.is_ignored(Path::new(path), is_dir).unwrap();
+        assert_eq!(is_ignored("net/fixtures", true), Some(true));
+        assert_eq!(is_ignored("net/fixtures", false), None);
+        assert_eq!(is_ignored("net/deeper/fixtures", true), None);
+        assert_eq!(is_ignored("fixtures", true), None);
+    }
+
+    #[test]
+    fn comments_blank_lines_and_trailing_spaces_are_left_out() {
+        let scratch = scratch_dir();
+        let path = scratch.join(BISHINIGNORE_FILE);
+        std::fs::write(&path, "# Build output\n\nbuild/   \n  \n*.log\n").unwrap();
+        let patterns: Vec<_> = read(&path).unwrap();
+        let sources: Vec<_> = patterns.iter().map(Pattern::as_str).collect();
+        assert_eq!(sources, ["build/", "*.log"]);
+        assert!(read(&path.with_file_name("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors_naming_the_file_and_line() {
+        let scratch = scratch_dir();
+        let path = scratch.join(BISHINIGNORE_FILE);
+        std::fs::write(&path, "ok\n# fine\n[a-z\n").unwrap();
+        let err = read(&path).unwrap_err();
+        assert!(
+            matches!(&err, Error::IgnoreFile { path: at, line: 3, .. } if *at == path),
+            "{err:?}"
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("invalid pattern on line 3 of {}", path.display())
+        );
+    }
+}
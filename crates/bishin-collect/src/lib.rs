@@ -0,0 +1,491 @@
+//! Discovery of test files.
+//!
+//! Every directory under the test root is a module, and every file with one
+//! of the test file extensions, [`FILE_EXTENSION`] unless configured
+//! otherwise, is a leaf module containing tests. The module path mirrors the
+//! filesystem, so `tests/net/dns.b` becomes `net::dns`.
+//!
+//! Files whose names start with `_`, such as `_helpers.b`, are helpers for
+//! tests to `@include` rather than tests, so they aren't collected.
+//!
+//! Tests can also be collected from several roots at once with
+//! [`load_test_roots`], where each root is a module of its own named by its
+//! last component, so `tests/unit/dns.b` becomes `unit::dns`.
+//!
+//! Files can be left out with [`CollectOptions::ignore`], or by the
+//! patterns of [`BISHINIGNORE_FILE`] and [`GITIGNORE_FILE`] files in the
+//! test directories when [`CollectOptions`] says to read them.
+
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::ignore::IgnoreFiles;
+
+mod fingerprint;
+mod ignore;
+pub mod pattern;
+#[cfg(test)]
+mod testing;
+
+pub use fingerprint::Fingerprint;
+pub use ignore::{BISHINIGNORE_FILE, GITIGNORE_FILE};
+pub use pattern::{Pattern, PatternError};
+
+/// The extension of files that contain tests, unless
+/// [`CollectOptions::extensions`] says otherwise.
+pub const FILE_EXTENSION: &str = "b";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read test directory")]
+    Walk(#[from] walkdir::Error),
+    #[error("test directory {} doesn't exist", .0.display())]
+    NoTestDir(PathBuf),
+    #[error("test directory {} has no name to put its tests under", .0.display())]
+    NoNamespace(PathBuf),
+    #[error("failed to read test file {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid pattern on line {line} of {}", path.display())]
+    IgnoreFile {
+        path: PathBuf,
+        line: usize,
+        #[source]
+        source: PatternError,
+    },
+}
+
+/// Whether a module is a directory of other modules or a file of tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleKind {
+    Directory,
+    File,
+}
+
+/// A directory or test file discovered under the test root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    path: Vec<String>,
+    file_path: PathBuf,
+    kind: ModuleKind,
+}
+
+impl Module {
+    /// The components of this module's path, outermost first.
+    pub fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// This module's path joined with `::`, e.g. `net::dns`.
+    pub fn name(&self) -> String {
+        self.path.join("::")
+    }
+
+    /// The directory or file this module was loaded from.
+    pub fn file_path(&self) -> &Path {
+        &self.file_path
+    }
+
+    pub fn kind(&self) -> ModuleKind {
+        self.kind
+    }
+
+    /// Whether this module is a test file rather than a directory.
+    pub fn is_leaf(&self) -> bool {
+        self.kind == ModuleKind::File
+    }
+}
+
+/// All of the modules found under the test roots, in filesystem order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleGraph {
+    roots: Vec<PathBuf>,
+    modules: Vec<Module>,
+}
+
+impl ModuleGraph {
+    /// The directories the modules were collected from.
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    pub fn iter_modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.iter()
+    }
+
+    /// Iterate over the modules that contain tests.
+    pub fn iter_leaf_modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.iter().filter(|module| module.is_leaf())
+    }
+
+    /// The number of modules, directories and test files alike.
+    pub fn module_count(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// The number of modules that contain tests.
+    pub fn leaf_count(&self) -> usize {
+        self.iter_leaf_modules().count()
+    }
+
+    /// The number of modules that contain tests, the same as
+    /// [`ModuleGraph::leaf_count`].
+    pub fn len(&self) -> usize {
+        self.leaf_count()
+    }
+
+    /// Whether there are no modules that contain tests, even if there are
+    /// directories.
+    pub fn is_empty(&self) -> bool {
+        self.iter_leaf_modules().next().is_none()
+    }
+}
+
+/// Settings for [`load_tests_with`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectOptions {
+    /// The extensions of files that contain tests, lowercase and without a
+    /// leading dot.
+    pub extensions: Vec<String>,
+    /// Files and directories to leave out, matched against their path
+    /// relative to the test root.
+    pub ignore: Vec<Pattern>,
+    /// Also leave out what the [`BISHINIGNORE_FILE`]s in the test
+    /// directories match, relative to the directory each is in.
+    pub respect_bishinignore: bool,
+    /// Also leave out what the [`GITIGNORE_FILE`]s in the test directories
+    /// match. Those of the directories above the test root aren't read.
+    pub respect_gitignore: bool,
+}
+
+impl Default for CollectOptions {
+    fn default() -> Self {
+        Self {
+            extensions: vec![FILE_EXTENSION.to_string()],
+            ignore: Vec::new(),
+            respect_bishinignore: false,
+            respect_gitignore: false,
+        }
+    }
+}
+
+impl CollectOptions {
+    /// Whether the file at `path` contains tests, going by its extension.
+    pub fn is_test_file(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            self.extensions.contains(&ext)
+        })
+    }
+}
+
+/// Whether the file at `path` is a helper for tests to include, rather than
+/// tests of its own, going by its name.
+pub fn is_helper_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('_'))
+}
+
+/// Collect every module under `root`.
+pub fn load_tests(root: &Path) -> Result<ModuleGraph, Error> {
+    load_tests_with(root, &CollectOptions::default())
+}
+
+/// Collect the modules under `root` that `options` doesn't leave out.
+///
+/// An ignored directory is skipped along with everything in it, and what
+/// the ignore files say takes precedence over [`CollectOptions::ignore`].
+pub fn load_tests_with(root: &Path, options: &CollectOptions) -> Result<ModuleGraph, Error> {
+    Ok(ModuleGraph {
+        roots: vec![root.to_path_buf()],
+        modules: collect_modules(root, options)?,
+    })
+}
+
+/// The name of the module that [`load_test_roots`] puts the tests under
+/// `root` in, or `None` if it has no last component to be named by.
+pub fn namespace(root: &Path) -> Option<String> {
+    Some(root.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Collect the modules under each of `roots`, each in a module named by its
+/// [`namespace`].
+///
+/// Roots should have different namespaces, otherwise their modules can have
+/// the same names.
+pub fn load_test_roots(roots: &[PathBuf], options: &CollectOptions) -> Result<ModuleGraph, Error> {
+    let mut modules = Vec::new();
+    for root in roots {
+        let namespace = namespace(root).ok_or_else(|| Error::NoNamespace(root.clone()))?;
+        modules.push(Module {
+            path: vec![namespace.clone()],
+            file_path: root.clone(),
+            kind: ModuleKind::Directory,
+        });
+        modules.extend(
+            collect_modules(root, options)?
+                .into_iter()
+                .map(|mut module| {
+                    module.path.insert(0, namespace.clone());
+                    module
+                }),
+        );
+    }
+    Ok(ModuleGraph {
+        roots: roots.to_vec(),
+        modules,
+    })
+}
+
+fn collect_modules(root: &Path, options: &CollectOptions) -> Result<Vec<Module>, Error> {
+    let relative = |path: &Path| {
+        path.strip_prefix(root)
+            .expect("walked paths are under the root")
+            .to_path_buf()
+    };
+    if !root.is_dir() {
+        return Err(Error::NoTestDir(root.to_path_buf()));
+    }
+    let mut ignore_files = IgnoreFiles::new(root, options);
+    let mut walk = WalkDir::new(root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter();
+    let mut modules = Vec::new();
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        let is_dir = entry.file_type().is_dir();
+        let path = relative(entry.path());
+        let ignored = match ignore_files.is_ignored(&path, is_dir)? {
+            Some(ignored) => ignored,
+            None => pattern::is_ignored(&options.ignore, &path, is_dir),
+        };
+        if ignored {
+            if is_dir {
+                walk.skip_current_dir();
+            }
+            continue;
+        }
+        let kind = if is_dir {
+            ModuleKind::Directory
+        } else if options.is_test_file(entry.path()) && !is_helper_file(entry.path()) {
+            ModuleKind::File
+        } else {
+            continue;
+        };
+        modules.push(Module {
+            path: module_path(&path, kind),
+            file_path: entry.path().to_path_buf(),
+            kind,
+        });
+    }
+    Ok(modules)
+}
+
+fn module_path(relative: &Path, kind: ModuleKind) -> Vec<String> {
+    let relative = match kind {
+        ModuleKind::Directory => relative.to_path_buf(),
+        ModuleKind::File => relative.with_extension(""),
+    };
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{leaf_names, scratch_dir, write_files};
+
+    const TEST: &str = "@test a {\n    true\n}\n";
+
+    #[test]
+    fn each_root_is_under_its_own_namespace() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                ("tests/unit/net/dns.b", TEST),
+                ("tests/unit/fs.b", TEST),
+                ("tests/integration/net/dns.b", TEST),
+            ],
+        );
+        let roots = [dir.join("tests/unit"), dir.join("tests/integration")];
+        let graph = load_test_roots(&roots, &CollectOptions::default()).unwrap();
+        assert_eq!(graph.roots(), roots);
+        assert_eq!(
+            leaf_names(&graph),
+            ["unit::fs", "unit::net::dns", "integration::net::dns"]
+        );
+        let directories: Vec<_> = graph
+            .iter_modules()
+            .filter(|module| module.kind() == ModuleKind::Directory)
+            .map(Module::name)
+            .collect();
+        assert_eq!(
+            directories,
+            ["unit", "unit::net", "integration", "integration::net"]
+        );
+    }
+
+    #[test]
+    fn a_root_without_a_name_has_no_namespace() {
+        assert_eq!(namespace(Path::new("tests/unit")).as_deref(), Some("unit"));
+        assert_eq!(namespace(Path::new("/")), None);
+        let err = load_test_roots(&[PathBuf::from("/")], &CollectOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::NoNamespace(_)), "{err}");
+    }
+
+    #[test]
+    fn a_single_root_has_no_namespace() {
+        let dir = scratch_dir();
+        write_files(&dir, &[("net/dns.b", TEST), ("README.md", "")]);
+        let graph = load_tests(&dir).unwrap();
+        assert_eq!(leaf_names(&graph), ["net::dns"]);
+        assert_eq!(graph.module_count(), 2);
+        assert_eq!(graph.len(), 1);
+    }
+
+    #[test]
+    fn helper_files_are_not_collected() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                ("_helpers.b", "greet() { echo hi; }\n"),
+                ("net/_lib.b", "resolve() { :; }\n"),
+                ("net/dns.b", TEST),
+                ("_fixtures/data.b", TEST),
+            ],
+        );
+        let graph = load_tests(&dir).unwrap();
+        // A directory named like a helper is still a directory of tests.
+        assert_eq!(leaf_names(&graph), ["_fixtures::data", "net::dns"]);
+        assert!(is_helper_file(Path::new("net/_lib.b")));
+        assert!(!is_helper_file(Path::new("_fixtures/data.b")));
+        assert!(!is_helper_file(Path::new("lib_.b")));
+    }
+
+    #[test]
+    fn counts_of_a_nested_graph() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                ("top.b", TEST),
+                ("net/dns.b", TEST),
+                ("net/http/get.b", TEST),
+                ("net/http/post.b", TEST),
+                ("empty/README.md", ""),
+            ],
+        );
+        let graph = load_tests(&dir).unwrap();
+        // The directories are empty, net, and net::http.
+        assert_eq!(graph.module_count(), 7);
+        assert_eq!(graph.leaf_count(), 4);
+        assert_eq!(graph.len(), graph.leaf_count());
+        assert!(!graph.is_empty());
+
+        let only_dirs = scratch_dir();
+        write_files(&only_dirs, &[("a/b/README.md", "")]);
+        let graph = load_tests(&only_dirs).unwrap();
+        assert_eq!((graph.module_count(), graph.len()), (2, 0));
+        assert!(graph.is_empty());
+    }
+
+    #[test]
+    fn missing_roots_are_an_error_naming_them() {
+        let scratch = scratch_dir();
+        let missing = scratch.join("tests");
+        let err = load_tests(&missing).unwrap_err();
+        assert!(
+            matches!(&err, Error::NoTestDir(path) if *path == missing),
+            "{err}"
+        );
+        let err = load_test_roots(std::slice::from_ref(&missing), &CollectOptions::default())
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::NoTestDir(path) if *path == missing),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn ignored_fixtures_are_left_out_of_the_graph() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                ("net/dns.b", TEST),
+                ("fixtures/ignored.b", TEST),
+                ("build.b", TEST),
+                (ignore::BISHINIGNORE_FILE, "fixtures/\n"),
+                (ignore::GITIGNORE_FILE, "build.b\n"),
+            ],
+        );
+        let options = |respect_gitignore| CollectOptions {
+            respect_bishinignore: true,
+            respect_gitignore,
+            ..CollectOptions::default()
+        };
+        let graph = load_tests_with(&dir, &options(false)).unwrap();
+        assert_eq!(leaf_names(&graph), ["build", "net::dns"]);
+        assert!(graph
+            .iter_modules()
+            .all(|module| module.name() != "fixtures"));
+        let graph = load_tests_with(&dir, &options(true)).unwrap();
+        assert_eq!(leaf_names(&graph), ["net::dns"]);
+        // load_tests reads no ignore files, as before they were supported.
+        let graph = load_tests(&dir).unwrap();
+        assert_eq!(
+            leaf_names(&graph),
+            ["build", "fixtures::ignored", "net::dns"]
+        );
+    }
+
+    #[test]
+    fn ignore_files_take_precedence_over_the_configured_patterns() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                ("slow/a.b", TEST),
+                ("slow/b.b", TEST),
+                ("slow/.bishinignore", "!a.b\n"),
+            ],
+        );
+        let options = CollectOptions {
+            ignore: vec![Pattern::new("slow/*.b").unwrap()],
+            respect_bishinignore: true,
+            ..CollectOptions::default()
+        };
+        let graph = load_tests_with(&dir, &options).unwrap();
+        assert_eq!(leaf_names(&graph), ["slow::a"]);
+    }
+
+    #[test]
+    fn only_files_with_the_configured_extensions_are_collected() {
+        let dir = scratch_dir();
+        write_files(
+            &dir,
+            &[
+                ("net/dns.bishin", TEST),
+                ("net/http.BISHIN", TEST),
+                ("net/old.b", TEST),
+                ("net/notes.txt", "not tests"),
+            ],
+        );
+        let options = CollectOptions {
+            extensions: vec!["bishin".to_string()],
+            ..CollectOptions::default()
+        };
+        let graph = load_tests_with(&dir, &options).unwrap();
+        assert_eq!(leaf_names(&graph), ["net::dns", "net::http"]);
+        assert_eq!(leaf_names(&load_tests(&dir).unwrap()), ["net::old"]);
+    }
+}
@@ -0,0 +1,319 @@
+//! Gitignore-style patterns for leaving files out of collection.
+//!
+//! - `*` matches anything except `/`, `?` matches one character except `/`,
+//!   and `[a-z]` or `[!a-z]` match one character in or not in a set,
+//! - `**` as a whole path segment matches any number of directories,
+//! - a pattern containing a `/` other than at the end is matched against the
+//!   whole path from the test root, otherwise against the name of each file
+//!   and directory at any depth,
+//! - a trailing `/` only matches directories,
+//! - a leading `!` re-includes what an earlier pattern left out,
+//! - `\` matches the character after it literally.
+
+use std::fmt;
+use std::path::{Component, Path};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PatternError {
+    #[error("empty pattern")]
+    Empty,
+    #[error("unterminated character class in pattern {0:?}")]
+    UnterminatedClass(String),
+    #[error("pattern {0:?} ends with an escape")]
+    TrailingEscape(String),
+}
+
+/// A compiled pattern, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    negated: bool,
+    dir_only: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// `**`, matching any number of path segments.
+    AnyDirs,
+    Glob(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`
+    One,
+    /// `*`
+    Any,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, PatternError> {
+        let (negated, rest) = match source.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, source),
+        };
+        let (dir_only, rest) = match rest.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        if rest.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        let anchored = rest.contains('/');
+        let mut segments = Vec::new();
+        if !anchored {
+            segments.push(Segment::AnyDirs);
+        }
+        for segment in rest.trim_start_matches('/').split('/') {
+            segments.push(match segment {
+                "**" => Segment::AnyDirs,
+                segment => Segment::Glob(tokens(segment, source)?),
+            });
+        }
+        Ok(Self {
+            source: source.to_string(),
+            negated,
+            dir_only,
+            segments,
+        })
+    }
+
+    /// The pattern as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether this is a `!` pattern that re-includes paths.
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Whether the pattern matches `path`, relative to the test root,
+    /// regardless of whether it is negated.
+    pub fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let names: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        match_segments(&self.segments, &names)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Whether `path` is left out by `patterns`, where later patterns take
+/// precedence over earlier ones.
+pub fn is_ignored(patterns: &[Pattern], path: &Path, is_dir: bool) -> bool {
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| pattern.matches(path, is_dir))
+        .is_some_and(|pattern| !pattern.negated)
+}
+
+fn tokens(segment: &str, source: &str) -> Result<Vec<Token>, PatternError> {
+    let chars: Vec<char> = segment.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let token = match chars[i] {
+            '\\' => {
+                i += 1;
+                Token::Char(
+                    *chars
+                        .get(i)
+                        .ok_or_else(|| PatternError::TrailingEscape(source.to_string()))?,
+                )
+            }
+            '?' => Token::One,
+            // `a**b` is just two stars in a row.
+            '*' if tokens.last() == Some(&Token::Any) => {
+                i += 1;
+                continue;
+            }
+            '*' => Token::Any,
+            '[' => {
+                let (token, end) = class(&chars, i + 1)
+                    .ok_or_else(|| PatternError::UnterminatedClass(source.to_string()))?;
+                i = end;
+                token
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// The class starting at `start`, just after its opening bracket, and the
+/// index of its closing bracket.
+fn class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let first = i;
+    let mut ranges = Vec::new();
+    loop {
+        let c = match *chars.get(i)? {
+            // A `]` straight after the opening bracket is part of the set.
+            ']' if i != first => return Some((Token::Class { negated, ranges }, i)),
+            '\\' => {
+                i += 1;
+                *chars.get(i)?
+            }
+            c => c,
+        };
+        i += 1;
+        let end = match (chars.get(i), chars.get(i + 1)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                i += 2;
+                end
+            }
+            _ => c,
+        };
+        ranges.push((c, end));
+    }
+}
+
+fn match_segments(segments: &[Segment], names: &[&str]) -> bool {
+    match segments.split_first() {
+        None => names.is_empty(),
+        Some((Segment::AnyDirs, rest)) => {
+            (0..=names.len()).any(|skip| match_segments(rest, &names[skip..]))
+        }
+        Some((Segment::Glob(tokens), rest)) => match names.split_first() {
+            Some((name, names)) => {
+                let name: Vec<char> = name.chars().collect();
+                match_tokens(tokens, &name) && match_segments(rest, names)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_tokens(tokens: &[Token], name: &[char]) -> bool {
+    match tokens.split_first() {
+        None => name.is_empty(),
+        Some((Token::Any, rest)) => (0..=name.len()).any(|skip| match_tokens(rest, &name[skip..])),
+        Some((token, rest)) => match name.split_first() {
+            Some((c, name)) => match_char(token, *c) && match_tokens(rest, name),
+            None => false,
+        },
+    }
+}
+
+fn match_char(token: &Token, c: char) -> bool {
+    match token {
+        Token::Char(expected) => *expected == c,
+        Token::One => true,
+        Token::Any => unreachable!("stars match runs of characters"),
+        Token::Class { negated, ranges } => {
+            ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&c))
+                != *negated
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str, is_dir: bool) -> bool {
+        Pattern::new(pattern)
+            .unwrap()
+            .matches(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn what_each_pattern_matches() {
+        let cases = [
+            ("wip_*.b", "wip_dns.b", true),
+            ("wip_*.b", "net/wip_dns.b", true),
+            ("wip_*.b", "net/dns.b", false),
+            ("wip_*.b", "wip_/x.b", false),
+            ("?.b", "a.b", true),
+            ("?.b", "ab.b", false),
+            ("[a-c].b", "b.b", true),
+            ("[a-c].b", "d.b", false),
+            ("[!a-c].b", "d.b", true),
+            ("[]].b", "].b", true),
+            ("a**b", "axyzb", true),
+            ("\\*.b", "*.b", true),
+            ("\\*.b", "x.b", false),
+            ("net/dns.b", "net/dns.b", true),
+            ("net/dns.b", "sub/net/dns.b", false),
+            ("/dns.b", "dns.b", true),
+            ("/dns.b", "net/dns.b", false),
+            ("**/fixtures/**", "fixtures", true),
+            ("**/fixtures/**", "a/b/fixtures/c/d.b", true),
+            ("**/fixtures/**", "a/fixtures.b", false),
+            ("net/**/dns.b", "net/dns.b", true),
+            ("net/**/dns.b", "net/a/b/dns.b", true),
+            ("net/**/dns.b", "other/dns.b", false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(
+                matches(pattern, path, false),
+                expected,
+                "{pattern} on {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_slashes_only_match_directories() {
+        assert!(matches("build/", "src/build", true));
+        assert!(!matches("build/", "src/build", false));
+        assert!(matches("build", "src/build", false));
+    }
+
+    #[test]
+    fn later_patterns_take_precedence() {
+        let patterns: Vec<Pattern> = ["*.b", "!keep.b", "keep.b"]
+            .into_iter()
+            .map(|source| Pattern::new(source).unwrap())
+            .collect();
+        assert!(is_ignored(&patterns, Path::new("x.b"), false));
+        assert!(is_ignored(&patterns, Path::new("keep.b"), false));
+        assert!(!is_ignored(&patterns[..2], Path::new("keep.b"), false));
+        assert!(!is_ignored(&patterns, Path::new("x.sh"), false));
+        assert!(patterns[1].is_negated());
+        assert_eq!(patterns[1].to_string(), "!keep.b");
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert_eq!(Pattern::new(""), Err(PatternError::Empty));
+        assert_eq!(Pattern::new("!/"), Err(PatternError::Empty));
+        assert_eq!(
+            Pattern::new("[a-"),
+            Err(PatternError::UnterminatedClass("[a-".to_string()))
+        );
+        assert_eq!(
+            Pattern::new("a\\"),
+            Err(PatternError::TrailingEscape("a\\".to_string()))
+        );
+    }
+}
@@ -0,0 +1,13 @@
+//! Helpers for the tests in this crate.
+
+pub(crate) use bishin_testing::{scratch_dir, write_files, ScratchDir};
+
+use crate::ModuleGraph;
+
+/// The names of the modules in `graph` that contain tests.
+pub(crate) fn leaf_names(graph: &ModuleGraph) -> Vec<String> {
+    graph
+        .iter_leaf_modules()
+        .map(|module| module.name())
+        .collect()
+}
@@ -0,0 +1,259 @@
+//! Errors in config files, pointing at where in the file they are.
+//!
+//! ```text
+//! invalid config: timeout: invalid type: integer `5`, expected a timeout ...
+//!  --> bishin.toml:2:11
+//!   |
+//! 2 | timeout = 5
+//!   |           ^
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+
+/// A config that isn't valid TOML, or has a value of the wrong type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The file the config came from, if it came straight from one.
+    pub path: Option<PathBuf>,
+    pub message: String,
+    /// The dotted path to the offending key, e.g. `env.RUST_LOG`.
+    pub field: Option<String>,
+    /// Where in the file the error is, if known.
+    pub location: Option<Location>,
+}
+
+/// A position in a config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The line number, starting from 1.
+    pub line: usize,
+    /// The column in characters, starting from 1.
+    pub column: usize,
+    /// The byte range of the offending text in the file.
+    pub span: Range<usize>,
+    /// The text of the line, for showing the error in context.
+    pub line_text: String,
+}
+
+impl ParseError {
+    /// Describe `err`, which came from parsing `source` if given.
+    pub(crate) fn new(err: toml::de::Error, source: Option<(&Path, &str)>) -> Self {
+        let (Some(span), Some((path, contents))) = (err.span(), source) else {
+            // Without a span the message is all there is, and toml's own
+            // rendering of it includes the key.
+            return Self {
+                path: source.map(|(path, _)| path.to_path_buf()),
+                message: err.to_string().trim_end().to_string(),
+                field: None,
+                location: None,
+            };
+        };
+        Self {
+            path: Some(path.to_path_buf()),
+            message: err.message().to_string(),
+            field: field_at(contents, span.start),
+            location: Some(Location::new(contents, span)),
+        }
+    }
+}
+
+impl Location {
+    pub(crate) fn new(contents: &str, span: Range<usize>) -> Self {
+        let start = span.start.min(contents.len());
+        let line_start = contents[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = contents[start..]
+            .find('\n')
+            .map_or(contents.len(), |i| start + i);
+        Self {
+            line: contents[..start].matches('\n').count() + 1,
+            column: contents[line_start..start].chars().count() + 1,
+            span,
+            line_text: contents[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+        }
+    }
+
+    /// The byte offset of the column within the line.
+    fn column_byte(&self) -> usize {
+        self.line_text
+            .char_indices()
+            .nth(self.column - 1)
+            .map_or(self.line_text.len(), |(i, _)| i)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid config")?;
+        if let (Some(path), None) = (&self.path, &self.location) {
+            write!(f, " in {}", path.display())?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": {field}")?;
+        }
+        write!(f, ": {}", self.message)?;
+
+        let Some(location) = &self.location else {
+            return Ok(());
+        };
+        let path = self.path.as_deref().unwrap_or(Path::new("<config>"));
+        let line = location.line.to_string();
+        let gutter = " ".repeat(line.len());
+        let rest = &location.line_text[location.column_byte()..];
+        let width = rest
+            .char_indices()
+            .take_while(|(i, _)| *i < location.span.len())
+            .count()
+            .max(1);
+        write!(
+            f,
+            "\n{gutter}--> {}:{}:{}\n{gutter} |\n{line} | {}\n{gutter} | {}{}",
+            path.display(),
+            location.line,
+            location.column,
+            location.line_text,
+            " ".repeat(location.column - 1),
+            "^".repeat(width),
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The dotted path to the key at byte `offset` in `contents`, or to the key
+/// whose value is there.
+fn field_at(contents: &str, offset: usize) -> Option<String> {
+    let table = DeTable::parse(contents).ok()?;
+    let mut path = Vec::new();
+    find_in_table(table.get_ref(), offset, &mut path).then(|| path.join("."))
+}
+
+fn find_in_table(table: &DeTable<'_>, offset: usize, path: &mut Vec<String>) -> bool {
+    for (key, value) in table {
+        path.push(key.get_ref().to_string());
+        if key.span().contains(&offset) || find_in_value(value, offset, path) {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
+
+fn find_in_value(value: &Spanned<DeValue<'_>>, offset: usize, path: &mut Vec<String>) -> bool {
+    // Tables from `[header]`s don't span their contents, so look inside
+    // before giving up on them.
+    let found = match value.get_ref() {
+        DeValue::Table(table) => find_in_table(table, offset, path),
+        DeValue::Array(array) => array.iter().enumerate().any(|(i, value)| {
+            path.push(i.to_string());
+            let found = find_in_value(value, offset, path);
+            if !found {
+                path.pop();
+            }
+            found
+        }),
+        _ => false,
+    };
+    found || value.span().contains(&offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write};
+    use crate::{Config, Error};
+
+    /// The error from loading `contents` as it's shown, with the file just
+    /// called `bishin.toml`.
+    fn rendered(contents: &str) -> String {
+        let scratch = scratch_dir();
+        let path = write(&scratch, crate::CONFIG_FILE, contents);
+        let err = Config::load(&path).unwrap_err();
+        err.to_string()
+            .replace(&path.display().to_string(), "bishin.toml")
+    }
+
+    #[test]
+    fn type_mismatches_point_at_the_value() {
+        assert_eq!(
+            rendered("timeout = 5\n"),
+            "invalid config: timeout: invalid type: integer `5`, expected a timeout given as a whole \
+             number followed by ms, s, m, or h (e.g. 500ms, 30s, 2m), or \"none\"\n \
+             --> bishin.toml:1:11\n  |\n1 | timeout = 5\n  |           ^"
+        );
+        assert_eq!(
+            rendered("[env]\nRUST_LOG = 1\n"),
+            "invalid config: env.RUST_LOG: invalid type: integer `1`, expected a string\n \
+             --> bishin.toml:2:12\n  |\n2 | RUST_LOG = 1\n  |            ^"
+        );
+        assert_eq!(
+            rendered("test-dirs = [\"a\", 2]\n"),
+            "invalid config: test-dirs.1: invalid type: integer `2`, expected path string\n \
+             --> bishin.toml:1:19\n  |\n1 | test-dirs = [\"a\", 2]\n  |                   ^"
+        );
+    }
+
+    #[test]
+    fn underlines_are_as_wide_as_the_value() {
+        assert_eq!(
+            rendered("jobs = \"∞\" # ok\n"),
+            "invalid config: jobs: invalid jobs value \"∞\", expected a positive number or \"auto\"\n \
+             --> bishin.toml:1:8\n  |\n1 | jobs = \"∞\" # ok\n  |        ^^^"
+        );
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_line() {
+        assert_eq!(
+            rendered("test-dir = \"tests\"\r\njobs = \r\n"),
+            "invalid config: string values must be quoted, expected literal string\n \
+             --> bishin.toml:2:8\n  |\n2 | jobs = \n  |        ^"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_point_at_the_key() {
+        assert_eq!(
+            rendered("wrok-dir = \"out\"\n"),
+            "bishin.toml:1:1: unknown key `wrok-dir`; did you mean `work-dir`?"
+        );
+    }
+
+    #[test]
+    fn locations_are_there_for_tools() {
+        let scratch = scratch_dir();
+        let path = write(
+            &scratch,
+            crate::CONFIG_FILE,
+            "jobs = 2\n\n[env]\nA = true\n",
+        );
+        let Err(Error::Parse(err)) = Config::load(&path) else {
+            panic!("loaded");
+        };
+        assert_eq!(err.path.as_deref(), Some(path.as_path()));
+        assert_eq!(err.field.as_deref(), Some("env.A"));
+        assert_eq!(
+            err.location,
+            Some(Location {
+                line: 4,
+                column: 5,
+                span: 20..24,
+                line_text: "A = true".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn errors_without_a_file_have_no_location() {
+        let err: toml::de::Error = toml::from_str::<Config>("jobs = []").unwrap_err();
+        let err = ParseError::new(err, None);
+        assert_eq!((err.path, err.field, err.location), (None, None, None));
+        assert!(err.message.contains("jobs"), "{}", err.message);
+    }
+}
@@ -0,0 +1,202 @@
+//! Configs kept in a table of a file that's mostly about something else,
+//! such as `[package.metadata.bishin]` in a `Cargo.toml`.
+//!
+//! Tables are named by their dotted path from the top of the file. Errors in
+//! them point at where they are in the whole file.
+
+use std::path::{Path, PathBuf};
+
+use serde::de::{DeserializeOwned, Error as _};
+use toml::de::{DeTable, DeValue};
+use toml::{Spanned, Table, Value};
+
+/// The name of Cargo's manifest.
+pub const CARGO_MANIFEST: &str = "Cargo.toml";
+
+/// The tables of a Cargo manifest that can hold a config, in the order
+/// they're looked for.
+pub const CARGO_TABLES: [&str; 2] = ["package.metadata.bishin", "workspace.metadata.bishin"];
+
+/// The nearest Cargo manifest in `dir` or a directory above it with a config
+/// in one of its [`CARGO_TABLES`], and the table it's in.
+///
+/// Manifests without one are passed over, so a member of a workspace finds
+/// the config of the workspace.
+pub fn find_cargo_config(dir: &Path) -> Option<(PathBuf, &'static str)> {
+    dir.ancestors().find_map(|dir| {
+        let path = dir.join(CARGO_MANIFEST);
+        let contents = std::fs::read_to_string(&path).ok()?;
+        let manifest: Table = toml::from_str(&contents).ok()?;
+        let table = CARGO_TABLES
+            .into_iter()
+            .find(|table| table_at(manifest.clone(), table).is_some())?;
+        Some((path, table))
+    })
+}
+
+/// The table at the dotted path `within` in `table`, if there is one.
+pub(crate) fn table_at(mut table: Table, within: &str) -> Option<Table> {
+    for key in within.split('.') {
+        match table.remove(key)? {
+            Value::Table(inner) => table = inner,
+            _ => return None,
+        }
+    }
+    Some(table)
+}
+
+/// Like [`table_at`], for a table that still knows where everything in it
+/// is in the file.
+pub(crate) fn de_table_at<'a, 's>(
+    mut table: &'a DeTable<'s>,
+    within: &str,
+) -> Option<&'a DeTable<'s>> {
+    for key in within.split('.') {
+        let (_, value) = table.iter().find(|(name, _)| name.get_ref() == key)?;
+        match value.get_ref() {
+            DeValue::Table(inner) => table = inner,
+            _ => return None,
+        }
+    }
+    Some(table)
+}
+
+/// Deserialize the table at `within` in `contents` as a `T`.
+pub(crate) fn from_table_at<T: DeserializeOwned>(
+    contents: &str,
+    within: &str,
+) -> Result<T, toml::de::Error> {
+    let mut table = DeTable::parse(contents)?;
+    for key in within.split('.') {
+        let value = table
+            .get_ref()
+            .iter()
+            .find(|(name, _)| name.get_ref() == key)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| toml::de::Error::custom(format!("there's no [{within}] table")))?;
+        let span = value.span();
+        table = match value.into_inner() {
+            DeValue::Table(inner) => Spanned::new(span, inner),
+            _ => return Err(toml::de::Error::custom(format!("{within} isn't a table"))),
+        };
+    }
+    T::deserialize(toml::de::Deserializer::from(table))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write};
+    use crate::{Config, ConfigSource, Error, Location};
+
+    const PACKAGE: &str = "[package]\nname = \"app\"\n\n[package.metadata.bishin]\njobs = 2\n";
+
+    #[test]
+    fn the_package_table_is_found_from_below() {
+        let dir = scratch_dir();
+        let manifest = write(&dir, CARGO_MANIFEST, PACKAGE);
+        let src = dir.join("src/bin");
+        std::fs::create_dir_all(&src).unwrap();
+        assert_eq!(
+            find_cargo_config(&src),
+            Some((manifest.clone(), "package.metadata.bishin"))
+        );
+        let config =
+            Config::load_table_with(&manifest, "package.metadata.bishin", &Default::default())
+                .unwrap();
+        assert_eq!(config.jobs, Some(2));
+        assert_eq!(config.dir(), dir.path());
+        assert_eq!(
+            config.source().to_string(),
+            format!("[package.metadata.bishin] in {}", manifest.display())
+        );
+        assert!(matches!(config.source(), ConfigSource::Table { .. }));
+    }
+
+    #[test]
+    fn members_find_the_workspace_table() {
+        let dir = scratch_dir();
+        let manifest = write(
+            &dir,
+            CARGO_MANIFEST,
+            "[workspace]\nmembers = [\"member\"]\n\n[workspace.metadata.bishin]\ntest-dir = \"e2e\"\n",
+        );
+        let member = dir.join("member");
+        std::fs::create_dir_all(&member).unwrap();
+        // The member's manifest has no config, so it's passed over.
+        write(&member, CARGO_MANIFEST, "[package]\nname = \"member\"\n");
+        let (path, table) = find_cargo_config(&member).unwrap();
+        assert_eq!((&path, table), (&manifest, "workspace.metadata.bishin"));
+        let config = Config::load_table_with(&path, table, &Default::default()).unwrap();
+        assert_eq!(config.resolved_test_dirs(), [dir.join("e2e")]);
+    }
+
+    #[test]
+    fn manifests_without_a_config_are_not_found() {
+        let scratch = scratch_dir();
+        let dir = scratch.join("nested");
+        std::fs::create_dir_all(&dir).unwrap();
+        write(
+            &dir,
+            CARGO_MANIFEST,
+            "[package]\nname = \"app\"\n[package.metadata]\nother = 1\n",
+        );
+        // A directory above could still have one, but not the scratch ones.
+        assert_eq!(
+            find_cargo_config(&dir).filter(|(path, _)| path.starts_with(&dir)),
+            None
+        );
+        let invalid = scratch_dir();
+        write(&invalid, CARGO_MANIFEST, "[package.metadata.bishin\n");
+        assert_eq!(
+            find_cargo_config(&invalid).filter(|(path, _)| path.starts_with(&invalid)),
+            None
+        );
+    }
+
+    #[test]
+    fn errors_point_into_the_whole_manifest() {
+        let dir = scratch_dir();
+        let manifest = write(
+            &dir,
+            CARGO_MANIFEST,
+            "[package]\nname = \"app\"\n\n[package.metadata.bishin]\njobs = \"many\"\n",
+        );
+        let Err(Error::Parse(err)) =
+            Config::load_table_with(&manifest, "package.metadata.bishin", &Default::default())
+        else {
+            panic!("loaded");
+        };
+        assert_eq!(err.field.as_deref(), Some("package.metadata.bishin.jobs"));
+        assert_eq!(
+            err.location.map(
+                |Location {
+                     line, line_text, ..
+                 }| (line, line_text)
+            ),
+            Some((5, "jobs = \"many\"".to_string()))
+        );
+    }
+
+    #[test]
+    fn tables_are_found_by_their_dotted_path() {
+        let table: Table = toml::from_str(PACKAGE).unwrap();
+        assert_eq!(
+            table_at(table.clone(), "package.metadata.bishin").unwrap()["jobs"],
+            Value::Integer(2)
+        );
+        assert_eq!(table_at(table.clone(), "package.name"), None);
+        assert_eq!(table_at(table, "workspace.metadata.bishin"), None);
+        let err = from_table_at::<Table>(PACKAGE, "workspace.metadata").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("there's no [workspace.metadata] table"),
+            "{err}"
+        );
+        let err = from_table_at::<Table>(PACKAGE, "package.name").unwrap_err();
+        assert!(
+            err.to_string().contains("package.name isn't a table"),
+            "{err}"
+        );
+    }
+}
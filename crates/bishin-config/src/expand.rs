@@ -0,0 +1,273 @@
+//! `${VAR}` references in config values, expanded when the config is loaded.
+//!
+//! - `${VAR}` is replaced by the value of `VAR`, which must be set,
+//! - `${VAR:-default}` falls back to `default` when `VAR` is unset or empty,
+//! - `$$` is a literal `$`.
+//!
+//! Any other `$` is left as it is.
+
+use std::path::PathBuf;
+
+use toml::{Table, Value};
+
+use crate::{Config, Error, Lookup};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ExpandError {
+    #[error("${{{0}}} is not set")]
+    Unset(String),
+    #[error("{0:?} is not a valid variable name")]
+    InvalidName(String),
+    #[error("unterminated variable reference in {0:?}")]
+    Unterminated(String),
+}
+
+/// Expand the references in `value`, looking variables up with `lookup`.
+pub fn expand(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, ExpandError> {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| ExpandError::Unterminated(value.to_string()))?;
+            output.push_str(&reference(&after[..end], &lookup)?);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = after;
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// The value of the `NAME` or `NAME:-default` between the braces of a
+/// reference.
+fn reference(
+    reference: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, ExpandError> {
+    let (name, default) = match reference.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (reference, None),
+    };
+    if !is_valid_name(name) {
+        return Err(ExpandError::InvalidName(name.to_string()));
+    }
+    match (lookup(name), default) {
+        (Some(value), Some(default)) if value.is_empty() => Ok(default.to_string()),
+        (Some(value), _) => Ok(value),
+        (None, Some(default)) => Ok(default.to_string()),
+        (None, None) => Err(ExpandError::Unset(name.to_string())),
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Config {
+    /// Expand the references in every value that may contain them. Keys are
+    /// left alone, as are profiles until one is selected.
+    pub(crate) fn expand(&mut self, lookup: &Lookup<'_>) -> Result<(), Error> {
+        expand_path(&mut self.test_dir, "test-dir".to_string(), lookup)?;
+        for (i, path) in self.test_dirs.iter_mut().enumerate() {
+            expand_path(path, format!("test-dirs[{i}]"), lookup)?;
+        }
+        expand_path(&mut self.work_dir, "work-dir".to_string(), lookup)?;
+        if let Some(path) = &mut self.setup_script {
+            expand_path(path, "setup-script".to_string(), lookup)?;
+        }
+        if let Some(path) = &mut self.teardown_script {
+            expand_path(path, "teardown-script".to_string(), lookup)?;
+        }
+        if let Some(path) = &mut self.report_dir {
+            expand_path(path, "report-dir".to_string(), lookup)?;
+        }
+        if let Some(path) = &mut self.snapshots.dir {
+            expand_path(path, "snapshots.dir".to_string(), lookup)?;
+        }
+        if let Some(prefix) = &mut self.name_prefix {
+            expand_field(prefix, "name-prefix".to_string(), lookup)?;
+        }
+        for (key, value) in &mut self.env {
+            expand_field(value, format!("env.{key}"), lookup)?;
+        }
+        Ok(())
+    }
+}
+
+/// Expand the references in the values of the profile called `name`, the same
+/// ones as [`Config::expand`] does at the top level.
+pub(crate) fn expand_profile(
+    profile: &mut Table,
+    name: &str,
+    lookup: &Lookup<'_>,
+) -> Result<(), Error> {
+    for key in [
+        "test-dir",
+        "work-dir",
+        "setup-script",
+        "teardown-script",
+        "report-dir",
+        "name-prefix",
+    ] {
+        if let Some(Value::String(value)) = profile.get_mut(key) {
+            expand_field(value, format!("profile.{name}.{key}"), lookup)?;
+        }
+    }
+    if let Some(Value::Table(snapshots)) = profile.get_mut("snapshots") {
+        if let Some(Value::String(value)) = snapshots.get_mut("dir") {
+            expand_field(value, format!("profile.{name}.snapshots.dir"), lookup)?;
+        }
+    }
+    if let Some(Value::Array(paths)) = profile.get_mut("test-dirs") {
+        for (i, value) in paths.iter_mut().enumerate() {
+            if let Value::String(value) = value {
+                expand_field(value, format!("profile.{name}.test-dirs[{i}]"), lookup)?;
+            }
+        }
+    }
+    if let Some(Value::Table(env)) = profile.get_mut("env") {
+        for (key, value) in env.iter_mut() {
+            if let Value::String(value) = value {
+                expand_field(value, format!("profile.{name}.env.{key}"), lookup)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn expand_path(path: &mut PathBuf, field: String, lookup: &Lookup<'_>) -> Result<(), Error> {
+    // Paths from TOML are always valid UTF-8.
+    let mut value = path.to_string_lossy().into_owned();
+    expand_field(&mut value, field, lookup)?;
+    *path = PathBuf::from(value);
+    Ok(())
+}
+
+fn expand_field(value: &mut String, field: String, lookup: &Lookup<'_>) -> Result<(), Error> {
+    *value = expand(value, lookup).map_err(|source| Error::Expand { field, source })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write};
+    use crate::LoadOptions;
+    use std::path::Path;
+
+    /// `HOME` is set, `EMPTY` is set to nothing and nothing else is set.
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/me".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn the_grammar() {
+        let cases = [
+            ("plain", "plain"),
+            ("${HOME}", "/home/me"),
+            ("${HOME}/a/${HOME}", "/home/me/a//home/me"),
+            ("${UNSET:-fallback}", "fallback"),
+            ("${EMPTY:-fallback}", "fallback"),
+            ("${HOME:-fallback}", "/home/me"),
+            ("${UNSET:-}", ""),
+            ("${UNSET:-a:-b}", "a:-b"),
+            ("${EMPTY}", ""),
+            ("$$", "$"),
+            ("$${HOME}", "${HOME}"),
+            ("$$$$", "$$"),
+            ("$HOME", "$HOME"),
+            ("cost: 5$", "cost: 5$"),
+            ("a$", "a$"),
+            ("${_X1:-y}", "y"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(expand(value, lookup).as_deref(), Ok(expected), "{value}");
+        }
+    }
+
+    #[test]
+    fn grammar_errors() {
+        let cases = [
+            ("${UNSET}", ExpandError::Unset("UNSET".to_string())),
+            ("a/${UNSET}/b", ExpandError::Unset("UNSET".to_string())),
+            ("${}", ExpandError::InvalidName(String::new())),
+            ("${1X}", ExpandError::InvalidName("1X".to_string())),
+            ("${A-B}", ExpandError::InvalidName("A-B".to_string())),
+            ("${:-x}", ExpandError::InvalidName(String::new())),
+            ("${HOME", ExpandError::Unterminated("${HOME".to_string())),
+            ("ok ${", ExpandError::Unterminated("ok ${".to_string())),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(expand(value, lookup), Err(expected), "{value}");
+        }
+    }
+
+    fn load(contents: &str) -> Result<Config, Error> {
+        let scratch = scratch_dir();
+        let path = write(&scratch, crate::CONFIG_FILE, contents);
+        Config::load_with(
+            &path,
+            &LoadOptions {
+                lookup: Some(&lookup),
+                ..LoadOptions::default()
+            },
+        )
+    }
+
+    #[test]
+    fn paths_and_env_values_are_expanded_but_not_keys() {
+        let config = load(
+            "test-dir = \"${HOME}/tests\"\nwork-dir = \"${UNSET:-out}\"\nsetup-script = \"$$HOME\"\n[env]\n\"${HOME}\" = \"${HOME}\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.test_dir, Path::new("/home/me/tests"));
+        assert_eq!(config.work_dir, Path::new("out"));
+        assert_eq!(config.setup_script.as_deref(), Some(Path::new("$HOME")));
+        assert_eq!(config.env["${HOME}"], "/home/me");
+    }
+
+    #[test]
+    fn errors_name_the_field_and_variable() {
+        let err = load("[env]\nURL = \"${BASE_URL}/api\"\n").unwrap_err();
+        assert!(
+            matches!(&err, Error::Expand { field, source: ExpandError::Unset(name) } if field == "env.URL" && name == "BASE_URL"),
+            "{err}"
+        );
+        assert_eq!(err.to_string(), "failed to expand env.URL");
+
+        let err = load("test-dirs = [\"a\", \"${NOPE}\"]\n").unwrap_err();
+        assert!(
+            matches!(&err, Error::Expand { field, .. } if field == "test-dirs[1]"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn selected_profiles_are_expanded() {
+        let mut profile: Table =
+            toml::from_str("work-dir = \"${HOME}/ci\"\n[env]\nA = \"${UNSET}\"\n").unwrap();
+        let err = expand_profile(&mut profile, "ci", &lookup).unwrap_err();
+        assert!(
+            matches!(&err, Error::Expand { field, .. } if field == "profile.ci.env.A"),
+            "{err}"
+        );
+        assert_eq!(profile["work-dir"].as_str(), Some("/home/me/ci"));
+    }
+}
@@ -0,0 +1,354 @@
+//! Config files that build on another with `extends = "path/to/base.toml"`.
+//!
+//! The base is loaded first and the extending file is merged over it with the
+//! same rules as profiles. Bases may extend further bases, up to
+//! [`MAX_DEPTH`] files deep.
+//!
+//! Relative paths in a base are relative to the base file, so they are
+//! rewritten to be relative to the extending file before merging.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::{embedded, profile, unknown, Config, Error};
+
+/// How many files a chain of `extends` may contain, counting the first.
+pub(crate) const MAX_DEPTH: usize = 16;
+
+/// Keys whose values are paths, with those in tables written `table.key`.
+const PATH_KEYS: &[&str] = &[
+    "test-dir",
+    "test-dirs",
+    "work-dir",
+    "setup-script",
+    "teardown-script",
+    "report-dir",
+    "snapshots.dir",
+    "reports.junit",
+    "reports.json",
+    "reports.jsonl-events",
+    "reports.html",
+    "reports.markdown",
+];
+
+/// The profiles of a config file, each of which should have the same types
+/// as the config itself.
+#[derive(Deserialize)]
+struct Profiles {
+    #[serde(default, alias = "profiles")]
+    #[allow(dead_code)]
+    profile: BTreeMap<String, Config>,
+}
+
+/// Read the config file at `path` as a table with every base it extends
+/// merged in. If `within` is given, the config is the table at that dotted
+/// path in the file rather than the whole file, see [`embedded`].
+///
+/// Unknown keys are an error unless `allow_unknown_keys` is set, or the file
+/// they're in sets `allow-unknown-keys` itself.
+pub(crate) fn load_table(
+    path: &Path,
+    within: Option<&str>,
+    allow_unknown_keys: bool,
+) -> Result<Table, Error> {
+    load_chain(path, within, allow_unknown_keys, &mut Vec::new())
+}
+
+/// Like [`load_table`], where `chain` holds the files that lead to `path`.
+fn load_chain(
+    path: &Path,
+    within: Option<&str>,
+    allow_unknown_keys: bool,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Table, Error> {
+    let contents = std::fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    // Compare canonical paths so that the same file reached two different
+    // ways is still recognised as a cycle. The file was just read, so this
+    // only fails in a race.
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&canonical) {
+        chain.push(canonical);
+        return Err(Error::ExtendsCycle(std::mem::take(chain)));
+    }
+    chain.push(canonical);
+    if chain.len() > MAX_DEPTH {
+        return Err(Error::ExtendsTooDeep(MAX_DEPTH));
+    }
+
+    let parse_error = |err| Error::parse(err, Some((path, &contents)));
+    let mut table: Table = toml::from_str(&contents).map_err(parse_error)?;
+    // Merged tables don't remember where their values came from, so check
+    // the types while the file's own spans are still around to point at.
+    if let Some(within) = within {
+        table = embedded::table_at(table, within).ok_or_else(|| Error::NoTable {
+            path: path.to_path_buf(),
+            table: within.to_string(),
+        })?;
+    }
+    // Before the types, which would call the second spelling a duplicate.
+    if table.contains_key("profile") && table.contains_key("profiles") {
+        return Err(Error::ProfileAndProfiles);
+    }
+    match within {
+        Some(within) => {
+            embedded::from_table_at::<Config>(&contents, within).map_err(parse_error)?;
+            embedded::from_table_at::<Profiles>(&contents, within).map_err(parse_error)?;
+        }
+        None => {
+            toml::from_str::<Config>(&contents).map_err(parse_error)?;
+            toml::from_str::<Profiles>(&contents).map_err(parse_error)?;
+        }
+    }
+    // From here on both spellings are `profile`.
+    if let Some(profiles) = table.remove("profiles") {
+        table.insert("profile".to_string(), profiles);
+    }
+    check_test_dirs(&table)?;
+    let allowed = table.get("allow-unknown-keys") == Some(&Value::Boolean(true));
+    if !allow_unknown_keys && !allowed {
+        let unknown = unknown::unknown_keys(path, &contents, within);
+        if !unknown.is_empty() {
+            return Err(Error::UnknownKeys(unknown));
+        }
+    }
+    let Some(extends) = table.remove("extends") else {
+        return Ok(table);
+    };
+    let Value::String(extends) = extends else {
+        return Err(Error::InvalidExtends);
+    };
+    let extends = PathBuf::from(extends);
+    let base_path = path.parent().unwrap_or(Path::new("")).join(&extends);
+    let mut base =
+        load_chain(&base_path, None, allow_unknown_keys, chain).map_err(|source| match source {
+            // These already say which file they're about, or are about the whole
+            // chain, so don't wrap them once per file in it.
+            Error::Io { .. }
+            | Error::Parse(_)
+            | Error::UnknownKeys(_)
+            | Error::Extends { .. }
+            | Error::ExtendsCycle(_)
+            | Error::ExtendsTooDeep(_) => source,
+            source => Error::Extends {
+                path: base_path.clone(),
+                source: Box::new(source),
+            },
+        })?;
+    rebase_paths(&mut base, extends.parent().unwrap_or(Path::new("")));
+    profile::merge(&mut base, &table);
+    Ok(base)
+}
+
+/// Check that a file, and each of its profiles, sets at most one of
+/// `test-dir` and `test-dirs`.
+fn check_test_dirs(table: &Table) -> Result<(), Error> {
+    let both = |table: &Table| table.contains_key("test-dir") && table.contains_key("test-dirs");
+    if both(table) {
+        return Err(Error::TestDirAndTestDirs);
+    }
+    if let Some(Value::Table(profiles)) = table.get("profile") {
+        for (name, profile) in profiles {
+            if let Value::Table(profile) = profile {
+                if both(profile) {
+                    return Err(Error::Profile {
+                        name: name.clone(),
+                        source: Box::new(Error::TestDirAndTestDirs),
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Make the relative paths in `table`, including those in its profiles,
+/// relative to the directory that `dir` is relative to.
+fn rebase_paths(table: &mut Table, dir: &Path) {
+    if dir.as_os_str().is_empty() {
+        return;
+    }
+    let rebase = |value: &mut Value| {
+        if let Value::String(value) = value {
+            // Variables aren't expanded yet, and a path that starts with one
+            // is almost always meant to be absolute.
+            if !value.starts_with("${") {
+                *value = dir.join(&*value).to_string_lossy().into_owned();
+            }
+        }
+    };
+    for key in PATH_KEYS {
+        let value = match key.split_once('.') {
+            Some((section, key)) => match table.get_mut(section) {
+                Some(Value::Table(section)) => section.get_mut(key),
+                _ => None,
+            },
+            None => table.get_mut(*key),
+        };
+        match value {
+            Some(Value::Array(values)) => values.iter_mut().for_each(rebase),
+            Some(value) => rebase(value),
+            None => {}
+        }
+    }
+    if let Some(Value::Table(profiles)) = table.get_mut("profile") {
+        for (_, profile) in profiles.iter_mut() {
+            if let Value::Table(profile) = profile {
+                rebase_paths(profile, dir);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write, ScratchDir};
+
+    /// A directory with `base.toml` in `shared/` and `package/` to extend it
+    /// from.
+    fn monorepo() -> ScratchDir {
+        let dir = scratch_dir();
+        std::fs::create_dir(dir.join("shared")).unwrap();
+        std::fs::create_dir(dir.join("package")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn chains_are_merged_with_the_leaf_on_top() {
+        let dir = monorepo();
+        write(
+            &dir,
+            "shared/root.toml",
+            "jobs = 1\nretries = 1\n[env]\nA = \"root\"\nB = \"root\"\n",
+        );
+        write(
+            &dir,
+            "shared/base.toml",
+            "extends = \"root.toml\"\njobs = 2\n[env]\nB = \"base\"\n",
+        );
+        let leaf = write(
+            &dir,
+            "package/bishin.toml",
+            "extends = \"../shared/base.toml\"\njobs = 3\n",
+        );
+        let config = Config::load(&leaf).unwrap();
+        assert_eq!(config.jobs, Some(3));
+        assert_eq!(config.retries, Some(1));
+        assert_eq!(config.env["A"], "root");
+        assert_eq!(config.env["B"], "base");
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_file_that_set_them() {
+        let dir = monorepo();
+        write(
+            &dir,
+            "shared/base.toml",
+            "test-dir = \"tests\"\nsetup-script = \"/abs/setup.sh\"\nwork-dir = \"${HOME}/out\"\n[reports]\njunit = \"out/junit.xml\"\n[profile.ci]\nreport-dir = \"reports\"\n",
+        );
+        let leaf = write(
+            &dir,
+            "package/bishin.toml",
+            "extends = \"../shared/base.toml\"\n",
+        );
+        let lookup = |_: &str| Some("/home".to_string());
+        let options = crate::LoadOptions {
+            lookup: Some(&lookup),
+            ..crate::LoadOptions::default()
+        };
+        let config = Config::load_with(&leaf, &options).unwrap();
+        assert_eq!(config.test_dir, Path::new("../shared/tests"));
+        assert_eq!(
+            config.setup_script.as_deref(),
+            Some(Path::new("/abs/setup.sh"))
+        );
+        assert_eq!(
+            config.reports.junit.as_deref(),
+            Some(Path::new("../shared/out/junit.xml"))
+        );
+        assert_eq!(
+            config.resolved_reports().junit,
+            Some(dir.join("package/../shared/out/junit.xml"))
+        );
+
+        let table = load_table(&leaf, None, false).unwrap();
+        assert_eq!(table["work-dir"].as_str(), Some("${HOME}/out"));
+        assert_eq!(
+            table["profile"]["ci"]["report-dir"].as_str(),
+            Some(Path::new("../shared/reports").to_str().unwrap())
+        );
+    }
+
+    #[test]
+    fn cycles_list_the_chain() {
+        let dir = monorepo();
+        write(
+            &dir,
+            "shared/base.toml",
+            "extends = \"../package/bishin.toml\"\n",
+        );
+        let leaf = write(
+            &dir,
+            "package/bishin.toml",
+            "extends = \"../shared/base.toml\"\n",
+        );
+        let err = Config::load(&leaf).unwrap_err();
+        let Error::ExtendsCycle(chain) = &err else {
+            panic!("{err}");
+        };
+        let names: Vec<_> = chain.iter().map(|path| path.file_name().unwrap()).collect();
+        assert_eq!(names, ["bishin.toml", "base.toml", "bishin.toml"]);
+        assert_eq!(err.to_string().matches(" -> ").count(), 2);
+    }
+
+    #[test]
+    fn files_extending_themselves_are_a_cycle() {
+        let dir = scratch_dir();
+        let path = write(&dir, "bishin.toml", "extends = \"./bishin.toml\"\n");
+        assert!(matches!(Config::load(&path), Err(Error::ExtendsCycle(chain)) if chain.len() == 2));
+    }
+
+    #[test]
+    fn chains_have_a_depth_limit() {
+        let dir = scratch_dir();
+        for depth in 0..=MAX_DEPTH {
+            write(
+                &dir,
+                &format!("{depth}.toml"),
+                &format!("extends = \"{}.toml\"\n", depth + 1),
+            );
+        }
+        write(&dir, &format!("{}.toml", MAX_DEPTH + 1), "");
+        let err = Config::load(&dir.join("0.toml")).unwrap_err();
+        assert!(matches!(err, Error::ExtendsTooDeep(MAX_DEPTH)), "{err}");
+    }
+
+    #[test]
+    fn errors_in_a_base_name_it() {
+        let dir = monorepo();
+        write(
+            &dir,
+            "shared/base.toml",
+            "test-dir = \"a\"\ntest-dirs = [\"b\"]\n",
+        );
+        let leaf = write(
+            &dir,
+            "package/bishin.toml",
+            "extends = \"../shared/base.toml\"\n",
+        );
+        let err = Config::load(&leaf).unwrap_err();
+        assert!(
+            matches!(&err, Error::Extends { path, source } if path.ends_with("shared/base.toml") && matches!(**source, Error::TestDirAndTestDirs)),
+            "{err}"
+        );
+
+        let leaf = write(&dir, "package/bishin.toml", "extends = 1\n");
+        assert!(matches!(Config::load(&leaf), Err(Error::InvalidExtends)));
+    }
+}
@@ -0,0 +1,82 @@
+//! The `ignore` setting, a list of patterns compiled as the config is loaded.
+
+use bishin_collect::Pattern;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// One pattern, compiled on its own so that an error points at it rather
+/// than at the whole list.
+struct Compiled(Pattern);
+
+impl<'de> Deserialize<'de> for Compiled {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Compiling in the visitor gives the error the span of the string.
+        deserializer.deserialize_str(CompiledVisitor)
+    }
+}
+
+struct CompiledVisitor;
+
+impl de::Visitor<'_> for CompiledVisitor {
+    type Value = Compiled;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a gitignore-style pattern")
+    }
+
+    fn visit_str<E: de::Error>(self, source: &str) -> Result<Compiled, E> {
+        Pattern::new(source)
+            .map(Compiled)
+            .map_err(|err| E::custom(format!("invalid ignore pattern: {err}")))
+    }
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Pattern>, D::Error> {
+    let patterns = Vec::<Compiled>::deserialize(deserializer)?;
+    Ok(patterns
+        .into_iter()
+        .map(|Compiled(pattern)| pattern)
+        .collect())
+}
+
+pub(crate) fn serialize<S: Serializer>(
+    patterns: &[Pattern],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let sources: Vec<&str> = patterns.iter().map(Pattern::as_str).collect();
+    sources.serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::load;
+
+    #[test]
+    fn patterns_are_compiled_as_the_config_loads() {
+        let config = load("ignore = [\"**/fixtures/**\", \"wip_*.b\"]\n").unwrap();
+        let sources: Vec<_> = config
+            .ignore
+            .iter()
+            .map(|pattern| pattern.as_str())
+            .collect();
+        assert_eq!(sources, ["**/fixtures/**", "wip_*.b"]);
+    }
+
+    #[test]
+    fn bad_patterns_are_config_errors_naming_them() {
+        let err = load("ignore = [\"ok\", \"[oops\"]\n").unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.ends_with("\n  |                 ^^^^^^^"),
+            "{message}"
+        );
+        assert!(
+            message.starts_with(
+                "invalid config: ignore.1: invalid ignore pattern: unterminated character class in pattern \"[oops\""
+            ),
+            "{message}"
+        );
+    }
+}
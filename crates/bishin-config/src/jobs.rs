@@ -0,0 +1,107 @@
+//! The `jobs` setting, which is either a positive number or `"auto"`.
+
+use std::fmt;
+
+use serde::de::{self, Deserializer, Visitor};
+
+/// Parse a `jobs` value given as a string, where `"auto"` yields `None`.
+pub fn parse_jobs(value: &str) -> Result<Option<usize>, String> {
+    if value == "auto" {
+        return Ok(None);
+    }
+    match value.parse::<usize>() {
+        Ok(0) => Err(zero_jobs()),
+        Ok(jobs) => Ok(Some(jobs)),
+        Err(_) => Err(format!(
+            "invalid jobs value {value:?}, expected a positive number or \"auto\""
+        )),
+    }
+}
+
+fn zero_jobs() -> String {
+    "jobs must be at least 1; use \"auto\" or leave it unset to match the number of CPUs"
+        .to_string()
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    deserializer.deserialize_any(JobsVisitor)
+}
+
+struct JobsVisitor;
+
+impl<'de> Visitor<'de> for JobsVisitor {
+    type Value = Option<usize>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a positive number or \"auto\"")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        match usize::try_from(value) {
+            Ok(0) => Err(E::custom(zero_jobs())),
+            Ok(jobs) => Ok(Some(jobs)),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Signed(value), &self)),
+        }
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        match usize::try_from(value) {
+            Ok(0) => Err(E::custom(zero_jobs())),
+            Ok(jobs) => Ok(Some(jobs)),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Unsigned(value), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        parse_jobs(value).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Table {
+        #[serde(deserialize_with = "deserialize")]
+        jobs: Option<usize>,
+    }
+
+    fn jobs(value: &str) -> Result<Option<usize>, String> {
+        toml::from_str::<Table>(&format!("jobs = {value}"))
+            .map(|table| table.jobs)
+            .map_err(|err| err.message().to_string())
+    }
+
+    #[test]
+    fn numbers_are_the_number_of_jobs() {
+        assert_eq!(jobs("4"), Ok(Some(4)));
+        assert_eq!(jobs("\"4\""), Ok(Some(4)));
+    }
+
+    #[test]
+    fn auto_is_one_per_cpu() {
+        assert_eq!(jobs("\"auto\""), Ok(None));
+        assert_eq!(parse_jobs("auto"), Ok(None));
+    }
+
+    #[test]
+    fn zero_is_rejected_with_the_alternatives() {
+        for value in ["0", "\"0\""] {
+            let err = jobs(value).unwrap_err();
+            assert!(err.contains("\"auto\""), "{err}");
+        }
+    }
+
+    #[test]
+    fn other_values_are_rejected() {
+        assert!(jobs("-1").is_err());
+        assert!(jobs("\"many\"").unwrap_err().contains("\"many\""));
+        assert!(jobs("true").is_err());
+        assert!(jobs("1.5").is_err());
+    }
+}
@@ -0,0 +1,1215 @@
+//! The `bishin.toml` configuration file.
+//!
+//! ```toml
+//! test-dir = "tests"
+//! work-dir = ".bishin"
+//! jobs = "auto"
+//! timeout = "2m"
+//! min-pass-rate = 0.9
+//! retries = 2
+//! max-failures = 10
+//! setup-script = "scripts/start_services.sh"
+//! teardown-script = "scripts/stop_services.sh"
+//! file-extensions = ["b", "bishin"]
+//! ignore = ["**/fixtures/**", "wip_*.b"]
+//! respect-gitignore = true
+//! redact = ["token=\\w+"]
+//!
+//! [env]
+//! RUST_LOG = "debug"
+//!
+//! [tags]
+//! default-exclude = ["network", "slow"]
+//!
+//! [reports]
+//! junit = "reports/junit.xml"
+//!
+//! [platform-shell]
+//! macos = "zsh"
+//!
+//! [script]
+//! errexit = true
+//! pipefail = true
+//!
+//! [profile.ci]
+//! jobs = 2
+//! ```
+//!
+//! String values such as paths and `[env]` values may refer to environment
+//! variables with `${VAR}` or `${VAR:-default}`, see [`expand`].
+//!
+//! A config may start with `extends = "../bishin.base.toml"` to build on
+//! another config file, see [`Config::load`].
+//!
+//! Single settings can be overridden from the command line as well, see
+//! [`Config::with_set`].
+//!
+//! Relative paths are relative to the directory of the config file, see
+//! [`Config::resolved_test_dirs`] and the like.
+//!
+//! Projects that would rather not have a file of their own for the config
+//! can keep it in the `[package.metadata.bishin]` or
+//! `[workspace.metadata.bishin]` table of their `Cargo.toml` instead, see
+//! [`find_cargo_config`] and [`Config::load_table_with`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use bishin_collect::Pattern;
+use bishin_parser::Shell;
+use serde::{Deserialize, Serialize, Serializer};
+
+mod diagnostic;
+mod embedded;
+pub mod expand;
+mod extends;
+mod ignore;
+mod jobs;
+mod platform_shell;
+mod profile;
+mod schema;
+mod set;
+mod template;
+#[cfg(test)]
+mod testing;
+mod timeout;
+mod unknown;
+mod validate;
+
+pub use diagnostic::{Location, ParseError};
+pub use embedded::{find_cargo_config, CARGO_MANIFEST, CARGO_TABLES};
+pub use expand::ExpandError;
+pub use jobs::parse_jobs;
+pub use unknown::UnknownKey;
+pub use validate::{ConfigIssue, Severity};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read config file {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to write config file {}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Parse(Box<ParseError>),
+    #[error("extends must be the path to a config file")]
+    InvalidExtends,
+    #[error("invalid base config file {}", path.display())]
+    Extends {
+        path: PathBuf,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("config files extend each other in a cycle: {}", extends_chain(.0))]
+    ExtendsCycle(Vec<PathBuf>),
+    #[error("config files extend each other more than {0} deep")]
+    ExtendsTooDeep(usize),
+    #[error("failed to expand {field}")]
+    Expand {
+        field: String,
+        #[source]
+        source: ExpandError,
+    },
+    #[error("{}", unknown_keys_message(.0))]
+    UnknownKeys(Vec<UnknownKey>),
+    #[error("invalid environment variable name {0:?} in [env]")]
+    InvalidEnvKey(String),
+    #[error("invalid environment variable name {0:?} in [env-policy] allow")]
+    InvalidEnvPolicyKey(String),
+    #[error("there's no [{table}] table in {}", path.display())]
+    NoTable { path: PathBuf, table: String },
+    #[error("set either test-dir or test-dirs, not both")]
+    TestDirAndTestDirs,
+    #[error("write profiles as either [profile.NAME] or [profiles.NAME], not both")]
+    ProfileAndProfiles,
+    #[error("test directory {} has no name to put its tests under", .0.display())]
+    UnnamedTestDir(PathBuf),
+    #[error(
+        "test directories {} and {} would both put their tests under `{namespace}`",
+        first.display(),
+        second.display()
+    )]
+    TestDirNamespaceClash {
+        namespace: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    #[error("file-extensions must list at least one extension")]
+    NoFileExtensions,
+    #[error("invalid file extension {0:?}, extensions are lowercase and written without a dot")]
+    InvalidFileExtension(String),
+    #[error("invalid path {path:?} for the {kind} report, it must name a file")]
+    InvalidReportPath { kind: &'static str, path: PathBuf },
+    #[error("invalid tag {0:?}, tags are made of letters, digits and underscores")]
+    InvalidTag(String),
+    #[error("retries must be at most {MAX_RETRIES}, got {0}")]
+    InvalidRetries(u32),
+    #[error("min-pass-rate must be between 0.0 and 1.0, got {0}")]
+    InvalidMinPassRate(f64),
+    #[error("unknown profile {name:?}, {}", available_profiles(available))]
+    UnknownProfile {
+        name: String,
+        available: Vec<String>,
+    },
+    #[error("unknown setting {key:?} for --set{}", match suggestion {
+        Some(suggestion) => format!("; did you mean `{suggestion}`?"),
+        None => String::new(),
+    })]
+    UnknownSetKey {
+        key: String,
+        suggestion: Option<String>,
+    },
+    #[error("invalid value for --set {key}")]
+    Set {
+        key: String,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("invalid settings in profile {name:?}")]
+    Profile {
+        name: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    fn parse(err: toml::de::Error, source: Option<(&Path, &str)>) -> Self {
+        Error::Parse(Box::new(ParseError::new(err, source)))
+    }
+}
+
+fn unknown_keys_message(keys: &[UnknownKey]) -> String {
+    match keys {
+        [key] => key.to_string(),
+        keys => {
+            let mut message = format!("found {} unknown keys in the config", keys.len());
+            for key in keys {
+                message.push_str(&format!("\n    {key}"));
+            }
+            message
+        }
+    }
+}
+
+fn available_profiles(available: &[String]) -> String {
+    if available.is_empty() {
+        "no profiles are defined".to_string()
+    } else {
+        format!("available profiles: {}", available.join(", "))
+    }
+}
+
+fn extends_chain(chain: &[PathBuf]) -> String {
+    let paths: Vec<_> = chain
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    paths.join(" -> ")
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// The directory that tests are collected from.
+    #[serde(default = "default_test_dir")]
+    pub test_dir: PathBuf,
+    /// Several directories that tests are collected from, instead of
+    /// `test_dir`. Each one's tests are in a module named by its last
+    /// component, see [`bishin_collect::load_test_roots`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub test_dirs: Vec<PathBuf>,
+    /// The directory that generated scripts are written to.
+    #[serde(default = "default_work_dir")]
+    pub work_dir: PathBuf,
+    /// How many tests to run at once, or `None` to use one per CPU.
+    #[serde(
+        default,
+        deserialize_with = "jobs::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub jobs: Option<usize>,
+    /// How long a test may run for unless it sets its own `@timeout`.
+    #[serde(default, with = "timeout", skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+    /// Environment variables set for every test.
+    ///
+    /// Per-test `@env` decorators take precedence over these.
+    #[serde(
+        default,
+        serialize_with = "sorted_map",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub env: HashMap<String, String>,
+    /// The fraction of tests that must pass for the run to succeed.
+    ///
+    /// When unset, any failure fails the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_pass_rate: Option<f64>,
+    /// How many times to retry a failed test that doesn't set its own
+    /// `@retries`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Stop the run at the first failure, as if `max-failures` were 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail_fast: Option<bool>,
+    /// Stop the run after this many failures, where 0 means never.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_failures: Option<u32>,
+    /// Keep the scratch directories of tests that fail, instead of removing
+    /// them along with those of tests that pass.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub keep_tmp_on_failure: bool,
+    /// Whether to show what passing tests printed, which is only shown for
+    /// failures otherwise.
+    #[serde(default, skip_serializing_if = "SuccessOutput::is_hidden")]
+    pub on_success_output: SuccessOutput,
+    /// Let a test out of the quarantine of its `@flaky` once it has passed
+    /// this many recorded runs in a row, so that its failures count again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flaky_promote_after: Option<u32>,
+    /// A script to run once before any test, relative to the config file.
+    /// If it fails, no tests run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setup_script: Option<PathBuf>,
+    /// A script to run once after every test has finished, relative to the
+    /// config file. It runs even if the setup script or tests failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teardown_script: Option<PathBuf>,
+    /// Where the files that tests list with `@artifact` are copied to once
+    /// they finish, relative to the config file. It's `reports` in the work
+    /// directory unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report_dir: Option<PathBuf>,
+    /// Put before the name of every test in what a run reports, so that
+    /// the reports of several runs, such as the shards of a CI job, can be
+    /// merged. It's usually made from variables, e.g. `"${SHARD}/"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_prefix: Option<String>,
+    /// The extensions of files that contain tests.
+    #[serde(default = "default_file_extensions")]
+    pub file_extensions: Vec<String>,
+    /// Gitignore-style patterns for test files and directories to leave out,
+    /// relative to the test directory.
+    #[serde(default, with = "ignore", skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<Pattern>,
+    /// Also leave out what the `.gitignore` files in the test directories
+    /// match, as well as their `.bishinignore` files.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub respect_gitignore: bool,
+    /// Regexes for secrets and unstable values in test output, which are
+    /// replaced with `[REDACTED]` before the output is shown or reported.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact: Vec<String>,
+    /// The tags of the tests to run when the command line doesn't say.
+    #[serde(default, skip_serializing_if = "TagDefaults::is_empty")]
+    pub tags: TagDefaults,
+    /// Reports to write about every run.
+    #[serde(default, skip_serializing_if = "ReportsConfig::is_empty")]
+    pub reports: ReportsConfig,
+    /// Which of bishin's environment variables tests inherit.
+    #[serde(default, skip_serializing_if = "EnvPolicy::is_empty")]
+    pub env_policy: EnvPolicy,
+    /// Where the snapshots of `@snapshot` tests are kept, and when they're
+    /// updated.
+    #[serde(default, skip_serializing_if = "SnapshotsConfig::is_empty")]
+    pub snapshots: SnapshotsConfig,
+    /// The shell options that generated scripts turn on.
+    #[serde(default, skip_serializing_if = "ScriptConfig::is_empty")]
+    pub script: ScriptConfig,
+    /// The shell to run tests with on each operating system, by the names
+    /// of `std::env::consts::OS`. Bash is used on any that aren't listed.
+    #[serde(
+        default,
+        with = "platform_shell",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub platform_shell: BTreeMap<String, Shell>,
+    /// Named sets of overrides, selected with [`Config::with_profile`]. The
+    /// sections may be spelled `[profiles.NAME]` too.
+    #[serde(
+        default,
+        alias = "profiles",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub profile: BTreeMap<String, toml::Table>,
+    /// Ignore keys that bishin doesn't know about, e.g. ones added by a newer
+    /// version, instead of failing.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_unknown_keys: bool,
+    /// The directory of the file the config was loaded from, which relative
+    /// paths in it are relative to.
+    #[serde(skip)]
+    dir: PathBuf,
+    #[serde(skip)]
+    source: ConfigSource,
+}
+
+/// Where a config was loaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// Nowhere, it's the defaults.
+    #[default]
+    Defaults,
+    /// A file of its own, such as `bishin.toml`.
+    File(PathBuf),
+    /// A table in a file that's mostly about something else, such as
+    /// `[package.metadata.bishin]` in a `Cargo.toml`.
+    Table { path: PathBuf, table: String },
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Defaults => f.write_str("the defaults"),
+            ConfigSource::File(path) => write!(f, "{}", path.display()),
+            ConfigSource::Table { path, table } => write!(f, "[{table}] in {}", path.display()),
+        }
+    }
+}
+
+/// The `[tags]` table, which chooses tests by their `@tags` unless the
+/// command line chooses otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TagDefaults {
+    /// Only run tests with at least one of these tags, unless empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_include: Vec<String>,
+    /// Don't run tests with any of these tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_exclude: Vec<String>,
+}
+
+impl TagDefaults {
+    pub fn is_empty(&self) -> bool {
+        self.default_include.is_empty() && self.default_exclude.is_empty()
+    }
+}
+
+/// The `[env-policy]` table, choosing which of bishin's environment variables
+/// tests inherit.
+///
+/// A test's environment is built up from, each over the one before:
+///
+/// 1. bishin's own environment, all of it or only the `allow`ed variables if
+///    `clean` is set,
+/// 2. the `[env]` table,
+/// 3. the test's `@env` decorators,
+/// 4. the variables bishin sets for every test, such as `$BISHIN_TMPDIR`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnvPolicy {
+    /// Start tests with only the `allow`ed variables from the environment.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub clean: bool,
+    /// The variables to keep when `clean` is set, e.g. `PATH`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+}
+
+impl EnvPolicy {
+    pub fn is_empty(&self) -> bool {
+        !self.clean && self.allow.is_empty()
+    }
+}
+
+/// The `[snapshots]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SnapshotsConfig {
+    /// The directory snapshots are kept in, created when the first one is
+    /// recorded. It's `__snapshots__` in the first test directory unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// When tests record their output as their snapshot, `never` unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<SnapshotUpdate>,
+}
+
+impl SnapshotsConfig {
+    pub fn is_empty(&self) -> bool {
+        self.dir.is_none() && self.update.is_none()
+    }
+}
+
+/// When a `@snapshot` test records its output instead of comparing it with
+/// its snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotUpdate {
+    /// Never, so a test without a snapshot fails.
+    #[default]
+    Never,
+    /// When the test has no snapshot yet. Mismatches still fail.
+    Missing,
+    /// Always, replacing snapshots that don't match.
+    Always,
+}
+
+impl SnapshotUpdate {
+    /// The name of the policy in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            SnapshotUpdate::Never => "never",
+            SnapshotUpdate::Missing => "missing",
+            SnapshotUpdate::Always => "always",
+        }
+    }
+}
+
+impl fmt::Display for SnapshotUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How much of the output of passing tests a run shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SuccessOutput {
+    /// None of it.
+    #[default]
+    Hidden,
+    /// All of it together after the last test, with each test's name.
+    Summary,
+    /// Each test's right after the line saying it passed.
+    Full,
+}
+
+impl SuccessOutput {
+    pub const ALL: [SuccessOutput; 3] = [
+        SuccessOutput::Hidden,
+        SuccessOutput::Summary,
+        SuccessOutput::Full,
+    ];
+
+    /// The name of the setting in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            SuccessOutput::Hidden => "hidden",
+            SuccessOutput::Summary => "summary",
+            SuccessOutput::Full => "full",
+        }
+    }
+
+    fn is_hidden(&self) -> bool {
+        *self == SuccessOutput::Hidden
+    }
+}
+
+impl fmt::Display for SuccessOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The `[script]` table, choosing the shell options that test scripts turn
+/// on before their body. Options a shell doesn't have are left out of its
+/// scripts, with a warning.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScriptConfig {
+    /// `set -e`, exit as soon as a command fails.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub errexit: bool,
+    /// `set -u`, treat unset variables as an error.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub nounset: bool,
+    /// `set -o pipefail`, fail a pipeline if any command in it fails.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pipefail: bool,
+    /// `set -x`, print each command before running it.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub xtrace: bool,
+}
+
+impl ScriptConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The `[reports]` table, giving the path to write each kind of report to.
+///
+/// The parent directories of the paths are created when the reports are
+/// written.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReportsConfig {
+    /// A JUnit XML report, for CI systems.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub junit: Option<PathBuf>,
+    /// A JSON document with every result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<PathBuf>,
+    /// A JSON object per line for each result as it comes in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jsonl_events: Option<PathBuf>,
+    /// A page with every result that opens in a browser on its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<PathBuf>,
+    /// A Markdown summary, e.g. for a comment on a pull request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub markdown: Option<PathBuf>,
+}
+
+impl ReportsConfig {
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// The configured reports as pairs of their key and path.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Path)> {
+        [
+            ("junit", &self.junit),
+            ("json", &self.json),
+            ("jsonl-events", &self.jsonl_events),
+            ("html", &self.html),
+            ("markdown", &self.markdown),
+        ]
+        .into_iter()
+        .filter_map(|(kind, path)| Some((kind, path.as_deref()?)))
+    }
+}
+
+/// The most `retries` a config may ask for, well past what any flaky test
+/// should need.
+pub const MAX_RETRIES: u32 = 100;
+
+/// Finds the value of a variable by name.
+pub type Lookup<'a> = dyn Fn(&str) -> Option<String> + 'a;
+
+/// The name of the config file that's used unless another is given.
+pub const CONFIG_FILE: &str = "bishin.toml";
+
+/// Settings for [`Config::load_with`].
+#[derive(Default)]
+pub struct LoadOptions<'a> {
+    /// Looks up the variables that values refer to, instead of the process
+    /// environment.
+    pub lookup: Option<&'a Lookup<'a>>,
+    /// Ignore unknown keys even in files that don't set `allow-unknown-keys`.
+    pub allow_unknown_keys: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            test_dir: default_test_dir(),
+            test_dirs: Vec::new(),
+            work_dir: default_work_dir(),
+            jobs: None,
+            timeout: None,
+            env: HashMap::new(),
+            min_pass_rate: None,
+            retries: None,
+            fail_fast: None,
+            max_failures: None,
+            keep_tmp_on_failure: false,
+            on_success_output: SuccessOutput::Hidden,
+            flaky_promote_after: None,
+            setup_script: None,
+            teardown_script: None,
+            report_dir: None,
+            name_prefix: None,
+            file_extensions: default_file_extensions(),
+            ignore: Vec::new(),
+            respect_gitignore: false,
+            redact: Vec::new(),
+            tags: TagDefaults::default(),
+            reports: ReportsConfig::default(),
+            env_policy: EnvPolicy::default(),
+            snapshots: SnapshotsConfig::default(),
+            script: ScriptConfig::default(),
+            platform_shell: BTreeMap::new(),
+            profile: BTreeMap::new(),
+            allow_unknown_keys: false,
+            dir: PathBuf::new(),
+            source: ConfigSource::Defaults,
+        }
+    }
+}
+
+impl Config {
+    /// Read and validate the config file at `path`.
+    ///
+    /// If the file `extends` another, that one is loaded first and this
+    /// file's values are merged over it, the same way as a profile.
+    ///
+    /// Variables referenced by its values are looked up in the process
+    /// environment, and keys that bishin doesn't know about are an error
+    /// unless the file sets `allow-unknown-keys`.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::load_with(path, &LoadOptions::default())
+    }
+
+    /// Like [`Config::load`], with some of its behavior changed by `options`.
+    pub fn load_with(path: &Path, options: &LoadOptions<'_>) -> Result<Self, Error> {
+        Self::load_from(path, None, options)
+    }
+
+    /// Like [`Config::load_with`], for a config in the table at the dotted
+    /// path `table` of the file at `path`, e.g. `package.metadata.bishin` in
+    /// a `Cargo.toml`. The rest of the file is left alone.
+    pub fn load_table_with(
+        path: &Path,
+        table: &str,
+        options: &LoadOptions<'_>,
+    ) -> Result<Self, Error> {
+        Self::load_from(path, Some(table), options)
+    }
+
+    fn load_from(
+        path: &Path,
+        within: Option<&str>,
+        options: &LoadOptions<'_>,
+    ) -> Result<Self, Error> {
+        let mut config: Config = extends::load_table(path, within, options.allow_unknown_keys)?
+            .try_into()
+            .map_err(|err| Error::parse(err, None))?;
+        let env_lookup = |name: &str| std::env::var(name).ok();
+        config.expand(options.lookup.unwrap_or(&env_lookup))?;
+        config.check()?;
+        config.dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        config.source = match within {
+            Some(table) => ConfigSource::Table {
+                path: path.to_path_buf(),
+                table: table.to_string(),
+            },
+            None => ConfigSource::File(path.to_path_buf()),
+        };
+        Ok(config)
+    }
+
+    /// Where the config was loaded from.
+    pub fn source(&self) -> &ConfigSource {
+        &self.source
+    }
+
+    /// The directory that relative paths in the config are relative to: that
+    /// of the file it was loaded from, or the current directory for a config
+    /// that wasn't loaded from one.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The directories that tests are collected from, [`Config::test_dirs`]
+    /// or else [`Config::test_dir`], relative to the current directory rather
+    /// than the config file.
+    pub fn resolved_test_dirs(&self) -> Vec<PathBuf> {
+        match self.test_dirs.as_slice() {
+            [] => vec![self.dir.join(&self.test_dir)],
+            test_dirs => test_dirs.iter().map(|dir| self.dir.join(dir)).collect(),
+        }
+    }
+
+    /// [`Config::work_dir`], relative to the current directory rather than
+    /// the config file.
+    pub fn resolved_work_dir(&self) -> PathBuf {
+        self.dir.join(&self.work_dir)
+    }
+
+    /// [`SnapshotsConfig::dir`], or its default, relative to the current
+    /// directory rather than the config file.
+    pub fn resolved_snapshot_dir(&self) -> PathBuf {
+        match &self.snapshots.dir {
+            Some(dir) => self.dir.join(dir),
+            None => self.resolved_test_dirs()[0].join("__snapshots__"),
+        }
+    }
+
+    /// [`Config::report_dir`], or its default, relative to the current
+    /// directory rather than the config file.
+    pub fn resolved_report_dir(&self) -> PathBuf {
+        match &self.report_dir {
+            Some(dir) => self.dir.join(dir),
+            None => self.resolved_work_dir().join("reports"),
+        }
+    }
+
+    /// [`Config::reports`], with each path relative to the current directory
+    /// rather than the config file.
+    pub fn resolved_reports(&self) -> ReportsConfig {
+        let resolve = |path: &Option<PathBuf>| path.as_ref().map(|path| self.dir.join(path));
+        ReportsConfig {
+            junit: resolve(&self.reports.junit),
+            json: resolve(&self.reports.json),
+            jsonl_events: resolve(&self.reports.jsonl_events),
+            html: resolve(&self.reports.html),
+            markdown: resolve(&self.reports.markdown),
+        }
+    }
+
+    /// [`Config::setup_script`], relative to the current directory rather
+    /// than the config file.
+    pub fn resolved_setup_script(&self) -> Option<PathBuf> {
+        Some(self.dir.join(self.setup_script.as_ref()?))
+    }
+
+    /// [`Config::teardown_script`], relative to the current directory rather
+    /// than the config file.
+    pub fn resolved_teardown_script(&self) -> Option<PathBuf> {
+        Some(self.dir.join(self.teardown_script.as_ref()?))
+    }
+
+    /// The names of the profiles this config defines, in sorted order.
+    pub fn profiles(&self) -> Vec<&str> {
+        self.profile.keys().map(String::as_str).collect()
+    }
+
+    /// This config with the profile called `name` merged over it.
+    ///
+    /// Scalars and arrays in the profile replace the base value, while tables
+    /// are merged key by key.
+    pub fn with_profile(&self, name: &str) -> Result<Config, Error> {
+        self.with_profile_with_lookup(name, |name| std::env::var(name).ok())
+    }
+
+    /// Like [`Config::with_profile`], but looking up the variables that the
+    /// profile refers to with `lookup`.
+    pub fn with_profile_with_lookup(
+        &self,
+        name: &str,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, Error> {
+        let overrides = self
+            .profile
+            .get(name)
+            .ok_or_else(|| Error::UnknownProfile {
+                name: name.to_string(),
+                available: self.profiles().into_iter().map(str::to_string).collect(),
+            })?;
+        let profile_error = |source| Error::Profile {
+            name: name.to_string(),
+            source: Box::new(source),
+        };
+
+        let mut overrides = overrides.clone();
+        expand::expand_profile(&mut overrides, name, &lookup)?;
+        let mut base = toml::Table::try_from(self).expect("config serializes to a table");
+        profile::merge(&mut base, &overrides);
+        let mut config: Config = base
+            .try_into()
+            .map_err(|err| profile_error(Error::parse(err, None)))?;
+        config.check().map_err(profile_error)?;
+        config.dir = self.dir.clone();
+        config.source = self.source.clone();
+        Ok(config)
+    }
+
+    /// The shell to run tests with on the operating system `os`, named as in
+    /// `std::env::consts::OS`.
+    pub fn default_shell(&self, os: &str) -> Shell {
+        self.platform_shell.get(os).copied().unwrap_or_default()
+    }
+
+    /// Check the values that serde can't.
+    fn check(&self) -> Result<(), Error> {
+        let mut namespaces: Vec<(String, &PathBuf)> = Vec::new();
+        for dir in &self.test_dirs {
+            let namespace =
+                normalized_namespace(dir).ok_or_else(|| Error::UnnamedTestDir(dir.clone()))?;
+            if let Some((_, first)) = namespaces.iter().find(|(name, _)| *name == namespace) {
+                return Err(Error::TestDirNamespaceClash {
+                    namespace,
+                    first: first.to_path_buf(),
+                    second: dir.clone(),
+                });
+            }
+            namespaces.push((namespace, dir));
+        }
+        if let Some(key) = self.env.keys().find(|key| !is_valid_env_key(key)) {
+            return Err(Error::InvalidEnvKey(key.clone()));
+        }
+        let allowed = &self.env_policy.allow;
+        if let Some(key) = allowed.iter().find(|key| !is_valid_env_key(key)) {
+            return Err(Error::InvalidEnvPolicyKey(key.clone()));
+        }
+        if let Some(rate) = self.min_pass_rate {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(Error::InvalidMinPassRate(rate));
+            }
+        }
+        if let Some(retries) = self.retries.filter(|retries| *retries > MAX_RETRIES) {
+            return Err(Error::InvalidRetries(retries));
+        }
+        let mut tags = self
+            .tags
+            .default_include
+            .iter()
+            .chain(&self.tags.default_exclude);
+        if let Some(tag) = tags.find(|tag| !is_valid_tag(tag)) {
+            return Err(Error::InvalidTag(tag.clone()));
+        }
+        for (kind, path) in self.reports.iter() {
+            if path.file_name().is_none() || path.is_dir() {
+                return Err(Error::InvalidReportPath {
+                    kind,
+                    path: path.to_path_buf(),
+                });
+            }
+        }
+        if self.file_extensions.is_empty() {
+            return Err(Error::NoFileExtensions);
+        }
+        let invalid_extension = |ext: &&String| {
+            ext.is_empty() || ext.contains('.') || ext.chars().any(char::is_uppercase)
+        };
+        if let Some(ext) = self.file_extensions.iter().find(invalid_extension) {
+            return Err(Error::InvalidFileExtension(ext.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// The namespace of a test directory's tests, going by the directory itself
+/// rather than how it's written, so `tests/unit/..` is `tests`.
+fn normalized_namespace(dir: &Path) -> Option<String> {
+    let mut normalized = PathBuf::new();
+    for component in dir.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    bishin_collect::namespace(&normalized)
+}
+
+/// Whether `key` can be used as the name of an environment variable.
+pub fn is_valid_env_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(['=', '\0'])
+}
+
+/// Whether `tag` could be given to a test with `@tags`.
+fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn default_test_dir() -> PathBuf {
+    PathBuf::from("tests")
+}
+
+fn default_work_dir() -> PathBuf {
+    PathBuf::from(".bishin")
+}
+
+fn default_file_extensions() -> Vec<String> {
+    vec![bishin_collect::FILE_EXTENSION.to_string()]
+}
+
+/// Serialize a map with its keys in sorted order so output is stable.
+fn sorted_map<S: Serializer>(
+    map: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{load, scratch_dir, write};
+
+    #[test]
+    fn env_table_is_read() {
+        let config = load("[env]\nRUST_LOG = \"debug\"\nEMPTY = \"\"\n").unwrap();
+        assert_eq!(config.env.len(), 2);
+        assert_eq!(config.env["RUST_LOG"], "debug");
+        assert_eq!(config.env["EMPTY"], "");
+    }
+
+    #[test]
+    fn env_keys_must_be_variable_names() {
+        for key in ["\"\"", "\"A=B\"", "\"A\\u0000\""] {
+            let err = load(&format!("[env]\n{key} = \"x\"\n")).unwrap_err();
+            assert!(matches!(err, Error::InvalidEnvKey(_)), "{key}: {err}");
+        }
+    }
+
+    #[test]
+    fn env_is_written_in_sorted_order() {
+        let mut config = Config::default();
+        for key in ["ZED", "ALPHA", "MIDDLE"] {
+            config.env.insert(key.to_string(), "1".to_string());
+        }
+        let written = toml::to_string(&config).unwrap();
+        let table = &written[written.find("[env]").unwrap()..];
+        let keys: Vec<_> = table
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .map(|line| line.split(" = ").next().unwrap())
+            .collect();
+        assert_eq!(keys, ["ALPHA", "MIDDLE", "ZED"]);
+    }
+
+    #[test]
+    fn file_extensions_default_to_b() {
+        assert_eq!(load("").unwrap().file_extensions, ["b"]);
+        let config = load("file-extensions = [\"b\", \"bishin\"]\n").unwrap();
+        assert_eq!(config.file_extensions, ["b", "bishin"]);
+    }
+
+    #[test]
+    fn file_extensions_are_checked() {
+        assert!(matches!(
+            load("file-extensions = []\n"),
+            Err(Error::NoFileExtensions)
+        ));
+        for ext in [".b", "B", "", "tar.gz"] {
+            let err = load(&format!("file-extensions = [\"b\", {ext:?}]\n")).unwrap_err();
+            assert!(
+                matches!(&err, Error::InvalidFileExtension(invalid) if invalid == ext),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn retries_are_capped() {
+        assert_eq!(load("retries = 100\n").unwrap().retries, Some(MAX_RETRIES));
+        assert!(matches!(
+            load("retries = 101\n"),
+            Err(Error::InvalidRetries(101))
+        ));
+    }
+
+    #[test]
+    fn stop_settings_are_read() {
+        let config = load("fail-fast = true\nmax-failures = 0\n").unwrap();
+        assert_eq!(
+            (config.fail_fast, config.max_failures),
+            (Some(true), Some(0))
+        );
+        assert!(load("max-failures = -1\n").is_err());
+    }
+
+    #[test]
+    fn default_tags_are_read_and_checked() {
+        let config = load("[tags]\ndefault-exclude = [\"network\", \"slow\"]\n").unwrap();
+        assert_eq!(config.tags.default_exclude, ["network", "slow"]);
+        assert!(config.tags.default_include.is_empty());
+        let err = load("[tags]\ndefault-include = [\"not-a-tag\"]\n").unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidTag(tag) if tag == "not-a-tag"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn the_default_shell_is_by_os() {
+        let config = load("[platform-shell]\nmacos = \"zsh\"\nlinux = \"bash\"\n").unwrap();
+        assert_eq!(config.default_shell("macos"), Shell::Zsh);
+        assert_eq!(config.default_shell("linux"), Shell::Bash);
+        assert_eq!(config.default_shell("windows"), Shell::Bash);
+        assert_eq!(Config::default().default_shell("macos"), Shell::Bash);
+        let err = load("[platform-shell]\nmacos = \"csh\"\n").unwrap_err();
+        assert!(err.to_string().contains("csh"), "{err}");
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_config_file() {
+        let scratch = crate::testing::scratch_dir();
+        let sub = scratch.join("project/sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let path = crate::testing::write(
+            &sub,
+            CONFIG_FILE,
+            concat!(
+                "test-dir = \"../tests\"\nwork-dir = \"/tmp/bishin-work\"\nsetup-script = \"setup.sh\"\n",
+                "[reports]\njunit = \"out/junit.xml\"\nhtml = \"/tmp/report.html\"\n",
+            ),
+        );
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.dir(), sub);
+        assert_eq!(config.resolved_test_dirs(), [sub.join("../tests")]);
+        // Absolute paths are left as they are.
+        assert_eq!(config.resolved_work_dir(), Path::new("/tmp/bishin-work"));
+        assert_eq!(
+            config.resolved_report_dir(),
+            Path::new("/tmp/bishin-work/reports")
+        );
+        assert_eq!(config.resolved_setup_script(), Some(sub.join("setup.sh")));
+        assert_eq!(config.resolved_teardown_script(), None);
+        let reports = config.resolved_reports();
+        assert_eq!(reports.junit, Some(sub.join("out/junit.xml")));
+        assert_eq!(reports.html, Some(PathBuf::from("/tmp/report.html")));
+        assert_eq!(reports.json, None);
+        assert_eq!(
+            config.resolved_snapshot_dir(),
+            sub.join("../tests/__snapshots__")
+        );
+
+        let config = load("test-dirs = [\"unit\", \"/abs/e2e\"]\n").unwrap();
+        assert_eq!(
+            config.resolved_test_dirs(),
+            [config.dir().join("unit"), PathBuf::from("/abs/e2e")]
+        );
+        assert_eq!(config.resolved_work_dir(), config.dir().join(".bishin"));
+    }
+
+    #[test]
+    fn test_dirs_are_one_or_a_list() {
+        let config = load("test-dir = \"spec\"\n").unwrap();
+        assert_eq!(config.resolved_test_dirs(), [config.dir().join("spec")]);
+        let config = load("test-dirs = [\"tests/unit\", \"tests/integration\"]\n").unwrap();
+        assert_eq!(
+            config.resolved_test_dirs(),
+            [
+                config.dir().join("tests/unit"),
+                config.dir().join("tests/integration")
+            ]
+        );
+        assert!(matches!(
+            load("test-dir = \"tests\"\ntest-dirs = [\"unit\"]\n"),
+            Err(Error::TestDirAndTestDirs)
+        ));
+    }
+
+    #[test]
+    fn test_dirs_need_names_of_their_own() {
+        let err = load("test-dirs = [\"a/tests\", \"b/tests\"]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test directories a/tests and b/tests would both put their tests under `tests`"
+        );
+        // What a directory is named by is where it ends up.
+        assert!(matches!(
+            load("test-dirs = [\"tests/./\", \"unit/../tests\"]\n"),
+            Err(Error::TestDirNamespaceClash { namespace, .. }) if namespace == "tests"
+        ));
+        assert!(matches!(
+            load("test-dirs = [\"unit\", \"..\"]\n"),
+            Err(Error::UnnamedTestDir(dir)) if dir == Path::new("..")
+        ));
+    }
+
+    #[test]
+    fn script_options_are_off_unless_set() {
+        assert!(load("").unwrap().script.is_empty());
+        let config = load("[script]\nerrexit = true\npipefail = true\n").unwrap();
+        assert_eq!(
+            config.script,
+            ScriptConfig {
+                errexit: true,
+                pipefail: true,
+                ..ScriptConfig::default()
+            }
+        );
+        assert!(load("[script]\nerrexit = \"yes\"\n").is_err());
+    }
+
+    #[test]
+    fn the_env_policy_is_read_and_checked() {
+        let config = load("[env-policy]\nclean = true\nallow = [\"HOME\", \"PATH\"]\n").unwrap();
+        assert_eq!(
+            config.env_policy,
+            EnvPolicy {
+                clean: true,
+                allow: vec!["HOME".to_string(), "PATH".to_string()],
+            }
+        );
+        assert_eq!(load("").unwrap().env_policy, EnvPolicy::default());
+        assert!(matches!(
+            load("[env-policy]\nallow = [\"A=B\"]\n"),
+            Err(Error::InvalidEnvPolicyKey(key)) if key == "A=B"
+        ));
+    }
+
+    #[test]
+    fn the_snapshot_policy_is_one_of_three() {
+        for (name, update) in [
+            ("never", SnapshotUpdate::Never),
+            ("missing", SnapshotUpdate::Missing),
+            ("always", SnapshotUpdate::Always),
+        ] {
+            let config = load(&format!("[snapshots]\nupdate = \"{name}\"\n")).unwrap();
+            assert_eq!(config.snapshots.update, Some(update));
+            assert_eq!(update.to_string(), name);
+        }
+        assert_eq!(load("").unwrap().snapshots.update, None);
+        let err = load("[snapshots]\nupdate = \"sometimes\"\n").unwrap_err();
+        assert!(err.to_string().contains("sometimes"), "{err}");
+    }
+
+    const SHARED: &str = "[tool.other]\nanything = { goes = [1, 2] }\n\n\
+        [tool.bishin]\ntest-dir = \"e2e\"\njobs = 3\n\n\
+        [tool.bishin.profile.ci]\njobs = 8\n";
+
+    #[test]
+    fn the_config_can_be_a_nested_table_of_a_shared_file() {
+        let dir = scratch_dir();
+        let path = write(&dir, "project.toml", SHARED);
+        let config =
+            Config::load_table_with(&path, "tool.bishin", &LoadOptions::default()).unwrap();
+        assert_eq!(config.jobs, Some(3));
+        assert_eq!(config.resolved_test_dirs(), [dir.join("e2e")]);
+        assert_eq!(
+            config.source().to_string(),
+            format!("[tool.bishin] in {}", path.display())
+        );
+        assert_eq!(config.with_profile("ci").unwrap().jobs, Some(8));
+    }
+
+    #[test]
+    fn only_the_nested_table_is_checked_for_unknown_keys() {
+        let dir = scratch_dir();
+        let path = write(&dir, "project.toml", &format!("{SHARED}jbos = 2\n"));
+        let Err(Error::UnknownKeys(unknown)) =
+            Config::load_table_with(&path, "tool.bishin.profile.ci", &LoadOptions::default())
+        else {
+            panic!("loaded");
+        };
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].field, "tool.bishin.profile.ci.jbos");
+        assert_eq!(unknown[0].suggestion, Some("jobs"));
+        assert_eq!(unknown[0].location.line, 10);
+    }
+
+    #[test]
+    fn a_missing_nested_table_is_an_error() {
+        let dir = scratch_dir();
+        let path = write(&dir, "project.toml", SHARED);
+        for table in ["tool.bishni", "tool.other.anything.goes"] {
+            assert!(
+                matches!(
+                    Config::load_table_with(&path, table, &LoadOptions::default()),
+                    Err(Error::NoTable { table: missing, .. }) if missing == table
+                ),
+                "{table}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_nested_table_can_extend_a_config_file() {
+        let dir = scratch_dir();
+        write(&dir, "base.toml", "jobs = 5\ntimeout = \"1m\"\n");
+        let path = write(
+            &dir,
+            "project.toml",
+            "[tool.bishin]\nextends = \"base.toml\"\njobs = 2\n",
+        );
+        let config =
+            Config::load_table_with(&path, "tool.bishin", &LoadOptions::default()).unwrap();
+        assert_eq!(config.jobs, Some(2));
+        assert_eq!(config.timeout, Some(Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,57 @@
+//! The `[platform-shell]` table, which picks the shell that tests run with
+//! by the operating system they run on.
+
+use std::collections::BTreeMap;
+
+use bishin_parser::Shell;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// The names `std::env::consts::OS` can have, for catching typos of them.
+pub(crate) const KNOWN_OSES: &[&str] = &[
+    "linux",
+    "macos",
+    "ios",
+    "freebsd",
+    "dragonfly",
+    "netbsd",
+    "openbsd",
+    "solaris",
+    "illumos",
+    "android",
+    "windows",
+    "haiku",
+    "aix",
+];
+
+/// One shell, parsed on its own so that an error points at it rather than at
+/// the whole table.
+struct Parsed(Shell);
+
+impl<'de> Deserialize<'de> for Parsed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(Parsed).map_err(de::Error::custom)
+    }
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Shell>, D::Error> {
+    let shells = BTreeMap::<String, Parsed>::deserialize(deserializer)?;
+    Ok(shells
+        .into_iter()
+        .map(|(os, Parsed(shell))| (os, shell))
+        .collect())
+}
+
+pub(crate) fn serialize<S: Serializer>(
+    shells: &BTreeMap<String, Shell>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let names: BTreeMap<&str, &str> = shells
+        .iter()
+        .map(|(os, shell)| (os.as_str(), shell.name()))
+        .collect();
+    names.serialize(serializer)
+}
@@ -0,0 +1,134 @@
+//! Named profiles that override parts of the config, e.g. `[profile.ci]`, or
+//! `[profiles.ci]` for those who'd rather write it that way.
+//!
+//! A profile is merged over the top level of the config key by key:
+//!
+//! - scalars (strings, numbers, booleans) in the profile replace the base
+//!   value,
+//! - tables are merged recursively, so `[profile.ci.env]` adds to and
+//!   overrides `[env]` rather than replacing it,
+//! - arrays in the profile replace the base array entirely,
+//! - `test-dir` and `test-dirs` replace each other, since a config can only
+//!   set one of them.
+
+use toml::{Table, Value};
+
+/// Merge `overlay` into `base` following the rules above.
+pub(crate) fn merge(base: &mut Table, overlay: &Table) {
+    for (key, other) in [("test-dir", "test-dirs"), ("test-dirs", "test-dir")] {
+        if overlay.contains_key(key) {
+            base.remove(other);
+        }
+    }
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::load;
+    use crate::Error;
+
+    fn merged(base: &str, overlay: &str) -> Table {
+        let mut base: Table = base.parse().unwrap();
+        merge(&mut base, &overlay.parse().unwrap());
+        base
+    }
+
+    #[test]
+    fn scalars_are_replaced() {
+        let table = merged("jobs = 2\ntimeout = \"1m\"\n", "jobs = 8\n");
+        assert_eq!(table, "jobs = 8\ntimeout = \"1m\"\n".parse().unwrap());
+    }
+
+    #[test]
+    fn tables_are_merged_key_by_key() {
+        let table = merged(
+            "[env]\nA = \"base\"\nB = \"base\"\n[script.deeper]\nx = 1\n",
+            "[env]\nB = \"profile\"\nC = \"profile\"\n[script.deeper]\ny = 2\n",
+        );
+        let expected = concat!(
+            "[env]\nA = \"base\"\nB = \"profile\"\nC = \"profile\"\n",
+            "[script.deeper]\nx = 1\ny = 2\n",
+        );
+        assert_eq!(table, expected.parse().unwrap());
+    }
+
+    #[test]
+    fn arrays_are_replaced() {
+        let table = merged("ignore = [\"a\", \"b\"]\n", "ignore = [\"c\"]\n");
+        assert_eq!(table, "ignore = [\"c\"]\n".parse().unwrap());
+    }
+
+    #[test]
+    fn values_of_another_kind_are_replaced() {
+        let table = merged("[reports]\njunit = \"a.xml\"\n", "reports = 1\n");
+        assert_eq!(table, "reports = 1\n".parse().unwrap());
+    }
+
+    #[test]
+    fn test_dir_and_test_dirs_replace_each_other() {
+        let table = merged("test-dir = \"tests\"\n", "test-dirs = [\"a\", \"b\"]\n");
+        assert_eq!(table, "test-dirs = [\"a\", \"b\"]\n".parse().unwrap());
+        let table = merged("test-dirs = [\"a\"]\n", "test-dir = \"t\"\n");
+        assert_eq!(table, "test-dir = \"t\"\n".parse().unwrap());
+    }
+
+    #[test]
+    fn profiles_are_applied_over_the_config() {
+        let config = load(concat!(
+            "jobs = 2\nretries = 1\n[env]\nA = \"base\"\n",
+            "[profile.ci]\njobs = 8\n[profile.ci.env]\nB = \"ci\"\n",
+        ))
+        .unwrap();
+        let ci = config.with_profile("ci").unwrap();
+        assert_eq!(ci.jobs, Some(8));
+        assert_eq!(ci.retries, Some(1));
+        assert_eq!(ci.env.len(), 2);
+        assert_eq!(ci.dir(), config.dir());
+    }
+
+    #[test]
+    fn profiles_can_be_written_either_way() {
+        let config = load("[profiles.local]\njobs = 1\n").unwrap();
+        assert_eq!(config.profiles(), ["local"]);
+        assert_eq!(config.with_profile("local").unwrap().jobs, Some(1));
+        let err = load("[profile.a]\njobs = 1\n[profiles.b]\njobs = 2\n").unwrap_err();
+        assert!(matches!(err, Error::ProfileAndProfiles), "{err}");
+    }
+
+    #[test]
+    fn unknown_profiles_list_the_others() {
+        let config = load("[profile.local]\n[profile.ci]\n").unwrap();
+        assert_eq!(config.profiles(), ["ci", "local"]);
+        let err = config.with_profile("nightly").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown profile \"nightly\", available profiles: ci, local"
+        );
+        let err = load("").unwrap().with_profile("ci").unwrap_err();
+        assert!(
+            err.to_string().ends_with("no profiles are defined"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn profiles_are_checked_like_the_top_level() {
+        let err = load("[profile.ci]\njbos = 2\n").unwrap_err();
+        assert!(matches!(err, Error::UnknownKeys(_)), "{err}");
+        let config = load("[profile.ci]\nretries = 1000\n").unwrap();
+        let err = config.with_profile("ci").unwrap_err();
+        assert!(
+            matches!(&err, Error::Profile { name, .. } if name == "ci"),
+            "{err}"
+        );
+    }
+}
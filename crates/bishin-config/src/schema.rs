@@ -0,0 +1,679 @@
+//! A JSON Schema of the config file, for editors that can check TOML files
+//! against one.
+//!
+//! Every key that a config can have, as listed for finding unknown keys,
+//! must have a schema here, and the descriptions are the comments that
+//! `bishin init` writes.
+
+use std::fmt::Write;
+
+use bishin_parser::Shell;
+
+use crate::template::FIELDS;
+use crate::unknown::{KEYS, SECTIONS, TOP_LEVEL_ONLY};
+use crate::{Config, SuccessOutput, MAX_RETRIES};
+
+/// The schema of a value.
+enum Schema {
+    String,
+    Boolean,
+    Integer {
+        minimum: u32,
+        maximum: Option<u32>,
+    },
+    Number {
+        minimum: f64,
+        maximum: f64,
+    },
+    Enum(Vec<&'static str>),
+    Const(&'static str),
+    Array {
+        items: Box<Schema>,
+        min_items: usize,
+    },
+    /// A table with any keys, whose values all have the same schema.
+    Map(Box<Schema>),
+    /// A table with only the keys in [`SECTIONS`] for it.
+    Section(&'static str),
+    AnyOf(Vec<Schema>),
+    /// The settings that the top level and profiles have in common.
+    Settings,
+}
+
+/// The schema of the value of the top-level key `key`.
+fn key_schema(key: &'static str) -> Option<Schema> {
+    let strings = || Schema::Array {
+        items: Box::new(Schema::String),
+        min_items: 0,
+    };
+    Some(match key {
+        "extends" | "test-dir" | "work-dir" | "setup-script" | "teardown-script" | "report-dir"
+        | "name-prefix" => Schema::String,
+        "test-dirs" | "ignore" | "redact" => strings(),
+        "jobs" => Schema::AnyOf(vec![
+            Schema::Integer {
+                minimum: 1,
+                maximum: None,
+            },
+            Schema::Const("auto"),
+        ]),
+        "timeout" => Schema::String,
+        "env" => Schema::Map(Box::new(Schema::String)),
+        "min-pass-rate" => Schema::Number {
+            minimum: 0.0,
+            maximum: 1.0,
+        },
+        "retries" => Schema::Integer {
+            minimum: 0,
+            maximum: Some(MAX_RETRIES),
+        },
+        "max-failures" => Schema::Integer {
+            minimum: 0,
+            maximum: None,
+        },
+        "flaky-promote-after" => Schema::Integer {
+            minimum: 1,
+            maximum: None,
+        },
+        "fail-fast" | "keep-tmp-on-failure" | "respect-gitignore" | "allow-unknown-keys" => {
+            Schema::Boolean
+        }
+        "on-success-output" => Schema::Enum(
+            SuccessOutput::ALL
+                .iter()
+                .map(|output| output.name())
+                .collect(),
+        ),
+        "file-extensions" => Schema::Array {
+            items: Box::new(Schema::String),
+            min_items: 1,
+        },
+        "tags" | "reports" | "env-policy" | "snapshots" | "script" => Schema::Section(key),
+        "platform-shell" => Schema::Map(Box::new(Schema::Enum(
+            Shell::ALL.iter().map(|shell| shell.name()).collect(),
+        ))),
+        "profile" | "profiles" => Schema::Map(Box::new(Schema::Settings)),
+        _ => return None,
+    })
+}
+
+/// The schema of the key `key` in the section `section`.
+fn section_key_schema(section: &str, key: &str) -> Schema {
+    match (section, key) {
+        ("tags", _) | ("env-policy", "allow") => Schema::Array {
+            items: Box::new(Schema::String),
+            min_items: 0,
+        },
+        ("env-policy", _) | ("script", _) => Schema::Boolean,
+        ("snapshots", "update") => Schema::Enum(vec!["never", "missing", "always"]),
+        _ => Schema::String,
+    }
+}
+
+impl Config {
+    /// A JSON Schema (draft 7) of the config file.
+    ///
+    /// # Panics
+    ///
+    /// If a key that the config can have has no schema, which is a bug.
+    pub fn json_schema() -> String {
+        let mut out = String::new();
+        out.push_str("{\n");
+        out.push_str("  \"$schema\": \"http://json-schema.org/draft-07/schema#\",\n");
+        out.push_str("  \"title\": \"bishin.toml\",\n");
+        out.push_str("  \"definitions\": {\n    \"settings\": ");
+        write_table(&mut out, 4, |key| !TOP_LEVEL_ONLY.contains(&key));
+        out.push_str("\n  },\n");
+        // The top level is the same as a profile, plus the keys only it has.
+        write_properties(&mut out, 2, |_| true);
+        out.push_str("\n}\n");
+        out
+    }
+}
+
+/// Write a table with the top-level `KEYS` that `include` says.
+fn write_table(out: &mut String, indent: usize, include: impl Fn(&str) -> bool) {
+    out.push_str("{\n");
+    write_properties(out, indent + 2, include);
+    write!(out, "\n{}}}", " ".repeat(indent)).unwrap();
+}
+
+/// Write the `type`, `properties` and `additionalProperties` of a table with
+/// the top-level `KEYS` that `include` says.
+fn write_properties(out: &mut String, indent: usize, include: impl Fn(&str) -> bool) {
+    let pad = " ".repeat(indent);
+    writeln!(out, "{pad}\"type\": \"object\",").unwrap();
+    writeln!(out, "{pad}\"properties\": {{").unwrap();
+    let keys: Vec<_> = KEYS.iter().filter(|key| include(key)).collect();
+    for (i, key) in keys.iter().enumerate() {
+        let schema = key_schema(key).unwrap_or_else(|| panic!("config key {key} has no schema"));
+        let description = FIELDS
+            .iter()
+            .find(|field| field.key == **key || (**key == "profiles" && field.key == "profile"))
+            .map(|field| field.comment);
+        write!(out, "{pad}  {}: ", json_string(key)).unwrap();
+        write_schema(out, indent + 2, &schema, description);
+        out.push_str(if i + 1 < keys.len() { ",\n" } else { "\n" });
+    }
+    writeln!(out, "{pad}}},").unwrap();
+    write!(out, "{pad}\"additionalProperties\": false").unwrap();
+}
+
+fn write_schema(out: &mut String, indent: usize, schema: &Schema, description: Option<&str>) {
+    let pad = " ".repeat(indent);
+    let mut fields: Vec<String> = Vec::new();
+    if let Some(description) = description {
+        fields.push(format!("\"description\": {}", json_string(description)));
+    }
+    match schema {
+        Schema::String => fields.push("\"type\": \"string\"".to_string()),
+        Schema::Boolean => fields.push("\"type\": \"boolean\"".to_string()),
+        Schema::Integer { minimum, maximum } => {
+            fields.push("\"type\": \"integer\"".to_string());
+            fields.push(format!("\"minimum\": {minimum}"));
+            if let Some(maximum) = maximum {
+                fields.push(format!("\"maximum\": {maximum}"));
+            }
+        }
+        Schema::Number { minimum, maximum } => {
+            fields.push("\"type\": \"number\"".to_string());
+            fields.push(format!("\"minimum\": {minimum:?}"));
+            fields.push(format!("\"maximum\": {maximum:?}"));
+        }
+        Schema::Enum(values) => {
+            let values: Vec<_> = values.iter().map(|value| json_string(value)).collect();
+            fields.push(format!("\"enum\": [{}]", values.join(", ")));
+        }
+        Schema::Const(value) => fields.push(format!("\"const\": {}", json_string(value))),
+        Schema::Array { items, min_items } => {
+            fields.push("\"type\": \"array\"".to_string());
+            if *min_items > 0 {
+                fields.push(format!("\"minItems\": {min_items}"));
+            }
+            fields.push(format!("\"items\": {}", inline(indent + 2, items)));
+        }
+        Schema::Map(values) => {
+            fields.push("\"type\": \"object\"".to_string());
+            fields.push(format!(
+                "\"additionalProperties\": {}",
+                inline(indent + 2, values)
+            ));
+        }
+        Schema::Section(section) => {
+            let keys = SECTIONS
+                .iter()
+                .find(|(name, _)| name == section)
+                .map_or(&[][..], |(_, keys)| keys);
+            let properties: Vec<_> = keys
+                .iter()
+                .map(|key| {
+                    format!(
+                        "{}: {}",
+                        json_string(key),
+                        inline(indent + 4, &section_key_schema(section, key))
+                    )
+                })
+                .collect();
+            fields.push("\"type\": \"object\"".to_string());
+            fields.push(format!(
+                "\"properties\": {{\n{pad}    {}\n{pad}  }}",
+                properties.join(&format!(",\n{pad}    "))
+            ));
+            fields.push("\"additionalProperties\": false".to_string());
+        }
+        Schema::AnyOf(schemas) => {
+            let schemas: Vec<_> = schemas
+                .iter()
+                .map(|schema| inline(indent + 4, schema))
+                .collect();
+            fields.push(format!(
+                "\"anyOf\": [\n{pad}    {}\n{pad}  ]",
+                schemas.join(&format!(",\n{pad}    "))
+            ));
+        }
+        Schema::Settings => fields.push("\"$ref\": \"#/definitions/settings\"".to_string()),
+    }
+    write!(
+        out,
+        "{{\n{pad}  {}\n{pad}}}",
+        fields.join(&format!(",\n{pad}  "))
+    )
+    .unwrap();
+}
+
+/// `schema` without a description, as a string.
+fn inline(indent: usize, schema: &Schema) -> String {
+    let mut out = String::new();
+    write_schema(&mut out, indent, schema, None);
+    out
+}
+
+/// `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::load;
+
+    /// A parsed JSON value, enough of one to read the schema back.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    impl Json {
+        fn get(&self, key: &str) -> Option<&Json> {
+            match self {
+                Json::Object(fields) => fields
+                    .iter()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value),
+                _ => None,
+            }
+        }
+    }
+
+    /// Parse `text` as a single JSON value, failing on anything else.
+    fn parse_json(text: &str) -> Json {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars);
+        skip_whitespace(&mut chars);
+        assert_eq!(chars.next(), None, "trailing text after the JSON value");
+        value
+    }
+
+    type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+    fn skip_whitespace(chars: &mut Chars) {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(chars: &mut Chars, expected: char) {
+        skip_whitespace(chars);
+        assert_eq!(chars.next(), Some(expected));
+    }
+
+    fn parse_value(chars: &mut Chars) -> Json {
+        skip_whitespace(chars);
+        match chars.peek().copied().expect("a value") {
+            '{' => {
+                chars.next();
+                let mut fields = Vec::new();
+                skip_whitespace(chars);
+                if chars.next_if_eq(&'}').is_some() {
+                    return Json::Object(fields);
+                }
+                loop {
+                    skip_whitespace(chars);
+                    let Json::String(key) = parse_value(chars) else {
+                        panic!("object keys are strings");
+                    };
+                    assert!(
+                        fields.iter().all(|(name, _)| *name != key),
+                        "duplicate key {key}"
+                    );
+                    expect(chars, ':');
+                    fields.push((key, parse_value(chars)));
+                    skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => {}
+                        Some('}') => return Json::Object(fields),
+                        other => panic!("expected , or }} in an object, found {other:?}"),
+                    }
+                }
+            }
+            '[' => {
+                chars.next();
+                let mut items = Vec::new();
+                skip_whitespace(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Json::Array(items);
+                }
+                loop {
+                    items.push(parse_value(chars));
+                    skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Json::Array(items),
+                        other => panic!("expected , or ] in an array, found {other:?}"),
+                    }
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next().expect("an unterminated string") {
+                        '"' => return Json::String(string),
+                        '\\' => match chars.next().expect("an escape") {
+                            'n' => string.push('\n'),
+                            't' => string.push('\t'),
+                            'u' => {
+                                let hex: String = chars.by_ref().take(4).collect();
+                                let code = u32::from_str_radix(&hex, 16).unwrap();
+                                string.push(char::from_u32(code).unwrap());
+                            }
+                            c @ ('"' | '\\' | '/') => string.push(c),
+                            c => panic!("unknown escape \\{c}"),
+                        },
+                        c => {
+                            assert!(!c.is_control(), "unescaped control character");
+                            string.push(c);
+                        }
+                    }
+                }
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e'))
+                {
+                    number.push(c);
+                }
+                Json::Number(number.parse().unwrap())
+            }
+            _ => {
+                let word: String =
+                    std::iter::from_fn(|| chars.next_if(char::is_ascii_lowercase)).collect();
+                match word.as_str() {
+                    "true" => Json::Bool(true),
+                    "false" => Json::Bool(false),
+                    "null" => Json::Null,
+                    _ => panic!("unexpected {word:?}"),
+                }
+            }
+        }
+    }
+
+    /// Check `value` against the parts of draft 7 that the schema uses,
+    /// returning the path to the first value that doesn't match.
+    fn validate(root: &Json, schema: &Json, value: &toml::Value, path: &str) -> Result<(), String> {
+        let fail = |why: &str| Err(format!("{path}: {why}"));
+        if let Some(Json::String(reference)) = schema.get("$ref") {
+            let name = reference
+                .strip_prefix("#/definitions/")
+                .expect("a local reference");
+            let definition = root.get("definitions").and_then(|d| d.get(name)).unwrap();
+            return validate(root, definition, value, path);
+        }
+        if let Some(Json::Array(schemas)) = schema.get("anyOf") {
+            if schemas
+                .iter()
+                .any(|schema| validate(root, schema, value, path).is_ok())
+            {
+                return Ok(());
+            }
+            return fail("matches none of anyOf");
+        }
+        if let Some(Json::String(expected)) = schema.get("const") {
+            if value.as_str() != Some(expected) {
+                return fail(&format!("is not {expected:?}"));
+            }
+        }
+        if let Some(Json::Array(values)) = schema.get("enum") {
+            let allowed = values
+                .iter()
+                .any(|allowed| matches!((allowed, value), (Json::String(a), toml::Value::String(v)) if a == v));
+            if !allowed {
+                return fail(&format!("{value} is not one of the enum"));
+            }
+        }
+        let number = match value {
+            toml::Value::Integer(n) => Some(*n as f64),
+            toml::Value::Float(n) => Some(*n),
+            _ => None,
+        };
+        if let (Some(Json::Number(minimum)), Some(n)) = (schema.get("minimum"), number) {
+            if n < *minimum {
+                return fail(&format!("{n} is below {minimum}"));
+            }
+        }
+        if let (Some(Json::Number(maximum)), Some(n)) = (schema.get("maximum"), number) {
+            if n > *maximum {
+                return fail(&format!("{n} is above {maximum}"));
+            }
+        }
+        let Some(Json::String(ty)) = schema.get("type") else {
+            return Ok(());
+        };
+        match (ty.as_str(), value) {
+            ("string", toml::Value::String(_))
+            | ("boolean", toml::Value::Boolean(_))
+            | ("integer", toml::Value::Integer(_))
+            | ("number", toml::Value::Integer(_) | toml::Value::Float(_)) => Ok(()),
+            ("array", toml::Value::Array(items)) => {
+                if let Some(Json::Number(min_items)) = schema.get("minItems") {
+                    if (items.len() as f64) < *min_items {
+                        return fail("has too few items");
+                    }
+                }
+                let item_schema = schema.get("items").unwrap();
+                for (i, item) in items.iter().enumerate() {
+                    validate(root, item_schema, item, &format!("{path}[{i}]"))?;
+                }
+                Ok(())
+            }
+            ("object", toml::Value::Table(table)) => {
+                let properties = schema.get("properties");
+                let additional = schema.get("additionalProperties");
+                for (key, value) in table {
+                    let path = format!("{path}.{key}");
+                    match (properties.and_then(|p| p.get(key)), additional) {
+                        (Some(schema), _) => validate(root, schema, value, &path)?,
+                        (None, Some(Json::Bool(false))) => {
+                            return Err(format!("{path}: is not allowed"));
+                        }
+                        (None, Some(schema @ Json::Object(_))) => {
+                            validate(root, schema, value, &path)?;
+                        }
+                        (None, _) => {}
+                    }
+                }
+                Ok(())
+            }
+            _ => fail(&format!("{value} is not a {ty}")),
+        }
+    }
+
+    fn validate_toml(contents: &str) -> Result<(), String> {
+        let schema = parse_json(&Config::json_schema());
+        let value = toml::Value::Table(contents.parse().unwrap());
+        validate(&schema, &schema, &value, "config")
+    }
+
+    const FULL_CONFIG: &str = r#"
+test-dirs = ["tests/unit", "tests/e2e"]
+work-dir = "."
+jobs = "auto"
+timeout = "90s"
+env = { LANG = "C" }
+min-pass-rate = 0.5
+retries = 2
+fail-fast = false
+max-failures = 3
+keep-tmp-on-failure = true
+on-success-output = "summary"
+flaky-promote-after = 2
+setup-script = "setup.sh"
+teardown-script = "teardown.sh"
+report-dir = "reports"
+name-prefix = "ci"
+file-extensions = ["bishin", "t"]
+ignore = ["**/wip/**"]
+respect-gitignore = true
+redact = ["token=\\S+"]
+platform-shell = { linux = "bash" }
+allow-unknown-keys = false
+
+[tags]
+default-include = ["fast"]
+default-exclude = ["slow"]
+
+[reports]
+junit = "junit.xml"
+json = "report.json"
+jsonl-events = "events.jsonl"
+html = "report.html"
+markdown = "report.md"
+
+[env-policy]
+clean = true
+allow = ["PATH", "HOME"]
+
+[snapshots]
+dir = "snapshots"
+update = "missing"
+
+[script]
+errexit = true
+nounset = true
+pipefail = false
+xtrace = false
+
+[profile.ci]
+jobs = 4
+retries = 1
+reports = { junit = "ci.xml" }
+"#;
+
+    #[test]
+    fn the_schema_is_well_formed_json() {
+        let schema = parse_json(&Config::json_schema());
+        assert_eq!(
+            schema.get("$schema"),
+            Some(&Json::String(
+                "http://json-schema.org/draft-07/schema#".to_string()
+            ))
+        );
+        let Some(Json::Object(properties)) = schema.get("properties") else {
+            panic!("the top level has properties");
+        };
+        let names: Vec<_> = properties.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, KEYS);
+    }
+
+    #[test]
+    fn profiles_can_have_every_key_but_the_top_level_only_ones() {
+        let schema = parse_json(&Config::json_schema());
+        let settings = schema.get("definitions").unwrap().get("settings").unwrap();
+        for key in KEYS {
+            let described = settings.get("properties").unwrap().get(key).is_some();
+            assert_eq!(described, !TOP_LEVEL_ONLY.contains(key), "{key}");
+        }
+    }
+
+    #[test]
+    fn every_section_key_is_described() {
+        let schema = parse_json(&Config::json_schema());
+        for (section, keys) in SECTIONS {
+            let properties = schema
+                .get("properties")
+                .and_then(|p| p.get(section))
+                .and_then(|s| s.get("properties"))
+                .unwrap_or_else(|| panic!("{section} has properties"));
+            for key in *keys {
+                assert!(properties.get(key).is_some(), "{section}.{key}");
+            }
+        }
+    }
+
+    #[test]
+    fn the_written_default_config_matches_the_schema() {
+        validate_toml(&Config::default().to_toml_string()).unwrap();
+    }
+
+    #[test]
+    fn a_config_with_every_key_matches_the_schema() {
+        let config = load(FULL_CONFIG).unwrap();
+        validate_toml(FULL_CONFIG).unwrap();
+        validate_toml(&config.to_toml_string()).unwrap();
+    }
+
+    #[test]
+    fn configs_that_fail_to_load_fail_the_schema_too() {
+        for (contents, error) in [
+            ("jobs = 0", "config.jobs: matches none of anyOf"),
+            ("jobs = \"all\"", "config.jobs: matches none of anyOf"),
+            (
+                "min-pass-rate = 1.5",
+                "config.min-pass-rate: 1.5 is above 1",
+            ),
+            (
+                "fail-fast = \"yes\"",
+                "config.fail-fast: \"yes\" is not a boolean",
+            ),
+            ("jbos = 2", "config.jbos: is not allowed"),
+            (
+                "[snapshots]\nupdate = \"sometimes\"",
+                "config.snapshots.update: \"sometimes\" is not one of the enum",
+            ),
+            (
+                "[profile.ci]\nextends = \"base.toml\"",
+                "config.profile.ci.extends: is not allowed",
+            ),
+            (
+                "file-extensions = []",
+                "config.file-extensions: has too few items",
+            ),
+        ] {
+            assert!(load(contents).is_err(), "{contents} loads");
+            assert_eq!(
+                validate_toml(contents),
+                Err(error.to_string()),
+                "{contents}"
+            );
+        }
+        let retries = format!("retries = {}", MAX_RETRIES + 1);
+        assert!(load(&retries).is_err());
+        assert!(validate_toml(&retries).is_err());
+    }
+
+    #[test]
+    fn descriptions_come_from_the_template() {
+        let schema = parse_json(&Config::json_schema());
+        let properties = schema.get("properties").unwrap();
+        for field in FIELDS {
+            let described = properties.get(field.key).and_then(|p| p.get("description"));
+            assert_eq!(
+                described,
+                Some(&Json::String(field.comment.to_string())),
+                "{}",
+                field.key
+            );
+        }
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(
+            json_string("a \"quote\"\\ and\nnew line\u{1}"),
+            "\"a \\\"quote\\\"\\\\ and\\nnew line\\u0001\""
+        );
+        let tricky = "\"\\\n\t\u{7f}é";
+        assert_eq!(
+            parse_json(&json_string(tricky)),
+            Json::String(tricky.to_string())
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Single values of the config set from the command line, e.g.
+//! `--set jobs=4` or `--set snapshots.update=always`, over the file and any
+//! profile.
+//!
+//! The key is a dotted path to a setting, with `_` allowed for `-`, and the
+//! value is written as in TOML, or taken as a string if it isn't valid TOML
+//! or the setting wants a string:
+//!
+//! ```
+//! let config = bishin_config::Config::default()
+//!     .with_set("jobs", "4")?
+//!     .with_set("env.GREETING", "hello")?
+//!     .with_set("script.xtrace", "true")?;
+//! assert_eq!(config.jobs, Some(4));
+//! assert_eq!(config.env["GREETING"], "hello");
+//! assert!(config.script.xtrace);
+//! # Ok::<(), bishin_config::Error>(())
+//! ```
+
+use toml::{Table, Value};
+
+use crate::unknown::{suggest, KEYS, SECTIONS, TOP_LEVEL_ONLY};
+use crate::{Config, Error};
+
+/// The tables whose keys are names rather than settings.
+const MAPS: &[&str] = &["env", "platform-shell"];
+
+impl Config {
+    /// This config with the setting at the dotted path `key` set to `value`.
+    pub fn with_set(&self, key: &str, value: &str) -> Result<Config, Error> {
+        let path = setting_path(key)?;
+        let parsed = toml::from_str::<Table>(&format!("value = {value}"))
+            .ok()
+            .and_then(|mut table| table.remove("value"));
+        let set_error = |source| Error::Set {
+            key: key.to_string(),
+            source: Box::new(source),
+        };
+        let result = match parsed {
+            Some(parsed) if !parsed.is_str() => self
+                .with_value(&path, parsed)
+                // A setting that wants a string, such as a path of digits.
+                .or_else(|err| {
+                    self.with_value(&path, Value::String(value.to_string()))
+                        .map_err(|_| err)
+                }),
+            _ => self.with_value(&path, Value::String(value.to_string())),
+        };
+        result.map_err(set_error)
+    }
+
+    fn with_value(&self, path: &[String], value: Value) -> Result<Config, Error> {
+        let mut base = Table::try_from(self).expect("config serializes to a table");
+        let (last, parents) = path.split_last().expect("setting paths aren't empty");
+        let mut table = &mut base;
+        for parent in parents {
+            let entry = table
+                .entry(parent.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+            table = entry.as_table_mut().expect("sections are tables");
+        }
+        // test-dir and test-dirs replace each other, as in a profile.
+        for (key, other) in [("test-dir", "test-dirs"), ("test-dirs", "test-dir")] {
+            if path.len() == 1 && last == key {
+                table.remove(other);
+            }
+        }
+        table.insert(last.clone(), value);
+        let mut config: Config = base.try_into().map_err(|err| Error::parse(err, None))?;
+        config.check()?;
+        config.dir = self.dir.clone();
+        config.source = self.source.clone();
+        Ok(config)
+    }
+}
+
+/// The keys of the setting at the dotted path `key`, checking that bishin
+/// knows it.
+fn setting_path(key: &str) -> Result<Vec<String>, Error> {
+    let unknown = |suggestion| Error::UnknownSetKey {
+        key: key.to_string(),
+        suggestion,
+    };
+    let (first, rest) = key
+        .split_once('.')
+        .map_or((key, None), |(first, rest)| (first, Some(rest)));
+    let first = first.replace('_', "-");
+    let top_level = |name: &str| KEYS.contains(&name) && !TOP_LEVEL_ONLY.contains(&name);
+    if !top_level(&first) {
+        return Err(unknown(suggest(&first, &top_level).map(str::to_string)));
+    }
+    let section = SECTIONS.iter().find(|(section, _)| *section == first);
+    match (rest, section) {
+        (None, None) if !MAPS.contains(&first.as_str()) => Ok(vec![first]),
+        (Some(name), None) if MAPS.contains(&first.as_str()) && !name.is_empty() => {
+            Ok(vec![first, name.to_string()])
+        }
+        (Some(name), Some((_, keys))) => {
+            let name = name.replace('_', "-");
+            let in_section = |key: &str| keys.contains(&key);
+            if in_section(&name) {
+                Ok(vec![first, name])
+            } else {
+                Err(unknown(
+                    suggest(&name, &in_section).map(|name| format!("{first}.{name}")),
+                ))
+            }
+        }
+        _ => Err(unknown(None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::SnapshotUpdate;
+
+    fn unknown_key(key: &str) -> Option<String> {
+        match Config::default().with_set(key, "1") {
+            Err(Error::UnknownSetKey { suggestion, .. }) => suggestion,
+            other => panic!("expected an unknown key, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn scalar_settings_are_set() {
+        let config = Config::default()
+            .with_set("jobs", "4")
+            .unwrap()
+            .with_set("retries", "2")
+            .unwrap()
+            .with_set("test_dir", "checks")
+            .unwrap();
+        assert_eq!(config.jobs, Some(4));
+        assert_eq!(config.retries, Some(2));
+        assert_eq!(config.test_dir, PathBuf::from("checks"));
+    }
+
+    #[test]
+    fn settings_in_sections_are_set() {
+        let config = Config::default()
+            .with_set("snapshots.update", "always")
+            .unwrap()
+            .with_set("reports.junit", "out/junit.xml")
+            .unwrap()
+            .with_set("script.errexit", "false")
+            .unwrap();
+        assert_eq!(config.snapshots.update, Some(SnapshotUpdate::Always));
+        assert_eq!(config.reports.junit, Some(PathBuf::from("out/junit.xml")));
+        assert!(!config.script.errexit);
+    }
+
+    #[test]
+    fn other_settings_are_kept() {
+        let config = Config::default()
+            .with_set("env.A", "1")
+            .unwrap()
+            .with_set("env.B", "two words")
+            .unwrap()
+            .with_set("jobs", "3")
+            .unwrap();
+        assert_eq!(config.env["A"], "1");
+        assert_eq!(config.env["B"], "two words");
+        assert_eq!(config.jobs, Some(3));
+    }
+
+    #[test]
+    fn strings_of_digits_are_kept_as_strings() {
+        let config = Config::default().with_set("test-dir", "2024").unwrap();
+        assert_eq!(config.test_dir, PathBuf::from("2024"));
+    }
+
+    #[test]
+    fn unknown_keys_are_suggested_a_known_one() {
+        assert_eq!(unknown_key("jbos"), Some("jobs".to_string()));
+        assert_eq!(
+            unknown_key("snapshots.updat"),
+            Some("snapshots.update".to_string())
+        );
+        assert_eq!(unknown_key("nonsense-setting"), None);
+        assert_eq!(unknown_key("snapshots"), None);
+        assert_eq!(unknown_key("jobs.count"), None);
+        assert_eq!(unknown_key("env."), None);
+        assert_eq!(unknown_key("profile"), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_with_the_suggestion() {
+        let err = Config::default().with_set("jbos", "4").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown setting \"jbos\" for --set; did you mean `jobs`?"
+        );
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_errors() {
+        for (key, value) in [
+            ("jobs", "many"),
+            ("jobs", "-1"),
+            ("script.xtrace", "sometimes"),
+            ("snapshots.update", "later"),
+            ("retries", "101"),
+        ] {
+            let err = Config::default().with_set(key, value).unwrap_err();
+            assert!(
+                matches!(&err, Error::Set { key: set, .. } if set == key),
+                "{key}={value}: {err:?}"
+            );
+            assert_eq!(err.to_string(), format!("invalid value for --set {key}"));
+        }
+    }
+}
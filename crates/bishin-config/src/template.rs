@@ -0,0 +1,357 @@
+//! Writing a config out as a commented `bishin.toml`.
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::{Config, Error};
+
+/// A key in the written config: what it does, and an example value to show
+/// commented out when the config leaves it unset.
+pub(crate) struct Field {
+    pub(crate) key: &'static str,
+    pub(crate) comment: &'static str,
+    example: &'static str,
+}
+
+/// The documented keys, in the order they're written. Keys missing from here
+/// are still written, after these and without a comment.
+pub(crate) const FIELDS: &[Field] = &[
+    Field {
+        key: "extends",
+        comment: "Another config file to build on, relative to this one.",
+        example: "extends = \"../bishin.base.toml\"",
+    },
+    Field {
+        key: "test-dir",
+        comment: "The directory that tests are collected from.",
+        example: "test-dir = \"tests\"",
+    },
+    Field {
+        key: "test-dirs",
+        comment: "Directories to collect tests from instead, each a module of its own.",
+        example: "test-dirs = [\"tests/unit\", \"tests/integration\"]",
+    },
+    Field {
+        key: "work-dir",
+        comment: "The directory that generated scripts are written to.",
+        example: "work-dir = \".bishin\"",
+    },
+    Field {
+        key: "jobs",
+        comment: "How many tests to run at once, or \"auto\" for one per CPU.",
+        example: "jobs = \"auto\"",
+    },
+    Field {
+        key: "timeout",
+        comment: "How long a test may run for unless it sets its own @timeout.",
+        example: "timeout = \"2m\"",
+    },
+    Field {
+        key: "min-pass-rate",
+        comment: "The fraction of tests that must pass, instead of all of them.",
+        example: "min-pass-rate = 0.9",
+    },
+    Field {
+        key: "retries",
+        comment: "How many times to retry failed tests that don't set @retries.",
+        example: "retries = 2",
+    },
+    Field {
+        key: "fail-fast",
+        comment: "Stop at the first failure, whatever max-failures says.",
+        example: "fail-fast = true",
+    },
+    Field {
+        key: "max-failures",
+        comment: "Stop after this many failures, or never if it's 0.",
+        example: "max-failures = 10",
+    },
+    Field {
+        key: "keep-tmp-on-failure",
+        comment: "Keep the $BISHIN_TMPDIR of failed tests to look at afterwards.",
+        example: "keep-tmp-on-failure = true",
+    },
+    Field {
+        key: "on-success-output",
+        comment: "Show the output of passing tests: hidden, summary (at the end) or full.",
+        example: "on-success-output = \"summary\"",
+    },
+    Field {
+        key: "flaky-promote-after",
+        comment: "Count the failures of a @flaky test again once it passes this many runs in a row.",
+        example: "flaky-promote-after = 20",
+    },
+    Field {
+        key: "setup-script",
+        comment: "A script to run before any test, relative to this file.",
+        example: "setup-script = \"scripts/start_services.sh\"",
+    },
+    Field {
+        key: "teardown-script",
+        comment: "A script to run after every test, even if some failed.",
+        example: "teardown-script = \"scripts/stop_services.sh\"",
+    },
+    Field {
+        key: "report-dir",
+        comment: "Where the files that tests list with @artifact are copied to.",
+        example: "report-dir = \"target/bishin-reports\"",
+    },
+    Field {
+        key: "name-prefix",
+        comment: "Put before every test name in reports, so those of several runs can be merged.",
+        example: "name-prefix = \"${SHARD:-local}/\"",
+    },
+    Field {
+        key: "file-extensions",
+        comment: "The extensions of files that contain tests.",
+        example: "file-extensions = [\"b\"]",
+    },
+    Field {
+        key: "ignore",
+        comment: "Gitignore-style patterns for test files to leave out.",
+        example: "ignore = [\"**/fixtures/**\"]",
+    },
+    Field {
+        key: "respect-gitignore",
+        comment: "Leave out what .gitignore files in the test directories match, too.",
+        example: "respect-gitignore = true",
+    },
+    Field {
+        key: "redact",
+        comment: "Regexes for secrets in test output, replaced before it's shown or reported.",
+        example: "redact = [\"token=\\\\w+\"]",
+    },
+    Field {
+        key: "allow-unknown-keys",
+        comment: "Ignore keys that this version of bishin doesn't know about.",
+        example: "allow-unknown-keys = true",
+    },
+    Field {
+        key: "env",
+        comment: "Environment variables set for every test.",
+        example: "[env]\nRUST_LOG = \"debug\"",
+    },
+    Field {
+        key: "tags",
+        comment: "Which tests to run by their @tags, unless --tag or --exclude-tag say.",
+        example: "[tags]\ndefault-exclude = [\"network\", \"slow\"]",
+    },
+    Field {
+        key: "reports",
+        comment: "Reports to write about every run, see --report and --no-report.",
+        example: "[reports]\njunit = \"reports/junit.xml\"",
+    },
+    Field {
+        key: "env-policy",
+        comment: "Start tests with only the allowed variables from bishin's environment.",
+        example: "[env-policy]\nclean = true\nallow = [\"HOME\", \"PATH\", \"TERM\", \"LANG\"]",
+    },
+    Field {
+        key: "snapshots",
+        comment: "Where @snapshot tests keep their output, and when to record it: never, missing or always.",
+        example: "[snapshots]\ndir = \"tests/__snapshots__\"\nupdate = \"missing\"",
+    },
+    Field {
+        key: "script",
+        comment: "Shell options each test script turns on, unless the test is @no_strict.",
+        example: "[script]\nerrexit = true\nnounset = true\npipefail = true",
+    },
+    Field {
+        key: "platform-shell",
+        comment: "The shell to run tests with on each OS, bash on any not listed.",
+        example: "[platform-shell]\nmacos = \"zsh\"\nlinux = \"bash\"",
+    },
+    Field {
+        key: "profile",
+        comment: "Overrides selected with --profile NAME.",
+        example: "[profile.ci]\njobs = 2",
+    },
+];
+
+impl Config {
+    /// The config as a commented TOML document, which loads back into an
+    /// equal config.
+    ///
+    /// Every key is written in the same order each time, and keys the config
+    /// leaves unset are shown commented out with an example value.
+    pub fn to_toml_string(&self) -> String {
+        let mut table = toml::Table::try_from(self).expect("config serializes to a table");
+        // A file can't set both, and test-dir is only used without test-dirs.
+        if table.contains_key("test-dirs") {
+            table.remove("test-dir");
+        }
+        let entry = |key: &str| {
+            let value = table.get(key)?;
+            let mut single = toml::Table::new();
+            single.insert(key.to_string(), value.clone());
+            Some((
+                value.is_table(),
+                toml::to_string(&single).expect("a value serializes"),
+            ))
+        };
+
+        let mut sections = Vec::new();
+        for field in FIELDS {
+            let section = match entry(field.key) {
+                Some((is_table, value)) => (is_table, format!("# {}\n{value}", field.comment)),
+                None => {
+                    let example: Vec<_> = field.example.lines().collect();
+                    let is_table = example[0].starts_with('[');
+                    let commented = example.join("\n# ");
+                    (is_table, format!("# {}\n# {commented}\n", field.comment))
+                }
+            };
+            sections.push(section);
+        }
+        let undocumented = table
+            .keys()
+            .filter(|key| FIELDS.iter().all(|field| field.key != key.as_str()));
+        sections.extend(undocumented.filter_map(|key| entry(key)));
+
+        // Everything after a table header belongs to that table, so all the
+        // tables go last.
+        sections.sort_by_key(|(is_table, _)| *is_table);
+        let sections: Vec<_> = sections.into_iter().map(|(_, section)| section).collect();
+        sections.join("\n")
+    }
+
+    /// Write the default config to `path`, failing if something is already
+    /// there.
+    pub fn write_default(path: &Path) -> Result<(), Error> {
+        let write_error = |source| Error::Write {
+            path: path.to_path_buf(),
+            source,
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(write_error)?;
+        file.write_all(Config::default().to_toml_string().as_bytes())
+            .map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{load, scratch_dir, write};
+    use crate::unknown::KEYS;
+
+    /// `loaded` with where it was loaded from forgotten, to compare with
+    /// configs that weren't loaded from anywhere.
+    fn unloaded(loaded: Config) -> Config {
+        let default = Config::default();
+        Config {
+            dir: default.dir,
+            source: default.source,
+            ..loaded
+        }
+    }
+
+    #[test]
+    fn the_default_round_trips() {
+        let written = Config::default().to_toml_string();
+        assert_eq!(unloaded(load(&written).unwrap()), Config::default());
+        assert_eq!(Config::default().to_toml_string(), written);
+    }
+
+    /// Each example uncommented, tables last. `extends` needs a file to
+    /// extend, and `test-dirs` would clash with `test-dir`.
+    fn every_example() -> String {
+        let (tables, keys): (Vec<&Field>, Vec<&Field>) = FIELDS
+            .iter()
+            .filter(|field| !["extends", "test-dirs"].contains(&field.key))
+            .partition(|field| field.example.starts_with('['));
+        keys.iter()
+            .chain(&tables)
+            .map(|field| format!("{}\n", field.example))
+            .collect()
+    }
+
+    #[test]
+    fn every_example_loads_and_round_trips() {
+        let config = unloaded(load(&every_example()).unwrap());
+        assert_ne!(config, Config::default());
+        let written = config.to_toml_string();
+        assert_eq!(unloaded(load(&written).unwrap()), config);
+    }
+
+    #[test]
+    fn every_key_is_documented() {
+        let undocumented: Vec<_> = KEYS
+            .iter()
+            // The other spelling of `profile`.
+            .filter(|key| **key != "profiles")
+            .filter(|key| FIELDS.iter().all(|field| field.key != **key))
+            .collect();
+        assert_eq!(undocumented, [] as [&&str; 0]);
+
+        // Whatever a config sets is written out, documented or not.
+        let config = unloaded(load(&every_example()).unwrap());
+        let table = toml::Table::try_from(&config).unwrap();
+        let written = config.to_toml_string();
+        for key in table.keys() {
+            let written_as = [
+                format!("\n{key} = "),
+                format!("\n[{key}]"),
+                format!("\n[{key}."),
+            ];
+            assert!(
+                written_as
+                    .iter()
+                    .any(|start| written.contains(start.as_str())),
+                "{key} isn't written"
+            );
+        }
+    }
+
+    #[test]
+    fn unset_keys_are_written_as_commented_examples() {
+        let written = Config::default().to_toml_string();
+        assert!(written.contains("\n# timeout = \"2m\"\n"), "{written}");
+        assert!(
+            written.contains("\n# [env]\n# RUST_LOG = \"debug\"\n"),
+            "{written}"
+        );
+        assert!(written
+            .contains("\n# The directory that tests are collected from.\ntest-dir = \"tests\"\n"));
+        let written = Config {
+            timeout: Some(std::time::Duration::from_secs(30)),
+            ..Config::default()
+        }
+        .to_toml_string();
+        assert!(written.contains("\ntimeout = \"30s\"\n"), "{written}");
+        assert!(!written.contains("# timeout ="), "{written}");
+    }
+
+    #[test]
+    fn test_dirs_are_written_instead_of_test_dir() {
+        let config = Config {
+            test_dirs: vec!["tests/unit".into(), "tests/integration".into()],
+            ..Config::default()
+        };
+        let written = config.to_toml_string();
+        assert!(!written.contains("\ntest-dir ="), "{written}");
+        assert_eq!(unloaded(load(&written).unwrap()), config);
+    }
+
+    #[test]
+    fn write_default_does_not_overwrite() {
+        let dir = scratch_dir();
+        let path = dir.join(crate::CONFIG_FILE);
+        Config::write_default(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            Config::default().to_toml_string()
+        );
+
+        let existing = write(&dir, "existing.toml", "jobs = 1\n");
+        let err = Config::write_default(&existing).unwrap_err();
+        assert!(
+            matches!(&err, Error::Write { path, .. } if *path == existing),
+            "{err}"
+        );
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "jobs = 1\n");
+    }
+}
@@ -0,0 +1,29 @@
+//! Helpers for the tests in this crate.
+
+use std::path::{Path, PathBuf};
+
+pub(crate) use bishin_testing::{scratch_dir, ScratchDir};
+
+use crate::{Config, Error, LoadOptions};
+
+/// Write `contents` to `name` in `dir`, and return its path.
+pub(crate) fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// The config in `contents`, loaded from a file of its own, with no
+/// variables for it to refer to.
+pub(crate) fn load(contents: &str) -> Result<Config, Error> {
+    let scratch = scratch_dir();
+    let path = write(&scratch, crate::CONFIG_FILE, contents);
+    let lookup = |_: &str| None;
+    Config::load_with(
+        &path,
+        &LoadOptions {
+            lookup: Some(&lookup),
+            ..LoadOptions::default()
+        },
+    )
+}
@@ -0,0 +1,81 @@
+//! The `timeout` setting, which is a duration string or `"none"`.
+
+use std::fmt;
+use std::time::Duration;
+
+use bishin_parser::duration::{format_duration, parse_duration, DURATION_FORMATS};
+use serde::de::{self, Deserializer, Visitor};
+use serde::Serializer;
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    deserializer.deserialize_str(TimeoutVisitor)
+}
+
+struct TimeoutVisitor;
+
+impl<'de> Visitor<'de> for TimeoutVisitor {
+    type Value = Option<Duration>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a timeout given as {DURATION_FORMATS}, or \"none\"")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        if value == "none" {
+            return Ok(None);
+        }
+        parse_duration(value)
+            .map(Some)
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+pub(crate) fn serialize<S: Serializer>(
+    timeout: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match timeout {
+        Some(timeout) => serializer.serialize_str(&format_duration(*timeout)),
+        None => serializer.serialize_str("none"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::load;
+    use crate::Config;
+
+    #[test]
+    fn timeouts_are_durations_or_none() {
+        let config = load("timeout = \"2m\"\n").unwrap();
+        assert_eq!(config.timeout, Some(Duration::from_secs(120)));
+        let config = load("timeout = \"500ms\"\n").unwrap();
+        assert_eq!(config.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(load("timeout = \"none\"\n").unwrap().timeout, None);
+        assert_eq!(load("").unwrap().timeout, None);
+    }
+
+    #[test]
+    fn invalid_timeouts_name_the_key_and_the_formats() {
+        let err = load("timeout = \"soon\"\n").unwrap_err().to_string();
+        assert!(err.contains("timeout"), "{err}");
+        assert!(err.contains("\"soon\""), "{err}");
+        assert!(err.contains(DURATION_FORMATS), "{err}");
+    }
+
+    #[test]
+    fn timeouts_are_written_as_they_are_read() {
+        for timeout in [Some(Duration::from_secs(30)), None] {
+            let config = Config {
+                timeout,
+                ..Config::default()
+            };
+            let written = toml::to_string(&config).unwrap();
+            let read: Config = toml::from_str(&written).unwrap();
+            assert_eq!(read.timeout, timeout);
+        }
+    }
+}
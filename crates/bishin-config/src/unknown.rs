@@ -0,0 +1,252 @@
+//! Keys in a config file that bishin doesn't know about, which are usually
+//! typos of ones it does.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use toml::de::{DeTable, DeValue};
+
+use crate::diagnostic::Location;
+use crate::embedded::de_table_at;
+
+/// Every key allowed at the top level of a config file. New fields of
+/// [`Config`](crate::Config) need adding here too.
+pub(crate) const KEYS: &[&str] = &[
+    "test-dir",
+    "test-dirs",
+    "work-dir",
+    "jobs",
+    "timeout",
+    "env",
+    "min-pass-rate",
+    "retries",
+    "fail-fast",
+    "max-failures",
+    "keep-tmp-on-failure",
+    "on-success-output",
+    "flaky-promote-after",
+    "setup-script",
+    "teardown-script",
+    "report-dir",
+    "name-prefix",
+    "file-extensions",
+    "ignore",
+    "respect-gitignore",
+    "redact",
+    "tags",
+    "reports",
+    "env-policy",
+    "snapshots",
+    "script",
+    "platform-shell",
+    "profile",
+    "profiles",
+    "extends",
+    "allow-unknown-keys",
+];
+
+/// Keys that only make sense at the top level, not in a profile.
+pub(crate) const TOP_LEVEL_ONLY: &[&str] =
+    &["profile", "profiles", "extends", "allow-unknown-keys"];
+
+/// The keys allowed in each table with a fixed set of them.
+pub(crate) const SECTIONS: &[(&str, &[&str])] = &[
+    ("tags", &["default-include", "default-exclude"]),
+    (
+        "reports",
+        &["junit", "json", "jsonl-events", "html", "markdown"],
+    ),
+    ("env-policy", &["clean", "allow"]),
+    ("snapshots", &["dir", "update"]),
+    ("script", &["errexit", "nounset", "pipefail", "xtrace"]),
+];
+
+/// A key that bishin doesn't know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// The file the key is in.
+    pub path: PathBuf,
+    /// The dotted path to the key, e.g. `profile.ci.jbos`.
+    pub field: String,
+    /// The known key it's closest to, if any is close enough to be a typo.
+    pub suggestion: Option<&'static str>,
+    pub location: Location,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: unknown key `{}`",
+            self.path.display(),
+            self.location.line,
+            self.location.column,
+            self.field
+        )?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, "; did you mean `{suggestion}`?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Find every unknown key in `contents`, which was read from `path` and is
+/// known to be valid TOML, or in the table at the dotted path `within` it.
+pub(crate) fn unknown_keys(path: &Path, contents: &str, within: Option<&str>) -> Vec<UnknownKey> {
+    let Ok(table) = DeTable::parse(contents) else {
+        return Vec::new();
+    };
+    let (table, within) = match within {
+        Some(within) => match de_table_at(table.get_ref(), within) {
+            Some(table) => (table, format!("{within}.")),
+            None => return Vec::new(),
+        },
+        None => (table.get_ref(), String::new()),
+    };
+    let mut unknown = Vec::new();
+    let mut check = |table: &DeTable<'_>, prefix: &str, allowed: &dyn Fn(&str) -> bool| {
+        for key in table.keys() {
+            let name = key.get_ref();
+            if !allowed(name) {
+                unknown.push(UnknownKey {
+                    path: path.to_path_buf(),
+                    field: format!("{within}{prefix}{name}"),
+                    suggestion: suggest(name, allowed),
+                    location: Location::new(contents, key.span()),
+                });
+            }
+        }
+    };
+
+    // The top level and each profile, which hold the same settings.
+    let mut configs = vec![(table, String::new())];
+    for key in ["profile", "profiles"] {
+        if let Some(DeValue::Table(profiles)) = get(table, key) {
+            for (name, profile) in profiles {
+                if let DeValue::Table(profile) = profile.get_ref() {
+                    configs.push((profile, format!("{key}.{}.", name.get_ref())));
+                }
+            }
+        }
+    }
+    for (config, prefix) in configs {
+        let top_level = prefix.is_empty();
+        check(config, &prefix, &|key| {
+            KEYS.contains(&key) && (top_level || !TOP_LEVEL_ONLY.contains(&key))
+        });
+        for (section, keys) in SECTIONS {
+            if let Some(DeValue::Table(section_table)) = get(config, section) {
+                check(section_table, &format!("{prefix}{section}."), &|key| {
+                    keys.contains(&key)
+                });
+            }
+        }
+    }
+    unknown.sort_by_key(|key| key.location.span.start);
+    unknown
+}
+
+fn get<'a, 's>(table: &'a DeTable<'s>, key: &str) -> Option<&'a DeValue<'s>> {
+    table
+        .iter()
+        .find(|(name, _)| name.get_ref() == key)
+        .map(|(_, value)| value.get_ref())
+}
+
+/// The allowed key that `key` is most likely a typo of.
+pub(crate) fn suggest(key: &str, allowed: &dyn Fn(&str) -> bool) -> Option<&'static str> {
+    KEYS.iter()
+        .chain(SECTIONS.iter().flat_map(|(_, keys)| keys.iter()))
+        .filter(|known| allowed(known))
+        .map(|known| (strsim::osa_distance(key, known), *known))
+        .filter(|(distance, _)| *distance <= (key.len() / 3).max(1))
+        .min()
+        .map(|(_, known)| known)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{load, scratch_dir, write};
+    use crate::{Config, Error, LoadOptions};
+
+    /// The unknown keys of `contents` and what they're suggested to be.
+    fn unknown(contents: &str) -> Vec<(String, Option<&'static str>)> {
+        unknown_keys(Path::new("bishin.toml"), contents, None)
+            .into_iter()
+            .map(|key| (key.field, key.suggestion))
+            .collect()
+    }
+
+    #[test]
+    fn a_typo_is_suggested_the_key_it_misspells() {
+        assert_eq!(
+            unknown("tests-dir = \"x\"\n"),
+            [("tests-dir".to_string(), Some("test-dir"))]
+        );
+        let err = load("tests-dir = \"x\"\n").unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with(":1:1: unknown key `tests-dir`; did you mean `test-dir`?"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn every_unknown_key_is_reported_in_file_order() {
+        let contents = "jbos = 2\nwork-dir = \"out\"\n[script]\nerrexti = true\n[profile.ci]\nretires = 1\nextends = \"x\"\n[profile.ci.reports]\njunti = \"j.xml\"\n";
+        assert_eq!(
+            unknown(contents),
+            [
+                ("jbos".to_string(), Some("jobs")),
+                ("script.errexti".to_string(), Some("errexit")),
+                ("profile.ci.retires".to_string(), Some("retries")),
+                ("profile.ci.extends".to_string(), None),
+                ("profile.ci.reports.junti".to_string(), Some("junit")),
+            ]
+        );
+        let err = load(contents).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("found 5 unknown keys in the config\n    "),
+            "{message}"
+        );
+        assert_eq!(message.lines().count(), 6);
+    }
+
+    #[test]
+    fn suggestions_are_only_for_likely_typos() {
+        let cases = [
+            ("wokr-dir", Some("work-dir")),
+            ("timout", Some("timeout")),
+            ("job", Some("jobs")),
+            ("retry", None),
+            ("colour", None),
+            ("x", None),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(
+                suggest(key, &|known| KEYS.contains(&known)),
+                expected,
+                "{key}"
+            );
+        }
+        // Section keys are only suggested in their own section.
+        assert_eq!(unknown("errexti = true\n"), [("errexti".to_string(), None)]);
+    }
+
+    #[test]
+    fn unknown_keys_can_be_allowed() {
+        let config = load("allow-unknown-keys = true\nfrom-the-future = 1\n").unwrap();
+        assert!(config.allow_unknown_keys);
+
+        let scratch = scratch_dir();
+        let path = write(&scratch, crate::CONFIG_FILE, "from-the-future = 1\n");
+        assert!(matches!(Config::load(&path), Err(Error::UnknownKeys(keys)) if keys.len() == 1));
+        let options = LoadOptions {
+            allow_unknown_keys: true,
+            ..LoadOptions::default()
+        };
+        Config::load_with(&path, &options).unwrap();
+    }
+}
@@ -0,0 +1,418 @@
+//! Checks on a config that go beyond whether it parses.
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use crate::platform_shell::KNOWN_OSES;
+use crate::{is_valid_env_key, Config};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Worth pointing out, but bishin can still run.
+    Warning,
+    /// bishin can't run with this config.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found by [`Config::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    /// The config key the issue is about, e.g. `test-dir`.
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    fn error(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            field,
+            message: message.into(),
+        }
+    }
+
+    fn warning(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.field, self.message)
+    }
+}
+
+impl Config {
+    /// Check that the settings make sense together and on this filesystem.
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+        // The directories are compared by their components, so they need
+        // all of them.
+        let base_dir = std::path::absolute(self.dir()).unwrap_or_else(|_| self.dir().to_path_buf());
+        let work_dir = normalize(&base_dir.join(&self.work_dir));
+        let (field, test_dirs) = match self.test_dirs.as_slice() {
+            [] => ("test-dir", std::slice::from_ref(&self.test_dir)),
+            test_dirs => ("test-dirs", test_dirs),
+        };
+
+        for configured in test_dirs {
+            let test_dir = normalize(&base_dir.join(configured));
+            if test_dir.parent().is_none() {
+                issues.push(ConfigIssue::error(
+                    field,
+                    "the filesystem root can't be a test directory",
+                ));
+            } else if !test_dir.exists() {
+                issues.push(ConfigIssue::error(
+                    field,
+                    format!("{} does not exist", configured.display()),
+                ));
+            } else if !test_dir.is_dir() {
+                issues.push(ConfigIssue::error(
+                    field,
+                    format!("{} is not a directory", configured.display()),
+                ));
+            }
+
+            if test_dir == work_dir {
+                issues.push(ConfigIssue::error(
+                    "work-dir",
+                    format!("can't be the same directory as {field}"),
+                ));
+            } else if test_dir.starts_with(&work_dir) {
+                issues.push(ConfigIssue::error(
+                    "work-dir",
+                    format!(
+                        "{} contains the test directory {}, so generated scripts would mix with tests",
+                        self.work_dir.display(),
+                        configured.display()
+                    ),
+                ));
+            } else if work_dir.starts_with(&test_dir) {
+                issues.push(ConfigIssue::warning(
+                    "work-dir",
+                    format!(
+                        "{} is inside the test directory {}",
+                        self.work_dir.display(),
+                        configured.display()
+                    ),
+                ));
+            }
+        }
+
+        let hooks = [
+            ("setup-script", &self.setup_script),
+            ("teardown-script", &self.teardown_script),
+        ];
+        for (field, script) in hooks {
+            let Some(script) = script else { continue };
+            if let Some(message) = script_problem(&base_dir.join(script)) {
+                issues.push(ConfigIssue::error(
+                    field,
+                    format!("{} {message}", script.display()),
+                ));
+            }
+        }
+
+        for key in self.env.keys().filter(|key| !is_valid_env_key(key)) {
+            issues.push(ConfigIssue::error(
+                "env",
+                format!("{key:?} is not a valid environment variable name"),
+            ));
+        }
+
+        let policy = &self.env_policy;
+        for key in policy.allow.iter().filter(|key| !is_valid_env_key(key)) {
+            issues.push(ConfigIssue::error(
+                "env-policy",
+                format!("{key:?} in allow is not a valid environment variable name"),
+            ));
+        }
+        if policy.clean && policy.allow.is_empty() {
+            issues.push(ConfigIssue::warning(
+                "env-policy",
+                "clean is set with nothing in allow, so tests won't even have PATH",
+            ));
+        }
+        if !policy.clean && !policy.allow.is_empty() {
+            issues.push(ConfigIssue::warning(
+                "env-policy",
+                "allow only has an effect when clean is set",
+            ));
+        }
+
+        let unknown_oses = self
+            .platform_shell
+            .keys()
+            .filter(|os| !KNOWN_OSES.contains(&os.as_str()));
+        for os in unknown_oses {
+            issues.push(ConfigIssue::warning(
+                "platform-shell",
+                format!("{os:?} isn't an operating system that bishin knows, so it's never used"),
+            ));
+        }
+
+        if self.timeout == Some(Duration::ZERO) {
+            issues.push(ConfigIssue::error(
+                "timeout",
+                "must be longer than zero, use \"none\" to turn it off",
+            ));
+        }
+
+        if self.flaky_promote_after == Some(0) {
+            issues.push(ConfigIssue::error(
+                "flaky-promote-after",
+                "must be at least 1, leave it out to keep @flaky tests quarantined",
+            ));
+        }
+
+        issues
+    }
+}
+
+/// What's wrong with `script` as something to run, if anything.
+fn script_problem(script: &Path) -> Option<&'static str> {
+    let metadata = match std::fs::metadata(script) {
+        Ok(metadata) => metadata,
+        Err(_) => return Some("does not exist"),
+    };
+    if !metadata.is_file() {
+        return Some("is not a file");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Some("is not executable");
+        }
+    }
+    None
+}
+
+/// Resolve `.` and `..` components without touching the filesystem, since
+/// the work directory may not exist yet.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, write, ScratchDir};
+    use crate::EnvPolicy;
+
+    /// A directory with `tests/` in it, a script that can run and one that
+    /// can't.
+    fn project() -> ScratchDir {
+        let dir = scratch_dir();
+        std::fs::create_dir(dir.join("tests")).unwrap();
+        write(&dir, "not-a-dir", "");
+        let script = write(&dir, "setup.sh", "#!/bin/sh\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        write(&dir, "plain.sh", "#!/bin/sh\n");
+        dir
+    }
+
+    fn config(dir: &Path) -> Config {
+        Config {
+            dir: dir.to_path_buf(),
+            test_dir: "tests".into(),
+            work_dir: ".bishin".into(),
+            ..Config::default()
+        }
+    }
+
+    /// The severity and field of each issue.
+    fn issues(config: &Config) -> Vec<(Severity, &'static str)> {
+        config
+            .validate()
+            .into_iter()
+            .map(|issue| (issue.severity, issue.field))
+            .collect()
+    }
+
+    /// What a case is called, how it changes the config and the issues
+    /// that it should then have.
+    type Case = (
+        &'static str,
+        fn(&mut Config),
+        &'static [(Severity, &'static str)],
+    );
+
+    #[test]
+    fn each_check_reports_its_field() {
+        use Severity::{Error, Warning};
+
+        let cases: &[Case] = &[
+            ("defaults", |_| {}, &[]),
+            (
+                "missing test dir",
+                |c| c.test_dir = "nope".into(),
+                &[(Error, "test-dir")],
+            ),
+            (
+                "test dir is a file",
+                |c| c.test_dir = "not-a-dir".into(),
+                &[(Error, "test-dir")],
+            ),
+            (
+                "root test dir",
+                |c| c.test_dir = "/".into(),
+                &[(Error, "test-dir"), (Warning, "work-dir")],
+            ),
+            (
+                "missing one of the test dirs",
+                |c| c.test_dirs = vec!["tests".into(), "nope".into()],
+                &[(Error, "test-dirs")],
+            ),
+            (
+                "work dir is the test dir",
+                |c| c.work_dir = "tests/.".into(),
+                &[(Error, "work-dir")],
+            ),
+            (
+                "work dir holds the test dir",
+                |c| c.work_dir = ".".into(),
+                &[(Error, "work-dir")],
+            ),
+            (
+                "work dir inside the test dir",
+                |c| c.work_dir = "tests/out".into(),
+                &[(Warning, "work-dir")],
+            ),
+            (
+                "setup script runs",
+                |c| c.setup_script = Some("setup.sh".into()),
+                &[],
+            ),
+            (
+                "missing setup script",
+                |c| c.setup_script = Some("nope.sh".into()),
+                &[(Error, "setup-script")],
+            ),
+            (
+                "teardown script is a dir",
+                |c| c.teardown_script = Some("tests".into()),
+                &[(Error, "teardown-script")],
+            ),
+            #[cfg(unix)]
+            (
+                "setup script can't run",
+                |c| c.setup_script = Some("plain.sh".into()),
+                &[(Error, "setup-script")],
+            ),
+            (
+                "env key",
+                |c| {
+                    c.env.insert("A=B".into(), String::new());
+                },
+                &[(Error, "env")],
+            ),
+            (
+                "allowed env key",
+                |c| {
+                    c.env_policy = EnvPolicy {
+                        clean: true,
+                        allow: vec!["".into()],
+                    }
+                },
+                &[(Error, "env-policy")],
+            ),
+            (
+                "clean with nothing allowed",
+                |c| {
+                    c.env_policy = EnvPolicy {
+                        clean: true,
+                        allow: Vec::new(),
+                    }
+                },
+                &[(Warning, "env-policy")],
+            ),
+            (
+                "allowed without clean",
+                |c| {
+                    c.env_policy = EnvPolicy {
+                        clean: false,
+                        allow: vec!["PATH".into()],
+                    }
+                },
+                &[(Warning, "env-policy")],
+            ),
+            (
+                "unknown os",
+                |c| {
+                    c.platform_shell.insert("plan9".into(), crate::Shell::Bash);
+                },
+                &[(Warning, "platform-shell")],
+            ),
+            (
+                "zero timeout",
+                |c| c.timeout = Some(Duration::ZERO),
+                &[(Error, "timeout")],
+            ),
+            (
+                "nonzero timeout",
+                |c| c.timeout = Some(Duration::from_secs(1)),
+                &[],
+            ),
+            (
+                "promoted after no runs",
+                |c| c.flaky_promote_after = Some(0),
+                &[(Error, "flaky-promote-after")],
+            ),
+        ];
+        let dir = project();
+        for (name, change, expected) in cases {
+            let mut config = config(&dir);
+            change(&mut config);
+            assert_eq!(issues(&config), *expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn issues_name_the_value() {
+        let dir = project();
+        let config = Config {
+            test_dir: "nope".into(),
+            ..config(&dir)
+        };
+        let messages: Vec<_> = config.validate().iter().map(ToString::to_string).collect();
+        assert_eq!(messages, ["error: test-dir: nope does not exist"]);
+    }
+
+    #[test]
+    fn errors_sort_after_warnings() {
+        assert!(Severity::Error > Severity::Warning);
+    }
+}
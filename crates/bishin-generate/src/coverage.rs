@@ -0,0 +1,389 @@
+//! Which lines of the tests' bodies ran, from the xtrace output of scripts
+//! generated with [`GenerateOptions::coverage`](crate::GenerateOptions::coverage).
+//!
+//! Each such script has a header saying which of its lines are the test's
+//! own body and where in the test file they came from, and traces to a file
+//! of its own with a `PS4` holding the line that each command is on. The
+//! lines of the preamble and of `@include`s aren't the test's own, so they're
+//! left out of the coverage altogether.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use bishin_parser::Test;
+
+/// The `PS4` given to covered scripts, with the line and script that each
+/// traced command is from. Bash repeats the leading `+` once per level of
+/// nesting.
+pub const COVERAGE_PS4: &str = "+bishin:line:${LINENO}:${BASH_SOURCE[0]##*/}: ";
+
+const TRACE_MARKER: &str = "bishin:line:";
+
+const HEADER: &str = "# bishin:provenance ";
+
+/// Words that can make up a line that isn't traced itself, such as the end
+/// of a loop.
+const NOT_COMMANDS: &[&str] = &[
+    "then", "do", "done", "fi", "else", "esac", "{", "}", "(", ")",
+];
+
+/// Where the body of a covered script came from, as its header says.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// The line of the script that the test's own body starts on.
+    pub script_line: usize,
+    /// How many lines the test's own body has.
+    pub lines: usize,
+    /// The line of `source` that the body starts on.
+    pub source_line: usize,
+    /// The test file, or the file the test takes its body from.
+    pub source: PathBuf,
+}
+
+impl Provenance {
+    /// Read the header of a covered script, if it has one.
+    pub fn of(script: &str) -> Option<Self> {
+        let header = script.lines().nth(1)?.strip_prefix(HEADER)?;
+        let mut fields = header.splitn(4, ' ');
+        let mut number = || fields.next()?.parse::<usize>().ok();
+        let (script_line, lines, source_line) = (number()?, number()?, number()?);
+        Some(Self {
+            script_line,
+            lines,
+            source_line,
+            source: PathBuf::from(fields.next()?),
+        })
+    }
+
+    fn header(&self) -> String {
+        format!(
+            "{HEADER}{} {} {} {}\n",
+            self.script_line,
+            self.lines,
+            self.source_line,
+            self.source.display()
+        )
+    }
+}
+
+/// The file that the script at `script` traces to, in `dir`.
+pub fn trace_path(dir: &Path, script: &Path) -> PathBuf {
+    let stem = script.file_stem().unwrap_or_default().to_string_lossy();
+    dir.join(format!("{stem}.trace"))
+}
+
+/// `script`, which is the script of `test` with its body last, with a header
+/// saying that the body is from `source` and tracing what the body runs to
+/// `trace`.
+pub(crate) fn instrument(script: &str, test: &Test, source: &Path, trace: &Path) -> String {
+    let (preamble, body) = script.split_at(script.len() - test.body.len());
+    let (shebang, preamble) = preamble.split_once('\n').unwrap_or((preamble, ""));
+    let setup = format!(
+        "exec {{BASH_XTRACEFD}}>>{}\nPS4='{COVERAGE_PS4}'\nset -x\n",
+        single_quoted(&trace.to_string_lossy())
+    );
+    // The shebang and header come before the rest of the preamble.
+    let before_body = 2 + preamble.matches('\n').count() + setup.matches('\n').count();
+    let provenance = Provenance {
+        script_line: before_body + test.included_lines + 1,
+        lines: body.lines().count().saturating_sub(test.included_lines),
+        source_line: test.body_line,
+        source: source.to_path_buf(),
+    };
+    format!("{shebang}\n{}{preamble}{setup}{body}", provenance.header())
+}
+
+fn single_quoted(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// How many times each line of the test files that could run did, by file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub files: BTreeMap<PathBuf, BTreeMap<usize, u64>>,
+}
+
+impl Coverage {
+    /// Add the lines of a test's body that could run, from the covered
+    /// `script` called `name`, and how often they did, from its `trace`.
+    ///
+    /// A line only counts if a command starts on it. One that a command
+    /// carries on to, such as with a trailing `\` or an open quote, is part
+    /// of the line that it started on.
+    pub fn add(&mut self, script: &str, name: &str, trace: &str) {
+        let Some(provenance) = Provenance::of(script) else {
+            return;
+        };
+        let body = script
+            .lines()
+            .skip(provenance.script_line - 1)
+            .take(provenance.lines);
+        let mut hits = vec![0; provenance.lines];
+        for line in traced_lines(trace, name) {
+            if let Some(hit) = line
+                .checked_sub(provenance.script_line)
+                .and_then(|index| hits.get_mut(index))
+            {
+                *hit += 1;
+            }
+        }
+        let file = self.files.entry(provenance.source).or_default();
+        for statement in statements(body) {
+            let count = hits[statement.clone()].iter().sum::<u64>();
+            *file
+                .entry(provenance.source_line + statement.start())
+                .or_default() += count;
+        }
+    }
+
+    /// How many lines that could run did, and how many could, in `file`.
+    pub fn counts(file: &BTreeMap<usize, u64>) -> (usize, usize) {
+        let covered = file.values().filter(|hits| **hits > 0).count();
+        (covered, file.len())
+    }
+
+    /// The coverage as an LCOV tracefile, for tools that read them.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (path, lines) in &self.files {
+            let (covered, total) = Self::counts(lines);
+            let _ = writeln!(lcov, "TN:\nSF:{}", path.display());
+            for (line, hits) in lines {
+                let _ = writeln!(lcov, "DA:{line},{hits}");
+            }
+            let _ = writeln!(lcov, "LF:{total}\nLH:{covered}\nend_of_record");
+        }
+        lcov
+    }
+}
+
+/// The lines of the script called `name` that the commands in `trace`
+/// are on, leaving out those of other scripts that it sourced.
+fn traced_lines<'a>(trace: &'a str, name: &'a str) -> impl Iterator<Item = usize> + 'a {
+    trace.lines().filter_map(move |line| {
+        let rest = line.trim_start_matches('+');
+        if rest.len() == line.len() {
+            return None;
+        }
+        let (number, rest) = rest.strip_prefix(TRACE_MARKER)?.split_once(':')?;
+        let (source, _) = rest.split_once(": ")?;
+        (source == name).then(|| number.parse().ok())?
+    })
+}
+
+/// The lines of `body` that each command spans, by index.
+fn statements<'a>(body: impl Iterator<Item = &'a str>) -> Vec<RangeInclusive<usize>> {
+    let mut statements = Vec::new();
+    let mut scanner = Scanner::default();
+    let mut open = None;
+    for (index, line) in body.enumerate() {
+        if let Some((delimiter, strip_tabs)) = scanner.heredocs.front() {
+            let end = if *strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            if end == delimiter {
+                scanner.heredocs.pop_front();
+            }
+            continue;
+        }
+        let (code, continues) = scanner.scan(line);
+        match open {
+            Some(start) if !continues => {
+                statements.push(start..=index);
+                open = None;
+            }
+            Some(_) => {}
+            None if !is_command(&code) => {}
+            None if continues => open = Some(index),
+            None => statements.push(index..=index),
+        }
+    }
+    if let Some(start) = open {
+        statements.push(start..=start);
+    }
+    statements
+}
+
+/// Whether a line, without its comment, runs something that bash traces,
+/// rather than only opening or closing a block or declaring a function.
+fn is_command(code: &str) -> bool {
+    let mut words = code
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|word| !word.is_empty())
+        .peekable();
+    match words.peek() {
+        Some(&"function") => {
+            words.next();
+            words.next();
+        }
+        // A function's name, or the pattern of a case.
+        Some(word) if word.ends_with("()") || (word.ends_with(')') && !word.contains('(')) => {
+            words.next();
+        }
+        _ => {}
+    }
+    words.any(|word| !NOT_COMMANDS.contains(&word))
+}
+
+/// What carries on from one line of a body to the next.
+#[derive(Default)]
+struct Scanner {
+    /// The quote that's open, if any.
+    quote: Option<char>,
+    /// The delimiters of the heredocs whose contents are next, and whether
+    /// their lines have leading tabs stripped.
+    heredocs: VecDeque<(String, bool)>,
+}
+
+impl Scanner {
+    /// The code of `line` without its comment, and whether the command on
+    /// it carries on to the next line.
+    fn scan(&mut self, line: &str) -> (String, bool) {
+        let mut code = String::new();
+        let mut chars = line.chars().peekable();
+        let mut escaped_newline = false;
+        while let Some(c) = chars.next() {
+            match (self.quote, c) {
+                (Some('\''), '\'') | (Some('"'), '"') => self.quote = None,
+                (Some('"'), '\\') | (None, '\\') => {
+                    code.push(c);
+                    match chars.next() {
+                        Some(next) => code.push(next),
+                        None => escaped_newline = true,
+                    }
+                    continue;
+                }
+                (None, '\'' | '"') => self.quote = Some(c),
+                (None, '#') if code.is_empty() || code.ends_with([' ', '\t', ';']) => break,
+                (None, '<') if chars.peek() == Some(&'<') => {
+                    chars.next();
+                    code.push_str("<<");
+                    if chars.next_if_eq(&'<').is_some() {
+                        code.push('<');
+                        continue;
+                    }
+                    let strip_tabs = chars.next_if_eq(&'-').is_some();
+                    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+                    let delimiter: String = std::iter::from_fn(|| {
+                        chars.next_if(|c| c.is_alphanumeric() || "_-'\"".contains(*c))
+                    })
+                    .filter(|c| *c != '\'' && *c != '"')
+                    .collect();
+                    code.push_str(&delimiter);
+                    if !delimiter.is_empty() {
+                        self.heredocs.push_back((delimiter, strip_tabs));
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            code.push(c);
+        }
+        let trimmed = code.trim_end();
+        let continues = self.quote.is_some()
+            || escaped_newline
+            || trimmed.ends_with('|')
+            || trimmed.ends_with("&&");
+        (code, continues)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lines of `body` that commands start on, from one.
+    fn command_lines(body: &str) -> Vec<usize> {
+        statements(body.lines())
+            .into_iter()
+            .map(|statement| statement.start() + 1)
+            .collect()
+    }
+
+    #[test]
+    fn only_lines_that_start_commands_count() {
+        let body = "if true; then\n    echo yes\nelse\n    echo no\nfi\n\n# a comment\n";
+        assert_eq!(command_lines(body), [1, 2, 4]);
+        let body = "f() {\n    echo in f\n}\ncase $1 in\n    a) echo a ;;\nesac\n";
+        assert_eq!(command_lines(body), [2, 4, 5]);
+    }
+
+    #[test]
+    fn commands_carried_over_lines_count_once() {
+        let body = "echo a \\\n    b\necho 'open\nquote'\ntrue &&\n    true\nls |\n    wc -l\n";
+        assert_eq!(command_lines(body), [1, 3, 5, 7]);
+    }
+
+    #[test]
+    fn heredoc_contents_are_not_commands() {
+        let body = "cat <<EOF\necho not run\nEOF\ncat <<-'END'\n\techo nor this\n\tEND\ntrue\n";
+        assert_eq!(command_lines(body), [1, 4, 7]);
+        assert_eq!(command_lines("cat <<< 'here string'\ntrue\n"), [1, 2]);
+    }
+
+    #[test]
+    fn provenance_round_trips_through_the_header() {
+        let provenance = Provenance {
+            script_line: 12,
+            lines: 3,
+            source_line: 7,
+            source: PathBuf::from("tests/with space.b"),
+        };
+        let script = format!("#!/bin/bash\n{}true\n", provenance.header());
+        assert_eq!(Provenance::of(&script), Some(provenance));
+        assert_eq!(Provenance::of("#!/bin/bash\ntrue\n"), None);
+    }
+
+    /// A covered script called `a.sh` whose body of `body` starts on line 4
+    /// and comes from line 10 of `a.b`.
+    fn script(body: &str) -> String {
+        let provenance = Provenance {
+            script_line: 4,
+            lines: body.lines().count(),
+            source_line: 10,
+            source: PathBuf::from("a.b"),
+        };
+        format!("#!/bin/bash\n{}set -x\n{body}", provenance.header())
+    }
+
+    #[test]
+    fn traced_lines_are_counted_against_the_source() {
+        let script = script("for i in 1 2; do\n    echo $i\ndone\nfalse || true\n");
+        let trace = [
+            "+bishin:line:4:a.sh: for i in 1 2",
+            "+bishin:line:5:a.sh: echo 1",
+            "+bishin:line:4:a.sh: for i in 1 2",
+            "+bishin:line:5:a.sh: echo 2",
+            // A script it sources, and output that isn't a trace.
+            "++bishin:line:5:helper.sh: echo helper",
+            "bishin:line:7:a.sh: not a trace",
+            "+bishin:line:2:a.sh: set -x",
+        ]
+        .join("\n");
+        let mut coverage = Coverage::default();
+        coverage.add(&script, "a.sh", &trace);
+        let lines = &coverage.files[Path::new("a.b")];
+        assert_eq!(
+            lines.iter().map(|(l, h)| (*l, *h)).collect::<Vec<_>>(),
+            [(10, 2), (11, 2), (13, 0)]
+        );
+        assert_eq!(Coverage::counts(lines), (2, 3));
+    }
+
+    #[test]
+    fn coverage_adds_up_across_tests_and_renders_as_lcov() {
+        let script = script("true\nfalse\n");
+        let mut coverage = Coverage::default();
+        coverage.add(&script, "a.sh", "+bishin:line:4:a.sh: true");
+        coverage.add(&script, "a.sh", "+bishin:line:4:a.sh: true");
+        coverage.add("#!/bin/bash\ntrue\n", "b.sh", "+bishin:line:2:b.sh: true");
+        assert_eq!(
+            coverage.to_lcov(),
+            "TN:\nSF:a.b\nDA:10,2\nDA:11,0\nLF:2\nLH:1\nend_of_record\n"
+        );
+    }
+}
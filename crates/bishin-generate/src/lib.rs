@@ -0,0 +1,1221 @@
+//! Generation of runnable scripts and jobs from collected test files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bishin_collect::{Module, ModuleGraph};
+use bishin_jobs::{
+    Bench, ExpectedExit, Fixture, Job, JobDir, LeakCheck, Resource, RetryOn, RetryPolicy, Skip,
+    Snapshot, SnapshotUpdate, XFail,
+};
+use bishin_parser::interpolate::interpolate;
+use bishin_parser::{parse_test_file, Flaky, ResolveError, Retries, Shell, Test};
+
+pub mod coverage;
+pub mod script;
+#[cfg(test)]
+mod testing;
+pub mod trace;
+
+pub use script::ScriptOptions;
+
+/// The variable holding the name of the test being run, so that tests from
+/// one `@test_for` can tell which they are.
+pub const TEST_NAME_ENV: &str = "BISHIN_TEST_NAME";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read test file {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to parse test file {}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: bishin_parser::Error,
+    },
+    #[error("failed to read the body of {test} from {}", path.display())]
+    BodyFile {
+        path: PathBuf,
+        test: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to read {} for {test} to include", path.display())]
+    Include {
+        path: PathBuf,
+        test: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to resolve the decorators of {test} in {}", path.display())]
+    Resolve {
+        path: PathBuf,
+        test: String,
+        #[source]
+        source: ResolveError,
+    },
+    #[error("failed to find the absolute path of {}", path.display())]
+    Absolute {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("tests {first} and {second} would both be written to {}", path.display())]
+    SameScript {
+        path: PathBuf,
+        first: String,
+        second: String,
+    },
+    #[error("failed to write script {}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Settings that apply to every generated job.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    /// Environment variables for every job, beneath any set by the test.
+    pub env: HashMap<String, String>,
+    /// The only variables jobs inherit from bishin's environment, or `None`
+    /// for all of them, see [`Job::clean_env`].
+    pub clean_env: Option<Vec<String>>,
+    /// The timeout for tests that don't set one with `@timeout`.
+    pub timeout: Option<Duration>,
+    /// Trace every command the scripts run, see [`trace`], if the shell can.
+    pub trace: bool,
+    /// Record which lines of its body each script runs to a file in this
+    /// directory, see [`coverage`]. Only bash can, and it takes the place of
+    /// `trace`.
+    pub coverage: Option<PathBuf>,
+    /// How many times to retry failed tests that don't have `@retries`.
+    pub retries: Option<u32>,
+    /// The shell that scripts are written for and run with.
+    pub shell: Shell,
+    /// The shell options that scripts turn on, unless the test is
+    /// `@no_strict`.
+    pub script: ScriptOptions,
+    /// Where the snapshots of `@snapshot` tests are kept, or `None` to not
+    /// check them.
+    pub snapshots: Option<SnapshotOptions>,
+    /// Measure the CPU time and memory of each test, see
+    /// [`Job::resource_stats`].
+    pub resource_stats: bool,
+    /// Fail tests that leave processes running, rather than only warning,
+    /// see [`Job::leak_check`].
+    pub strict_processes: bool,
+}
+
+/// Where snapshots are kept and when they're updated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotOptions {
+    /// The directory of snapshots, where `net::dns::resolves` has its
+    /// snapshot at `net/dns/resolves.snap`.
+    pub dir: PathBuf,
+    pub update: SnapshotUpdate,
+}
+
+/// A test whose script has been written to disk, and the job that runs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestJob {
+    /// The fully qualified name of the test, e.g. `net::dns::resolves`.
+    pub id: String,
+    /// The test file that the test is in.
+    pub file: PathBuf,
+    /// The generated script.
+    pub script: PathBuf,
+    pub job: Job,
+    /// Whether the test is quarantined by `@flaky`, which the job doesn't
+    /// need to know but the run does.
+    pub flaky: Option<Flaky>,
+    /// The patterns of the test's `@artifact`s, for the files in the job's
+    /// `tmp_dir` that the run keeps.
+    pub artifacts: Vec<String>,
+    /// The test's `@tags`, for anything after generation that sorts tests
+    /// by them.
+    pub tags: Vec<String>,
+}
+
+/// Parse every test file in `graph` and write a script per test to `work_dir`.
+pub fn generate_test_jobs(
+    graph: &ModuleGraph,
+    work_dir: &Path,
+    options: &GenerateOptions,
+) -> Result<Vec<TestJob>, Error> {
+    let mut jobs = Vec::new();
+    for module in graph.iter_leaf_modules() {
+        jobs.extend(test_jobs_for_module(module, work_dir, options)?);
+    }
+    check_distinct_scripts(&jobs)?;
+    Ok(jobs)
+}
+
+/// An error if two of `jobs` have the same script, where the later one was
+/// written over the earlier, such as tests in test directories with the same
+/// name. Tests with the same name in one file don't get this far, since the
+/// file doesn't parse.
+pub fn check_distinct_scripts(jobs: &[TestJob]) -> Result<(), Error> {
+    let mut seen = HashMap::new();
+    for test_job in jobs {
+        if let Some(first) = seen.insert(&test_job.script, &test_job.id) {
+            return Err(Error::SameScript {
+                path: test_job.script.clone(),
+                first: first.clone(),
+                second: test_job.id.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The name of a test's script and scratch directory in the work directory,
+/// e.g. `net.dns.resolves` for `net::dns::resolves`.
+///
+/// The components of the module's path are joined by `.`, with every
+/// character in them but letters, digits, `_` and `-` escaped as `%` and its
+/// bytes in hex, so that tests with different paths can't have the same
+/// stem.
+pub fn script_stem(module: &Module, test: &Test) -> String {
+    let mut stem = String::new();
+    for component in module.path() {
+        for c in component.chars() {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                stem.push(c);
+            } else {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    stem.push_str(&format!("%{byte:02x}"));
+                }
+            }
+        }
+        stem.push('.');
+    }
+    stem.push_str(&test.name);
+    stem
+}
+
+/// Parse a single test file and write a script per test to `work_dir`.
+pub fn test_jobs_for_module(
+    module: &Module,
+    work_dir: &Path,
+    options: &GenerateOptions,
+) -> Result<Vec<TestJob>, Error> {
+    let tests = parse_module(module)?;
+    generate_module(module, &tests, work_dir, options)
+}
+
+/// The fully qualified name of a test in `module`, e.g. `net::dns::resolves`.
+pub fn test_id(module: &Module, test: &Test) -> String {
+    format!("{}::{}", module.name(), test.name)
+}
+
+/// The IDs of the jobs that [`generate_module`] makes for `test`: its
+/// [`test_id`], or one for each shell where it lists more than one in
+/// `@shells`, e.g. `net::dns::resolves[fish]`.
+pub fn test_ids(module: &Module, test: &Test) -> Vec<String> {
+    let id = test_id(module, test);
+    match &test.shells {
+        Some(shells) if shells.len() > 1 => shells
+            .iter()
+            .map(|shell| format!("{id}[{shell}]"))
+            .collect(),
+        _ => vec![id],
+    }
+}
+
+/// Read and parse the tests in a single test file, along with any bodies
+/// they keep in other files.
+pub fn parse_module(module: &Module) -> Result<Vec<Test>, Error> {
+    let path = module.file_path();
+    let contents = std::fs::read_to_string(path).map_err(|source| Error::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let file = parse_test_file(&contents).map_err(|source| Error::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut tests = file.tests;
+    let setup = file.setup.as_deref().map(dedent);
+    let teardown = file.teardown.as_deref().map(dedent);
+    let base = path.parent().unwrap_or(Path::new(""));
+    for test in &mut tests {
+        if let Some(body_file) = &test.body_file {
+            let body_path = base.join(body_file);
+            let body = std::fs::read_to_string(&body_path).map_err(|source| Error::BodyFile {
+                path: body_path.clone(),
+                test: test_id(module, test),
+                source,
+            })?;
+            test.body_line = if body.starts_with("#!") { 2 } else { 1 };
+            test.body = without_shebang(normalize_line_endings(body));
+            // So that nothing put after the body joins its last line.
+            if !test.body.is_empty() && !test.body.ends_with('\n') {
+                test.body.push('\n');
+            }
+        } else {
+            test.body = dedent(&test.body);
+        }
+        let mut included = String::new();
+        for include in &test.includes {
+            let include_path = base.join(include);
+            let code = std::fs::read_to_string(&include_path).map_err(|source| Error::Include {
+                path: include_path.clone(),
+                test: test_id(module, test),
+                source,
+            })?;
+            included.push_str(&without_shebang(normalize_line_endings(code)));
+            if !included.ends_with('\n') {
+                included.push('\n');
+            }
+        }
+        // The setup comes after the includes, so that it can use what they
+        // define.
+        if let Some(setup) = &setup {
+            included.push_str(setup);
+        }
+        test.teardown.clone_from(&teardown);
+        test.included_lines = included.matches('\n').count();
+        test.body.insert_str(0, &included);
+    }
+    Ok(tests)
+}
+
+/// `code` with its `\r\n` line endings turned into `\n`, as the test file
+/// parser does for bodies, so that a script is the same whichever its code
+/// came from.
+fn normalize_line_endings(code: String) -> String {
+    match code.contains("\r\n") {
+        true => code.replace("\r\n", "\n"),
+        false => code,
+    }
+}
+
+/// `body` without the indentation that all of its lines share, ignoring
+/// blank ones, so that a test's script doesn't depend on how deeply the test
+/// was indented in its file, and a heredoc's terminator can end up at the
+/// start of its line.
+///
+/// Only indentation that's the same, character for character, is removed,
+/// so a body mixing tabs and spaces keeps whatever they don't share.
+pub fn dedent(body: &str) -> String {
+    let is_blank = |line: &str| line.trim().is_empty();
+    let indent =
+        |line: &'_ str| -> usize { line.len() - line.trim_start_matches([' ', '\t']).len() };
+    let mut lines = body.lines().filter(|line| !is_blank(line));
+    let Some(first) = lines.next() else {
+        return body.to_string();
+    };
+    let mut common = &first[..indent(first)];
+    for line in lines {
+        let shared = common
+            .char_indices()
+            .zip(line.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(common.len().min(indent(line)), |((i, _), _)| i);
+        common = &common[..shared];
+    }
+    if common.is_empty() {
+        return body.to_string();
+    }
+    let mut dedented = String::with_capacity(body.len());
+    for line in body.split_inclusive('\n') {
+        match line.strip_prefix(common) {
+            Some(rest) => dedented.push_str(rest),
+            // A blank line with less indentation than the rest.
+            None => dedented.push_str(line.trim_start_matches([' ', '\t'])),
+        }
+    }
+    dedented
+}
+
+/// `script` without its `#!` line, since the generated script has its own.
+fn without_shebang(script: String) -> String {
+    match script.strip_prefix("#!") {
+        Some(rest) => rest
+            .split_once('\n')
+            .map_or("", |(_, rest)| rest)
+            .to_string(),
+        None => script,
+    }
+}
+
+/// Write a script to `work_dir` for each of the `tests` parsed from `module`,
+/// or one for each of the shells a test lists in `@shells`.
+///
+/// The scripts are named by their [`script_stem`], and those of a test with
+/// `@shells` have the shell after an `@`, e.g. `net.dns.resolves@fish.sh`.
+/// Where it lists more than one shell, so do the tests' names, e.g.
+/// `net::dns::resolves[fish]`.
+pub fn generate_module(
+    module: &Module,
+    tests: &[Test],
+    work_dir: &Path,
+    options: &GenerateOptions,
+) -> Result<Vec<TestJob>, Error> {
+    let mut jobs = Vec::new();
+    for test in tests {
+        let id = test_id(module, test);
+        let stem = script_stem(module, test);
+        let Some(shells) = &test.shells else {
+            jobs.push(generate_test(
+                test,
+                id,
+                &stem,
+                module.file_path(),
+                work_dir,
+                options,
+            )?);
+            continue;
+        };
+        for (shell, id) in shells.iter().zip(test_ids(module, test)) {
+            let options = GenerateOptions {
+                shell: *shell,
+                ..options.clone()
+            };
+            jobs.push(generate_test(
+                test,
+                id,
+                &format!("{stem}@{shell}"),
+                module.file_path(),
+                work_dir,
+                &options,
+            )?);
+        }
+    }
+    Ok(jobs)
+}
+
+/// Write the script for a single test to `work_dir` as `<stem>.sh`, where
+/// `path` is the test file it came from and `id` its fully qualified name.
+pub fn generate_test(
+    test: &Test,
+    id: String,
+    stem: &str,
+    path: &Path,
+    work_dir: &Path,
+    options: &GenerateOptions,
+) -> Result<TestJob, Error> {
+    let resolve_error = |source| Error::Resolve {
+        path: path.to_path_buf(),
+        test: id.clone(),
+        source,
+    };
+    let envs = job_envs(test, options).map_err(resolve_error)?;
+    let timeout = match &test.timeout {
+        Some(timeout) => Some(
+            timeout
+                .resolve(|name| lookup(options, name))
+                .map_err(resolve_error)?,
+        ),
+        None => options.timeout,
+    };
+    let script = work_dir.join(format!("{stem}.sh"));
+    let mut contents = transform_body(test, options);
+    if let Some(dir) = &options.coverage {
+        if options.shell == Shell::Bash {
+            let source = match &test.body_file {
+                Some(body_file) => path.parent().unwrap_or(Path::new("")).join(body_file),
+                None => path.to_path_buf(),
+            };
+            let trace = absolute(&coverage::trace_path(dir, &script))?;
+            contents = coverage::instrument(&contents, test, &source, &trace);
+        }
+    }
+    std::fs::write(&script, contents).map_err(|source| Error::Write {
+        path: script.clone(),
+        source,
+    })?;
+    let tmp_dir = absolute(&work_dir.join("tmp").join(stem))?;
+    let dir = job_dir(test, path, &tmp_dir);
+    // A job with its own directory doesn't run where the script's path is
+    // relative to.
+    let script_arg = match &dir {
+        Some(_) => absolute(&script)?,
+        None => script.clone(),
+    };
+    let job = Job {
+        name: id.clone(),
+        args: vec![
+            options.shell.name().to_string(),
+            script_arg.to_string_lossy().into_owned(),
+        ],
+        envs,
+        clean_env: options.clean_env.clone(),
+        timeout,
+        dir,
+        tmp_dir: Some(tmp_dir),
+        retry: match &test.retries {
+            Some(retries) => Some(retry_policy(retries)),
+            // A test that's expected to fail would only be retried for it.
+            None if test.xfail.is_some() => None,
+            // A benchmark fails on its first failed run, which a retry would
+            // only hide.
+            None if test.bench.is_some() => None,
+            None => options
+                .retries
+                .filter(|count| *count > 0)
+                .map(|count| RetryPolicy {
+                    count,
+                    on: Vec::new(),
+                }),
+        },
+        snapshot: options
+            .snapshots
+            .as_ref()
+            .filter(|_| test.snapshot)
+            .map(|snapshots| Snapshot {
+                path: snapshots
+                    .dir
+                    .join(format!("{}.snap", id.replace("::", "/"))),
+                update: snapshots.update,
+            }),
+        xfail: test.xfail.as_ref().map(|xfail| XFail {
+            reason: xfail.reason.clone(),
+        }),
+        skip: test.skip.as_ref().map(|skip| Skip {
+            reason: skip.reason.clone(),
+        }),
+        expected_exit: test.expected_exit.map(|expected_exit| match expected_exit {
+            bishin_parser::ExpectedExit::Code(code) => ExpectedExit::Code(i32::from(code)),
+            bishin_parser::ExpectedExit::NonZero => ExpectedExit::NonZero,
+        }),
+        bench: test.bench.map(|bench| Bench {
+            iterations: bench.iterations,
+            warmup: bench.warmup,
+        }),
+        resource_stats: options.resource_stats,
+        stdin: test
+            .stdin_file
+            .as_ref()
+            .map(|stdin_file| path.parent().unwrap_or(Path::new("")).join(stdin_file)),
+        ssh: None,
+        resources: test
+            .resources
+            .iter()
+            .map(|resource| Resource {
+                name: resource.name.clone(),
+                max: resource.max as usize,
+            })
+            .collect(),
+        leak_check: Some(if options.strict_processes {
+            LeakCheck::Fail
+        } else {
+            LeakCheck::Warn
+        }),
+        tty: test.tty,
+        skip_if_missing: true,
+    };
+    Ok(TestJob {
+        id,
+        file: path.to_path_buf(),
+        script,
+        job,
+        flaky: test.flaky.clone(),
+        artifacts: test.artifacts.clone(),
+        tags: test.tags.clone(),
+    })
+}
+
+/// A job that checks the syntax of a test's script without running it, or
+/// `None` if it isn't run by a shell that bishin knows.
+pub fn syntax_check_job(test_job: &TestJob) -> Option<Job> {
+    let (interpreter, script) = test_job.job.args.split_first()?;
+    // Every shell bishin knows parses without running when given `-n`.
+    Shell::from_name(interpreter)?;
+    Some(Job {
+        name: test_job.id.clone(),
+        args: [interpreter.clone(), "-n".to_string()]
+            .into_iter()
+            .chain(script.iter().cloned())
+            .collect(),
+        envs: HashMap::new(),
+        clean_env: None,
+        timeout: Some(Duration::from_secs(10)),
+        dir: None,
+        tmp_dir: None,
+        retry: None,
+        snapshot: None,
+        xfail: None,
+        skip: None,
+        expected_exit: None,
+        bench: None,
+        resource_stats: false,
+        stdin: None,
+        ssh: None,
+        resources: Vec::new(),
+        leak_check: None,
+        tty: false,
+        skip_if_missing: false,
+    })
+}
+
+/// Turn the body of a test into a complete script.
+pub fn transform_body(test: &Test, options: &GenerateOptions) -> String {
+    let mut script = format!("#!/usr/bin/env {}\n", options.shell);
+    let mut script_options = options.script;
+    if test.no_strict {
+        script_options = script_options.without_strict();
+    }
+    // Tracing for the command report or coverage turns on xtrace itself.
+    let coverage = options.coverage.is_some() && options.shell == Shell::Bash;
+    script_options.xtrace &= !options.trace && !coverage;
+    script.push_str(&script_options.preamble(options.shell));
+    if options.trace && !coverage && options.shell.has_xtrace() {
+        // Bash won't take PS4 from the environment when running as root, so
+        // it has to be set by the script itself.
+        script.push_str(&format!("PS4='{}'\nset -x\n", trace::TRACE_PS4));
+    }
+    if let Some(teardown) = &test.teardown {
+        script.push_str(&script::exit_hook(options.shell, teardown));
+    }
+    script.push_str(&test.body);
+    if let Some(teardown) = test
+        .teardown
+        .as_ref()
+        .filter(|_| options.shell == Shell::Tcsh)
+    {
+        // tcsh has nothing to run on exit, so the teardown only runs if the
+        // body gets to the end.
+        script.push_str(teardown);
+    }
+    script
+}
+
+/// The config environment with the test's own `@env` values layered on top,
+/// and the test's name in [`TEST_NAME_ENV`].
+fn job_envs(
+    test: &Test,
+    options: &GenerateOptions,
+) -> Result<HashMap<String, String>, ResolveError> {
+    let mut envs = options.env.clone();
+    for (key, value) in &test.env {
+        let value = interpolate(value, |name| lookup(options, name))?;
+        envs.insert(key.clone(), value);
+    }
+    envs.insert(TEST_NAME_ENV.to_string(), test.name.clone());
+    Ok(envs)
+}
+
+/// The directory the test should run in, if it has fixtures to put there.
+///
+/// Fixture sources are relative to the test file at `path`, and the
+/// directory is the test's scratch directory at `dir`.
+fn job_dir(test: &Test, path: &Path, dir: &Path) -> Option<JobDir> {
+    if test.fixtures.is_empty() {
+        return None;
+    }
+    let base = path.parent().unwrap_or(Path::new(""));
+    let fixtures = test
+        .fixtures
+        .iter()
+        .map(|fixture| Fixture {
+            src: base.join(&fixture.src),
+            dest: PathBuf::from(&fixture.dest),
+        })
+        .collect();
+    Some(JobDir {
+        path: dir.to_path_buf(),
+        fixtures,
+    })
+}
+
+fn retry_policy(retries: &Retries) -> RetryPolicy {
+    RetryPolicy {
+        count: retries.count,
+        on: retries
+            .on
+            .iter()
+            .map(|on| match on {
+                bishin_parser::RetryOn::Timeout => RetryOn::Timeout,
+                bishin_parser::RetryOn::ExitCode(code) => RetryOn::ExitCode(*code),
+            })
+            .collect(),
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf, Error> {
+    std::path::absolute(path).map_err(|source| Error::Absolute {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Look up a variable referenced by a decorator, preferring the config
+/// environment over the process environment.
+fn lookup(options: &GenerateOptions, name: &str) -> Option<String> {
+    options
+        .env
+        .get(name)
+        .cloned()
+        .or_else(|| std::env::var(name).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bishin_testing::ScratchDir;
+
+    use crate::testing::suite;
+
+    /// The jobs of a suite of `files`, and the directory their scripts are
+    /// in, which goes when that's dropped.
+    fn generate(
+        files: &[(&str, &str)],
+        options: &GenerateOptions,
+    ) -> Result<(Vec<TestJob>, ScratchDir), Error> {
+        let (graph, work_dir, dir) = suite(files);
+        std::fs::create_dir_all(&work_dir).unwrap();
+        Ok((generate_test_jobs(&graph, &work_dir, options)?, dir))
+    }
+
+    #[test]
+    fn test_env_is_layered_over_config_env() {
+        let options = GenerateOptions {
+            env: HashMap::from([
+                ("SHARED".to_string(), "config".to_string()),
+                ("ONLY_CONFIG".to_string(), "config".to_string()),
+            ]),
+            ..GenerateOptions::default()
+        };
+        let (jobs, _dir) = generate(
+            &[(
+                "env.b",
+                "@env(SHARED=test, ONLY_TEST=test)\n@test a {\n    true\n}\n",
+            )],
+            &options,
+        )
+        .unwrap();
+        let envs = &jobs[0].job.envs;
+        assert_eq!(envs["SHARED"], "test");
+        assert_eq!(envs["ONLY_CONFIG"], "config");
+        assert_eq!(envs["ONLY_TEST"], "test");
+        assert_eq!(envs[TEST_NAME_ENV], "a");
+    }
+
+    #[test]
+    fn config_timeout_is_the_default_for_tests() {
+        let options = GenerateOptions {
+            timeout: Some(Duration::from_secs(60)),
+            ..GenerateOptions::default()
+        };
+        let (jobs, _dir) = generate(
+            &[(
+                "timeouts.b",
+                "@test default {\n    true\n}\n@timeout(5s)\n@test own {\n    true\n}\n",
+            )],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(jobs[0].job.timeout, Some(Duration::from_secs(60)));
+        assert_eq!(jobs[1].job.timeout, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn syntax_checks_find_broken_bodies_without_running_them() {
+        let (jobs, _dir) = generate(
+            &[(
+                "syntax.b",
+                "@test broken {\n    if true; then\n        touch ran\n}\n@test fine {\n    touch ran\n}\n",
+            )],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        let results: Vec<_> = jobs
+            .iter()
+            .map(|test_job| syntax_check_job(test_job).unwrap().run().unwrap())
+            .collect();
+        assert_eq!(results[0].name, "syntax::broken");
+        assert_eq!(results[0].status, bishin_jobs::Status::Failed);
+        assert!(
+            results[0].stderr.contains("syntax error"),
+            "{}",
+            results[0].stderr
+        );
+        assert_eq!(results[1].status, bishin_jobs::Status::Passed);
+        assert!(!Path::new("ran").exists());
+    }
+
+    #[test]
+    fn only_known_shells_are_syntax_checked() {
+        let (mut jobs, _dir) = generate(
+            &[("syntax.b", "@test a {\n    true\n}\n")],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(syntax_check_job(&jobs[0]).unwrap().args[1], "-n");
+        jobs[0].job.args[0] = "python3".to_string();
+        assert!(syntax_check_job(&jobs[0]).is_none());
+    }
+
+    /// The tests of the only module of a suite of `files`.
+    fn parse(files: &[(&str, &str)]) -> Result<Vec<Test>, Error> {
+        let (graph, _, _dir) = suite(files);
+        let mut modules = graph.iter_leaf_modules();
+        let module = modules.next().unwrap();
+        assert!(modules.next().is_none());
+        parse_module(module)
+    }
+
+    #[test]
+    fn bodies_can_be_read_from_scripts_next_to_the_test_file() {
+        let tests = parse(&[
+            (
+                "net/dns.b",
+                "@test own = \"scripts/own.sh\"\n@test inline {\n    true\n}\n",
+            ),
+            (
+                "net/scripts/own.sh",
+                "#!/bin/bash\r\necho from the script\r\n",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(tests[0].body, "echo from the script\n");
+        assert_eq!(tests[0].body_file.as_deref(), Some("scripts/own.sh"));
+        assert_eq!(tests[0].body_line, 2);
+        assert_eq!(tests[1].body, "true\n");
+        assert_eq!(tests[1].body_file, None);
+    }
+
+    #[test]
+    fn missing_body_files_name_the_test_and_file() {
+        let err = parse(&[("net/dns.b", "@test own = \"scripts/gone.sh\"\n")]).unwrap_err();
+        assert!(
+            matches!(&err, Error::BodyFile { path, test, .. } if test == "net::dns::own" && path.ends_with("net/scripts/gone.sh")),
+            "{err}"
+        );
+    }
+
+    /// Options with `DEFAULT_TIMEOUT` and `BASE_URL` in the config
+    /// environment.
+    fn with_variables() -> GenerateOptions {
+        GenerateOptions {
+            env: HashMap::from([
+                ("DEFAULT_TIMEOUT".to_string(), "3s".to_string()),
+                ("BASE_URL".to_string(), "http://localhost".to_string()),
+            ]),
+            ..GenerateOptions::default()
+        }
+    }
+
+    #[test]
+    fn env_decorators_refer_to_config_variables() {
+        let (jobs, _dir) = generate(
+            &[(
+                "vars.b",
+                "@env(URL=${BASE_URL}/api)\n@test a {\n    true\n}\n",
+            )],
+            &with_variables(),
+        )
+        .unwrap();
+        assert_eq!(jobs[0].job.envs["URL"], "http://localhost/api");
+    }
+
+    #[test]
+    fn timeout_decorators_refer_to_config_variables() {
+        let (jobs, _dir) = generate(
+            &[(
+                "vars.b",
+                "@timeout(${DEFAULT_TIMEOUT})\n@test a {\n    true\n}\n",
+            )],
+            &with_variables(),
+        )
+        .unwrap();
+        assert_eq!(jobs[0].job.timeout, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn unresolved_references_name_the_test() {
+        let err = generate(
+            &[(
+                "vars.b",
+                "@env(URL=${BISHIN_TEST_UNSET_URL})\n@test a {\n    true\n}\n",
+            )],
+            &with_variables(),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, Error::Resolve { test, source: ResolveError::Interpolate(_), .. } if test == "vars::a"),
+            "{err}"
+        );
+
+        let err = generate(
+            &[("vars.b", "@timeout(${BASE_URL})\n@test a {\n    true\n}\n")],
+            &with_variables(),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, Error::Resolve { source: ResolveError::Timeout { value, .. }, .. } if value == "http://localhost"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn tests_with_different_paths_have_different_scripts() {
+        let (jobs, _dir) = generate(
+            &[
+                ("a_b/c.b", "@test d {\n    true\n}\n"),
+                ("a/b_c.b", "@test d {\n    exit 1\n}\n"),
+            ],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_ne!(jobs[0].script, jobs[1].script);
+        for test_job in &jobs {
+            let script = std::fs::read_to_string(&test_job.script).unwrap();
+            let expected = match test_job.id.as_str() {
+                "a::b_c::d" => "exit 1",
+                _ => "true",
+            };
+            assert!(script.ends_with(&format!("{expected}\n")), "{script}");
+        }
+    }
+
+    #[test]
+    fn stems_escape_what_would_be_ambiguous() {
+        let (jobs, _dir) = generate(
+            &[
+                ("x.y/z.b", "@test t {\n    true\n}\n"),
+                ("x/y.z.b", "@test t {\n    true\n}\n"),
+            ],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        let names: Vec<_> = jobs
+            .iter()
+            .map(|test_job| test_job.script.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["x.y%2ez.t.sh", "x%2ey.z.t.sh"]);
+    }
+
+    #[test]
+    fn tests_writing_the_same_script_are_an_error() {
+        let (mut jobs, _dir) = generate(
+            &[("dup.b", "@test a {\n    true\n}\n")],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        let mut other = jobs[0].clone();
+        other.id = "other::a".to_string();
+        jobs.push(other);
+        let err = check_distinct_scripts(&jobs).unwrap_err();
+        assert!(
+            matches!(&err, Error::SameScript { first, second, .. } if first == "dup::a" && second == "other::a"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn tests_with_the_same_name_in_one_file_are_an_error_naming_the_file() {
+        let err = generate(
+            &[("dup.b", "@test a {\n    true\n}\n@test a {\n    false\n}\n")],
+            &GenerateOptions::default(),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, Error::Parse { path, source: bishin_parser::Error::Parse { line: 4, .. } } if path.ends_with("dup.b")),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn tests_without_shells_run_with_the_default_one() {
+        let options = GenerateOptions {
+            shell: Shell::Zsh,
+            ..GenerateOptions::default()
+        };
+        let (jobs, _dir) = generate(
+            &[(
+                "shells.b",
+                "@test default {\n    true\n}\n@shells(fish)\n@test own {\n    true\n}\n",
+            )],
+            &options,
+        )
+        .unwrap();
+        let programs: Vec<_> = jobs.iter().map(|job| job.job.args[0].as_str()).collect();
+        assert_eq!(programs, ["zsh", "fish"]);
+        let script = std::fs::read_to_string(&jobs[0].job.args[1]).unwrap();
+        assert!(script.starts_with("#!/usr/bin/env zsh\n"), "{script}");
+    }
+
+    #[test]
+    fn tests_get_a_job_and_script_per_listed_shell() {
+        let (jobs, _dir) = generate(
+            &[(
+                "net/dns.b",
+                "@shells(bash, fish, zsh)\n@test resolves {\n    true\n}\n@shells(fish)\n@test only_fish {\n    true\n}\n",
+            )],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        let ids: Vec<_> = jobs.iter().map(|job| job.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "net::dns::resolves[bash]",
+                "net::dns::resolves[fish]",
+                "net::dns::resolves[zsh]",
+                "net::dns::only_fish",
+            ]
+        );
+        for (test_job, shell) in jobs.iter().zip(["bash", "fish", "zsh", "fish"]) {
+            assert_eq!(test_job.job.args[0], shell);
+            let name = test_job.script.file_name().unwrap().to_str().unwrap();
+            assert!(name.ends_with(&format!("@{shell}.sh")), "{name}");
+            let script = std::fs::read_to_string(&test_job.script).unwrap();
+            assert!(
+                script.starts_with(&format!("#!/usr/bin/env {shell}\n")),
+                "{script}"
+            );
+        }
+        let mut scripts: Vec<_> = jobs.iter().map(|job| &job.script).collect();
+        scripts.dedup();
+        assert_eq!(scripts.len(), 4);
+    }
+
+    #[test]
+    fn skipped_tests_still_get_a_job_marked_skipped() {
+        let (jobs, _dir) = generate(
+            &[(
+                "skip.b",
+                "@skip(\"needs a GPU\")\n@test gpu {\n    true\n}\n@skip\n@test later {\n    true\n}\n@test runs {\n    true\n}\n",
+            )],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        let skips: Vec<_> = jobs
+            .iter()
+            .map(|test_job| test_job.job.skip.clone())
+            .collect();
+        assert_eq!(
+            skips,
+            [
+                Some(Skip {
+                    reason: Some("needs a GPU".to_string())
+                }),
+                Some(Skip { reason: None }),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn setup_and_teardown_go_around_each_test() {
+        let (jobs, _dir) = generate(
+            &[(
+                "fixtures.b",
+                "setup {\n    mkdir data\n}\nteardown {\n    rm -r data\n}\n@test first {\n    ls data\n}\n@test second {\n    true\n}\n",
+            )],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        for (test_job, body) in jobs.iter().zip(["ls data\n", "true\n"]) {
+            let script = std::fs::read_to_string(&test_job.script).unwrap();
+            let hook = "bishin_teardown() {\nrm -r data\n}\ntrap bishin_teardown EXIT\n";
+            assert!(
+                script.ends_with(&format!("{hook}mkdir data\n{body}")),
+                "{script}"
+            );
+        }
+    }
+
+    #[test]
+    fn scripts_of_crlf_files_have_lf_endings() {
+        let lf = "setup {\n    cd /\n}\n@test a {\n    echo a\n    echo b\n}\n";
+        let scripts: Vec<_> = [lf.to_string(), lf.replace('\n', "\r\n")]
+            .iter()
+            .map(|source| {
+                let (jobs, _dir) =
+                    generate(&[("a.b", source)], &GenerateOptions::default()).unwrap();
+                std::fs::read_to_string(&jobs[0].script).unwrap()
+            })
+            .collect();
+        assert!(!scripts[1].contains('\r'), "{:?}", scripts[1]);
+        assert_eq!(scripts[0], scripts[1]);
+    }
+
+    #[test]
+    fn files_without_a_last_line_ending_generate_the_same_script() {
+        let scripts: Vec<_> = ["@test a {\n    echo a\n}\n", "@test a {\n    echo a\n}"]
+            .iter()
+            .map(|source| {
+                let (jobs, _dir) =
+                    generate(&[("a.b", source)], &GenerateOptions::default()).unwrap();
+                std::fs::read_to_string(&jobs[0].script).unwrap()
+            })
+            .collect();
+        assert_eq!(scripts[0], scripts[1]);
+        let tests = parse(&[
+            ("own.b", "@test own = \"own.sh\""),
+            ("own.sh", "echo no newline"),
+        ])
+        .unwrap();
+        assert_eq!(tests[0].body, "echo no newline\n");
+    }
+
+    #[test]
+    fn setup_blocks_come_after_the_includes() {
+        let tests = parse(&[
+            ("net/_helpers.b", "greet() { echo hi; }\n"),
+            (
+                "net/dns.b",
+                "@setup {\n    export NAME=dns\n    greet\n}\n@include(\"_helpers.b\")\n@test a {\n    echo \"$NAME\"\n}\n@test b {\n    true\n}\n",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            tests[0].body,
+            "greet() { echo hi; }\nexport NAME=dns\ngreet\necho \"$NAME\"\n"
+        );
+        // Line numbers in the test's own part of the body start after them.
+        assert_eq!(tests[0].included_lines, 3);
+        assert_eq!(tests[1].body, "export NAME=dns\ngreet\ntrue\n");
+        assert_eq!(tests[1].included_lines, 2);
+    }
+
+    #[test]
+    fn two_setup_blocks_are_an_error_naming_the_file() {
+        let err =
+            parse(&[("dup.b", "@setup {\n}\nsetup {\n}\n@test a {\n    true\n}\n")]).unwrap_err();
+        assert!(
+            matches!(&err, Error::Parse { path, source: bishin_parser::Error::Parse { line: 3, .. } } if path.ends_with("dup.b")),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn tags_are_kept_on_the_test_job() {
+        let (jobs, _dir) = generate(
+            &[(
+                "tags.b",
+                "@tags(slow, network)\n@test tagged {\n    true\n}\n@test plain {\n    true\n}\n",
+            )],
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs[0].tags, ["slow", "network"]);
+        assert!(jobs[1].tags.is_empty());
+    }
+
+    #[test]
+    fn no_strict_tests_leave_out_the_strict_options() {
+        let options = GenerateOptions {
+            script: ScriptOptions {
+                errexit: true,
+                nounset: true,
+                pipefail: true,
+                xtrace: false,
+            },
+            ..GenerateOptions::default()
+        };
+        let (jobs, _dir) = generate(
+            &[(
+                "strict.b",
+                "@test strict {\n    true\n}\n@no_strict\n@test lax {\n    true\n}\n",
+            )],
+            &options,
+        )
+        .unwrap();
+        let scripts: Vec<_> = jobs
+            .iter()
+            .map(|job| std::fs::read_to_string(&job.script).unwrap())
+            .collect();
+        assert!(
+            scripts[0].starts_with("#!/usr/bin/env bash\nset -e\nset -u\nset -o pipefail\n"),
+            "{}",
+            scripts[0]
+        );
+        assert!(!scripts[1].contains("set -"), "{}", scripts[1]);
+    }
+
+    #[test]
+    fn helper_files_can_be_included() {
+        let tests = parse(&[
+            ("net/_helpers.b", "#!/bin/sh\r\ngreet() { echo hi; }"),
+            (
+                "net/dns.b",
+                "@include(\"_helpers.b\")\n@test greets {\n    greet\n}\n",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(tests[0].body, "greet() { echo hi; }\ngreet\n");
+        assert_eq!(tests[0].included_lines, 1);
+
+        let err = parse(&[(
+            "dns.b",
+            "@include(\"_missing.b\")\n@test greets {\n    greet\n}\n",
+        )])
+        .unwrap_err();
+        assert!(
+            matches!(&err, Error::Include { path, test, .. }
+                if path.ends_with("_missing.b") && test == "dns::greets"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn bodies_lose_the_indentation_their_lines_share() {
+        for indent in ["  ", "    ", "\t"] {
+            let body = format!("{indent}if true; then\n{indent}{indent}echo yes\n\n{indent}fi\n");
+            assert_eq!(
+                dedent(&body),
+                format!("if true; then\n{indent}echo yes\n\nfi\n"),
+                "{indent:?}"
+            );
+        }
+        // Heredoc terminators indented with the body end up flush.
+        assert_eq!(
+            dedent("    cat <<EOF\n    text\n    EOF\n"),
+            "cat <<EOF\ntext\nEOF\n"
+        );
+        // Blank lines are ignored, however little they're indented.
+        assert_eq!(dedent("    a\n  \n\n      b\n"), "a\n\n\n  b\n");
+        // Only indentation that's the same character for character goes.
+        assert_eq!(dedent("\t  a\n\t\tb\n"), "  a\n\tb\n");
+        assert_eq!(dedent("  a\n\tb\n"), "  a\n\tb\n");
+        assert_eq!(dedent("a\n  b"), "a\n  b");
+        // Other whitespace, such as a form feed, isn't indentation.
+        assert_eq!(dedent("\x0ca\n\x0cb\n"), "\x0ca\n\x0cb\n");
+        assert_eq!(dedent("   \n"), "   \n");
+        assert_eq!(dedent(""), "");
+    }
+
+    #[test]
+    fn tests_indented_differently_have_the_same_body() {
+        let nested = "@test a {\n        cat <<EOF\n        hi\n        EOF\n    }\n";
+        let flush = "@test a {\ncat <<EOF\nhi\nEOF\n}\n";
+        let tabbed = "@test a {\n\tcat <<EOF\n\thi\n\tEOF\n}\n";
+        for source in [nested, flush, tabbed] {
+            let tests = parse(&[("a.b", source)]).unwrap();
+            assert_eq!(tests[0].body, "cat <<EOF\nhi\nEOF\n", "{source:?}");
+        }
+        // The scripts are the same whether the closing brace is indented or not.
+        let scripts: Vec<_> = [
+            "@test a {\n        echo }\n    }\n",
+            "@test a {\n    echo }\n}\n",
+        ]
+        .iter()
+        .map(|source| {
+            let (jobs, _dir) = generate(&[("a.b", source)], &GenerateOptions::default()).unwrap();
+            std::fs::read_to_string(&jobs[0].script).unwrap()
+        })
+        .collect();
+        assert_eq!(scripts[0], scripts[1]);
+        assert!(scripts[0].ends_with("\necho }\n"), "{}", scripts[0]);
+        // Bodies from files are left as they are.
+        let tests = parse(&[
+            ("own.b", "@test own = \"own.sh\"\n"),
+            ("own.sh", "  indented\n  too\n"),
+        ])
+        .unwrap();
+        assert_eq!(tests[0].body, "  indented\n  too\n");
+    }
+}
@@ -0,0 +1,186 @@
+//! The shell options that generated scripts turn on before a test's body,
+//! such as `set -e`.
+//!
+//! Not every shell has every option, so each is only written for the shells
+//! that have it, and [`ScriptOptions::unsupported`] says which were left out.
+
+use bishin_parser::Shell;
+
+/// Which shell options the scripts set, all off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScriptOptions {
+    /// Exit as soon as a command fails, `set -e`.
+    pub errexit: bool,
+    /// Treat unset variables as an error, `set -u`.
+    pub nounset: bool,
+    /// Fail a pipeline if any command in it fails, `set -o pipefail`.
+    pub pipefail: bool,
+    /// Print each command before running it, `set -x`.
+    pub xtrace: bool,
+}
+
+impl ScriptOptions {
+    /// The options with errexit, nounset and pipefail off, for tests marked
+    /// `@no_strict`.
+    pub fn without_strict(self) -> Self {
+        Self {
+            xtrace: self.xtrace,
+            ..Self::default()
+        }
+    }
+
+    /// The names of the options that are on and that `shell` doesn't have,
+    /// which scripts for it leave out.
+    pub fn unsupported(self, shell: Shell) -> Vec<&'static str> {
+        self.enabled()
+            .filter(|name| command(name, shell).is_none())
+            .collect()
+    }
+
+    /// The lines that turn the options on in a script for `shell`.
+    pub fn preamble(self, shell: Shell) -> String {
+        self.enabled()
+            .filter_map(|name| command(name, shell))
+            .map(|command| format!("{command}\n"))
+            .collect()
+    }
+
+    /// The names of the options that are on.
+    fn enabled(self) -> impl Iterator<Item = &'static str> {
+        [
+            ("errexit", self.errexit),
+            ("nounset", self.nounset),
+            ("pipefail", self.pipefail),
+            ("xtrace", self.xtrace),
+        ]
+        .into_iter()
+        .filter_map(|(name, on)| on.then_some(name))
+    }
+}
+
+/// The lines that run `teardown` once a script for `shell` exits, however it
+/// does, which go before the test's body. tcsh can't, so it has none.
+pub fn exit_hook(shell: Shell, teardown: &str) -> String {
+    // A function can't be empty in sh.
+    let teardown = match teardown.trim() {
+        "" => ":\n",
+        _ => teardown,
+    };
+    match shell {
+        Shell::Bash | Shell::Zsh | Shell::Sh => {
+            format!("bishin_teardown() {{\n{teardown}}}\ntrap bishin_teardown EXIT\n")
+        }
+        Shell::Fish => format!("function bishin_teardown --on-event fish_exit\n{teardown}end\n"),
+        Shell::Tcsh => String::new(),
+    }
+}
+
+/// The command that turns on the option called `name` in `shell`, if it has
+/// the option.
+fn command(name: &str, shell: Shell) -> Option<&'static str> {
+    use Shell::*;
+    match (name, shell) {
+        ("errexit", Bash | Zsh | Sh) => Some("set -e"),
+        ("nounset", Bash | Zsh | Sh) => Some("set -u"),
+        // Only some sh implementations have it, and a script that fails to
+        // set it would fail every test.
+        ("pipefail", Bash | Zsh) => Some("set -o pipefail"),
+        ("xtrace", shell) if shell.has_xtrace() => Some("set -x"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRICT: ScriptOptions = ScriptOptions {
+        errexit: true,
+        nounset: true,
+        pipefail: true,
+        xtrace: false,
+    };
+
+    #[test]
+    fn the_preamble_has_what_each_shell_can_do() {
+        assert_eq!(
+            STRICT.preamble(Shell::Bash),
+            "set -e\nset -u\nset -o pipefail\n"
+        );
+        assert_eq!(STRICT.preamble(Shell::Zsh), STRICT.preamble(Shell::Bash));
+        assert_eq!(STRICT.preamble(Shell::Sh), "set -e\nset -u\n");
+        assert_eq!(STRICT.preamble(Shell::Fish), "");
+        assert_eq!(STRICT.preamble(Shell::Tcsh), "");
+        let traced = ScriptOptions {
+            xtrace: true,
+            ..ScriptOptions::default()
+        };
+        assert_eq!(traced.preamble(Shell::Sh), "set -x\n");
+        assert_eq!(traced.preamble(Shell::Fish), "");
+        assert_eq!(ScriptOptions::default().preamble(Shell::Bash), "");
+    }
+
+    #[test]
+    fn what_the_shell_lacks_is_unsupported() {
+        assert!(STRICT.unsupported(Shell::Bash).is_empty());
+        assert_eq!(STRICT.unsupported(Shell::Sh), ["pipefail"]);
+        assert_eq!(
+            STRICT.unsupported(Shell::Fish),
+            ["errexit", "nounset", "pipefail"]
+        );
+        assert!(ScriptOptions::default().unsupported(Shell::Tcsh).is_empty());
+    }
+
+    #[test]
+    fn no_strict_keeps_only_xtrace() {
+        let options = ScriptOptions {
+            xtrace: true,
+            ..STRICT
+        };
+        assert_eq!(
+            options.without_strict(),
+            ScriptOptions {
+                xtrace: true,
+                ..ScriptOptions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn teardown_runs_on_exit() {
+        assert_eq!(
+            exit_hook(Shell::Bash, "rm -f x\n"),
+            "bishin_teardown() {\nrm -f x\n}\ntrap bishin_teardown EXIT\n"
+        );
+        assert_eq!(
+            exit_hook(Shell::Sh, "  \n"),
+            "bishin_teardown() {\n:\n}\ntrap bishin_teardown EXIT\n"
+        );
+        assert_eq!(
+            exit_hook(Shell::Fish, "rm -f x\n"),
+            "function bishin_teardown --on-event fish_exit\nrm -f x\nend\n"
+        );
+        assert_eq!(exit_hook(Shell::Tcsh, "rm -f x\n"), "");
+    }
+
+    #[test]
+    fn teardowns_run_on_exit_where_the_shell_can() {
+        assert_eq!(
+            exit_hook(Shell::Bash, "rm -r data\n"),
+            "bishin_teardown() {\nrm -r data\n}\ntrap bishin_teardown EXIT\n"
+        );
+        assert_eq!(
+            exit_hook(Shell::Sh, "rm -r data\n"),
+            exit_hook(Shell::Zsh, "rm -r data\n")
+        );
+        assert_eq!(
+            exit_hook(Shell::Sh, "\n"),
+            "bishin_teardown() {\n:\n}\ntrap bishin_teardown EXIT\n"
+        );
+        assert_eq!(
+            exit_hook(Shell::Fish, "rm -r data\n"),
+            "function bishin_teardown --on-event fish_exit\nrm -r data\nend\n"
+        );
+        assert_eq!(exit_hook(Shell::Tcsh, "rm -r data\n"), "");
+    }
+}
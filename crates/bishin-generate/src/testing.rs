@@ -0,0 +1,17 @@
+//! Helpers for the tests in this crate.
+
+use std::path::PathBuf;
+
+use bishin_collect::{load_tests, ModuleGraph};
+use bishin_testing::{scratch_dir, write_files, ScratchDir};
+
+/// The modules of a new test directory holding `files`, a work directory
+/// next to it, and the directory they're both in, which goes when that's
+/// dropped.
+pub(crate) fn suite(files: &[(&str, &str)]) -> (ModuleGraph, PathBuf, ScratchDir) {
+    let dir = scratch_dir();
+    write_files(&dir.join("tests"), files);
+    let graph = load_tests(&dir.join("tests")).unwrap();
+    let work_dir = dir.join("work");
+    (graph, work_dir, dir)
+}
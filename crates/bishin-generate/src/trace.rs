@@ -0,0 +1,159 @@
+//! Parsing of the xtrace output that traced scripts write to stderr.
+//!
+//! Traced jobs run with a distinctive `PS4` so that trace lines can be told
+//! apart from whatever the test itself writes to stderr.
+
+use std::collections::BTreeMap;
+
+/// The `PS4` given to traced scripts. Bash repeats the leading `+` once per
+/// level of nesting.
+pub const TRACE_PS4: &str = "+bishin:trace: ";
+
+const TRACE_MARKER: &str = "bishin:trace: ";
+
+/// Words that bash runs itself rather than by looking up a program.
+#[rustfmt::skip]
+const SHELL_WORDS: &[&str] = &[
+    "!", ".", ":", "[", "[[", "]]", "{", "}", "((", "alias", "bg", "bind", "break", "builtin",
+    "caller", "case", "cd", "command", "compgen", "complete", "compopt", "continue", "coproc",
+    "declare", "dirs", "disown", "do", "done", "echo", "elif", "else", "enable", "esac", "eval",
+    "exec", "exit", "export", "false", "fc", "fg", "fi", "for", "function", "getopts", "hash",
+    "help", "history", "if", "in", "jobs", "kill", "let", "local", "logout", "mapfile", "popd",
+    "printf", "pushd", "pwd", "read", "readarray", "readonly", "return", "select", "set", "shift",
+    "shopt", "source", "suspend", "test", "then", "time", "times", "trap", "true", "type",
+    "typeset", "ulimit", "umask", "unalias", "unset", "until", "wait", "while",
+];
+
+/// Separate the trace lines in `stderr` from the rest of its output.
+///
+/// Returns the remaining stderr and the traced commands, without their
+/// prompt.
+pub fn split_trace(stderr: &str) -> (String, Vec<String>) {
+    let mut output = String::new();
+    let mut trace = Vec::new();
+    for line in stderr.split_inclusive('\n') {
+        match trace_command(line) {
+            Some(command) => trace.push(command.to_string()),
+            None => output.push_str(line),
+        }
+    }
+    (output, trace)
+}
+
+fn trace_command(line: &str) -> Option<&str> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let rest = line.trim_start_matches('+');
+    if rest.len() == line.len() {
+        return None;
+    }
+    rest.strip_prefix(TRACE_MARKER)
+}
+
+/// Count how many times each external program appears in `trace`.
+///
+/// Builtins, keywords, and variable assignments are left out, and programs
+/// run by path are counted by their file name.
+pub fn command_counts<'a>(trace: impl IntoIterator<Item = &'a str>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for command in trace {
+        if let Some(program) = program(command) {
+            *counts.entry(program).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// The program a traced command runs, if it runs one.
+fn program(command: &str) -> Option<String> {
+    let word = words(command).find(|word| !is_assignment(word))?;
+    if SHELL_WORDS.contains(&word.as_str()) {
+        return None;
+    }
+    let name = word.rsplit('/').next().unwrap_or(&word);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Split a traced command into words, undoing the single quoting that bash
+/// applies to words containing special characters.
+fn words(command: &str) -> impl Iterator<Item = String> + '_ {
+    let mut chars = command.chars().peekable();
+    std::iter::from_fn(move || {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        chars.peek()?;
+        let mut word = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => quoted = !quoted,
+                '\\' if !quoted => word.extend(chars.next()),
+                c if c.is_whitespace() && !quoted => break,
+                c => word.push(c),
+            }
+        }
+        Some(word)
+    })
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::suite;
+    use crate::{generate_test_jobs, GenerateOptions};
+
+    #[test]
+    fn trace_lines_are_split_from_the_rest() {
+        let stderr = "+bishin:trace: grep x file\nreal output\n++bishin:trace: cat\n+ not ours\n";
+        let (output, trace) = split_trace(stderr);
+        assert_eq!(output, "real output\n+ not ours\n");
+        assert_eq!(trace, ["grep x file", "cat"]);
+    }
+
+    #[test]
+    fn only_external_programs_are_counted() {
+        let counts = command_counts([
+            "grep -q x file",
+            "/usr/bin/grep y",
+            "FOO=1 curl 'http://example.com/a b'",
+            "echo hi",
+            "[ -f file ]",
+            "x=1",
+            "'my tool' --flag",
+        ]);
+        let counts: Vec<_> = counts.iter().map(|(name, n)| (name.as_str(), *n)).collect();
+        assert_eq!(counts, [("curl", 1), ("grep", 2), ("my tool", 1)]);
+    }
+
+    #[test]
+    fn traced_scripts_count_what_they_run() {
+        let (graph, work_dir, _dir) = suite(&[(
+            "commands.b",
+            concat!(
+                "@test one {\n    ls / >/dev/null\n    cat </dev/null\n}\n",
+                "@test two {\n    ls / >/dev/null\n    echo done\n}\n",
+            ),
+        )]);
+        std::fs::create_dir_all(&work_dir).unwrap();
+        let options = GenerateOptions {
+            trace: true,
+            ..GenerateOptions::default()
+        };
+        let mut trace = Vec::new();
+        for test_job in generate_test_jobs(&graph, &work_dir, &options).unwrap() {
+            let result = test_job.job.run().unwrap();
+            trace.extend(split_trace(&result.stderr).1);
+        }
+        let counts = command_counts(trace.iter().map(String::as_str));
+        assert_eq!(counts.get("ls"), Some(&2));
+        assert_eq!(counts.get("cat"), Some(&1));
+        assert_eq!(counts.get("echo"), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! The command line of a libtest test binary, as `cargo test` and nextest
+//! pass it on.
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A line per test.
+    #[default]
+    Pretty,
+    /// A character per test.
+    Terse,
+}
+
+/// The arguments that bishin makes use of. Those that only make sense for
+/// Rust tests, like `--force-run-in-process`, are accepted and ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    /// Run only the tests whose names contain one of these, unless empty.
+    pub filters: Vec<String>,
+    /// Leave out the tests whose names contain any of these.
+    pub skip: Vec<String>,
+    /// Match names against the filters exactly instead.
+    pub exact: bool,
+    /// List the tests instead of running them.
+    pub list: bool,
+    /// Run only the ignored tests, of which bishin has none.
+    pub ignored: bool,
+    /// Run the `@bench` benchmarks instead of the tests, as `cargo bench`
+    /// asks for.
+    pub bench: bool,
+    /// Print each test's output as it finishes.
+    pub nocapture: bool,
+    /// Print the output of the tests that passed too.
+    pub show_output: bool,
+    pub test_threads: Option<usize>,
+    pub format: Format,
+}
+
+/// Flags that take a value and don't matter to bishin.
+const IGNORED_WITH_VALUE: [&str; 4] = ["--color", "--logfile", "--shuffle-seed", "-Z"];
+
+/// Flags that don't matter to bishin.
+const IGNORED: [&str; 7] = [
+    "--test",
+    "--include-ignored",
+    "--report-time",
+    "--ensure-time",
+    "--shuffle",
+    "--force-run-in-process",
+    "--exclude-should-panic",
+];
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Values can follow a flag or be attached with `=`.
+            let (flag, attached) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                attached
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{flag} needs a value"))
+            };
+            match flag.as_str() {
+                "--list" => parsed.list = true,
+                "--exact" => parsed.exact = true,
+                "--ignored" => parsed.ignored = true,
+                "--bench" => parsed.bench = true,
+                "--nocapture" | "--no-capture" => parsed.nocapture = true,
+                "--show-output" => parsed.show_output = true,
+                "-q" | "--quiet" => parsed.format = Format::Terse,
+                "--skip" => parsed.skip.push(value()?),
+                "--test-threads" => {
+                    let threads = value()?;
+                    parsed.test_threads = match threads.parse() {
+                        Ok(0) | Err(_) => {
+                            return Err(format!(
+                                "--test-threads must be a positive number, not {threads:?}"
+                            ))
+                        }
+                        Ok(threads) => Some(threads),
+                    };
+                }
+                "--format" => {
+                    parsed.format = match value()?.as_str() {
+                        "pretty" => Format::Pretty,
+                        "terse" => Format::Terse,
+                        format => return Err(format!("unsupported --format {format:?}")),
+                    }
+                }
+                flag if IGNORED.contains(&flag) => {}
+                flag if IGNORED_WITH_VALUE.contains(&flag) => {
+                    value()?;
+                }
+                // Like `-Zunstable-options`.
+                flag if flag.starts_with("-Z") => {}
+                flag if flag.starts_with('-') => return Err(format!("unrecognized option {flag}")),
+                _ => parsed.filters.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(ToString::to_string))
+    }
+
+    #[test]
+    fn libtest_flags_are_read() {
+        assert_eq!(
+            parse(&[
+                "net",
+                "--exact",
+                "--skip",
+                "slow",
+                "--skip=flaky",
+                "--nocapture",
+                "--test-threads=2",
+                "-q",
+                "fs",
+            ]),
+            Ok(Args {
+                filters: vec!["net".to_string(), "fs".to_string()],
+                skip: vec!["slow".to_string(), "flaky".to_string()],
+                exact: true,
+                nocapture: true,
+                test_threads: Some(2),
+                format: Format::Terse,
+                ..Args::default()
+            })
+        );
+        assert_eq!(parse(&[]), Ok(Args::default()));
+        let list = parse(&["--list", "--format", "terse", "--ignored", "--bench"]).unwrap();
+        assert!(list.list && list.ignored && list.bench);
+        assert_eq!(list.format, Format::Terse);
+        assert!(parse(&["--no-capture", "--show-output"]).unwrap().nocapture);
+    }
+
+    #[test]
+    fn flags_for_rust_tests_are_ignored() {
+        let ignored = parse(&[
+            "--include-ignored",
+            "--color",
+            "always",
+            "--logfile=out.log",
+            "-Z",
+            "unstable-options",
+            "-Zunstable-options",
+            "--report-time",
+            "--shuffle-seed",
+            "7",
+        ]);
+        assert_eq!(ignored, Ok(Args::default()));
+    }
+
+    #[test]
+    fn bad_flags_are_errors() {
+        assert_eq!(
+            parse(&["--test-threads", "many"]),
+            Err("--test-threads must be a positive number, not \"many\"".to_string())
+        );
+        assert_eq!(parse(&["--skip"]), Err("--skip needs a value".to_string()));
+        assert_eq!(
+            parse(&["--format=json"]),
+            Err("unsupported --format \"json\"".to_string())
+        );
+        assert_eq!(
+            parse(&["--bogus"]),
+            Err("unrecognized option --bogus".to_string())
+        );
+    }
+}
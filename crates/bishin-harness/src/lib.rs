@@ -0,0 +1,584 @@
+//! Running a bishin suite as a Cargo test target, so that `cargo test` runs
+//! it with the Rust tests.
+//!
+//! The target needs Cargo's own harness turned off in `Cargo.toml`:
+//!
+//! ```toml
+//! [[test]]
+//! name = "shell"
+//! harness = false
+//! ```
+//!
+//! and `tests/shell.rs` hands over to bishin:
+//!
+//! ```no_run
+//! fn main() -> std::process::ExitCode {
+//!     bishin_harness::main()
+//! }
+//! ```
+//!
+//! The suite is the one configured in the package's `bishin.toml`, or its
+//! `[package.metadata.bishin]` table. The target takes the arguments that a
+//! libtest one does, such as filters, `--exact`, `--list`, `--nocapture` and
+//! `--test-threads`, and prints the same lines, so that `cargo test` and
+//! tools driving it, like nextest, can't tell the difference. Tests marked
+//! `@bench` only run with `--bench`, as for a `[[bench]]` target that
+//! `cargo bench` runs.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use anyhow::bail;
+use bishin::report::Reporter;
+use bishin::timings::Timings;
+use bishin::{collect, NamePattern, Runner, TestFilter};
+use bishin_config::{find_cargo_config, Config, LoadOptions, Severity, CONFIG_FILE};
+use bishin_generate::{parse_module, test_ids};
+use bishin_jobs::{JobResult, RunSummary, Status};
+
+mod args;
+
+pub use args::{Args, Format};
+
+/// The exit code of a failed run, as libtest's.
+const FAILED_EXIT_CODE: u8 = 101;
+
+/// How many results a line of terse output holds.
+const TERSE_LINE: usize = 88;
+
+/// Run the suite of the package being tested with the arguments that this
+/// process was started with.
+pub fn main() -> ExitCode {
+    // Cargo and nextest both set this when running a test target.
+    let dir = std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+    run(&dir, std::env::args().skip(1))
+}
+
+/// Run the suite configured in `dir` with the libtest arguments `args`.
+pub fn run(dir: &Path, args: impl IntoIterator<Item = String>) -> ExitCode {
+    run_to(dir, args, Stdout)
+}
+
+/// Like [`run`], printing what libtest would print to its standard output to
+/// `out` instead.
+fn run_to(
+    dir: &Path,
+    args: impl IntoIterator<Item = String>,
+    out: impl Write + Clone + 'static,
+) -> ExitCode {
+    let result = Args::parse(args)
+        .map_err(anyhow::Error::msg)
+        .and_then(|args| run_with(dir, &args, out));
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::from(FAILED_EXIT_CODE)
+        }
+    }
+}
+
+fn run_with(
+    dir: &Path,
+    args: &Args,
+    mut out: impl Write + Clone + 'static,
+) -> anyhow::Result<ExitCode> {
+    let config = load_config(dir)?;
+    let filter = filter(args);
+    let names = test_names(&config)?;
+    // A bishin test can only be skipped once it runs, so there are no
+    // ignored tests to list or run.
+    let matching: Vec<_> = names
+        .iter()
+        .filter(|(name, _)| !args.ignored && filter.matches(name, &[]))
+        .collect();
+    if args.list {
+        for (name, bench) in &matching {
+            writeln!(out, "{name}: {}", if *bench { "bench" } else { "test" })?;
+        }
+        if args.format != Format::Terse {
+            let benches = matching.iter().filter(|(_, bench)| *bench).count();
+            writeln!(
+                out,
+                "\n{} tests, {benches} benchmarks",
+                matching.len() - benches
+            )?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let selected = matching
+        .iter()
+        .filter(|(_, bench)| *bench == args.bench)
+        .count();
+    let of_kind = names
+        .iter()
+        .filter(|(_, bench)| *bench == args.bench)
+        .count();
+
+    let started = Instant::now();
+    let mut runner = Runner::new(config).filter(filter);
+    if let Some(threads) = args.test_threads {
+        runner = runner.jobs(threads);
+    }
+    let mut plan = match args.ignored {
+        true => None,
+        false => Some(runner.plan()?),
+    };
+    // What's left in the plan's warnings would be the outcome's as well.
+    if let Some(plan) = &mut plan {
+        warn(&std::mem::take(&mut plan.warnings));
+    }
+    let running = plan.as_ref().map_or(0, |plan| plan.tests.len());
+    writeln!(
+        out,
+        "\nrunning {running} {}",
+        if running == 1 { "test" } else { "tests" }
+    )?;
+    let reporter = LibtestReporter {
+        out: out.clone(),
+        format: args.format,
+        nocapture: args.nocapture,
+        shown: 0,
+    };
+    let outcome = match plan {
+        Some(plan) => Some(runner.reporter(Box::new(reporter)).execute(plan)?),
+        None => None,
+    };
+    let summary = outcome
+        .as_ref()
+        .map(|outcome| outcome.summary.clone())
+        .unwrap_or_default();
+    if let Some(outcome) = &outcome {
+        warn(&outcome.warnings);
+    }
+    if args.format == Format::Terse && running > 0 {
+        writeln!(out)?;
+    }
+
+    if args.show_output {
+        let successes: Vec<_> = summary
+            .results
+            .iter()
+            .filter(|result| !result.status.is_failure())
+            .collect();
+        print_outputs(&mut out, "successes", &successes, !args.nocapture)?;
+    }
+    let failures: Vec<_> = summary
+        .results
+        .iter()
+        .filter(|result| result.status.is_failure())
+        .chain(
+            summary
+                .hooks
+                .iter()
+                .filter(|hook| hook.status != Status::Passed),
+        )
+        .collect();
+    // With --nocapture the output was printed as each test finished.
+    print_outputs(&mut out, "failures", &failures, !args.nocapture)?;
+
+    let passed = outcome.as_ref().is_none_or(|outcome| outcome.passed());
+    let count = |status| summary.count(status);
+    let measured = summary.results.iter().filter(|r| r.bench.is_some()).count();
+    writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed; {} ignored; {measured} measured; {} filtered out; finished in {:.2}s\n",
+        if passed { "ok" } else { "FAILED" },
+        count(Status::Passed) + count(Status::XFail) + count(Status::XPass) - measured,
+        summary.results.iter().filter(|r| r.status.is_failure()).count(),
+        count(Status::Skipped),
+        of_kind - selected,
+        started.elapsed().as_secs_f64()
+    )?;
+    Ok(if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(FAILED_EXIT_CODE)
+    })
+}
+
+/// Print the warnings that the library leaves to its caller.
+fn warn(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+}
+
+/// Load the config in `dir`, or the one in the nearest Cargo manifest if
+/// there's no [`CONFIG_FILE`], and check it.
+fn load_config(dir: &Path) -> anyhow::Result<Config> {
+    let path = dir.join(CONFIG_FILE);
+    let config = match find_cargo_config(dir) {
+        Some((manifest, table)) if !path.exists() => {
+            Config::load_table_with(&manifest, table, &LoadOptions::default())?
+        }
+        _ => Config::load(&path)?,
+    };
+    let issues = config.validate();
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+    if issues.iter().any(|issue| issue.severity == Severity::Error) {
+        bail!("found problems in {}", config.source());
+    }
+    Ok(config)
+}
+
+/// The name of every test in the suite, with one for each shell of a test
+/// that lists several, and whether it's a benchmark.
+fn test_names(config: &Config) -> anyhow::Result<Vec<(String, bool)>> {
+    let graph = collect(config)?;
+    let mut names = Vec::new();
+    for module in graph.iter_leaf_modules() {
+        for test in parse_module(module)? {
+            let bench = test.bench.is_some();
+            names.extend(test_ids(module, &test).into_iter().map(|id| (id, bench)));
+        }
+    }
+    Ok(names)
+}
+
+/// The tests that libtest would run with `args`: those matching any filter,
+/// and none matching a `--skip`.
+fn filter(args: &Args) -> TestFilter {
+    let pattern = |filter: &String| match args.exact {
+        true => NamePattern::Exact(filter.clone()),
+        false => NamePattern::Substring(filter.clone()),
+    };
+    TestFilter {
+        include: args.filters.iter().map(pattern).collect(),
+        exclude: args.skip.iter().map(pattern).collect(),
+        benches: args.bench,
+        ..TestFilter::default()
+    }
+}
+
+/// Print the output of `results` under a `heading` if `with_output`, and
+/// then their names, like libtest does for its failures.
+fn print_outputs(
+    out: &mut impl Write,
+    heading: &str,
+    results: &[&JobResult],
+    with_output: bool,
+) -> io::Result<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+    if with_output {
+        writeln!(out, "\n{heading}:\n")?;
+        for result in results {
+            print_output(out, result)?;
+        }
+    }
+    writeln!(out, "\n{heading}:")?;
+    for result in results {
+        writeln!(out, "    {}", result.name)?;
+    }
+    Ok(())
+}
+
+fn print_output(out: &mut impl Write, result: &JobResult) -> io::Result<()> {
+    for (stream, output) in [("stdout", &result.stdout), ("stderr", &result.stderr)] {
+        if output.is_empty() {
+            continue;
+        }
+        writeln!(out, "---- {} {stream} ----", result.name)?;
+        write!(out, "{output}")?;
+        if !output.ends_with('\n') {
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// `n` with commas between each three digits, e.g. `1,234,567`.
+fn thousands(n: u128) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Standard output, which a [`LibtestReporter`] can hold a copy of.
+#[derive(Clone, Copy)]
+struct Stdout;
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// Prints a line per result, as libtest does as each test finishes.
+struct LibtestReporter<W> {
+    out: W,
+    format: Format,
+    nocapture: bool,
+    /// How many results are on the current line of terse output.
+    shown: usize,
+}
+
+impl<W: Write> Reporter for LibtestReporter<W> {
+    fn result(&mut self, result: &JobResult) -> io::Result<()> {
+        if self.format == Format::Terse {
+            let c = match result.status {
+                status if status.is_failure() => 'F',
+                Status::Skipped => 'i',
+                _ => '.',
+            };
+            write!(self.out, "{c}")?;
+            self.shown += 1;
+            if self.shown.is_multiple_of(TERSE_LINE) {
+                writeln!(self.out)?;
+            }
+            return self.out.flush();
+        }
+        let status = match (result.status, &result.skip_reason, &result.bench) {
+            (status, _, _) if status.is_failure() => "FAILED".to_string(),
+            (Status::Skipped, Some(reason), _) => format!("ignored, {reason}"),
+            (Status::Skipped, None, _) => "ignored".to_string(),
+            // Like `cargo bench`, in the median's nanoseconds and how far
+            // the rest spread from it.
+            (_, _, Some(stats)) => format!(
+                "bench: {:>11} ns/iter (+/- {})",
+                thousands(stats.median().as_nanos()),
+                thousands((stats.max() - stats.min()).as_nanos())
+            ),
+            _ => "ok".to_string(),
+        };
+        writeln!(self.out, "test {} ... {status}", result.name)?;
+        // The output isn't captured as it goes, but this is as close as it
+        // gets.
+        if self.nocapture {
+            write!(self.out, "{}", result.stdout)?;
+            eprint!("{}", result.stderr);
+        }
+        Ok(())
+    }
+
+    fn hook(&mut self, result: &JobResult) -> io::Result<()> {
+        if result.status != Status::Passed {
+            writeln!(self.out, "{} ... FAILED", result.name)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &RunSummary, _timings: &Timings) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use bishin_testing::{scratch_dir, write_files, ScratchDir};
+
+    use super::*;
+
+    /// What a run printed, shared with the reporter printing it.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const SUITE: &[(&str, &str)] = &[
+        (
+            "tests/net.b",
+            "@test dns {\n    echo resolved\n}\n@test http {\n    echo refused >&2\n    exit 1\n}\n",
+        ),
+        (
+            "tests/fs.b",
+            "@test copy {\n    true\n}\n@test link {\n    echo no links\n    exit 77\n}\n",
+        ),
+    ];
+
+    /// A package with an empty `bishin.toml` and the tests in `files`, which
+    /// goes when what's returned is dropped.
+    fn package(files: &[(&str, &str)]) -> ScratchDir {
+        let dir = scratch_dir();
+        write_files(&dir, &[(CONFIG_FILE, "")]);
+        write_files(&dir, files);
+        dir
+    }
+
+    /// The exit code and output of the harness run with `args` in `dir`,
+    /// with the time it took left out.
+    fn harness(dir: &Path, args: &[&str]) -> (ExitCode, String) {
+        let out = Captured::default();
+        let code = run_to(dir, args.iter().map(ToString::to_string), out.clone());
+        let output = String::from_utf8(out.0.take()).unwrap();
+        let output = match output.split_once("; finished in ") {
+            Some((before, after)) => {
+                let (_, rest) = after.split_once('s').unwrap();
+                format!("{before}; finished in 0.00s{rest}")
+            }
+            None => output,
+        };
+        (code, output)
+    }
+
+    #[test]
+    fn list_output() {
+        let dir = package(SUITE);
+        let (code, output) = harness(&dir, &["--list"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            output,
+            "fs::copy: test\nfs::link: test\nnet::dns: test\nnet::http: test\n\n4 tests, 0 benchmarks\n"
+        );
+        let (_, output) = harness(&dir, &["--list", "--format", "terse", "net"]);
+        assert_eq!(output, "net::dns: test\nnet::http: test\n");
+        let (_, output) = harness(&dir, &["--list", "--ignored"]);
+        assert_eq!(output, "\n0 tests, 0 benchmarks\n");
+    }
+
+    #[test]
+    fn pretty_output() {
+        let dir = package(SUITE);
+        let (code, output) = harness(&dir, &["--test-threads", "1"]);
+        assert_eq!(code, ExitCode::from(FAILED_EXIT_CODE));
+        assert_eq!(
+            output,
+            "
+running 4 tests
+test fs::copy ... ok
+test fs::link ... ignored
+test net::dns ... ok
+test net::http ... FAILED
+
+failures:
+
+---- net::http stderr ----
+refused
+
+failures:
+    net::http
+
+test result: FAILED. 2 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+"
+        );
+    }
+
+    #[test]
+    fn terse_output() {
+        let dir = package(SUITE);
+        let (_, output) = harness(&dir, &["-q", "--test-threads=1", "--skip", "http"]);
+        assert_eq!(
+            output,
+            "
+running 3 tests
+.i.
+
+test result: ok. 2 passed; 0 failed; 1 ignored; 0 measured; 1 filtered out; finished in 0.00s
+
+"
+        );
+    }
+
+    #[test]
+    fn filters_match_substrings_or_exact_names() {
+        let dir = package(SUITE);
+        let (code, output) = harness(&dir, &["s::", "--show-output"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(output.starts_with("\nrunning 2 tests\n"), "{output}");
+        assert!(
+            output.contains("successes:\n\n---- fs::link stdout ----\nno links\n"),
+            "{output}"
+        );
+        assert!(output.contains("; 2 filtered out;"), "{output}");
+        let (_, output) = harness(&dir, &["--exact", "net::dns", "net::dn"]);
+        assert!(
+            output.contains("running 1 test\ntest net::dns ... ok\n"),
+            "{output}"
+        );
+        assert!(
+            output.contains("1 passed; 0 failed; 0 ignored; 0 measured; 3 filtered out"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn tests_in_several_shells_are_listed_and_run_by_their_own_names() {
+        let dir = package(&[(
+            "tests/x.b",
+            "@test one {\n    true\n}\n@shells(bash, sh)\n@test two {\n    true\n}\n",
+        )]);
+        let (_, output) = harness(&dir, &["--list", "--format", "terse"]);
+        assert_eq!(
+            output,
+            "x::one: test\nx::two[bash]: test\nx::two[sh]: test\n"
+        );
+        let (code, output) = harness(&dir, &["--exact", "x::two[sh]"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(
+            output.contains("running 1 test\ntest x::two[sh] ... ok\n"),
+            "{output}"
+        );
+        assert!(
+            output.contains("1 passed; 0 failed; 0 ignored; 0 measured; 2 filtered out"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn nocapture_prints_the_output_with_each_result() {
+        let dir = package(SUITE);
+        let (_, output) = harness(&dir, &["--nocapture", "--exact", "net::dns"]);
+        assert!(
+            output.contains("test net::dns ... ok\nresolved\n"),
+            "{output}"
+        );
+        let (_, output) = harness(&dir, &["--nocapture", "--test-threads", "1"]);
+        // The failures' output isn't printed a second time.
+        assert!(output.contains("\nfailures:\n    net::http\n"), "{output}");
+        assert!(!output.contains("---- net::http"), "{output}");
+    }
+
+    #[test]
+    fn bad_arguments_fail_like_libtest() {
+        let dir = package(SUITE);
+        for args in [
+            &["--test-threads", "0"][..],
+            &["--test-threads"],
+            &["--format", "json"],
+            &["--frobnicate"],
+        ] {
+            let (code, output) = harness(&dir, args);
+            assert_eq!(code, ExitCode::from(FAILED_EXIT_CODE), "{args:?}");
+            assert_eq!(output, "", "{args:?}");
+        }
+    }
+
+    #[test]
+    fn numbers_are_grouped_by_thousands() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(1_000), "1,000");
+        assert_eq!(thousands(1_234_567), "1,234,567");
+    }
+}
@@ -0,0 +1,174 @@
+//! Running a job over and over to time it.
+
+use std::time::Duration;
+
+use crate::{Error, Job, JobResult, Status};
+
+/// How to run a job as a benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bench {
+    /// How many runs are timed.
+    pub iterations: u32,
+    /// How many runs come before them, to warm up caches, and aren't timed.
+    pub warmup: u32,
+}
+
+/// How long each timed run of a benchmark took.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BenchStats {
+    /// The wall time of each run, in the order they ran.
+    pub times: Vec<Duration>,
+}
+
+impl BenchStats {
+    pub fn min(&self) -> Duration {
+        self.times.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.times.iter().copied().max().unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.times.len() {
+            0 => Duration::ZERO,
+            n => self.times.iter().sum::<Duration>() / n as u32,
+        }
+    }
+
+    /// The middle time, or the mean of the two middle ones.
+    pub fn median(&self) -> Duration {
+        let sorted = self.sorted();
+        match sorted.len() {
+            0 => Duration::ZERO,
+            n if n % 2 == 1 => sorted[n / 2],
+            n => (sorted[n / 2 - 1] + sorted[n / 2]) / 2,
+        }
+    }
+
+    /// The time that 95% of the runs took at most, by the nearest rank.
+    pub fn p95(&self) -> Duration {
+        let sorted = self.sorted();
+        if sorted.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (sorted.len() * 95).div_ceil(100);
+        sorted[rank.max(1) - 1]
+    }
+
+    fn sorted(&self) -> Vec<Duration> {
+        let mut sorted = self.times.clone();
+        sorted.sort();
+        sorted
+    }
+}
+
+/// Run `job` as `bench` says, stopping at the first run that doesn't pass
+/// and returning its result.
+///
+/// Otherwise the result is that of the last run, with the times of the
+/// timed runs and how long they took together.
+pub(crate) fn run(job: &Job, bench: &Bench) -> Result<JobResult, Error> {
+    for _ in 0..bench.warmup {
+        let result = job.run_once()?;
+        if result.status != Status::Passed {
+            return Ok(result);
+        }
+    }
+    let mut times = Vec::with_capacity(bench.iterations as usize);
+    let mut last = None;
+    for _ in 0..bench.iterations.max(1) {
+        let result = job.run_once()?;
+        if result.status != Status::Passed {
+            return Ok(result);
+        }
+        times.push(result.duration);
+        last = Some(result);
+    }
+    let mut result = last.expect("a benchmark runs at least once");
+    result.duration = times.iter().sum();
+    result.bench = Some(BenchStats { times });
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, sh};
+
+    fn stats(milliseconds: &[u64]) -> BenchStats {
+        BenchStats {
+            times: milliseconds
+                .iter()
+                .copied()
+                .map(Duration::from_millis)
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn statistics_of_the_times() {
+        let stats = stats(&[30, 10, 20, 40]);
+        assert_eq!(stats.min(), Duration::from_millis(10));
+        assert_eq!(stats.max(), Duration::from_millis(40));
+        assert_eq!(stats.mean(), Duration::from_millis(25));
+        assert_eq!(stats.median(), Duration::from_millis(25));
+        assert_eq!(stats.p95(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn odd_counts_have_a_middle_time() {
+        assert_eq!(stats(&[3, 1, 2]).median(), Duration::from_millis(2));
+        let twenty: Vec<u64> = (1..=20).collect();
+        assert_eq!(stats(&twenty).p95(), Duration::from_millis(19));
+    }
+
+    #[test]
+    fn no_times_are_all_zero() {
+        let stats = BenchStats::default();
+        for duration in [
+            stats.min(),
+            stats.max(),
+            stats.mean(),
+            stats.median(),
+            stats.p95(),
+        ] {
+            assert_eq!(duration, Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn warmups_are_run_but_not_timed() {
+        let scratch = scratch_dir();
+        let count = scratch.join("count");
+        let job = sh("bench", &format!("echo run >> '{}'", count.display()));
+        let bench = Bench {
+            iterations: 4,
+            warmup: 2,
+        };
+        let result = run(&job, &bench).unwrap();
+        assert_eq!(result.status, Status::Passed);
+        assert_eq!(result.bench.as_ref().unwrap().times.len(), 4);
+        assert_eq!(std::fs::read_to_string(&count).unwrap().lines().count(), 6);
+        assert_eq!(result.duration, result.bench.unwrap().times.iter().sum());
+    }
+
+    #[test]
+    fn a_failing_run_fails_the_benchmark() {
+        let scratch = scratch_dir();
+        let count = scratch.join("count");
+        // The third run fails, the first timed one.
+        let script = format!(
+            "echo run >> '{0}'; [ \"$(wc -l < '{0}')\" -lt 3 ]",
+            count.display()
+        );
+        let bench = Bench {
+            iterations: 5,
+            warmup: 2,
+        };
+        let result = run(&sh("bench", &script), &bench).unwrap();
+        assert_eq!(result.status, Status::Failed);
+        assert_eq!(result.bench, None);
+        assert_eq!(std::fs::read_to_string(&count).unwrap().lines().count(), 3);
+    }
+}
@@ -0,0 +1,277 @@
+//! Line diffs between what a job was expected to print and what it printed.
+
+/// How many unchanged lines to show around each change.
+pub const DIFF_CONTEXT: usize = 3;
+
+/// Past this many pairs of lines to compare, the changed part of the output
+/// is shown as all removed and then all added, rather than taking so long
+/// to find the smallest diff that it holds up the run.
+const MAX_COMPARISONS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// A unified diff from `expected` to `actual`, with `context` unchanged
+/// lines around each change, or an empty string if they're the same.
+///
+/// ```
+/// let diff = bishin_jobs::unified_diff("a\nb\nc\n", "a\nB\nc\n", "snapshot", "stdout", 1);
+/// assert_eq!(diff, "--- snapshot\n+++ stdout\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n");
+/// ```
+pub fn unified_diff(
+    expected: &str,
+    actual: &str,
+    expected_name: &str,
+    actual_name: &str,
+    context: usize,
+) -> String {
+    if expected == actual {
+        return String::new();
+    }
+    let expected: Vec<_> = expected.split_inclusive('\n').collect();
+    let actual: Vec<_> = actual.split_inclusive('\n').collect();
+    let lines = diff(&expected, &actual);
+    let mut diff = format!("--- {expected_name}\n+++ {actual_name}\n");
+    for hunk in hunks(&lines, context) {
+        let (mut old_line, mut new_line) = (hunk.old_start, hunk.new_start);
+        let (mut old_len, mut new_len) = (0, 0);
+        let mut body = String::new();
+        for line in &lines[hunk.range] {
+            let (marker, text) = match *line {
+                Line::Same(text) => {
+                    old_len += 1;
+                    new_len += 1;
+                    (' ', text)
+                }
+                Line::Removed(text) => {
+                    old_len += 1;
+                    ('-', text)
+                }
+                Line::Added(text) => {
+                    new_len += 1;
+                    ('+', text)
+                }
+            };
+            body.push(marker);
+            body.push_str(text);
+            if !text.ends_with('\n') {
+                body.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        // An empty side starts at the line before, as in diff(1).
+        if old_len == 0 {
+            old_line -= 1;
+        }
+        if new_len == 0 {
+            new_line -= 1;
+        }
+        diff.push_str(&format!(
+            "@@ -{old_line},{old_len} +{new_line},{new_len} @@\n{body}"
+        ));
+    }
+    diff
+}
+
+/// The lines of `old` and `new` in order, as kept, removed or added, with
+/// as few removed and added as can be found.
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut lines: Vec<_> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_COMPARISONS {
+        lines.extend(old_middle.iter().map(|line| Line::Removed(line)));
+        lines.extend(new_middle.iter().map(|line| Line::Added(line)));
+    } else {
+        lines.extend(longest_common(old_middle, new_middle));
+    }
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+    lines
+}
+
+/// The diff of `old` and `new` by their longest common subsequence.
+fn longest_common<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // lengths[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..].
+    let width = new.len() + 1;
+    let mut lengths = vec![0usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len()
+            || (i < old.len() && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// The part of a diff shown under one `@@` line.
+struct Hunk {
+    range: std::ops::Range<usize>,
+    /// The lines of the old and new text that it starts at, counting from 1.
+    old_start: usize,
+    new_start: usize,
+}
+
+/// The changes in `lines` with `context` lines around them, merging those
+/// whose context would overlap.
+fn hunks(lines: &[Line], context: usize) -> Vec<Hunk> {
+    let changed: Vec<_> = (0..lines.len())
+        .filter(|&index| !matches!(lines[index], Line::Same(_)))
+        .collect();
+    let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+    for index in changed {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some(range) if start <= range.end => range.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|range| {
+            let before = &lines[..range.start];
+            let old = before
+                .iter()
+                .filter(|line| !matches!(line, Line::Added(_)))
+                .count();
+            let new = before
+                .iter()
+                .filter(|line| !matches!(line, Line::Removed(_)))
+                .count();
+            Hunk {
+                range,
+                old_start: old + 1,
+                new_start: new + 1,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(expected: &str, actual: &str) -> String {
+        unified_diff(expected, actual, "snapshot", "stdout", 1)
+    }
+
+    #[test]
+    fn the_same_text_has_no_diff() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), "");
+        assert_eq!(diff("", ""), "");
+    }
+
+    #[test]
+    fn changes_far_apart_have_hunks_of_their_own() {
+        let expected = "1\n2\n3\n4\n5\n6\n7\n";
+        let actual = "1\nTWO\n3\n4\n5\nSIX\n7\n";
+        assert_eq!(
+            diff(expected, actual),
+            "--- snapshot\n+++ stdout\n\
+             @@ -1,3 +1,3 @@\n 1\n-2\n+TWO\n 3\n\
+             @@ -5,3 +5,3 @@\n 5\n-6\n+SIX\n 7\n"
+        );
+    }
+
+    #[test]
+    fn changes_whose_context_overlaps_share_a_hunk() {
+        assert_eq!(
+            diff("1\n2\n3\n4\n", "1\nTWO\n3\nFOUR\n"),
+            "--- snapshot\n+++ stdout\n@@ -1,4 +1,4 @@\n 1\n-2\n+TWO\n 3\n-4\n+FOUR\n"
+        );
+    }
+
+    #[test]
+    fn lines_only_added_or_removed() {
+        assert_eq!(
+            diff("a\nc\n", "a\nb\nc\n"),
+            "--- snapshot\n+++ stdout\n@@ -1,2 +1,3 @@\n a\n+b\n c\n"
+        );
+        // An empty side starts at the line before it.
+        assert_eq!(
+            diff("", "new\n"),
+            "--- snapshot\n+++ stdout\n@@ -0,0 +1,1 @@\n+new\n"
+        );
+        assert_eq!(
+            diff("old\n", ""),
+            "--- snapshot\n+++ stdout\n@@ -1,1 +0,0 @@\n-old\n"
+        );
+    }
+
+    #[test]
+    fn a_missing_final_newline_is_marked() {
+        assert_eq!(
+            diff("a\n", "a"),
+            "--- snapshot\n+++ stdout\n@@ -1,1 +1,1 @@\n-a\n+a\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn the_smallest_diff_is_found() {
+        let lines = |text: &'static str| text.split_inclusive('\n').collect::<Vec<_>>();
+        let changes = super::diff(&lines("a\nb\nc\nd\n"), &lines("b\nc\nd\ne\n"));
+        assert_eq!(
+            changes,
+            [
+                Line::Removed("a\n"),
+                Line::Same("b\n"),
+                Line::Same("c\n"),
+                Line::Same("d\n"),
+                Line::Added("e\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn huge_changes_are_all_removed_then_all_added() {
+        let old: Vec<String> = (0..3000).map(|n| format!("old {n}\n")).collect();
+        let new: Vec<String> = (0..3000).map(|n| format!("new {n}\n")).collect();
+        let old: Vec<_> = old.iter().map(String::as_str).collect();
+        let new: Vec<_> = new.iter().map(String::as_str).collect();
+        let changes = super::diff(&old, &new);
+        assert_eq!(changes.len(), 6000);
+        assert!(changes[..3000]
+            .iter()
+            .all(|line| matches!(line, Line::Removed(_))));
+        assert!(changes[3000..]
+            .iter()
+            .all(|line| matches!(line, Line::Added(_))));
+    }
+}
@@ -0,0 +1,173 @@
+//! Scratch directories that jobs run in, seeded with fixture files.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A directory created fresh for a job to run in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobDir {
+    pub path: PathBuf,
+    /// Files and directories copied into the directory before the job starts.
+    pub fixtures: Vec<Fixture>,
+}
+
+/// A file or directory to copy into a [`JobDir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    pub src: PathBuf,
+    /// Where to put the copy, relative to the job's directory.
+    pub dest: PathBuf,
+}
+
+impl JobDir {
+    /// Create the directory, replacing anything left over from an earlier
+    /// run, and copy the fixtures into it.
+    pub(crate) fn prepare(&self) -> Result<(), crate::Error> {
+        recreate(&self.path)?;
+        for fixture in &self.fixtures {
+            let dest = self.path.join(&fixture.dest);
+            copy(&fixture.src, &dest).map_err(|source| crate::Error::Fixture {
+                src: fixture.src.clone(),
+                dest: fixture.dest.clone(),
+                source,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Create an empty directory at `path`, replacing anything already there.
+pub(crate) fn recreate(path: &Path) -> Result<(), crate::Error> {
+    let dir_error = |source| crate::Error::Dir {
+        path: path.to_path_buf(),
+        source,
+    };
+    if path.exists() {
+        std::fs::remove_dir_all(path).map_err(dir_error)?;
+    }
+    std::fs::create_dir_all(path).map_err(dir_error)
+}
+
+/// Copy the file or directory at `src` to `dest`, creating the parents of
+/// `dest` as needed.
+///
+/// Symbolic links are copied as links rather than followed, so that a link
+/// out of `src`, or back up to it, doesn't copy what it points to.
+pub fn copy(src: &Path, dest: &Path) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file_type = std::fs::symlink_metadata(src)?.file_type();
+    if file_type.is_symlink() {
+        return symlink(&std::fs::read_link(src)?, dest);
+    }
+    if !file_type.is_dir() {
+        return std::fs::copy(src, dest).map(drop);
+    }
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        copy(&entry.path(), &dest.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("can't copy a symbolic link to {}", target.display()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, sh};
+    use crate::{Job, Status};
+
+    #[test]
+    fn fixtures_are_in_the_jobs_directory() {
+        let scratch = scratch_dir();
+        let src = scratch.join("src");
+        std::fs::create_dir_all(src.join("tree/nested")).unwrap();
+        std::fs::write(src.join("input.txt"), "input\n").unwrap();
+        std::fs::write(src.join("tree/nested/file"), "nested\n").unwrap();
+        let dir = scratch.join("job");
+        // Something left over from an earlier run, which shouldn't be.
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stale"), "").unwrap();
+
+        let job = Job {
+            dir: Some(JobDir {
+                path: dir.clone(),
+                fixtures: vec![
+                    Fixture {
+                        src: src.join("input.txt"),
+                        dest: "input.txt".into(),
+                    },
+                    Fixture {
+                        src: src.join("tree"),
+                        dest: "data/tree".into(),
+                    },
+                ],
+            }),
+            ..sh(
+                "fixtures",
+                "test ! -e stale && cat input.txt data/tree/nested/file",
+            )
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::Passed, "{}", result.stderr);
+        assert_eq!(result.stdout, "input\nnested\n");
+
+        job.remove_dirs();
+        assert!(!dir.exists());
+        assert!(src.join("input.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_are_copied_rather_than_followed() {
+        let scratch = scratch_dir();
+        let src = scratch.join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("file"), "file\n").unwrap();
+        std::os::unix::fs::symlink("..", src.join("up")).unwrap();
+        std::os::unix::fs::symlink("/", src.join("root")).unwrap();
+
+        let dest = scratch.join("dest");
+        copy(&src, &dest).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dest.join("file")).unwrap(),
+            "file\n"
+        );
+        for (link, target) in [("up", ".."), ("root", "/")] {
+            let link = dest.join(link);
+            assert!(link.symlink_metadata().unwrap().is_symlink());
+            assert_eq!(std::fs::read_link(link).unwrap(), Path::new(target));
+        }
+    }
+
+    #[test]
+    fn missing_fixtures_are_an_error() {
+        let scratch = scratch_dir();
+        let job_dir = JobDir {
+            path: scratch.join("job"),
+            fixtures: vec![Fixture {
+                src: scratch.join("missing"),
+                dest: "missing".into(),
+            }],
+        };
+        let err = job_dir.prepare().unwrap_err();
+        assert!(
+            matches!(&err, crate::Error::Fixture { dest, .. } if dest == Path::new("missing")),
+            "{err}"
+        );
+    }
+}
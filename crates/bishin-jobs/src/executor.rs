@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+use crate::{Job, JobResult};
+
+/// Runs jobs on a fixed number of worker threads.
+#[derive(Debug, Clone)]
+pub struct Executor {
+    concurrency: usize,
+    max_failures: Option<usize>,
+}
+
+/// Something that at most `max` jobs may use at once, such as a shared
+/// database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    pub name: String,
+    pub max: usize,
+}
+
+impl Executor {
+    /// An executor that runs at most `concurrency` jobs at a time, or one
+    /// per CPU if it's 0.
+    pub fn new(concurrency: usize) -> Self {
+        let concurrency = match concurrency {
+            0 => thread::available_parallelism().map_or(1, |cpus| cpus.get()),
+            concurrency => concurrency,
+        };
+        Self {
+            concurrency,
+            max_failures: None,
+        }
+    }
+
+    /// Stop starting jobs once `max_failures` of them have failed, or never
+    /// if it's `None`. Jobs that are already running are left to finish.
+    pub fn max_failures(mut self, max_failures: Option<usize>) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Run every job, calling `on_result` with each job's index as it finishes.
+    ///
+    /// The returned results are in the same order as `jobs`, regardless of
+    /// the order in which they completed. Jobs that fail to start produce a
+    /// result with [`Status::Error`](crate::Status::Error). Jobs that never
+    /// started because of [`Executor::max_failures`] have no result.
+    ///
+    /// Jobs start in order, except that one whose [`Resource`]s are all in
+    /// use waits for them while the jobs after it start. Where jobs give a
+    /// resource different maximums, the smallest is used.
+    pub fn run<F>(&self, jobs: &[Job], mut on_result: F) -> Vec<JobResult>
+    where
+        F: FnMut(usize, &JobResult),
+    {
+        let schedule = Mutex::new(Schedule::new(jobs));
+        let changed = Condvar::new();
+        let failures = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let (tx, rx) = mpsc::channel();
+        let mut results: Vec<Option<JobResult>> =
+            std::iter::repeat_with(|| None).take(jobs.len()).collect();
+
+        thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(jobs.len()) {
+                let tx = tx.clone();
+                let (schedule, changed) = (&schedule, &changed);
+                let failures = &failures;
+                let stop = &stop;
+                scope.spawn(move || {
+                    while let Some(index) = next_job(schedule, changed, stop) {
+                        let job = &jobs[index];
+                        let result = job
+                            .run()
+                            .unwrap_or_else(|err| JobResult::from_error(&job.name, &err));
+                        // Counted here rather than as results arrive, so that
+                        // this worker doesn't start another job in the meantime.
+                        if result.status.is_failure() {
+                            let failed = failures.fetch_add(1, Ordering::Relaxed) + 1;
+                            if self.max_failures.is_some_and(|max| failed >= max) {
+                                stop.store(true, Ordering::Relaxed);
+                            }
+                        }
+                        schedule
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .release(job);
+                        changed.notify_all();
+                        if tx.send((index, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+            for (index, result) in rx {
+                on_result(index, &result);
+                results[index] = Some(result);
+            }
+        });
+
+        results.into_iter().flatten().collect()
+    }
+}
+
+/// The index of the next job to run, waiting for its resources if need be,
+/// or `None` once there are none left to start.
+fn next_job(schedule: &Mutex<Schedule>, changed: &Condvar, stop: &AtomicBool) -> Option<usize> {
+    let mut schedule = schedule
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    loop {
+        if stop.load(Ordering::Relaxed) || schedule.pending.is_empty() {
+            return None;
+        }
+        if let Some(index) = schedule.take() {
+            return Some(index);
+        }
+        schedule = changed
+            .wait(schedule)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+}
+
+/// The jobs that haven't started yet, and the resources those that are
+/// running use.
+struct Schedule<'a> {
+    jobs: &'a [Job],
+    pending: Vec<usize>,
+    limits: HashMap<&'a str, usize>,
+    in_use: HashMap<&'a str, usize>,
+}
+
+impl<'a> Schedule<'a> {
+    fn new(jobs: &'a [Job]) -> Self {
+        let mut limits = HashMap::new();
+        for resource in jobs.iter().flat_map(|job| &job.resources) {
+            let limit = limits.entry(resource.name.as_str()).or_insert(usize::MAX);
+            *limit = resource.max.max(1).min(*limit);
+        }
+        Self {
+            jobs,
+            pending: (0..jobs.len()).collect(),
+            limits,
+            in_use: HashMap::new(),
+        }
+    }
+
+    /// Take the first pending job whose resources aren't all in use, and
+    /// mark them as used by it.
+    fn take(&mut self) -> Option<usize> {
+        let position = self.pending.iter().position(|index| {
+            self.jobs[*index].resources.iter().all(|resource| {
+                let name = resource.name.as_str();
+                self.in_use.get(name).copied().unwrap_or(0) < self.limits[name]
+            })
+        })?;
+        let index = self.pending.remove(position);
+        for resource in &self.jobs[index].resources {
+            *self.in_use.entry(resource.name.as_str()).or_default() += 1;
+        }
+        Some(index)
+    }
+
+    fn release(&mut self, job: &Job) {
+        for resource in &job.resources {
+            if let Some(count) = self.in_use.get_mut(resource.name.as_str()) {
+                *count -= 1;
+            }
+        }
+    }
+}
+
+/// Run every job with at most `concurrency` running at once, or one per CPU
+/// if it's 0, returning their results in the same order as `jobs`.
+pub fn run_jobs(jobs: &[Job], concurrency: usize) -> Vec<JobResult> {
+    Executor::new(concurrency).run(jobs, |_, _| {})
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::testing::{scratch_dir, sh};
+    use crate::Status;
+
+    #[test]
+    fn results_are_in_the_order_of_the_jobs() {
+        let jobs: Vec<_> = (0..8)
+            .map(|index| sh(&format!("t{index}"), &format!("sleep 0.0{}", 8 - index)))
+            .collect();
+        let results = run_jobs(&jobs, 4);
+        let names: Vec<_> = results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(names, ["t0", "t1", "t2", "t3", "t4", "t5", "t6", "t7"]);
+        assert!(results.iter().all(|result| result.status == Status::Passed));
+    }
+
+    #[test]
+    fn zero_concurrency_is_one_job_per_cpu() {
+        let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        assert_eq!(Executor::new(0).concurrency, cpus);
+        assert_eq!(Executor::new(3).concurrency, 3);
+    }
+
+    #[test]
+    fn no_more_jobs_run_at_once_than_the_concurrency() {
+        let sleep = Duration::from_millis(200);
+        let jobs: Vec<_> = (0..10)
+            .map(|index| sh(&format!("t{index}"), "sleep 0.2"))
+            .collect();
+        let start = Instant::now();
+        let results = run_jobs(&jobs, 2);
+        let elapsed = start.elapsed();
+        assert_eq!(results.len(), 10);
+        // Five rounds of two, rather than one round of ten or ten of one.
+        assert!(elapsed >= sleep * 5, "{elapsed:?}");
+        assert!(elapsed < sleep * 9, "{elapsed:?}");
+    }
+
+    #[test]
+    fn every_result_is_passed_on_as_its_job_finishes() {
+        let jobs: Vec<_> = (0..5)
+            .map(|index| sh(&format!("t{index}"), "true"))
+            .collect();
+        let mut seen = Vec::new();
+        Executor::new(3).run(&jobs, |index, result| {
+            assert_eq!(result.name, jobs[index].name);
+            seen.push(index);
+        });
+        seen.sort_unstable();
+        assert_eq!(seen, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn jobs_that_fail_to_start_have_an_error_result() {
+        let missing = Job {
+            args: vec!["bishin-no-such-program".to_string()],
+            ..sh("missing", "")
+        };
+        let results = run_jobs(&[missing, sh("fine", "true")], 2);
+        assert_eq!(results[0].status, Status::Error);
+        assert!(
+            results[0]
+                .stderr
+                .contains("failed to spawn bishin-no-such-program"),
+            "{}",
+            results[0].stderr
+        );
+        assert_eq!(results[1].status, Status::Passed);
+    }
+
+    #[test]
+    fn no_jobs_start_once_enough_have_failed() {
+        let jobs: Vec<_> = (0..6)
+            .map(|index| sh(&format!("t{index}"), "false"))
+            .collect();
+        let results = Executor::new(1).max_failures(Some(2)).run(&jobs, |_, _| {});
+        let names: Vec<_> = results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(names, ["t0", "t1"]);
+        assert_eq!(Executor::new(1).run(&jobs, |_, _| {}).len(), 6);
+    }
+
+    fn using(job: Job, name: &str, max: usize) -> Job {
+        Job {
+            resources: vec![Resource {
+                name: name.to_string(),
+                max,
+            }],
+            ..job
+        }
+    }
+
+    /// A job that fails if another job holding the lock at `lock` is
+    /// running at the same time.
+    fn exclusive(name: &str, lock: &std::path::Path) -> Job {
+        let lock = lock.display();
+        sh(
+            name,
+            &format!("mkdir '{lock}' || exit 1; sleep 0.05; rmdir '{lock}'"),
+        )
+    }
+
+    /// A job that only passes if all `count` jobs waiting in `dir` run at
+    /// the same time.
+    fn together(name: &str, dir: &std::path::Path, count: usize) -> Job {
+        let dir = dir.display();
+        sh(
+            name,
+            &format!(
+                "touch '{dir}/{name}'; i=0; \
+                 while [ \"$(ls '{dir}' | wc -l)\" -lt {count} ]; do \
+                 i=$((i + 1)); [ $i -lt 200 ] || exit 1; sleep 0.02; done"
+            ),
+        )
+    }
+
+    #[test]
+    fn jobs_of_a_resource_with_one_slot_never_overlap() {
+        let dir = scratch_dir();
+        let lock = dir.join("db.lock");
+        let barrier = dir.join("free");
+        std::fs::create_dir(&barrier).unwrap();
+        let mut jobs = Vec::new();
+        for index in 0..4 {
+            jobs.push(using(exclusive(&format!("db{index}"), &lock), "db", 1));
+            jobs.push(together(&format!("free{index}"), &barrier, 4));
+        }
+        let results = run_jobs(&jobs, 8);
+        for result in &results {
+            assert_eq!(
+                result.status,
+                Status::Passed,
+                "{}: {}",
+                result.name,
+                result.stderr
+            );
+        }
+    }
+
+    #[test]
+    fn resources_with_more_slots_run_that_many_at_once() {
+        let dir = scratch_dir();
+        let jobs: Vec<_> = (0..3)
+            .map(|index| using(together(&format!("t{index}"), &dir, 3), "license", 3))
+            .collect();
+        let results = run_jobs(&jobs, 3);
+        assert!(results.iter().all(|result| result.status == Status::Passed));
+    }
+
+    #[test]
+    fn the_smallest_max_of_a_resource_is_used() {
+        let scratch = scratch_dir();
+        let lock = scratch.join("db.lock");
+        let jobs = [
+            using(exclusive("a", &lock), "db", 2),
+            using(exclusive("b", &lock), "db", 1),
+            using(exclusive("c", &lock), "db", 2),
+        ];
+        let results = run_jobs(&jobs, 3);
+        assert!(results.iter().all(|result| result.status == Status::Passed));
+    }
+
+    #[test]
+    fn jobs_waiting_for_a_resource_let_later_ones_start() {
+        let jobs = [
+            using(sh("a", ""), "db", 1),
+            using(sh("b", ""), "db", 1),
+            sh("c", ""),
+        ];
+        let mut schedule = Schedule::new(&jobs);
+        assert_eq!(schedule.take(), Some(0));
+        assert_eq!(schedule.take(), Some(2));
+        assert_eq!(schedule.take(), None);
+        schedule.release(&jobs[0]);
+        assert_eq!(schedule.take(), Some(1));
+        assert!(schedule.pending.is_empty());
+    }
+}
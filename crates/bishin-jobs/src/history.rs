@@ -0,0 +1,421 @@
+//! A record of past runs, for looking at how a test has done over time and
+//! which tests flip between passing and failing.
+//!
+//! The history is a text file with a line per run, oldest first:
+//!
+//! ```text
+//! v1 1760400000-4242 1760400000 3f2a9c1 net::dns::resolves=passed:12 net::dns::times_out=timed-out:5000
+//! ```
+//!
+//! That's the version of the format, the run's id, when it started in
+//! seconds since the Unix epoch, the commit it ran at or `-`, and then each
+//! test's name, status and duration in milliseconds. Whitespace, `%` and `=`
+//! in names are percent-encoded. Lines that can't be read, such as a line cut
+//! short by a crash or one from a later version, are passed over.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{RunSummary, Status};
+
+/// The version of the format that lines are written in.
+pub const HISTORY_VERSION: u32 = 1;
+
+/// How many runs a history keeps unless asked to keep some other number.
+pub const DEFAULT_HISTORY_RUNS: usize = 200;
+
+/// A run as the history remembers it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunRecord {
+    pub id: String,
+    /// When the run started, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The commit that was checked out, if the tests are in a git repository.
+    pub git_sha: Option<String>,
+    pub tests: Vec<TestRecord>,
+}
+
+/// How a test did in a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestRecord {
+    pub name: String,
+    pub status: Status,
+    pub duration: Duration,
+}
+
+impl RunRecord {
+    /// The record of a run with the results in `summary`.
+    pub fn new(
+        id: impl Into<String>,
+        timestamp: u64,
+        git_sha: Option<String>,
+        summary: &RunSummary,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            timestamp,
+            git_sha,
+            tests: summary
+                .results
+                .iter()
+                .map(|result| TestRecord {
+                    name: result.name.clone(),
+                    status: result.status,
+                    duration: result.duration,
+                })
+                .collect(),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        let mut line = format!(
+            "v{HISTORY_VERSION} {} {} {}",
+            encode(&self.id),
+            self.timestamp,
+            self.git_sha.as_deref().map_or("-".to_string(), encode)
+        );
+        for test in &self.tests {
+            line.push_str(&format!(
+                " {}={}:{}",
+                encode(&test.name),
+                test.status.name(),
+                test.duration.as_millis()
+            ));
+        }
+        line
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split(' ');
+        if fields.next()? != format!("v{HISTORY_VERSION}") {
+            return None;
+        }
+        let id = decode(fields.next()?)?;
+        let timestamp = fields.next()?.parse().ok()?;
+        let git_sha = match fields.next()? {
+            "-" => None,
+            sha => Some(decode(sha)?),
+        };
+        let tests = fields
+            .map(|field| {
+                let (name, outcome) = field.rsplit_once('=')?;
+                let (status, milliseconds) = outcome.split_once(':')?;
+                Some(TestRecord {
+                    name: decode(name)?,
+                    status: Status::from_name(status)?,
+                    duration: Duration::from_millis(milliseconds.parse().ok()?),
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            id,
+            timestamp,
+            git_sha,
+            tests,
+        })
+    }
+}
+
+/// The runs in a history file, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct History {
+    pub runs: Vec<RunRecord>,
+}
+
+/// How often a test flipped between passing and failing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flakiness<'a> {
+    pub name: &'a str,
+    /// How many times it passed in a run after failing in the one before,
+    /// or the other way around.
+    pub flips: usize,
+    /// How many of the runs it passed or failed in.
+    pub runs: usize,
+}
+
+impl History {
+    /// Read the history at `path`, which is empty if there's no such file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        // A line with bytes that aren't UTF-8 is passed over like any other
+        // that can't be read, rather than taking the rest with it.
+        let runs = contents
+            .split(|&byte| byte == b'\n')
+            .filter_map(|line| std::str::from_utf8(line).ok())
+            .filter_map(RunRecord::from_line)
+            .collect();
+        Ok(Self { runs })
+    }
+
+    /// Add `run` to the history at `path`, dropping the oldest runs so that
+    /// at most `keep` are left.
+    ///
+    /// The file is replaced rather than appended to, so that a run that's
+    /// interrupted while writing it leaves the old history.
+    pub fn append(path: &Path, run: &RunRecord, keep: usize) -> io::Result<()> {
+        let mut history = Self::load(path)?;
+        history.runs.push(run.clone());
+        let excess = history.runs.len().saturating_sub(keep);
+        history.runs.drain(..excess);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let contents: String = history
+            .runs
+            .iter()
+            .map(|run| run.to_line() + "\n")
+            .collect();
+        std::fs::write(&partial, contents)?;
+        std::fs::rename(&partial, path)
+    }
+
+    /// How the test called `name` did in each run it was in, oldest first.
+    pub fn test<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = (&'a RunRecord, &'a TestRecord)> + 'a {
+        self.runs.iter().filter_map(move |run| {
+            let test = run.tests.iter().find(|test| test.name == name)?;
+            Some((run, test))
+        })
+    }
+
+    /// How long the test called `name` took on average in the runs that it
+    /// wasn't skipped in, or `None` if there are none.
+    pub fn mean_duration(&self, name: &str) -> Option<Duration> {
+        let durations: Vec<_> = self
+            .test(name)
+            .filter(|(_, test)| test.status != Status::Skipped)
+            .map(|(_, test)| test.duration)
+            .collect();
+        match durations.len() {
+            0 => None,
+            n => Some(durations.iter().sum::<Duration>() / n as u32),
+        }
+    }
+
+    /// The tests that flipped between passing and failing in the last `last`
+    /// runs, the ones that flipped most often first.
+    pub fn flaky(&self, last: usize) -> Vec<Flakiness<'_>> {
+        let start = self.runs.len().saturating_sub(last);
+        let mut outcomes: BTreeMap<&str, Vec<bool>> = BTreeMap::new();
+        for run in &self.runs[start..] {
+            for test in &run.tests {
+                // Skips and expected failures say nothing either way.
+                let passed = match test.status {
+                    Status::Passed => true,
+                    status if status.is_failure() => false,
+                    _ => continue,
+                };
+                outcomes.entry(&test.name).or_default().push(passed);
+            }
+        }
+        let mut flaky: Vec<_> = outcomes
+            .into_iter()
+            .map(|(name, outcomes)| Flakiness {
+                name,
+                flips: outcomes
+                    .windows(2)
+                    .filter(|pair| pair[0] != pair[1])
+                    .count(),
+                runs: outcomes.len(),
+            })
+            .filter(|flakiness| flakiness.flips > 0)
+            .collect();
+        flaky.sort_by(|a, b| b.flips.cmp(&a.flips).then_with(|| a.name.cmp(b.name)));
+        flaky
+    }
+}
+
+/// `value` with `%`, whitespace and the other characters that separate
+/// fields percent-encoded.
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '%' || c == '=' || c.is_whitespace() || c.is_control() {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{byte:02X}"));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+fn decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch_dir;
+
+    fn run(id: &str, tests: &[(&str, Status, u64)]) -> RunRecord {
+        RunRecord {
+            id: id.to_string(),
+            timestamp: 1_760_400_000,
+            git_sha: None,
+            tests: tests
+                .iter()
+                .map(|&(name, status, milliseconds)| TestRecord {
+                    name: name.to_string(),
+                    status,
+                    duration: Duration::from_millis(milliseconds),
+                })
+                .collect(),
+        }
+    }
+
+    fn history(runs: &[&[(&str, Status)]]) -> History {
+        History {
+            runs: runs
+                .iter()
+                .enumerate()
+                .map(|(i, tests)| {
+                    let tests: Vec<_> = tests
+                        .iter()
+                        .map(|&(name, status)| (name, status, 1))
+                        .collect();
+                    run(&i.to_string(), &tests)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn records_read_back_from_their_lines() {
+        let mut record = run(
+            "1760400000-42",
+            &[
+                ("net::dns::resolves", Status::Passed, 12),
+                ("odd name=100%\ttabbed ✓", Status::TimedOut, 5000),
+                ("", Status::XFail, 0),
+            ],
+        );
+        record.git_sha = Some("3f2a9c1".to_string());
+        let line = record.to_line();
+        assert_eq!(
+            line,
+            "v1 1760400000-42 1760400000 3f2a9c1 net::dns::resolves=passed:12 \
+             odd%20name%3D100%25%09tabbed%20✓=timed-out:5000 =xfail:0"
+        );
+        assert_eq!(RunRecord::from_line(&line), Some(record));
+        let empty = run("id", &[]);
+        assert_eq!(empty.to_line(), "v1 id 1760400000 -");
+        assert_eq!(RunRecord::from_line(&empty.to_line()), Some(empty));
+    }
+
+    #[test]
+    fn lines_that_cant_be_read_are_passed_over() {
+        let good = run("good", &[("a", Status::Passed, 1)]).to_line();
+        for line in [
+            "",
+            "v2 id 1760400000 - a=passed:1",
+            "v1 id",
+            "v1 id soon - a=passed:1",
+            "v1 id 1760400000 - a=sort-of:1",
+            "v1 id 1760400000 - a=passed:",
+            "v1 id 1760400000 - a=passed",
+            "v1 id 1760400000 - a%2=passed:1",
+        ] {
+            assert_eq!(RunRecord::from_line(line), None, "{line:?}");
+        }
+        let scratch = scratch_dir();
+        let path = scratch.join("history");
+        let mut contents = format!("{good}\nv1 cut 17604").into_bytes();
+        contents.extend(b"\nv1 \xff\xfe 1 -\n");
+        contents.extend(format!("{good}\n").into_bytes());
+        std::fs::write(&path, contents).unwrap();
+        let history = History::load(&path).unwrap();
+        assert_eq!(history.runs.len(), 2);
+        assert!(history.runs.iter().all(|run| run.id == "good"));
+    }
+
+    #[test]
+    fn appending_keeps_only_the_latest_runs() {
+        let scratch = scratch_dir();
+        let path = scratch.join("nested/history");
+        assert_eq!(History::load(&path).unwrap(), History::default());
+        for i in 0..5 {
+            History::append(&path, &run(&i.to_string(), &[]), 3).unwrap();
+        }
+        let ids: Vec<_> = History::load(&path)
+            .unwrap()
+            .runs
+            .into_iter()
+            .map(|run| run.id)
+            .collect();
+        assert_eq!(ids, ["2", "3", "4"]);
+        assert!(!path.with_file_name("history.partial").exists());
+    }
+
+    #[test]
+    fn a_tests_runs_and_mean_duration() {
+        let history = History {
+            runs: vec![
+                run("0", &[("a", Status::Passed, 10), ("b", Status::Passed, 1)]),
+                run("1", &[("b", Status::Passed, 1)]),
+                run("2", &[("a", Status::Skipped, 0)]),
+                run("3", &[("a", Status::Failed, 30)]),
+            ],
+        };
+        let ids: Vec<_> = history.test("a").map(|(run, _)| run.id.as_str()).collect();
+        assert_eq!(ids, ["0", "2", "3"]);
+        // The skip doesn't bring the mean down.
+        assert_eq!(history.mean_duration("a"), Some(Duration::from_millis(20)));
+        assert_eq!(history.mean_duration("c"), None);
+    }
+
+    #[test]
+    fn the_tests_that_flip_most_are_the_flakiest() {
+        use Status::*;
+        let history = history(&[
+            &[("a", Passed), ("b", Passed), ("c", Passed), ("d", Failed)],
+            &[("a", Failed), ("b", Passed), ("c", XFail), ("d", Failed)],
+            &[
+                ("a", Passed),
+                ("b", Failed),
+                ("c", Skipped),
+                ("d", TimedOut),
+            ],
+            &[("a", Failed), ("b", Failed), ("c", Failed), ("d", Passed)],
+        ]);
+        let flaky: Vec<_> = history
+            .flaky(50)
+            .into_iter()
+            .map(|flakiness| (flakiness.name, flakiness.flips, flakiness.runs))
+            .collect();
+        assert_eq!(flaky, [("a", 3, 4), ("b", 1, 4), ("c", 1, 2), ("d", 1, 4)]);
+        let recent: Vec<_> = history
+            .flaky(2)
+            .into_iter()
+            .map(|flakiness| (flakiness.name, flakiness.flips))
+            .collect();
+        assert_eq!(recent, [("a", 1), ("d", 1)]);
+        assert_eq!(history.flaky(0), []);
+    }
+}
@@ -0,0 +1,1340 @@
+//! Jobs are the processes bishin runs to execute tests.
+//!
+//! Jobs run on this host unless [`Job::ssh`] names another. Nothing in the
+//! config or on bishin's command line sets it yet, so [`SshBackend`] is only
+//! for programs that build their jobs with this crate.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+mod bench;
+mod diff;
+pub mod dir;
+mod executor;
+mod history;
+mod retry;
+mod snapshot;
+mod ssh;
+mod summary;
+#[cfg(test)]
+mod testing;
+#[cfg(unix)]
+mod tty;
+mod usage;
+
+pub use bench::{Bench, BenchStats};
+pub use diff::{unified_diff, DIFF_CONTEXT};
+pub use dir::{Fixture, JobDir};
+pub use executor::{run_jobs, Executor, Resource};
+pub use history::{
+    Flakiness, History, RunRecord, TestRecord, DEFAULT_HISTORY_RUNS, HISTORY_VERSION,
+};
+pub use retry::{RetryOn, RetryPolicy};
+pub use snapshot::{Snapshot, SnapshotUpdate};
+pub use ssh::SshBackend;
+pub use summary::RunSummary;
+pub use usage::ResourceUsage;
+
+/// The exit code a job uses to say that it skipped itself, as with
+/// automake's test harness. The last line of its stderr, if any, says why.
+pub const SKIP_EXIT_CODE: i32 = 77;
+
+/// How long a job that ran past its timeout has to exit once it's asked to,
+/// before it's killed.
+pub const TERMINATE_GRACE: Duration = Duration::from_secs(2);
+
+/// The variable holding the path of a job's [`Job::tmp_dir`].
+pub const TMPDIR_ENV: &str = "BISHIN_TMPDIR";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("job {0} has no program to run")]
+    NoProgram(String),
+    #[error("failed to spawn {program}")]
+    Spawn {
+        program: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to wait for {0}")]
+    Wait(String, #[source] std::io::Error),
+    #[error("failed to open a terminal for job {0}")]
+    Tty(String, #[source] std::io::Error),
+    #[error("failed to read or write snapshot {}", path.display())]
+    Snapshot {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to set up the job directory {}", path.display())]
+    Dir {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to open {} as the stdin of job {job}", path.display())]
+    Stdin {
+        job: String,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("job {0} runs over SSH, which this build of bishin-jobs leaves out")]
+    NoSsh(String),
+    #[error("failed to copy fixture {} to {}", src.display(), dest.display())]
+    Fixture {
+        src: PathBuf,
+        dest: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// A command to run, along with the environment to run it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// A human readable name for the job, e.g. the test it runs.
+    pub name: String,
+    /// The program to run followed by its arguments.
+    pub args: Vec<String>,
+    /// Environment variables set on top of the inherited environment.
+    pub envs: HashMap<String, String>,
+    /// If set, the job inherits only these variables from bishin's
+    /// environment instead of all of them. Its own `envs` are set either way.
+    pub clean_env: Option<Vec<String>>,
+    /// How long the job may run before it's stopped, with `SIGTERM` and
+    /// then, if it's still running [`TERMINATE_GRACE`] later, `SIGKILL`.
+    pub timeout: Option<Duration>,
+    /// A directory to run the job in, or `None` to run it in the current
+    /// directory.
+    pub dir: Option<JobDir>,
+    /// A scratch directory for the job, created empty before each attempt
+    /// and passed to it in [`TMPDIR_ENV`]. It may be the same as `dir`.
+    pub tmp_dir: Option<PathBuf>,
+    /// When to run the job again if it fails, or `None` to never do so.
+    pub retry: Option<RetryPolicy>,
+    /// The snapshot that the job's stdout must match for it to pass, if any.
+    pub snapshot: Option<Snapshot>,
+    /// Whether the job is expected to fail, which turns its failures into
+    /// [`Status::XFail`] and its passes into [`Status::XPass`].
+    pub xfail: Option<XFail>,
+    /// Whether to report the job as skipped without running it.
+    pub skip: Option<Skip>,
+    /// The exit code the job passes with, or `None` for 0.
+    pub expected_exit: Option<ExpectedExit>,
+    /// Whether to run the job as a benchmark, timing several runs of it
+    /// instead of retrying it.
+    pub bench: Option<Bench>,
+    /// Whether to measure the CPU time and memory the job uses, which is
+    /// only done on Unix.
+    pub resource_stats: bool,
+    /// A file to stream to the job's stdin, which is empty otherwise.
+    pub stdin: Option<PathBuf>,
+    /// The host to run the job on instead of this one, which needs the
+    /// `backend-ssh` feature. Only callers of this crate set it; the tests
+    /// that bishin generates always run locally.
+    pub ssh: Option<SshBackend>,
+    /// The resources that the job uses, which [`Executor`] runs no more
+    /// jobs at once with than each allows.
+    pub resources: Vec<Resource>,
+    /// What to do about processes that the job leaves running in its
+    /// process group, or `None` to leave them be, such as for a script that
+    /// starts services. Only checked on Unix.
+    pub leak_check: Option<LeakCheck>,
+    /// Whether the job writes its stdout and stderr to a pseudo-terminal,
+    /// which bishin reads as its stdout, instead of to pipes. Only done on
+    /// Unix, and not over SSH.
+    pub tty: bool,
+    /// Whether to skip the job rather than fail it if its program isn't
+    /// installed, as for a test in a shell that this host doesn't have. Not
+    /// checked over SSH, where the program is looked for on the other host.
+    pub skip_if_missing: bool,
+}
+
+/// What to do about a job that leaves processes running once it exits,
+/// which are killed either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeakCheck {
+    /// Only say so, see [`JobResult::leaked_processes`].
+    Warn,
+    /// Fail the job if it would have passed.
+    Fail,
+}
+
+/// A job that's skipped without being run, and why.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Skip {
+    pub reason: Option<String>,
+}
+
+/// The exit code a job passes with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedExit {
+    Code(i32),
+    /// Any code but 0.
+    NonZero,
+}
+
+impl ExpectedExit {
+    /// Whether a job that exited with `code` passed.
+    pub fn matches(self, code: i32) -> bool {
+        match self {
+            ExpectedExit::Code(expected) => code == expected,
+            ExpectedExit::NonZero => code != 0,
+        }
+    }
+}
+
+impl fmt::Display for ExpectedExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectedExit::Code(code) => write!(f, "{code}"),
+            ExpectedExit::NonZero => f.write_str("nonzero"),
+        }
+    }
+}
+
+/// A job that's expected to fail, and why.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XFail {
+    pub reason: Option<String>,
+}
+
+impl Job {
+    /// Run the job to completion, capturing its output.
+    ///
+    /// If the job has a timeout and runs past it, the job and everything it
+    /// started are killed and the result is [`Status::TimedOut`].
+    ///
+    /// A job with a [`JobDir`] runs in it. It and the job's `tmp_dir` are
+    /// left behind for the caller to look at, see [`Job::remove_dirs`].
+    ///
+    /// A job with a [`RetryPolicy`] is run again for as long as it fails in
+    /// a way the policy retries, and the last attempt is returned.
+    ///
+    /// A job with an [`XFail`] has its result turned around once it's done.
+    ///
+    /// A job with a [`Bench`] is run as many times as it says instead, see
+    /// [`JobResult::bench`].
+    pub fn run(&self) -> Result<JobResult, Error> {
+        if let Some(skip) = &self.skip {
+            return Ok(JobResult::skipped(&self.name, skip));
+        }
+        if let Some(result) = self.missing_program() {
+            return Ok(result);
+        }
+        if let Some(bench) = &self.bench {
+            return bench::run(self, bench);
+        }
+        let retries = self.retry.as_ref().map_or(0, |retry| retry.count);
+        let mut attempts = 1;
+        loop {
+            let mut result = self.run_once()?;
+            result.attempts = attempts;
+            let retry = self
+                .retry
+                .as_ref()
+                .is_some_and(|retry| retry.retries(&result));
+            if !retry || attempts > retries {
+                if let Some(xfail) = &self.xfail {
+                    result.status = match result.status {
+                        Status::Failed | Status::TimedOut => Status::XFail,
+                        Status::Passed => Status::XPass,
+                        status => status,
+                    };
+                    result.xfail_reason = xfail.reason.clone();
+                }
+                return Ok(result);
+            }
+            attempts += 1;
+        }
+    }
+
+    /// The environment the job's process starts with: the inherited
+    /// environment, or the allowed part of it, with the job's own variables
+    /// over the top.
+    pub fn environment(&self) -> BTreeMap<String, String> {
+        let mut environment: BTreeMap<String, String> = std::env::vars()
+            .filter(|(key, _)| self.inherits(key))
+            .collect();
+        environment.extend(self.envs.clone());
+        if let Some(tmp_dir) = &self.tmp_dir {
+            environment.insert(
+                TMPDIR_ENV.to_string(),
+                tmp_dir.to_string_lossy().into_owned(),
+            );
+        }
+        environment
+    }
+
+    /// Whether the job's process starts with the variable `key` from bishin's
+    /// environment, if it's set there.
+    fn inherits(&self, key: &str) -> bool {
+        self.clean_env
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|allowed| allowed == key))
+    }
+
+    /// Remove the directories the job ran with, once whatever is in them is
+    /// no longer needed.
+    pub fn remove_dirs(&self) {
+        // A directory that can't be removed is replaced on the next run
+        // anyway, so it's not worth failing over.
+        if let Some(dir) = &self.dir {
+            let _ = std::fs::remove_dir_all(&dir.path);
+        }
+        if let Some(tmp_dir) = &self.tmp_dir {
+            let _ = std::fs::remove_dir_all(tmp_dir);
+        }
+    }
+
+    /// A skipped result for the job if it's to be skipped when its program
+    /// isn't installed, and it isn't.
+    fn missing_program(&self) -> Option<JobResult> {
+        if !self.skip_if_missing || self.ssh.is_some() {
+            return None;
+        }
+        let program = self.args.first()?;
+        let path = match self.envs.get("PATH") {
+            Some(path) => Some(OsString::from(path)),
+            None => std::env::var_os("PATH"),
+        };
+        if is_installed(program, path.as_deref()) {
+            return None;
+        }
+        Some(JobResult {
+            status: Status::Skipped,
+            skipped_by: Some(SkipReason::MissingShell),
+            skip_reason: Some(format!("{program} isn't installed")),
+            ..JobResult::error(&self.name, "")
+        })
+    }
+
+    fn run_once(&self) -> Result<JobResult, Error> {
+        if let Some(tmp_dir) = &self.tmp_dir {
+            dir::recreate(tmp_dir)?;
+        }
+        if let Some(dir) = &self.dir {
+            dir.prepare()?;
+        }
+        let mut result = self.run_in(self.dir.as_ref())?;
+        if let Some(snapshot) = &self.snapshot {
+            snapshot.check(&mut result)?;
+        }
+        Ok(result)
+    }
+
+    fn run_in(&self, dir: Option<&JobDir>) -> Result<JobResult, Error> {
+        if let Some(backend) = &self.ssh {
+            #[cfg(feature = "backend-ssh")]
+            return ssh::run(self, backend, dir);
+            #[cfg(not(feature = "backend-ssh"))]
+            {
+                let _ = backend;
+                return Err(Error::NoSsh(self.name.clone()));
+            }
+        }
+        let (program, args) = self
+            .args
+            .split_first()
+            .ok_or_else(|| Error::NoProgram(self.name.clone()))?;
+        let mut command = Command::new(program);
+        if self.clean_env.is_some() {
+            command.env_clear().envs(
+                std::env::vars_os()
+                    .filter(|(key, _)| key.to_str().is_some_and(|key| self.inherits(key))),
+            );
+        }
+        command
+            .args(args)
+            .envs(&self.envs)
+            .envs(self.tmp_dir.iter().map(|tmp_dir| (TMPDIR_ENV, tmp_dir)))
+            .stdin(self.stdin_stdio()?);
+        let terminal = self.output_stdio(&mut command)?;
+        if let Some(dir) = dir {
+            command.current_dir(&dir.path);
+        }
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        self.capture(program, command, terminal, self.resource_stats, kill)
+    }
+
+    /// Point the command's stdout and stderr at a new terminal if the job
+    /// wants one, returning the controller to read them from, or at pipes
+    /// otherwise.
+    fn output_stdio(&self, command: &mut Command) -> Result<Option<File>, Error> {
+        #[cfg(unix)]
+        if self.tty {
+            let tty_error = |source| Error::Tty(self.name.clone(), source);
+            let (controller, terminal) = tty::open().map_err(tty_error)?;
+            command
+                .stdout(terminal.try_clone().map_err(tty_error)?)
+                .stderr(terminal);
+            return Ok(Some(controller));
+        }
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        Ok(None)
+    }
+
+    /// What the job's process reads its stdin from.
+    fn stdin_stdio(&self) -> Result<Stdio, Error> {
+        let Some(path) = &self.stdin else {
+            return Ok(Stdio::null());
+        };
+        let file = std::fs::File::open(path).map_err(|source| Error::Stdin {
+            job: self.name.clone(),
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Stdio::from(file))
+    }
+
+    /// Run `command`, which runs `program` for the job, and wait for it,
+    /// stopping it with `kill` if it runs past the job's timeout.
+    fn capture(
+        &self,
+        program: &str,
+        mut command: Command,
+        terminal: Option<File>,
+        measure: bool,
+        kill: impl FnOnce(&mut Child),
+    ) -> Result<JobResult, Error> {
+        let start = Instant::now();
+        let mut child = command.spawn().map_err(|source| Error::Spawn {
+            program: program.to_string(),
+            source,
+        })?;
+        // The command holds the job's end of the terminal, which has to be
+        // closed for reading the other end to finish.
+        drop(command);
+        // Set once the job has exited and whatever it left running that's
+        // going to be killed has been, since what's left holds the pipes
+        // open as long as it runs.
+        let exited = Arc::new(AtomicBool::new(false));
+        let stdout = match terminal {
+            Some(terminal) => read_output(Some(terminal), &exited),
+            None => read_output(child.stdout.take(), &exited),
+        };
+        let stderr = read_output(child.stderr.take(), &exited);
+        let wait_error = |err| Error::Wait(self.name.clone(), err);
+        let exit = match self.timeout {
+            Some(timeout) => wait_with_timeout(&mut child, timeout, measure),
+            None => wait(&mut child, measure).map(Some),
+        }
+        .map_err(wait_error)?;
+        let timed_out = exit.is_none();
+        let (status, usage) = match exit {
+            Some(exit) => exit,
+            None => {
+                // Asked to stop first, so that it can clean up after itself.
+                // Over SSH, there's only `kill` to stop it with.
+                let stopped = match self.ssh {
+                    None => {
+                        terminate(&child);
+                        wait_with_timeout(&mut child, TERMINATE_GRACE, measure)
+                            .map_err(wait_error)?
+                    }
+                    Some(_) => None,
+                };
+                match stopped {
+                    Some(exit) => exit,
+                    None => {
+                        kill(&mut child);
+                        wait(&mut child, measure).map_err(wait_error)?
+                    }
+                }
+            }
+        };
+        let duration = start.elapsed();
+        // Killed before the output is read, since they may hold its pipes
+        // open. What's left of a job that timed out is killed without
+        // counting as leaked, since it was stopped before it could finish.
+        let leftovers =
+            self.ssh.is_none() && (self.leak_check.is_some() || timed_out) && group_alive(&child);
+        let leaked_processes = leftovers && !timed_out;
+        if leftovers {
+            crate::kill(&mut child);
+        }
+        exited.store(true, Ordering::Release);
+
+        let exit_code = status.code();
+        let mut stderr = join_output(stderr);
+        let expected = self.expected_exit.unwrap_or(ExpectedExit::Code(0));
+        let mut status = match (timed_out, exit_code) {
+            (true, _) => Status::TimedOut,
+            // A job can still skip itself, unless skipping is what's expected.
+            (false, Some(SKIP_EXIT_CODE)) if expected != ExpectedExit::Code(SKIP_EXIT_CODE) => {
+                Status::Skipped
+            }
+            (false, Some(code)) if expected.matches(code) => Status::Passed,
+            (false, _) => Status::Failed,
+        };
+        if status == Status::Failed && self.expected_exit.is_some() {
+            let code = exit_code.map_or("none, as it was killed".to_string(), |code| {
+                code.to_string()
+            });
+            stderr.push_str(&format!(
+                "bishin: expected the exit code to be {expected}, but it was {code}\n"
+            ));
+        }
+        if leaked_processes {
+            stderr.push_str(
+                "bishin: the job left processes running in its process group, which were killed\n",
+            );
+            if self.leak_check == Some(LeakCheck::Fail) && status == Status::Passed {
+                status = Status::Failed;
+            }
+        }
+        let skip_reason = (status == Status::Skipped)
+            .then(|| stderr.lines().rev().find(|line| !line.trim().is_empty()))
+            .flatten()
+            .map(|line| line.trim().to_string());
+        Ok(JobResult {
+            name: self.name.clone(),
+            status,
+            exit_code,
+            stdout: join_output(stdout),
+            stderr,
+            skipped_by: (status == Status::Skipped).then_some(SkipReason::ExitCode),
+            skip_reason,
+            xfail_reason: None,
+            duration,
+            attempts: 1,
+            usage,
+            bench: None,
+            leaked_processes,
+        })
+    }
+}
+
+/// How long a reader of a job's output waits for more of it before looking
+/// at whether the job has exited.
+#[cfg(unix)]
+const POLL_INTERVAL: libc::c_int = 20;
+
+/// Read a job's output from `pipe` until it's closed, or until `exited` is
+/// set and everything written to it so far has been read. A process that
+/// the job left running, such as a service that a setup script starts, can
+/// hold the pipe open long after the job itself has finished.
+#[cfg(unix)]
+fn read_output(
+    pipe: Option<impl Read + AsRawFd + Send + 'static>,
+    exited: &Arc<AtomicBool>,
+) -> JoinHandle<Vec<u8>> {
+    let exited = Arc::clone(exited);
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let Some(mut pipe) = pipe else {
+            return buf;
+        };
+        let mut chunk = [0; 8192];
+        loop {
+            // Looked at before polling, so that nothing written before the
+            // job exited is left behind.
+            let done = exited.load(Ordering::Acquire);
+            let mut fd = libc::pollfd {
+                fd: pipe.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = if done { 0 } else { POLL_INTERVAL };
+            match unsafe { libc::poll(&mut fd, 1, timeout) } {
+                0 if done => break,
+                0 => continue,
+                -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {
+                    continue
+                }
+                -1 => break,
+                _ => {}
+            }
+            // Whatever was read before an error is still worth reporting.
+            match pipe.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => buf.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+        buf
+    })
+}
+
+#[cfg(not(unix))]
+fn read_output(
+    pipe: Option<impl Read + Send + 'static>,
+    _exited: &Arc<AtomicBool>,
+) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            // Whatever was read before an error is still worth reporting.
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+fn join_output(handle: JoinHandle<Vec<u8>>) -> String {
+    let output = handle.join().unwrap_or_default();
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+/// How a child exited, and what it used if that was measured.
+type Exit = (ExitStatus, Option<ResourceUsage>);
+
+/// Wait for `child` to exit, measuring what it used if `measure` is set and
+/// that can be done here.
+fn wait(child: &mut Child, measure: bool) -> std::io::Result<Exit> {
+    #[cfg(unix)]
+    if measure {
+        return usage::wait(child).map(|(status, usage)| (status, Some(usage)));
+    }
+    #[cfg(not(unix))]
+    let _ = measure;
+    Ok((child.wait()?, None))
+}
+
+/// Like [`wait`], without waiting if `child` is still running.
+fn try_wait(child: &mut Child, measure: bool) -> std::io::Result<Option<Exit>> {
+    #[cfg(unix)]
+    if measure {
+        return Ok(usage::try_wait(child)?.map(|(status, usage)| (status, Some(usage))));
+    }
+    #[cfg(not(unix))]
+    let _ = measure;
+    Ok(child.try_wait()?.map(|status| (status, None)))
+}
+
+/// Wait for `child` to exit, returning `None` if it's still running after
+/// `timeout`.
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+    measure: bool,
+) -> std::io::Result<Option<Exit>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(exit) = try_wait(child, measure)? {
+            return Ok(Some(exit));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(10)));
+    }
+}
+
+/// Whether anything is left in the process group that `child` led, now that
+/// it has exited.
+fn group_alive(child: &Child) -> bool {
+    #[cfg(unix)]
+    {
+        // Signal 0 is never sent, it only checks that there's something to
+        // send it to.
+        let pgid = child.id() as libc::pid_t;
+        unsafe { libc::kill(-pgid, 0) == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = child;
+        false
+    }
+}
+
+/// Ask `child` and the processes it started to exit, with `SIGTERM`. Only
+/// done on Unix.
+fn terminate(child: &Child) {
+    #[cfg(unix)]
+    {
+        let pgid = child.id() as libc::pid_t;
+        unsafe {
+            libc::kill(-pgid, libc::SIGTERM);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = child;
+}
+
+/// Kill `child` along with any processes it started.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // The child leads its own process group, so this reaches everything
+        // it spawned that hasn't moved to another group.
+        let pgid = child.id() as libc::pid_t;
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = child.kill();
+}
+
+/// Whether `program` can be run: the file it names if it's a path, or one of
+/// that name in a directory of `path` otherwise.
+fn is_installed(program: &str, path: Option<&OsStr>) -> bool {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        return program.is_file();
+    }
+    path.into_iter().flat_map(std::env::split_paths).any(|dir| {
+        let file = dir.join(program);
+        file.is_file()
+            || file
+                .with_extension(std::env::consts::EXE_EXTENSION)
+                .is_file()
+    })
+}
+
+/// How a job turned out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Passed,
+    Failed,
+    /// The job ran for longer than its timeout and was killed.
+    TimedOut,
+    /// The job could not be run at all.
+    Error,
+    /// The job exited with [`SKIP_EXIT_CODE`] to say it didn't apply.
+    Skipped,
+    /// The job failed or timed out, as its [`XFail`] expected.
+    XFail,
+    /// The job passed even though its [`XFail`] expected it not to.
+    XPass,
+}
+
+impl Status {
+    pub const ALL: [Status; 7] = [
+        Status::Passed,
+        Status::Failed,
+        Status::TimedOut,
+        Status::Error,
+        Status::Skipped,
+        Status::XFail,
+        Status::XPass,
+    ];
+
+    /// The name of the status in reports and the history, e.g.
+    /// `timed-out`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Passed => "passed",
+            Status::Failed => "failed",
+            Status::TimedOut => "timed-out",
+            Status::Error => "error",
+            Status::Skipped => "skipped",
+            Status::XFail => "xfail",
+            Status::XPass => "xpass",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.name() == name)
+    }
+
+    /// Whether the job went wrong in a way that should fail a run. An
+    /// [`Status::XPass`] may too, but that's up to the caller.
+    pub fn is_failure(self) -> bool {
+        matches!(self, Status::Failed | Status::TimedOut | Status::Error)
+    }
+}
+
+/// What made a job [`Status::Skipped`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The job exited with [`SKIP_EXIT_CODE`] to skip itself.
+    ExitCode,
+    /// The job was marked to be skipped, see [`Job::skip`].
+    Decorator,
+    /// The shell the job runs in isn't installed, see
+    /// [`Job::skip_if_missing`].
+    MissingShell,
+    /// Filters left the test out of the run, so it has no result of its
+    /// own, see [`RunSummary::filtered`].
+    Filter,
+}
+
+impl SkipReason {
+    pub const ALL: [SkipReason; 4] = [
+        SkipReason::ExitCode,
+        SkipReason::Decorator,
+        SkipReason::MissingShell,
+        SkipReason::Filter,
+    ];
+
+    /// The name of the reason in reports, e.g. `exit-code`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SkipReason::ExitCode => "exit-code",
+            SkipReason::Decorator => "decorator",
+            SkipReason::MissingShell => "missing-shell",
+            SkipReason::Filter => "filter",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.name() == name)
+    }
+}
+
+/// The outcome and captured output of a job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobResult {
+    pub name: String,
+    pub status: Status,
+    /// The exit code, or `None` if the process was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// What skipped the job, if it was skipped.
+    pub skipped_by: Option<SkipReason>,
+    /// Why the job skipped itself, if it did and said why.
+    pub skip_reason: Option<String>,
+    /// Why the job was expected to fail, if it was and the reason was given.
+    pub xfail_reason: Option<String>,
+    /// How long the last attempt took.
+    pub duration: Duration,
+    /// How many times the job was run, including retries.
+    pub attempts: u32,
+    /// What the last attempt used, if [`Job::resource_stats`] was set.
+    pub usage: Option<ResourceUsage>,
+    /// The times of the runs of a benchmark, if it was one and every run
+    /// passed.
+    pub bench: Option<BenchStats>,
+    /// Whether the job left processes running when it exited, if its
+    /// [`Job::leak_check`] looked.
+    pub leaked_processes: bool,
+}
+
+impl JobResult {
+    /// A result for a job that could not be run, with `message` as its stderr.
+    pub fn error(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Error,
+            exit_code: None,
+            stdout: String::new(),
+            stderr: message.into(),
+            skipped_by: None,
+            skip_reason: None,
+            xfail_reason: None,
+            duration: Duration::ZERO,
+            attempts: 1,
+            usage: None,
+            bench: None,
+            leaked_processes: false,
+        }
+    }
+
+    /// A result for a job that was skipped by [`Job::skip`] without being
+    /// run.
+    pub fn skipped(name: impl Into<String>, skip: &Skip) -> Self {
+        Self {
+            status: Status::Skipped,
+            skipped_by: Some(SkipReason::Decorator),
+            skip_reason: skip.reason.clone(),
+            ..Self::error(name, "")
+        }
+    }
+
+    /// A result for a job that could not be run because of `err`.
+    pub fn from_error(name: impl Into<String>, err: &Error) -> Self {
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(err);
+        while let Some(err) = source {
+            message.push_str(&format!(": {err}"));
+            source = err.source();
+        }
+        Self::error(name, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::testing::sh;
+
+    #[test]
+    fn output_exit_codes_and_durations_are_captured() {
+        let result = sh("prints", "echo out; echo err >&2; sleep 0.1; exit 3")
+            .run()
+            .unwrap();
+        assert_eq!(result.name, "prints");
+        assert_eq!(result.stdout, "out\n");
+        assert_eq!(result.stderr, "err\n");
+        assert_eq!(result.exit_code, Some(3));
+        assert_eq!(result.status, Status::Failed);
+        assert!(result.duration >= Duration::from_millis(100));
+        let passed = sh("passes", "true").run().unwrap();
+        assert_eq!((passed.status, passed.exit_code), (Status::Passed, Some(0)));
+    }
+
+    #[test]
+    fn job_variables_are_set_over_the_inherited_environment() {
+        let job = Job {
+            envs: HashMap::from([
+                ("BISHIN_TEST_VAR".to_string(), "set".to_string()),
+                ("HOME".to_string(), "/nowhere".to_string()),
+            ]),
+            ..sh("env", "echo \"$BISHIN_TEST_VAR $HOME\"; test -n \"$PATH\"")
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.stdout, "set /nowhere\n");
+        assert_eq!(result.status, Status::Passed, "PATH is inherited");
+    }
+
+    #[test]
+    fn programs_that_do_not_exist_are_errors() {
+        let job = Job {
+            args: vec!["bishin-no-such-program".to_string()],
+            ..sh("missing", "")
+        };
+        let err = job.run().unwrap_err();
+        assert!(
+            matches!(&err, Error::Spawn { program, .. } if program == "bishin-no-such-program"),
+            "{err:?}"
+        );
+        let empty = Job {
+            args: Vec::new(),
+            ..sh("empty", "")
+        };
+        assert!(matches!(empty.run(), Err(Error::NoProgram(name)) if name == "empty"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn jobs_killed_by_a_signal_have_no_exit_code() {
+        let result = sh("killed", "kill -9 $$").run().unwrap();
+        assert_eq!(result.exit_code, None);
+        assert_eq!(result.status, Status::Failed);
+    }
+
+    #[test]
+    fn skipped_jobs_are_not_run() {
+        let scratch = crate::testing::scratch_dir();
+        let marker = scratch.join("ran");
+        let job = Job {
+            skip: Some(Skip {
+                reason: Some("not yet".to_string()),
+            }),
+            ..sh("skipped", &format!("touch '{}'", marker.display()))
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::Skipped);
+        assert_eq!(result.skipped_by, Some(SkipReason::Decorator));
+        assert_eq!(result.skip_reason.as_deref(), Some("not yet"));
+        assert_eq!(result.exit_code, None);
+        assert!(!marker.exists());
+        let without_reason = Job {
+            skip: Some(Skip::default()),
+            ..sh("skipped", "true")
+        };
+        assert_eq!(without_reason.run().unwrap().skip_reason, None);
+    }
+
+    #[test]
+    fn jobs_whose_program_is_missing_can_be_skipped() {
+        let missing = |skip_if_missing| Job {
+            args: vec!["bishin-no-such-shell".to_string()],
+            skip_if_missing,
+            ..sh("missing", "")
+        };
+        let result = missing(true).run().unwrap();
+        assert_eq!(result.status, Status::Skipped);
+        assert_eq!(result.skipped_by, Some(SkipReason::MissingShell));
+        assert_eq!(
+            result.skip_reason.as_deref(),
+            Some("bishin-no-such-shell isn't installed")
+        );
+        assert!(matches!(missing(false).run(), Err(Error::Spawn { .. })));
+        let installed = Job {
+            skip_if_missing: true,
+            ..sh("installed", "true")
+        };
+        assert_eq!(installed.run().unwrap().status, Status::Passed);
+    }
+
+    #[test]
+    fn jobs_pass_with_the_exit_code_they_expect() {
+        let expecting = |expected, script| Job {
+            expected_exit: Some(expected),
+            ..sh("exits", script)
+        };
+        assert_eq!(
+            expecting(ExpectedExit::Code(3), "exit 3")
+                .run()
+                .unwrap()
+                .status,
+            Status::Passed
+        );
+        assert_eq!(
+            expecting(ExpectedExit::NonZero, "exit 1")
+                .run()
+                .unwrap()
+                .status,
+            Status::Passed
+        );
+        // Skipping still skips unless it's what's expected.
+        assert_eq!(
+            expecting(ExpectedExit::NonZero, "exit 77")
+                .run()
+                .unwrap()
+                .status,
+            Status::Skipped
+        );
+        assert_eq!(
+            expecting(ExpectedExit::Code(77), "exit 77")
+                .run()
+                .unwrap()
+                .status,
+            Status::Passed
+        );
+        let wrong = expecting(ExpectedExit::Code(3), "exit 0").run().unwrap();
+        assert_eq!(wrong.status, Status::Failed);
+        assert!(
+            wrong
+                .stderr
+                .ends_with("bishin: expected the exit code to be 3, but it was 0\n"),
+            "{}",
+            wrong.stderr
+        );
+        let passed = expecting(ExpectedExit::NonZero, "true").run().unwrap();
+        assert!(
+            passed
+                .stderr
+                .ends_with("expected the exit code to be nonzero, but it was 0\n"),
+            "{}",
+            passed.stderr
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn jobs_killed_while_expecting_a_failure_still_fail() {
+        let job = Job {
+            expected_exit: Some(ExpectedExit::NonZero),
+            ..sh("killed", "kill -9 $$")
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::Failed);
+        assert!(
+            result
+                .stderr
+                .ends_with("but it was none, as it was killed\n"),
+            "{}",
+            result.stderr
+        );
+    }
+
+    #[test]
+    fn jobs_past_their_timeout_are_stopped() {
+        let job = Job {
+            timeout: Some(Duration::from_millis(200)),
+            ..sh("slow", "sleep 10")
+        };
+        let start = Instant::now();
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn busy_jobs_are_measured_when_asked() {
+        // The loop runs in a subshell, which the job waits for.
+        let busy = "(i=0; while [ $i -lt 100000 ]; do i=$((i + 1)); done)";
+        let measured = Job {
+            resource_stats: true,
+            ..sh("busy", busy)
+        };
+        let result = measured.run().unwrap();
+        assert_eq!(result.status, Status::Passed);
+        let usage = result.usage.expect("the job was measured");
+        assert!(usage.cpu_time > Duration::ZERO, "{usage:?}");
+        assert!(usage.cpu_time <= result.duration * 2, "{usage:?}");
+        assert!(usage.max_rss > 0, "{usage:?}");
+        assert_eq!(sh("busy", busy).run().unwrap().usage, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timed_out_jobs_are_still_measured() {
+        let job = Job {
+            resource_stats: true,
+            timeout: Some(Duration::from_millis(200)),
+            ..sh("slow", "sleep 10")
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::TimedOut);
+        assert!(result.usage.is_some());
+    }
+
+    #[test]
+    fn output_is_read_until_the_job_exits_not_what_it_left_running() {
+        let job = sh(
+            "starts-a-service",
+            "sleep 8 &\necho \"$!\"\nhead -c 100000 /dev/zero | tr '\\0' a >&2\n",
+        );
+        let start = Instant::now();
+        let result = job.run().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(result.status, Status::Passed);
+        // Everything written before the job exited is still there.
+        assert_eq!(result.stderr.len(), 100000);
+        let pid: libc::pid_t = result.stdout.trim().parse().unwrap();
+        assert_eq!(unsafe { libc::kill(pid, libc::SIGKILL) }, 0);
+    }
+
+    #[test]
+    fn expected_failures_are_turned_around() {
+        let xfail = |script| Job {
+            xfail: Some(XFail {
+                reason: Some("bug #12".to_string()),
+            }),
+            ..sh("xfail", script)
+        };
+        let result = xfail("exit 1").run().unwrap();
+        assert_eq!(result.status, Status::XFail);
+        assert_eq!(result.xfail_reason.as_deref(), Some("bug #12"));
+        assert_eq!(xfail("true").run().unwrap().status, Status::XPass);
+        let timed_out = Job {
+            timeout: Some(Duration::from_millis(100)),
+            ..xfail("sleep 10")
+        };
+        assert_eq!(timed_out.run().unwrap().status, Status::XFail);
+        // Skipping isn't failing.
+        assert_eq!(xfail("exit 77").run().unwrap().status, Status::Skipped);
+        assert!(!Status::XFail.is_failure() && !Status::XPass.is_failure());
+    }
+
+    #[test]
+    fn the_stdin_file_is_streamed_to_the_job() {
+        let scratch = crate::testing::scratch_dir();
+        let stdin = scratch.join("input.txt");
+        std::fs::write(&stdin, "line one\nline two\n").unwrap();
+        let job = Job {
+            stdin: Some(stdin),
+            ..sh("reads", "cat")
+        };
+        assert_eq!(job.run().unwrap().stdout, "line one\nline two\n");
+        // Without one, stdin is empty rather than bishin's.
+        assert_eq!(sh("reads", "cat").run().unwrap().stdout, "");
+    }
+
+    #[test]
+    fn a_missing_stdin_file_is_an_error_naming_it() {
+        let scratch = crate::testing::scratch_dir();
+        let stdin = scratch.join("missing.txt");
+        let job = Job {
+            stdin: Some(stdin.clone()),
+            ..sh("reads", "cat")
+        };
+        let err = job.run().unwrap_err();
+        assert!(
+            matches!(&err, Error::Stdin { path, .. } if *path == stdin),
+            "{err}"
+        );
+        let result = JobResult::from_error(&job.name, &err);
+        assert_eq!(result.status, Status::Error);
+        assert!(
+            result.stderr.starts_with(&format!(
+                "failed to open {} as the stdin of job reads: ",
+                stdin.display()
+            )),
+            "{}",
+            result.stderr
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tty_jobs_write_to_a_terminal() {
+        let script = "[ -t 1 ] && echo stdout is a tty; [ -t 2 ] && echo stderr is too >&2; [ -t 0 ] || echo stdin is not";
+        let job = Job {
+            tty: true,
+            ..sh("tty", script)
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::Passed);
+        // Both go to the one terminal, with their newlines as written.
+        assert_eq!(
+            result.stdout,
+            "stdout is a tty\nstderr is too\nstdin is not\n"
+        );
+        assert_eq!(result.stderr, "");
+        let piped = sh("piped", "[ -t 1 ] || echo not a tty").run().unwrap();
+        assert_eq!(piped.stdout, "not a tty\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tty_output_is_read_in_full() {
+        let job = Job {
+            tty: true,
+            ..sh(
+                "tty",
+                "i=0; while [ $i -lt 2000 ]; do echo line $i; i=$((i + 1)); done; exit 4",
+            )
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.exit_code, Some(4));
+        assert_eq!(result.stdout.lines().count(), 2000);
+        assert_eq!(result.stdout.lines().last(), Some("line 1999"));
+    }
+
+    /// A job that leaves a `sleep` running in the background, writing its
+    /// process ID to `pid_file`.
+    #[cfg(target_os = "linux")]
+    fn backgrounds(pid_file: &Path, leak_check: Option<LeakCheck>) -> Job {
+        let script = format!(
+            "sleep 30 >/dev/null 2>&1 &\necho $! > '{}'",
+            pid_file.display()
+        );
+        Job {
+            leak_check,
+            ..sh("leaks", &script)
+        }
+    }
+
+    /// Whether the process with the ID in `pid_file` is still running a
+    /// second later, killing it if so. One that's exited but not been reaped
+    /// yet isn't running.
+    #[cfg(target_os = "linux")]
+    fn still_running(pid_file: &Path) -> bool {
+        let pid: libc::pid_t = std::fs::read_to_string(pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let running = || {
+            let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap_or_default();
+            // The state comes after the command, which is in parentheses.
+            let state = stat.rsplit_once(") ").map(|(_, rest)| rest);
+            state.is_some_and(|state| !state.starts_with(['Z', 'X']))
+        };
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            if !running() {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+        true
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn leaked_processes_are_warned_about_and_killed() {
+        let scratch = crate::testing::scratch_dir();
+        let pid_file = scratch.join("pid");
+        let result = backgrounds(&pid_file, Some(LeakCheck::Warn)).run().unwrap();
+        assert_eq!(result.status, Status::Passed);
+        assert!(result.leaked_processes);
+        assert_eq!(
+            result.stderr,
+            "bishin: the job left processes running in its process group, which were killed\n"
+        );
+        assert!(!still_running(&pid_file));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn leaked_processes_can_fail_the_job() {
+        let scratch = crate::testing::scratch_dir();
+        let pid_file = scratch.join("pid");
+        let result = backgrounds(&pid_file, Some(LeakCheck::Fail)).run().unwrap();
+        assert_eq!(result.status, Status::Failed);
+        assert!(result.leaked_processes);
+        assert!(!still_running(&pid_file));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn processes_are_left_be_without_a_leak_check() {
+        let scratch = crate::testing::scratch_dir();
+        let pid_file = scratch.join("pid");
+        let result = backgrounds(&pid_file, None).run().unwrap();
+        assert_eq!(result.status, Status::Passed);
+        assert!(!result.leaked_processes);
+        assert!(still_running(&pid_file));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn children_that_are_waited_for_are_not_leaks() {
+        let job = Job {
+            leak_check: Some(LeakCheck::Fail),
+            ..sh("waits", "sleep 0.1 &\nwait")
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::Passed);
+        assert!(!result.leaked_processes);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timed_out_jobs_are_asked_to_stop_first() {
+        let job = Job {
+            timeout: Some(Duration::from_millis(200)),
+            ..sh(
+                "cleans_up",
+                "trap 'echo cleaning up; exit 1' TERM\nwhile :; do sleep 0.05; done",
+            )
+        };
+        let start = Instant::now();
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::TimedOut);
+        assert_eq!(result.stdout, "cleaning up\n");
+        assert!(start.elapsed() < TERMINATE_GRACE, "{:?}", start.elapsed());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timed_out_jobs_that_ignore_being_asked_are_killed() {
+        let job = Job {
+            timeout: Some(Duration::from_millis(100)),
+            ..sh("stubborn", "trap '' TERM\nwhile :; do sleep 0.05; done")
+        };
+        let start = Instant::now();
+        let result = job.run().unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(result.status, Status::TimedOut);
+        assert_eq!(result.exit_code, None);
+        assert!(elapsed >= TERMINATE_GRACE, "{elapsed:?}");
+        assert!(elapsed < TERMINATE_GRACE * 3, "{elapsed:?}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn what_timed_out_jobs_started_is_stopped_too() {
+        let scratch = crate::testing::scratch_dir();
+        let pid_file = scratch.join("pid");
+        let script = format!(
+            "sleep 30 >/dev/null 2>&1 &\necho $! > '{}'\nsleep 30",
+            pid_file.display()
+        );
+        let job = Job {
+            timeout: Some(Duration::from_millis(200)),
+            ..sh("starts_more", &script)
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::TimedOut);
+        assert!(!result.leaked_processes);
+        assert!(!still_running(&pid_file));
+    }
+}
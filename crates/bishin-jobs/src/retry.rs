@@ -0,0 +1,137 @@
+//! Running failed jobs again.
+
+use crate::{JobResult, Status};
+
+/// When a failed job should be run again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many more times to run the job after it first fails.
+    pub count: u32,
+    /// The failures worth retrying, or empty to retry any failure.
+    pub on: Vec<RetryOn>,
+}
+
+/// A kind of failure that a [`RetryPolicy`] can be limited to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOn {
+    Timeout,
+    ExitCode(i32),
+}
+
+impl RetryPolicy {
+    /// Whether the failure in `result` is one this policy retries.
+    ///
+    /// Jobs that couldn't be run at all are never retried, since they won't
+    /// do any better the second time.
+    pub fn retries(&self, result: &JobResult) -> bool {
+        let kind = match (result.status, result.exit_code) {
+            (Status::TimedOut, _) => RetryOn::Timeout,
+            (Status::Failed, Some(code)) => RetryOn::ExitCode(code),
+            // Killed by a signal, which only a catch-all policy retries.
+            (Status::Failed, None) => return self.on.is_empty(),
+            (
+                Status::Passed | Status::Error | Status::Skipped | Status::XFail | Status::XPass,
+                _,
+            ) => return false,
+        };
+        self.on.is_empty() || self.on.contains(&kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{scratch_dir, sh};
+    use crate::Job;
+
+    fn policy(on: Vec<RetryOn>) -> RetryPolicy {
+        RetryPolicy { count: 3, on }
+    }
+
+    fn result(status: Status, exit_code: Option<i32>) -> JobResult {
+        JobResult {
+            status,
+            exit_code,
+            ..JobResult::error("job", "")
+        }
+    }
+
+    #[test]
+    fn what_each_policy_retries() {
+        let timeout = result(Status::TimedOut, None);
+        let exit_1 = result(Status::Failed, Some(1));
+        let exit_75 = result(Status::Failed, Some(75));
+        let signal = result(Status::Failed, None);
+        let any = policy(Vec::new());
+        let timeout_only = policy(vec![RetryOn::Timeout]);
+        let timeout_or_75 = policy(vec![RetryOn::Timeout, RetryOn::ExitCode(75)]);
+
+        for failure in [&timeout, &exit_1, &exit_75, &signal] {
+            assert!(any.retries(failure), "{:?}", failure.status);
+        }
+        assert!(timeout_only.retries(&timeout));
+        assert!(!timeout_only.retries(&exit_75));
+        assert!(timeout_or_75.retries(&timeout));
+        assert!(timeout_or_75.retries(&exit_75));
+        assert!(!timeout_or_75.retries(&exit_1));
+        assert!(!timeout_or_75.retries(&signal));
+    }
+
+    #[test]
+    fn only_failures_are_retried() {
+        for status in [
+            Status::Passed,
+            Status::Error,
+            Status::Skipped,
+            Status::XFail,
+            Status::XPass,
+        ] {
+            assert!(
+                !policy(Vec::new()).retries(&result(status, Some(0))),
+                "{status:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn jobs_are_retried_on_timeout_only() {
+        let scratch = scratch_dir();
+        let marker = scratch.join("ran");
+        let job = Job {
+            timeout: Some(Duration::from_millis(300)),
+            retry: Some(RetryPolicy {
+                count: 2,
+                on: vec![RetryOn::Timeout],
+            }),
+            ..sh(
+                "slow-once",
+                &format!(
+                    "[ -e {0} ] && exit 0; touch {0}; sleep 10",
+                    marker.display()
+                ),
+            )
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::Passed);
+        assert_eq!(result.attempts, 2);
+    }
+
+    #[test]
+    fn jobs_are_not_retried_on_exit_1() {
+        let job = Job {
+            retry: Some(policy(vec![RetryOn::Timeout, RetryOn::ExitCode(75)])),
+            ..sh("assertion", "exit 1")
+        };
+        let result = job.run().unwrap();
+        assert_eq!(result.status, Status::Failed);
+        assert_eq!(result.attempts, 1);
+
+        let job = Job {
+            retry: Some(policy(vec![RetryOn::ExitCode(75)])),
+            ..sh("temporary", "exit 75")
+        };
+        assert_eq!(job.run().unwrap().attempts, 4);
+    }
+}
@@ -0,0 +1,173 @@
+//! Comparing what a job prints with a snapshot of what it printed before.
+
+use std::path::PathBuf;
+
+use crate::{unified_diff, Error, JobResult, Status, DIFF_CONTEXT};
+
+/// When a job records its output as its snapshot instead of comparing it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotUpdate {
+    /// Never, so a job without a snapshot fails.
+    #[default]
+    Never,
+    /// Only when it has no snapshot yet, so new jobs pass and record one
+    /// while mismatches still fail.
+    Missing,
+    /// Always, replacing any snapshot that doesn't match.
+    Always,
+}
+
+/// The file a job's stdout is compared with once it passes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub update: SnapshotUpdate,
+}
+
+impl Snapshot {
+    /// Fail `result` if it passed but its stdout doesn't match the snapshot,
+    /// or record the stdout as the snapshot if that's what the update policy
+    /// says to do.
+    pub(crate) fn check(&self, result: &mut JobResult) -> Result<(), Error> {
+        if result.status != Status::Passed {
+            return Ok(());
+        }
+        let mut diff = String::new();
+        let problem = match std::fs::read_to_string(&self.path) {
+            Ok(expected) if expected == result.stdout => return Ok(()),
+            Ok(_) if self.update == SnapshotUpdate::Always => return self.record(result),
+            Ok(expected) => {
+                diff = unified_diff(
+                    &expected,
+                    &result.stdout,
+                    "snapshot",
+                    "stdout",
+                    DIFF_CONTEXT,
+                );
+                "doesn't match its snapshot"
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => match self.update {
+                SnapshotUpdate::Never => "has no snapshot",
+                SnapshotUpdate::Missing | SnapshotUpdate::Always => return self.record(result),
+            },
+            Err(source) => {
+                return Err(Error::Snapshot {
+                    path: self.path.clone(),
+                    source,
+                })
+            }
+        };
+        result.status = Status::Failed;
+        if !result.stderr.is_empty() && !result.stderr.ends_with('\n') {
+            result.stderr.push('\n');
+        }
+        result.stderr.push_str(&format!(
+            "bishin: stdout {problem} at {}, run with --update-snapshots to record it\n",
+            self.path.display()
+        ));
+        result.stderr.push_str(&diff);
+        Ok(())
+    }
+
+    fn record(&self, result: &JobResult) -> Result<(), Error> {
+        let error = |source| Error::Snapshot {
+            path: self.path.clone(),
+            source,
+        };
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(error)?;
+        }
+        std::fs::write(&self.path, &result.stdout).map_err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bishin_testing::ScratchDir;
+
+    use crate::testing::scratch_dir;
+
+    /// A snapshot holding `contents`, if any, and the directory it's in,
+    /// which goes when that's dropped.
+    fn snapshot(contents: Option<&str>, update: SnapshotUpdate) -> (Snapshot, ScratchDir) {
+        let dir = scratch_dir();
+        let path = dir.join("__snapshots__/a.out");
+        if let Some(contents) = contents {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+        }
+        (Snapshot { path, update }, dir)
+    }
+
+    fn passed(stdout: &str) -> JobResult {
+        JobResult {
+            status: Status::Passed,
+            stdout: stdout.to_string(),
+            ..JobResult::error("a", "")
+        }
+    }
+
+    #[test]
+    fn a_mismatch_fails_with_a_diff() {
+        let (snapshot, _dir) = snapshot(Some("one\ntwo\n"), SnapshotUpdate::Missing);
+        let mut result = passed("one\nTWO\n");
+        snapshot.check(&mut result).unwrap();
+        assert_eq!(result.status, Status::Failed);
+        assert_eq!(
+            result.stderr,
+            format!(
+                "bishin: stdout doesn't match its snapshot at {}, run with --update-snapshots to record it\n\
+                 --- snapshot\n+++ stdout\n@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n",
+                snapshot.path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn a_match_passes() {
+        let (snapshot, _dir) = snapshot(Some("same\n"), SnapshotUpdate::Never);
+        let mut result = passed("same\n");
+        snapshot.check(&mut result).unwrap();
+        assert_eq!(result, passed("same\n"));
+    }
+
+    #[test]
+    fn what_is_recorded_depends_on_the_update_policy() {
+        let snapshot_of = |contents, update| {
+            let (snapshot, _dir) = snapshot(contents, update);
+            let mut result = passed("new\n");
+            snapshot.check(&mut result).unwrap();
+            (result.status, std::fs::read_to_string(&snapshot.path).ok())
+        };
+        let recorded = Some("new\n".to_string());
+        assert_eq!(
+            snapshot_of(None, SnapshotUpdate::Never),
+            (Status::Failed, None)
+        );
+        assert_eq!(
+            snapshot_of(None, SnapshotUpdate::Missing),
+            (Status::Passed, recorded.clone())
+        );
+        assert_eq!(
+            snapshot_of(Some("old\n"), SnapshotUpdate::Missing),
+            (Status::Failed, Some("old\n".to_string()))
+        );
+        assert_eq!(
+            snapshot_of(Some("old\n"), SnapshotUpdate::Always),
+            (Status::Passed, recorded)
+        );
+    }
+
+    #[test]
+    fn failed_jobs_are_not_compared() {
+        let (snapshot, _dir) = snapshot(None, SnapshotUpdate::Always);
+        let mut result = JobResult {
+            status: Status::Failed,
+            ..passed("new\n")
+        };
+        snapshot.check(&mut result).unwrap();
+        assert!(!snapshot.path.exists());
+        assert_eq!(result.stderr, "");
+    }
+}
@@ -0,0 +1,361 @@
+//! Running jobs on another host over SSH.
+//!
+//! This shells out to `ssh` and `scp` rather than speaking the protocol
+//! itself, so that the user's SSH config, keys and agent all apply.
+
+/// A host to run jobs on over SSH, see [`Job::ssh`](crate::Job::ssh).
+///
+/// The files that a job's arguments name, such as its script, and its
+/// [`JobDir`](crate::JobDir) are copied to a directory of its own under
+/// `remote_work_dir` first, and removed again once it's done. The job runs
+/// there with its own variables set on the command line, while the rest of
+/// its environment is the host's, and its stdin file is streamed to it
+/// through `ssh`. Its `tmp_dir` is on the host too, and isn't copied back.
+///
+/// If the job runs past its timeout, its process group on the host is
+/// killed along with the local `ssh`. A job whose `ssh` exits with 255,
+/// which `ssh` does when it can't connect, is [`Status::Error`](crate::Status::Error)
+/// and has what `ssh` said on its stderr.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshBackend {
+    pub host: String,
+    /// Who to log in as, or `None` for whoever the SSH config says.
+    pub user: Option<String>,
+    /// A directory on the host to keep the jobs' files in, e.g. `/tmp/bishin`.
+    pub remote_work_dir: String,
+    /// Options for both `ssh` and `scp`, before their other arguments, e.g.
+    /// `["-o", "Port=2222"]`.
+    pub ssh_args: Vec<String>,
+}
+
+#[cfg(feature = "backend-ssh")]
+pub(crate) use remote::run;
+
+#[cfg(feature = "backend-ssh")]
+mod remote {
+    use std::path::Path;
+    use std::process::{Child, Command, Output, Stdio};
+    use std::time::Duration;
+
+    use super::SshBackend;
+    use crate::{Error, Job, JobDir, JobResult, Status, TMPDIR_ENV};
+
+    /// What `ssh` exits with when something goes wrong before or while
+    /// connecting, rather than in the command it runs.
+    const SSH_ERROR_EXIT_CODE: i32 = 255;
+
+    /// Run `job` on the host once, in `dir` if it has one.
+    pub(crate) fn run(
+        job: &Job,
+        backend: &SshBackend,
+        dir: Option<&JobDir>,
+    ) -> Result<JobResult, Error> {
+        let remote = Remote::new(job, backend);
+        let result = remote.run(job, dir);
+        // Left over files would only be replaced by the next run anyway.
+        let _ = remote.ssh(&format!("rm -rf -- {}", quote(&remote.dir)));
+        result
+    }
+
+    /// A job's directory on the host.
+    struct Remote<'a> {
+        backend: &'a SshBackend,
+        dir: String,
+    }
+
+    impl<'a> Remote<'a> {
+        fn new(job: &Job, backend: &'a SshBackend) -> Self {
+            let name: String = job
+                .name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            // The process ID keeps two runs on the same host apart.
+            let dir = format!(
+                "{}/{name}-{}",
+                backend.remote_work_dir.trim_end_matches('/'),
+                std::process::id()
+            );
+            Self { backend, dir }
+        }
+
+        fn run(&self, job: &Job, dir: Option<&JobDir>) -> Result<JobResult, Error> {
+            let work_dir = format!("{}/work", self.dir);
+            let tmp_dir = match (dir, &job.tmp_dir) {
+                (Some(dir), Some(tmp_dir)) if dir.path == *tmp_dir => work_dir.clone(),
+                _ => format!("{}/tmp", self.dir),
+            };
+            let mut dirs = vec![quote(&format!("{}/files", self.dir)), quote(&tmp_dir)];
+            if dir.is_none() {
+                dirs.push(quote(&work_dir));
+            }
+            let output = self.ssh(&format!("mkdir -p -- {}", dirs.join(" ")))?;
+            if !output.status.success() {
+                return Ok(error_result(job, "create its directory", &output));
+            }
+            if let Some(dir) = dir {
+                let output = self.scp(&dir.path, &work_dir)?;
+                if !output.status.success() {
+                    return Ok(error_result(job, "copy its directory", &output));
+                }
+            }
+            let mut args = Vec::with_capacity(job.args.len());
+            for (index, arg) in job.args.iter().enumerate() {
+                let path = Path::new(arg);
+                if !path.is_file() {
+                    args.push(quote(arg));
+                    continue;
+                }
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let copy = format!("{}/files/{index}-{file_name}", self.dir);
+                let output = self.scp(path, &copy)?;
+                if !output.status.success() {
+                    return Ok(error_result(job, &format!("copy {arg}"), &output));
+                }
+                args.push(quote(&copy));
+            }
+            if args.is_empty() {
+                return Err(Error::NoProgram(job.name.clone()));
+            }
+
+            let mut env = vec!["env".to_string()];
+            if let Some(allowed) = &job.clean_env {
+                env.push("-i".to_string());
+                // Only those of the allowed variables that the host has.
+                env.extend(
+                    allowed
+                        .iter()
+                        .map(|name| format!("${{{name}+\"{name}=${name}\"}}")),
+                );
+            }
+            let mut envs: Vec<_> = job.envs.iter().collect();
+            envs.sort();
+            env.extend(
+                envs.into_iter()
+                    .map(|(key, value)| quote(&format!("{key}={value}"))),
+            );
+            env.push(quote(&format!("{TMPDIR_ENV}={tmp_dir}")));
+            // The shell that ssh starts leads its own process group, which
+            // is what a timeout kills.
+            let command = format!(
+                "cd {} && echo $$ > {} && exec {} {}",
+                quote(&work_dir),
+                quote(&self.pid_file()),
+                env.join(" "),
+                args.join(" "),
+            );
+            let mut ssh = self.command("ssh");
+            ssh.arg("--")
+                .arg(self.destination())
+                .arg(command)
+                .stdin(job.stdin_stdio()?)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            let mut result = job.capture("ssh", ssh, None, false, |child: &mut Child| {
+                let _ = child.kill();
+                let _ = self.ssh(&format!(
+                    "kill -KILL -- -$(cat {})",
+                    quote(&self.pid_file())
+                ));
+            })?;
+            if result.exit_code == Some(SSH_ERROR_EXIT_CODE) {
+                result.status = Status::Error;
+            }
+            Ok(result)
+        }
+
+        fn pid_file(&self) -> String {
+            format!("{}/pid", self.dir)
+        }
+
+        fn destination(&self) -> String {
+            match &self.backend.user {
+                Some(user) => format!("{user}@{}", self.backend.host),
+                None => self.backend.host.clone(),
+            }
+        }
+
+        fn command(&self, program: &str) -> Command {
+            let mut command = Command::new(program);
+            command.args(&self.backend.ssh_args);
+            command
+        }
+
+        /// Run the shell command `command` on the host and wait for it.
+        fn ssh(&self, command: &str) -> Result<Output, Error> {
+            let mut ssh = self.command("ssh");
+            ssh.arg("--")
+                .arg(self.destination())
+                .arg(command)
+                .stdin(Stdio::null());
+            output("ssh", ssh)
+        }
+
+        /// Copy the local file or directory at `src` to `dest` on the host.
+        fn scp(&self, src: &Path, dest: &str) -> Result<Output, Error> {
+            let mut scp = self.command("scp");
+            scp.args(["-q", "-r", "--"])
+                .arg(src)
+                .arg(format!("{}:{dest}", self.destination()))
+                .stdin(Stdio::null());
+            output("scp", scp)
+        }
+    }
+
+    fn output(program: &str, mut command: Command) -> Result<Output, Error> {
+        command.output().map_err(|source| Error::Spawn {
+            program: program.to_string(),
+            source,
+        })
+    }
+
+    /// The result of a job that couldn't be set up on the host, because
+    /// `ssh` or `scp` failed to do what `what` says.
+    fn error_result(job: &Job, what: &str, output: &Output) -> JobResult {
+        JobResult {
+            name: job.name.clone(),
+            status: Status::Error,
+            exit_code: output.status.code(),
+            stdout: String::new(),
+            stderr: format!(
+                "bishin: failed to {what} on the host\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            skipped_by: None,
+            skip_reason: None,
+            xfail_reason: None,
+            duration: Duration::ZERO,
+            attempts: 1,
+            usage: None,
+            bench: None,
+            leaked_processes: false,
+        }
+    }
+
+    /// `value` quoted for the host's shell.
+    fn quote(value: &str) -> String {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use super::*;
+        use crate::testing::{scratch_dir, sh};
+
+        fn backend(host: &str) -> SshBackend {
+            SshBackend {
+                host: host.to_string(),
+                user: None,
+                remote_work_dir: "/tmp/bishin/".to_string(),
+                ssh_args: Vec::new(),
+            }
+        }
+
+        #[test]
+        fn values_are_quoted_for_the_hosts_shell() {
+            assert_eq!(quote("plain"), "'plain'");
+            assert_eq!(quote("it's $HOME"), r"'it'\''s $HOME'");
+        }
+
+        #[test]
+        fn each_job_has_a_directory_of_its_own() {
+            let mut backend = backend("ci-runner");
+            let remote = Remote::new(&sh("net::dns resolves", "true"), &backend);
+            assert_eq!(
+                remote.dir,
+                format!("/tmp/bishin/net__dns_resolves-{}", std::process::id())
+            );
+            assert_eq!(remote.destination(), "ci-runner");
+            backend.user = Some("deploy".to_string());
+            let remote = Remote::new(&sh("a", "true"), &backend);
+            assert_eq!(remote.destination(), "deploy@ci-runner");
+        }
+
+        #[test]
+        fn failing_to_connect_is_an_error_with_what_ssh_said() {
+            let backend = SshBackend {
+                ssh_args: ["-o", "BatchMode=yes", "-o", "ConnectTimeout=2"]
+                    .map(String::from)
+                    .to_vec(),
+                ..backend("bishin-test.invalid")
+            };
+            let result = run(&sh("a", "true"), &backend, None).unwrap();
+            assert_eq!(result.status, Status::Error);
+            assert!(!result.stderr.is_empty());
+        }
+
+        /// The host to run the tests against, from `BISHIN_SSH_TEST_HOST`,
+        /// which is `[user@]host`, with any `ssh` options separated by spaces
+        /// in `BISHIN_SSH_TEST_ARGS`, e.g. to reach a local sshd container.
+        fn test_host() -> Option<SshBackend> {
+            let host = std::env::var("BISHIN_SSH_TEST_HOST").ok()?;
+            let (user, host) = match host.split_once('@') {
+                Some((user, host)) => (Some(user.to_string()), host.to_string()),
+                None => (None, host),
+            };
+            let ssh_args = std::env::var("BISHIN_SSH_TEST_ARGS").unwrap_or_default();
+            Some(SshBackend {
+                host,
+                user,
+                remote_work_dir: "/tmp/bishin-test".to_string(),
+                ssh_args: ssh_args.split_whitespace().map(String::from).collect(),
+            })
+        }
+
+        #[test]
+        fn jobs_run_on_the_host_with_their_env_and_stdin() {
+            let Some(backend) = test_host() else {
+                return;
+            };
+            let dir = scratch_dir();
+            let stdin = dir.join("stdin");
+            std::fs::write(&stdin, "from stdin\n").unwrap();
+            let mut job = sh("remote", "echo \"$GREETING\"; cat; echo oops >&2; exit 3");
+            job.envs
+                .insert("GREETING".to_string(), "it's me".to_string());
+            job.stdin = Some(stdin);
+            let result = run(&job, &backend, None).unwrap();
+            assert_eq!(result.exit_code, Some(3));
+            assert_eq!(result.stdout, "it's me\nfrom stdin\n");
+            assert_eq!(result.stderr, "oops\n");
+            let remote = Remote::new(&job, &backend);
+            let output = remote
+                .ssh(&format!("test -e {}", quote(&remote.dir)))
+                .unwrap();
+            assert!(!output.status.success(), "{} was left behind", remote.dir);
+        }
+
+        #[test]
+        fn timeouts_kill_the_job_on_the_host() {
+            let Some(backend) = test_host() else {
+                return;
+            };
+            let mut job = sh("sleeps", "sleep 30");
+            job.timeout = Some(Duration::from_millis(500));
+            let result = run(&job, &backend, None).unwrap();
+            assert_eq!(result.status, Status::TimedOut);
+            assert!(result.duration < Duration::from_secs(10));
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "backend-ssh")))]
+mod tests {
+    use super::*;
+    use crate::testing::sh;
+    use crate::Error;
+
+    #[test]
+    fn builds_without_ssh_refuse_ssh_jobs() {
+        let mut job = sh("remote", "true");
+        job.ssh = Some(SshBackend {
+            host: "ci-runner".to_string(),
+            user: None,
+            remote_work_dir: "/tmp/bishin".to_string(),
+            ssh_args: Vec::new(),
+        });
+        assert!(matches!(job.run(), Err(Error::NoSsh(name)) if name == "remote"));
+    }
+}
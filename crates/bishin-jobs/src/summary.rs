@@ -0,0 +1,82 @@
+use crate::{JobResult, Status};
+
+/// The results of every job in a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunSummary {
+    pub results: Vec<JobResult>,
+    /// The results of the scripts run before and after the tests, which
+    /// don't count towards the other numbers here.
+    pub hooks: Vec<JobResult>,
+    /// The names of the tests that filters left out of the run, which have
+    /// no results since they never ran.
+    pub filtered: Vec<String>,
+    /// The seed of the shuffled order the tests ran in, if they were.
+    pub seed: Option<u64>,
+}
+
+impl RunSummary {
+    pub fn count(&self, status: Status) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    }
+
+    /// The results of the jobs that were skipped.
+    pub fn skipped(&self) -> impl Iterator<Item = &JobResult> {
+        self.results
+            .iter()
+            .filter(|result| result.status == Status::Skipped)
+    }
+
+    /// The fraction of jobs that passed out of those that weren't skipped or
+    /// expected to fail, which is `1.0` if none of them ran.
+    pub fn pass_rate(&self) -> f64 {
+        let ran = self.results.len()
+            - self.count(Status::Skipped)
+            - self.count(Status::XFail)
+            - self.count(Status::XPass);
+        if ran == 0 {
+            return 1.0;
+        }
+        self.count(Status::Passed) as f64 / ran as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(statuses: &[Status]) -> RunSummary {
+        RunSummary {
+            results: statuses
+                .iter()
+                .enumerate()
+                .map(|(index, status)| JobResult {
+                    status: *status,
+                    ..JobResult::error(format!("t{index}"), "")
+                })
+                .collect(),
+            ..RunSummary::default()
+        }
+    }
+
+    #[test]
+    fn pass_rate_is_of_the_tests_that_ran() {
+        let summary = summary(&[
+            Status::Passed,
+            Status::Passed,
+            Status::Passed,
+            Status::Failed,
+            Status::Skipped,
+            Status::XFail,
+        ]);
+        assert_eq!(summary.pass_rate(), 0.75);
+    }
+
+    #[test]
+    fn pass_rate_without_tests_is_one() {
+        assert_eq!(summary(&[]).pass_rate(), 1.0);
+        assert_eq!(summary(&[Status::Skipped]).pass_rate(), 1.0);
+    }
+}
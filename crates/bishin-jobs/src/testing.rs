@@ -0,0 +1,33 @@
+//! Helpers for the tests in this crate.
+
+use std::collections::HashMap;
+
+pub(crate) use bishin_testing::scratch_dir;
+
+use crate::Job;
+
+/// A job called `name` that runs `script` with `sh`, and nothing else.
+pub(crate) fn sh(name: &str, script: &str) -> Job {
+    Job {
+        name: name.to_string(),
+        args: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        envs: HashMap::new(),
+        clean_env: None,
+        timeout: None,
+        dir: None,
+        tmp_dir: None,
+        retry: None,
+        snapshot: None,
+        xfail: None,
+        skip: None,
+        expected_exit: None,
+        bench: None,
+        resource_stats: false,
+        stdin: None,
+        ssh: None,
+        resources: Vec::new(),
+        leak_check: None,
+        tty: false,
+        skip_if_missing: false,
+    }
+}
@@ -0,0 +1,51 @@
+//! Giving a job a pseudo-terminal to write to, so that tools that check
+//! for a terminal behave as they would in one, while bishin still reads
+//! everything they write.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::ptr;
+
+/// A new pseudo-terminal, as the controller that reads what's written to
+/// the terminal, and the terminal itself.
+///
+/// The terminal passes output through as it's written, rather than turning
+/// each `\n` into `\r\n`, so it reads the same as it would from a pipe.
+pub(crate) fn open() -> io::Result<(File, File)> {
+    let (mut controller, mut terminal) = (-1, -1);
+    // SAFETY: openpty only writes the two descriptors, and the name, modes
+    // and window size it would otherwise take are optional.
+    let opened = unsafe {
+        libc::openpty(
+            &mut controller,
+            &mut terminal,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if opened == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: openpty opened both, and nothing else owns them.
+    let (controller, terminal) =
+        unsafe { (File::from_raw_fd(controller), File::from_raw_fd(terminal)) };
+    for file in [&controller, &terminal] {
+        // Only the job's stdout and stderr should hold the terminal open, or
+        // reading the controller would never end.
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    // SAFETY: termios is plain data that tcgetattr fills in.
+    let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(terminal.as_raw_fd(), &mut modes) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    modes.c_oflag &= !libc::OPOST;
+    if unsafe { libc::tcsetattr(terminal.as_raw_fd(), libc::TCSANOW, &modes) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((controller, terminal))
+}
@@ -0,0 +1,101 @@
+//! Measuring the CPU time and memory that a job's process used, which takes
+//! waiting for it with `wait4` instead of the standard library.
+
+#[cfg(unix)]
+use std::process::{Child, ExitStatus};
+use std::time::Duration;
+
+/// The resources a job's process and the processes it waited for used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// User and system CPU time together.
+    pub cpu_time: Duration,
+    /// The most memory resident at once, in bytes.
+    pub max_rss: u64,
+}
+
+/// Wait for `child` to exit, returning how it did and what it used.
+///
+/// Once this has returned, `child` has been reaped and must not be waited
+/// for again.
+#[cfg(unix)]
+pub(crate) fn wait(child: &Child) -> std::io::Result<(ExitStatus, ResourceUsage)> {
+    Ok(wait4(child, 0)?.expect("wait4 without WNOHANG waits for the child"))
+}
+
+/// Like [`wait`], without waiting if `child` is still running.
+#[cfg(unix)]
+pub(crate) fn try_wait(child: &Child) -> std::io::Result<Option<(ExitStatus, ResourceUsage)>> {
+    wait4(child, libc::WNOHANG)
+}
+
+#[cfg(unix)]
+fn wait4(
+    child: &Child,
+    flags: libc::c_int,
+) -> std::io::Result<Option<(ExitStatus, ResourceUsage)>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid = child.id() as libc::pid_t;
+    let mut status = 0;
+    // SAFETY: rusage is plain data that wait4 fills in.
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        match unsafe { libc::wait4(pid, &mut status, flags, &mut rusage) } {
+            0 => return Ok(None),
+            -1 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            _ => break,
+        }
+    }
+    let usage = ResourceUsage {
+        cpu_time: duration(rusage.ru_utime) + duration(rusage.ru_stime),
+        max_rss: max_rss_bytes(rusage.ru_maxrss),
+    };
+    Ok(Some((ExitStatus::from_raw(status), usage)))
+}
+
+#[cfg(unix)]
+fn duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+}
+
+/// `ru_maxrss` in bytes: macOS gives it in bytes, everything else in KiB.
+#[cfg(unix)]
+fn max_rss_bytes(max_rss: libc::c_long) -> u64 {
+    let max_rss = max_rss.max(0) as u64;
+    if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_add_up_seconds_and_microseconds() {
+        let time = libc::timeval {
+            tv_sec: 2,
+            tv_usec: 500_000,
+        };
+        assert_eq!(duration(time), Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn max_rss_is_in_bytes() {
+        let expected = if cfg!(target_os = "macos") {
+            4096
+        } else {
+            4096 * 1024
+        };
+        assert_eq!(max_rss_bytes(4096), expected);
+        assert_eq!(max_rss_bytes(-1), 0);
+    }
+}
@@ -0,0 +1,114 @@
+//! Human friendly durations such as `500ms`, `30s`, `2m`, and `1h`.
+
+use std::time::Duration;
+
+/// The formats accepted by [`parse_duration`], for use in error messages.
+pub const DURATION_FORMATS: &str =
+    "a whole number followed by ms, s, m, or h (e.g. 500ms, 30s, 2m)";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid duration {0:?}, expected {DURATION_FORMATS}")]
+pub struct InvalidDuration(pub String);
+
+/// Parse a duration made of a whole number and a unit suffix.
+pub fn parse_duration(value: &str) -> Result<Duration, InvalidDuration> {
+    let invalid = || InvalidDuration(value.to_string());
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => return Ok(Duration::from_millis(number)),
+        "s" => number,
+        "m" => number.checked_mul(60).ok_or_else(invalid)?,
+        "h" => number.checked_mul(60 * 60).ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Format a duration using the largest unit that represents it exactly, so
+/// that the output can be read back by [`parse_duration`].
+pub fn format_duration(duration: Duration) -> String {
+    if duration.subsec_nanos() != 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    let seconds = duration.as_secs();
+    match seconds {
+        0 => "0s".to_string(),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TimeoutError {
+    #[error(transparent)]
+    Invalid(#[from] InvalidDuration),
+    #[error("a test's timeout must be longer than zero")]
+    Zero,
+}
+
+/// Parse the argument of a `@timeout` decorator, which has to be nonzero.
+pub fn parse_timeout(value: &str) -> Result<Duration, TimeoutError> {
+    match parse_duration(value)? {
+        Duration::ZERO => Err(TimeoutError::Zero),
+        timeout => Ok(timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_take_each_unit() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("0s"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn invalid_durations_say_what_is_accepted() {
+        for value in [
+            "",
+            "30",
+            "s",
+            "1.5s",
+            "-1s",
+            "30 s",
+            "5d",
+            "99999999999999999999h",
+        ] {
+            let err = parse_duration(value).unwrap_err();
+            assert_eq!(err, InvalidDuration(value.to_string()));
+            assert!(err.to_string().contains(DURATION_FORMATS));
+        }
+    }
+
+    #[test]
+    fn formatted_durations_parse_back() {
+        for duration in [
+            Duration::ZERO,
+            Duration::from_millis(1500),
+            Duration::from_secs(45),
+            Duration::from_secs(120),
+            Duration::from_secs(7200),
+            Duration::from_secs(3660),
+        ] {
+            assert_eq!(parse_duration(&format_duration(duration)), Ok(duration));
+        }
+        assert_eq!(format_duration(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_duration(Duration::from_secs(90)), "90s");
+    }
+
+    #[test]
+    fn timeouts_must_be_nonzero() {
+        assert_eq!(parse_timeout("0ms"), Err(TimeoutError::Zero));
+        assert_eq!(parse_timeout("10s"), Ok(Duration::from_secs(10)));
+    }
+}
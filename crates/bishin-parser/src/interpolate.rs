@@ -0,0 +1,71 @@
+//! `${NAME}` references in decorator arguments, which are resolved when
+//! scripts are generated rather than when the file is parsed.
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InterpolateError {
+    #[error("${{{0}}} is not set")]
+    Unset(String),
+    #[error("unterminated variable reference in {0:?}")]
+    Unterminated(String),
+}
+
+/// Whether `value` refers to any variables.
+pub fn has_variables(value: &str) -> bool {
+    value.contains("${")
+}
+
+/// Replace each `${NAME}` in `value` with the result of `lookup(NAME)`.
+pub fn interpolate(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, InterpolateError> {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| InterpolateError::Unterminated(value.to_string()))?;
+        let name = &after[..end];
+        let resolved = lookup(name).ok_or_else(|| InterpolateError::Unset(name.to_string()))?;
+        output.push_str(&resolved);
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "HOST").then(|| "example.com".to_string())
+    }
+
+    #[test]
+    fn references_are_replaced() {
+        assert_eq!(
+            interpolate("https://${HOST}/api/${HOST}", lookup).unwrap(),
+            "https://example.com/api/example.com"
+        );
+        assert_eq!(interpolate("plain $HOST", lookup).unwrap(), "plain $HOST");
+    }
+
+    #[test]
+    fn unresolved_references_are_errors() {
+        assert_eq!(
+            interpolate("${PORT}", lookup),
+            Err(InterpolateError::Unset("PORT".to_string()))
+        );
+        assert_eq!(
+            interpolate("a${HOST", lookup),
+            Err(InterpolateError::Unterminated("a${HOST".to_string()))
+        );
+        assert_eq!(
+            InterpolateError::Unset("PORT".to_string()).to_string(),
+            "${PORT} is not set"
+        );
+    }
+}
@@ -0,0 +1,463 @@
+//! Parsing for bishin test files.
+//!
+//! A test file contains any number of tests, each of which is a header line
+//! followed by a shell body and a closing brace:
+//!
+//! ```text
+//! @env(GREETING=hello)
+//! @test says_hello {
+//!     echo "$GREETING"
+//! }
+//! ```
+//!
+//! The closing brace may be indented too, as long as it's indented less than
+//! the body, so that the braces of blocks in the body are told apart from it.
+//!
+//! Decorators such as `@env(...)`, `@timeout(30s)`, `@tags(slow)`,
+//! `@xfail("known bug")`, `@flaky` and
+//! `@fixture("data/input.txt" -> "input.txt")` may appear on the lines
+//! directly above a `@test` header and apply to that test only.
+//!
+//! A test marked `@bench(iterations = 20, warmup = 3)` is a benchmark, which
+//! is timed over several runs and only run when benchmarks are asked for.
+//!
+//! Files that a test leaves in its temp dir, such as logs, are kept for later
+//! with `@artifact("logs/*.log")`, and a test reads a file as its stdin with
+//! `@stdin_file("input.txt")`.
+//!
+//! Tests that share something there's only so much of, such as a database,
+//! say so with `@resource(db, max=2)`, and no more than `max` of the tests
+//! using it run at once, or one at a time without a `max`.
+//!
+//! A test written for particular shells lists them with `@shells(bash, fish)`,
+//! each only once.
+//!
+//! A test that passes by failing in a particular way says how with `@exit(2)`,
+//! or `@exit(nonzero)` for any code but 0.
+//!
+//! A test that's not ready to run is reported as skipped with `@skip` or
+//! `@skip("reason")`, and one that needs a terminal to write to, such as to
+//! print colors, gets one with `@tty`.
+//!
+//! Shell code shared by several tests, such as helper functions, can be kept
+//! in a file of its own and put before each test's body with
+//! `@include("_helpers.b")`.
+//!
+//! A test can also take its body from a shell script elsewhere, which keeps
+//! the script usable on its own, with `@test name = "scripts/name.sh"`.
+//!
+//! Tests that differ only in name can share a body with
+//! `@test_for(first, second) { ... }`, which declares one test per name,
+//! each with the decorators above it. The body can tell them apart by
+//! `$BISHIN_TEST_NAME`.
+//!
+//! A file can also have one `setup { ... }` block, whose code runs before
+//! the body of each of its tests, and one `teardown { ... }` block, whose
+//! code runs after each body however it ended, both outside of any test.
+//! They can also be written `@setup { ... }` and `@teardown { ... }`, but
+//! either way there's only one of each.
+//!
+//! Lines may end with `\n` or `\r\n`, mixed in the same file however they
+//! are, and bodies always end their lines with `\n`. The last line of a file
+//! doesn't need to end with either.
+//!
+//! Lines starting with `#` between and around tests are comments and are
+//! left out. In a body they're kept as they are, being shell comments too,
+//! but nothing may follow a test's closing brace on its line.
+//!
+//! Tools that need to know where each of these is in the file, rather than
+//! what it means, can use its [`syntax`] tree instead.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::duration::{parse_timeout, TimeoutError};
+use crate::interpolate::{interpolate, InterpolateError};
+use crate::syntax::SyntaxKind;
+
+pub mod duration;
+pub mod interpolate;
+mod parser;
+mod shell;
+pub mod syntax;
+
+pub use shell::{Shell, UnknownShell};
+
+/// What a test file declares: its tests, and the shell code that goes
+/// around each of their bodies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestFile {
+    /// The body of the file's `setup { ... }` block, which runs before each
+    /// test's body, as it's written.
+    pub setup: Option<String>,
+    /// The body of the file's `teardown { ... }` block, which runs once each
+    /// test's body has, however it ended, as it's written.
+    pub teardown: Option<String>,
+    pub tests: Vec<Test>,
+}
+
+/// A single test parsed from a test file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Test {
+    /// The name given in the `@test` header.
+    pub name: String,
+    /// The lines between the header and the closing brace, verbatim.
+    ///
+    /// For a test whose body is in another file this is empty until the file
+    /// is read, see [`Test::body_file`].
+    pub body: String,
+    /// The script that holds the body, relative to the test file, from
+    /// `@test name = "path/to/script.sh"`.
+    pub body_file: Option<String>,
+    /// The line that the test's own part of the body starts on, counting
+    /// from 1, in the test file or, once it's read, the body file. Zero when
+    /// the test isn't from a file.
+    pub body_line: usize,
+    /// How many lines at the start of the body are the code of `@include`s
+    /// and of the file's `setup` block, which is put there once the includes
+    /// are read.
+    pub included_lines: usize,
+    /// Shell code that runs once the body has, however it ended, which is
+    /// the `teardown` block of the test's file once generation puts it here.
+    pub teardown: Option<String>,
+    /// Environment variables set by `@env` decorators.
+    pub env: HashMap<String, String>,
+    /// How long the test may run for, set by `@timeout`.
+    pub timeout: Option<Interpolated<Duration>>,
+    /// Files to copy into the test's directory, set by `@fixture`.
+    pub fixtures: Vec<Fixture>,
+    /// How many times to retry the test if it fails, set by `@retries`.
+    pub retries: Option<Retries>,
+    /// Labels for choosing which tests to run, set by `@tags(slow, network)`,
+    /// in the order they were first given.
+    pub tags: Vec<String>,
+    /// Whether the test is known to fail, set by `@xfail`.
+    pub xfail: Option<XFail>,
+    /// Whether the test is reported as skipped instead of being run, set by
+    /// `@skip`.
+    pub skip: Option<Skip>,
+    /// The exit code the test passes with, set by `@exit(N)`, or `None` for
+    /// 0.
+    pub expected_exit: Option<ExpectedExit>,
+    /// Whether the test is quarantined, set by `@flaky`.
+    pub flaky: Option<Flaky>,
+    /// Whether the test is a benchmark, set by `@bench`.
+    pub bench: Option<Bench>,
+    /// Whether the script leaves out the strict shell options the config
+    /// turns on, set by `@no_strict`.
+    pub no_strict: bool,
+    /// Whether the test's stdout must match its snapshot, set by
+    /// `@snapshot`.
+    pub snapshot: bool,
+    /// Whether the test runs with a terminal for its stdout and stderr,
+    /// set by `@tty`.
+    pub tty: bool,
+    /// Files of shell code to put before the body, relative to the test file
+    /// and in the order given, set by `@include("_helpers.b")`.
+    pub includes: Vec<String>,
+    /// Gitignore-style patterns for the files in the test's temp dir to keep
+    /// once it has run, set by `@artifact("logs/*.log")`.
+    pub artifacts: Vec<String>,
+    /// A file whose contents are the test's stdin, relative to the test
+    /// file, set by `@stdin_file("input.txt")`.
+    pub stdin_file: Option<String>,
+    /// The resources that only so many tests may use at once, set by
+    /// `@resource(db, max=2)`.
+    pub resources: Vec<Resource>,
+    /// The shells that the test is meant to run with, set by
+    /// `@shells(bash, fish)`, or `None` for the one the config says, which
+    /// is bash unless it says otherwise.
+    pub shells: Option<Vec<Shell>>,
+}
+
+/// Something that at most `max` tests may use at once, from `@resource(db)`
+/// or `@resource(db, max=2)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    pub name: String,
+    /// How many tests may use it at once, at least one.
+    pub max: u32,
+}
+
+/// A test that's expected to fail, from `@xfail` or `@xfail("reason")`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XFail {
+    pub reason: Option<String>,
+}
+
+/// A test that's never run, from `@skip` or `@skip("reason")`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Skip {
+    /// The reason given, or `None` for `@skip` and `@skip("")` alike.
+    pub reason: Option<String>,
+}
+
+/// The exit code a test passes with, from `@exit(3)` or `@exit(nonzero)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedExit {
+    Code(u8),
+    /// Any code but 0.
+    NonZero,
+}
+
+/// A test whose failures don't fail the run, from `@flaky` or
+/// `@flaky(20%)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Flaky {
+    /// The percentage of its recorded runs that the test may fail in and
+    /// stay quarantined. A test that fails more often than that is broken
+    /// rather than flaky.
+    pub max_failure_percent: Option<u8>,
+}
+
+/// A test that's timed over several runs, from `@bench` or
+/// `@bench(iterations = 20, warmup = 3)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bench {
+    /// How many runs are timed, at least one.
+    pub iterations: u32,
+    /// How many untimed runs come before them.
+    pub warmup: u32,
+}
+
+impl Bench {
+    pub const DEFAULT_ITERATIONS: u32 = 10;
+}
+
+impl Default for Bench {
+    fn default() -> Self {
+        Self {
+            iterations: Self::DEFAULT_ITERATIONS,
+            warmup: 0,
+        }
+    }
+}
+
+/// When to run a failed test again, from `@retries(3)` or
+/// `@retries(3, on=[timeout, 75])`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retries {
+    /// How many more times to run the test after it first fails.
+    pub count: u32,
+    /// The failures worth retrying, or empty to retry any failure.
+    pub on: Vec<RetryOn>,
+}
+
+/// A kind of failure that [`Retries`] can be limited to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOn {
+    Timeout,
+    ExitCode(i32),
+}
+
+/// A file or directory that a test needs, from `@fixture("src" -> "dest")`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    /// The path to copy, relative to the test file.
+    pub src: String,
+    /// Where to copy it to, relative to the directory the test runs in.
+    pub dest: String,
+}
+
+/// A decorator argument that may refer to variables with `${NAME}`.
+///
+/// Arguments without references are checked when the file is parsed, the
+/// rest only once the variables are known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Interpolated<T> {
+    Value(T),
+    Template(String),
+}
+
+impl Interpolated<Duration> {
+    /// The timeout, looking up any variables it refers to with `lookup`.
+    pub fn resolve(
+        &self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Duration, ResolveError> {
+        match self {
+            Interpolated::Value(timeout) => Ok(*timeout),
+            Interpolated::Template(template) => {
+                let value = interpolate(template, lookup)?;
+                parse_timeout(&value).map_err(|source| ResolveError::Timeout { value, source })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ResolveError {
+    #[error(transparent)]
+    Interpolate(#[from] InterpolateError),
+    #[error("@timeout resolved to {value:?}")]
+    Timeout {
+        value: String,
+        #[source]
+        source: TimeoutError,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The file doesn't parse at `line` and `column`, both counted from 1,
+    /// where `snippet` is the whole of that line and `message` says what
+    /// was expected there.
+    #[error(
+        "invalid syntax at line {line}, column {column}\n{}",
+        pointer(*.line, *.column, .snippet, .message)
+    )]
+    Parse {
+        line: usize,
+        column: usize,
+        snippet: String,
+        message: String,
+    },
+}
+
+impl Error {
+    /// The error for `input` failing to parse at the byte `offset`.
+    fn parse(input: &str, offset: usize, message: String) -> Self {
+        let before = &input[..offset];
+        let start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |newline| offset + newline);
+        Error::Parse {
+            line: before.matches('\n').count() + 1,
+            column: before[start..].chars().count() + 1,
+            snippet: input[start..end].trim_end_matches('\r').to_string(),
+            message,
+        }
+    }
+}
+
+/// The line `snippet` numbered as `line`, with a caret under `column` and
+/// `message` after it.
+fn pointer(line: usize, column: usize, snippet: &str, message: &str) -> String {
+    let gutter = " ".repeat(line.to_string().len());
+    // Tabs are kept so that the caret lines up however wide they're shown.
+    let indent: String = snippet
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!("{gutter} |\n{line} | {snippet}\n{gutter} | {indent}^\n{message}")
+}
+
+/// Parse the contents of a test file into the tests and blocks it declares.
+pub fn parse_test_file(input: &str) -> Result<TestFile, Error> {
+    let tree = syntax::parse(input)?;
+    let mut seen = Vec::new();
+    for block in tree
+        .root()
+        .child_nodes()
+        .filter(|node| node.kind() == SyntaxKind::Block)
+    {
+        let keyword = parser::block_keyword(block);
+        if seen.contains(&keyword) {
+            return Err(Error::parse(
+                input,
+                block.span().start,
+                format!("the file already has a {keyword} block, and can only have one"),
+            ));
+        }
+        seen.push(keyword);
+    }
+    let mut names = Vec::new();
+    for name in tree.tests().flat_map(|test| test.test_names()) {
+        if names.contains(&name.text()) {
+            return Err(Error::parse(
+                input,
+                name.span().start,
+                format!("the file already has a test named {}", name.text()),
+            ));
+        }
+        names.push(name.text());
+    }
+    Ok(parser::lower(tree.root()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where and why `input` fails to parse.
+    fn failure(input: &str) -> (usize, usize, String, String) {
+        match parse_test_file(input) {
+            Err(Error::Parse {
+                line,
+                column,
+                snippet,
+                message,
+            }) => (line, column, snippet, message),
+            Ok(file) => panic!("parsed {file:?}"),
+        }
+    }
+
+    #[test]
+    fn errors_in_a_header_point_at_it() {
+        let input = "@test a {\n    true\n}\n\n@test bad name {\n    true\n}\n";
+        let (line, column, snippet, message) = failure(input);
+        assert_eq!((line, column), (5, 11));
+        assert_eq!(snippet, "@test bad name {");
+        assert!(message.starts_with("expected `{`"), "{message}");
+        assert_eq!(
+            parse_test_file(input).unwrap_err().to_string(),
+            format!("invalid syntax at line 5, column 11\n  |\n5 | @test bad name {{\n  |           ^\n{message}")
+        );
+    }
+
+    #[test]
+    fn errors_in_a_decorator_point_at_it() {
+        let (line, column, snippet, _) =
+            failure("@test a {\n    true\n}\n@timeout(soon)\n@test b {\n    true\n}\n");
+        assert_eq!((line, column), (4, 10));
+        assert_eq!(snippet, "@timeout(soon)");
+    }
+
+    #[test]
+    fn bodies_left_open_fail_at_the_end_of_the_file() {
+        let (line, column, snippet, message) =
+            failure("@test a {\n    true\n}\n\n@test b {\n    echo hi\n");
+        assert_eq!((line, column), (7, 1));
+        assert_eq!(snippet, "");
+        assert_eq!(message, "expected `}`");
+        // Without a newline at the end, the error is at the end of the last line.
+        let (line, column, snippet, _) = failure("@test a {\n    true");
+        assert_eq!((line, column), (2, 9));
+        assert_eq!(snippet, "    true");
+    }
+
+    #[test]
+    fn text_left_at_the_end_of_the_file_is_an_error() {
+        let (line, column, snippet, message) = failure("@test a {\n    true\n}\n@tes");
+        assert_eq!((line, column), (4, 1));
+        assert_eq!(snippet, "@tes");
+        assert_eq!(message, "unexpected text");
+    }
+
+    #[test]
+    fn tests_with_the_same_name_point_at_the_second() {
+        let (line, column, snippet, message) =
+            failure("@test a {\n    true\n}\n@test_for(b, a) {\n    true\n}\n");
+        assert_eq!((line, column), (4, 14));
+        assert_eq!(snippet, "@test_for(b, a) {");
+        assert_eq!(message, "the file already has a test named a");
+    }
+
+    #[test]
+    fn snippets_leave_out_carriage_returns() {
+        let (line, _, snippet, _) = failure("@test a {\r\n    true\r\n}\r\n@test b c {\r\n}\r\n");
+        assert_eq!(line, 4);
+        assert_eq!(snippet, "@test b c {");
+    }
+
+    #[test]
+    fn carets_line_up_under_tabs_and_wide_line_numbers() {
+        assert_eq!(
+            pointer(12, 4, "\t\tab", "expected `{`"),
+            "   |\n12 | \t\tab\n   | \t\t ^\nexpected `{`"
+        );
+        assert_eq!(pointer(1, 1, "", "m"), "  |\n1 | \n  | ^\nm");
+    }
+}
@@ -0,0 +1,1516 @@
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::time::Duration;
+
+use winnow::ascii::{dec_int, dec_uint, line_ending, space0, space1};
+use winnow::combinator::{
+    alt, cut_err, delimited, eof, fail, not, opt, peek, preceded, repeat, separated,
+    separated_pair, terminated,
+};
+use winnow::error::{StrContext, StrContextValue};
+use winnow::prelude::*;
+use winnow::token::{none_of, one_of, take_till, take_while};
+
+use crate::duration::parse_timeout;
+use crate::interpolate::has_variables;
+use crate::syntax::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::{
+    Bench, ExpectedExit, Fixture, Flaky, Interpolated, Resource, Retries, RetryOn, Shell, Skip,
+    Test, TestFile, XFail,
+};
+
+/// A decorator line attached to the test that follows it.
+enum Decorator {
+    Env(Vec<(String, String)>),
+    Timeout(Interpolated<Duration>),
+    Fixture(Fixture),
+    Retries(Retries),
+    Tags(Vec<String>),
+    XFail(XFail),
+    Skip(Skip),
+    Exit(ExpectedExit),
+    Flaky(Flaky),
+    Bench(Bench),
+    NoStrict,
+    Include(String),
+    Artifact(String),
+    StdinFile(String),
+    Resource(Resource),
+    Shells(Vec<Shell>),
+    Snapshot,
+    Tty,
+}
+
+type Elements<'s> = Vec<SyntaxElement<'s>>;
+
+fn token(kind: SyntaxKind, text: &str) -> SyntaxElement<'_> {
+    SyntaxElement::Token(SyntaxToken::new(kind, text))
+}
+
+fn node(kind: SyntaxKind, children: Elements<'_>) -> SyntaxElement<'_> {
+    SyntaxElement::Node(SyntaxNode::new(kind, children))
+}
+
+/// The tree of a whole test file: its tests and blocks, and the blank space
+/// before and after each.
+pub(crate) fn file<'s>(input: &mut &'s str) -> ModalResult<SyntaxNode<'s>> {
+    let mut children = trivia(input)?;
+    let items: Vec<(SyntaxNode<'s>, Elements<'s>)> =
+        repeat(0.., (alt((block_node, test)), trivia)).parse_next(input)?;
+    for (item, trivia) in items {
+        children.push(SyntaxElement::Node(item));
+        children.extend(trivia);
+    }
+    Ok(SyntaxNode::new(SyntaxKind::File, children))
+}
+
+/// The tests and blocks that the tree of a file declares.
+pub(crate) fn lower(file: &SyntaxNode) -> TestFile {
+    let text = file.text();
+    let mut test_file = TestFile::default();
+    for node in file.child_nodes() {
+        match node.kind() {
+            SyntaxKind::Test => test_file.tests.extend(lower_test(node, &text)),
+            SyntaxKind::Block => {
+                let body = node
+                    .child_nodes()
+                    .find(|child| child.kind() == SyntaxKind::Body)
+                    .map(body_text)
+                    .unwrap_or_default();
+                match block_keyword(node) {
+                    "setup" => test_file.setup = Some(body),
+                    _ => test_file.teardown = Some(body),
+                }
+            }
+            _ => {}
+        }
+    }
+    test_file
+}
+
+/// Whether a `Block` node is a `setup` or a `teardown`, however it's
+/// spelled.
+pub(crate) fn block_keyword<'s>(node: &SyntaxNode<'s>) -> &'s str {
+    node.tokens()
+        .find(|token| token.kind() == SyntaxKind::BlockKeyword)
+        .map(|token| token.text().trim_start_matches('@'))
+        .expect("a block starts with its keyword")
+}
+
+/// The lines of a `Body` node, as they're written.
+fn body_text(node: &SyntaxNode) -> String {
+    let mut body = String::new();
+    for token in node.tokens() {
+        match token.kind() {
+            SyntaxKind::Newline => body.push('\n'),
+            _ => body.push_str(token.text()),
+        }
+    }
+    body
+}
+
+/// The test of a `@test`, or the several tests of a `@test_for`, which share
+/// their decorators and body.
+fn lower_test(node: &SyntaxNode, text: &str) -> Vec<Test> {
+    let mut test = Test {
+        name: String::new(),
+        body: String::new(),
+        body_file: None,
+        body_line: 0,
+        included_lines: 0,
+        teardown: None,
+        env: HashMap::new(),
+        timeout: None,
+        fixtures: Vec::new(),
+        retries: None,
+        tags: Vec::new(),
+        xfail: None,
+        skip: None,
+        expected_exit: None,
+        flaky: None,
+        bench: None,
+        no_strict: false,
+        includes: Vec::new(),
+        snapshot: false,
+        artifacts: Vec::new(),
+        stdin_file: None,
+        resources: Vec::new(),
+        shells: None,
+        tty: false,
+    };
+    for child in node.children() {
+        match child {
+            SyntaxElement::Node(child) if child.kind() == SyntaxKind::Decorator => {
+                let decorator = decorator
+                    .parse(child.text().as_str())
+                    .expect("the tree only holds decorators that parse");
+                apply(&mut test, decorator);
+            }
+            SyntaxElement::Token(token) if token.kind() == SyntaxKind::OpenBrace => {
+                // The body starts on the line after its opening brace.
+                test.body_line = text[..token.span().start].matches('\n').count() + 2;
+            }
+            SyntaxElement::Node(child) if child.kind() == SyntaxKind::Body => {
+                test.body.push_str(&body_text(child));
+            }
+            SyntaxElement::Token(token) if token.kind() == SyntaxKind::BodyPath => {
+                let path = quoted_string
+                    .parse(token.text())
+                    .expect("the tree only holds paths that parse");
+                test.body_file = Some(path);
+            }
+            _ => {}
+        }
+    }
+    node.test_names()
+        .map(|name| Test {
+            name: name.text().to_string(),
+            ..test.clone()
+        })
+        .collect()
+}
+
+fn apply(test: &mut Test, decorator: Decorator) {
+    match decorator {
+        Decorator::Env(pairs) => test.env.extend(pairs),
+        Decorator::Timeout(timeout) => test.timeout = Some(timeout),
+        Decorator::Fixture(fixture) => test.fixtures.push(fixture),
+        Decorator::Retries(retries) => test.retries = Some(retries),
+        Decorator::XFail(xfail) => test.xfail = Some(xfail),
+        Decorator::Skip(skip) => test.skip = Some(skip),
+        Decorator::Exit(exit) => test.expected_exit = Some(exit),
+        Decorator::Flaky(flaky) => test.flaky = Some(flaky),
+        Decorator::Bench(bench) => test.bench = Some(bench),
+        Decorator::NoStrict => test.no_strict = true,
+        Decorator::Include(path) => test.includes.push(path),
+        Decorator::Artifact(pattern) => test.artifacts.push(pattern),
+        Decorator::StdinFile(path) => test.stdin_file = Some(path),
+        // A resource given twice takes the last max, like other decorators.
+        Decorator::Resource(resource) => {
+            test.resources.retain(|other| other.name != resource.name);
+            test.resources.push(resource);
+        }
+        Decorator::Shells(shells) => test.shells = Some(shells),
+        Decorator::Snapshot => test.snapshot = true,
+        Decorator::Tty => test.tty = true,
+        Decorator::Tags(tags) => {
+            for tag in tags {
+                if !test.tags.contains(&tag) {
+                    test.tags.push(tag);
+                }
+            }
+        }
+    }
+}
+
+/// Spaces, tabs, line endings and comments, which only ever start at the
+/// start of a line.
+fn trivia<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    repeat(
+        0..,
+        alt((
+            space1.map(|text| token(SyntaxKind::Whitespace, text)),
+            line_ending.map(|text| token(SyntaxKind::Newline, text)),
+            ('#', line_text)
+                .take()
+                .map(|text| token(SyntaxKind::Comment, text)),
+            // A carriage return that doesn't end a line.
+            "\r".map(|text| token(SyntaxKind::Whitespace, text)),
+        )),
+    )
+    .parse_next(input)
+}
+
+/// Any spaces and tabs.
+fn space<'s>(input: &mut &'s str) -> ModalResult<Option<SyntaxElement<'s>>> {
+    space0
+        .map(|text: &'s str| (!text.is_empty()).then(|| token(SyntaxKind::Whitespace, text)))
+        .parse_next(input)
+}
+
+/// A `@test` or `@test_for` with its decorators and body.
+fn test<'s>(input: &mut &'s str) -> ModalResult<SyntaxNode<'s>> {
+    let mut children = Vec::new();
+    let decorators: Vec<(SyntaxNode<'s>, Elements<'s>)> =
+        repeat(0.., (decorator_node, decorator_end)).parse_next(input)?;
+    for (decorator, end) in decorators {
+        children.push(SyntaxElement::Node(decorator));
+        children.extend(end);
+    }
+    children.push(SyntaxElement::Node(
+        alt((test_for_header, header)).parse_next(input)?,
+    ));
+    let (before, opener) = cut_err((space, alt(("{", "="))))
+        .context(StrContext::Expected(StrContextValue::CharLiteral('{')))
+        .context(StrContext::Expected(StrContextValue::StringLiteral(
+            "= \"path\"",
+        )))
+        .parse_next(input)?;
+    children.extend(before);
+    if opener == "{" {
+        children.push(token(SyntaxKind::OpenBrace, opener));
+        children.extend(cut_err(block).parse_next(input)?);
+    } else {
+        children.push(token(SyntaxKind::Equals, opener));
+        children.extend(cut_err(body_file).parse_next(input)?);
+    }
+    Ok(SyntaxNode::new(SyntaxKind::Test, children))
+}
+
+/// A `setup { ... }` or `teardown { ... }` block, either of which may start
+/// with an `@`.
+fn block_node<'s>(input: &mut &'s str) -> ModalResult<SyntaxNode<'s>> {
+    let (keyword, before, brace) = (
+        alt(("setup", "teardown", "@setup", "@teardown")),
+        space,
+        "{",
+    )
+        .parse_next(input)?;
+    let mut children = vec![token(SyntaxKind::BlockKeyword, keyword)];
+    children.extend(before);
+    children.push(token(SyntaxKind::OpenBrace, brace));
+    children.extend(cut_err(block).parse_next(input)?);
+    Ok(SyntaxNode::new(SyntaxKind::Block, children))
+}
+
+fn decorator_node<'s>(input: &mut &'s str) -> ModalResult<SyntaxNode<'s>> {
+    decorator
+        .take()
+        .map(|text: &'s str| {
+            let (name, args) = text.split_at(text.find('(').unwrap_or(text.len()));
+            let mut children = vec![token(SyntaxKind::DecoratorName, name)];
+            if !args.is_empty() {
+                children.push(token(SyntaxKind::DecoratorArgs, args));
+            }
+            SyntaxNode::new(SyntaxKind::Decorator, children)
+        })
+        .parse_next(input)
+}
+
+/// The end of a decorator's line and any blank lines after it.
+fn decorator_end<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    (space, line_ending, trivia)
+        .map(|(space, newline, trivia)| {
+            space
+                .into_iter()
+                .chain([token(SyntaxKind::Newline, newline)])
+                .chain(trivia)
+                .collect()
+        })
+        .parse_next(input)
+}
+
+fn decorator(input: &mut &str) -> ModalResult<Decorator> {
+    alt((
+        env_decorator.map(Decorator::Env),
+        timeout_decorator.map(Decorator::Timeout),
+        fixture_decorator.map(Decorator::Fixture),
+        retries_decorator.map(Decorator::Retries),
+        tags_decorator.map(Decorator::Tags),
+        // A single `alt` takes at most nine parsers.
+        alt((
+            xfail_decorator.map(Decorator::XFail),
+            flaky_decorator.map(Decorator::Flaky),
+            bench_decorator.map(Decorator::Bench),
+            skip_decorator.map(Decorator::Skip),
+            exit_decorator.map(Decorator::Exit),
+        )),
+        "@no_strict".map(|_| Decorator::NoStrict),
+        alt((
+            include_decorator.map(Decorator::Include),
+            artifact_decorator.map(Decorator::Artifact),
+            stdin_file_decorator.map(Decorator::StdinFile),
+            resource_decorator.map(Decorator::Resource),
+            shells_decorator.map(Decorator::Shells),
+        )),
+        alt((
+            "@snapshot".map(|_| Decorator::Snapshot),
+            "@tty".map(|_| Decorator::Tty),
+        )),
+    ))
+    .parse_next(input)
+}
+
+fn header<'s>(input: &mut &'s str) -> ModalResult<SyntaxNode<'s>> {
+    ("@test", space1, cut_err(name))
+        .map(|(keyword, space, name)| {
+            SyntaxNode::new(
+                SyntaxKind::Header,
+                vec![
+                    token(SyntaxKind::TestKeyword, keyword),
+                    token(SyntaxKind::Whitespace, space),
+                    token(SyntaxKind::TestName, name),
+                ],
+            )
+        })
+        .context(StrContext::Label("test header"))
+        .parse_next(input)
+}
+
+fn test_for_header<'s>(input: &mut &'s str) -> ModalResult<SyntaxNode<'s>> {
+    ("@test_for", cut_err(test_for_names))
+        .map(|(keyword, names)| {
+            let mut children = vec![token(SyntaxKind::TestKeyword, keyword)];
+            children.extend(names);
+            SyntaxNode::new(SyntaxKind::Header, children)
+        })
+        .context(StrContext::Label("@test_for header"))
+        .parse_next(input)
+}
+
+/// The parenthesized names of a `@test_for`.
+fn test_for_names<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    let mut names = Vec::new();
+    names.extend(space(input)?);
+    names.push(token(SyntaxKind::LParen, "(".parse_next(input)?));
+    names.extend(space(input)?);
+    names.push(token(SyntaxKind::TestName, name(input)?));
+    let rest: Vec<(Elements<'s>, &'s str)> = repeat(0.., (separator, name)).parse_next(input)?;
+    for (separator, name) in rest {
+        names.extend(separator);
+        names.push(token(SyntaxKind::TestName, name));
+    }
+    names.extend(space(input)?);
+    names.push(token(SyntaxKind::RParen, ")".parse_next(input)?));
+    Ok(names)
+}
+
+/// The rest of a body between braces, after the opening one.
+fn block<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    let mut elements = Vec::new();
+    elements.extend(space(input)?);
+    elements.push(token(SyntaxKind::Newline, line_ending(input)?));
+    elements.push(node(SyntaxKind::Body, body(input)?));
+    elements.extend(terminator(input)?);
+    Ok(elements)
+}
+
+/// The rest of `@test name = "path"` after the `=`, where the file at the
+/// path holds the body.
+fn body_file<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    (
+        space,
+        quoted_string
+            .take()
+            .context(StrContext::Label("script path"))
+            .context(StrContext::Expected(StrContextValue::Description(
+                "a quoted path",
+            ))),
+        space,
+        line_end,
+    )
+        .map(|(before, path, after, newline)| {
+            before
+                .into_iter()
+                .chain([token(SyntaxKind::BodyPath, path)])
+                .chain(after)
+                .chain(newline.map(|newline| token(SyntaxKind::Newline, newline)))
+                .collect()
+        })
+        .parse_next(input)
+}
+
+fn name<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
+    take_while(1.., |c: char| c.is_ascii_alphanumeric() || c == '_')
+        .context(StrContext::Label("test name"))
+        .parse_next(input)
+}
+
+/// The lines of a body, up to the line with its closing brace.
+///
+/// A `}` at the start of a line closes the body, as does one indented less
+/// than the body's first line that isn't blank, so that braces closing
+/// blocks of shell code in the body are left to it.
+fn body<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    let mut body = Vec::new();
+    let mut indent = None;
+    while let Some((line, newline)) =
+        opt(move |input: &mut &'s str| line(input, indent)).parse_next(input)?
+    {
+        if !line.trim().is_empty() {
+            indent.get_or_insert(indentation(line));
+        }
+        // An empty line is only its line ending.
+        if !line.is_empty() {
+            body.push(token(SyntaxKind::BodyLine, line));
+        }
+        body.extend(newline.map(|newline| token(SyntaxKind::Newline, newline)));
+    }
+    Ok(body)
+}
+
+/// A line of a body whose first line that isn't blank is indented by
+/// `indent`, if it's had one yet, and its line ending, which the last line
+/// of a file may not have.
+fn line<'s>(input: &mut &'s str, indent: Option<usize>) -> ModalResult<(&'s str, Option<&'s str>)> {
+    (line_text, line_end)
+        .verify(|(line, newline): &(&str, Option<&str>)| {
+            if line.is_empty() && newline.is_none() {
+                return false;
+            }
+            let closes =
+                indentation(line) == 0 || indent.is_none_or(|indent| indentation(line) < indent);
+            !(line.trim_start().starts_with('}') && closes)
+        })
+        .parse_next(input)
+}
+
+/// The text of a line up to its line ending, which may have carriage
+/// returns that don't end it.
+fn line_text<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
+    repeat::<_, _, (), _, _>(
+        0..,
+        alt((
+            take_till(1.., ['\r', '\n']).void(),
+            ('\r', not('\n')).void(),
+        )),
+    )
+    .take()
+    .parse_next(input)
+}
+
+/// How many bytes of spaces and tabs `line` starts with.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+fn terminator<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    (
+        space,
+        "}".context(StrContext::Expected(StrContextValue::CharLiteral('}'))),
+        cut_err(line_end).context(StrContext::Expected(StrContextValue::Description(
+            "the end of the line after `}`, since comments go on lines of their own",
+        ))),
+    )
+        .map(|(space, brace, newline)| {
+            space
+                .into_iter()
+                .chain([token(SyntaxKind::CloseBrace, brace)])
+                .chain(newline.map(|newline| token(SyntaxKind::Newline, newline)))
+                .collect()
+        })
+        .parse_next(input)
+}
+
+/// A line ending, or `None` at the end of a file that doesn't end with one.
+fn line_end<'s>(input: &mut &'s str) -> ModalResult<Option<&'s str>> {
+    alt((line_ending.map(Some), eof.value(None))).parse_next(input)
+}
+
+/// A [`list_sep`] as it's written.
+fn separator<'s>(input: &mut &'s str) -> ModalResult<Elements<'s>> {
+    (space, ",", space)
+        .map(|(before, comma, after)| {
+            before
+                .into_iter()
+                .chain([token(SyntaxKind::Comma, comma)])
+                .chain(after)
+                .collect()
+        })
+        .parse_next(input)
+}
+
+fn list_sep(input: &mut &str) -> ModalResult<()> {
+    (space0, ',', space0).void().parse_next(input)
+}
+
+fn env_decorator(input: &mut &str) -> ModalResult<Vec<(String, String)>> {
+    preceded(
+        "@env",
+        cut_err(delimited(
+            ('(', space0),
+            separated(1.., env_pair, list_sep),
+            (space0, ')'),
+        )),
+    )
+    .context(StrContext::Label("@env decorator"))
+    .parse_next(input)
+}
+
+fn timeout_decorator(input: &mut &str) -> ModalResult<Interpolated<Duration>> {
+    preceded(
+        "@timeout",
+        cut_err(delimited(
+            ('(', space0),
+            take_till(1.., [')', ' ', '\t', '\r', '\n']).try_map(|value: &str| {
+                if has_variables(value) {
+                    Ok(Interpolated::Template(value.to_string()))
+                } else {
+                    parse_timeout(value).map(Interpolated::Value)
+                }
+            }),
+            (space0, ')'),
+        )),
+    )
+    .context(StrContext::Label("@timeout decorator"))
+    .parse_next(input)
+}
+
+fn fixture_decorator(input: &mut &str) -> ModalResult<Fixture> {
+    preceded(
+        "@fixture",
+        cut_err(delimited(
+            ('(', space0),
+            separated_pair(
+                quoted_string.context(StrContext::Label("fixture source")),
+                (space0, "->", space0),
+                quoted_string
+                    .verify(|dest: &String| is_contained(dest))
+                    .context(StrContext::Label("fixture destination"))
+                    .context(StrContext::Expected(StrContextValue::Description(
+                        "a relative path that stays inside the test directory",
+                    ))),
+            ),
+            (space0, ')'),
+        )),
+    )
+    .map(|(src, dest)| Fixture { src, dest })
+    .context(StrContext::Label("@fixture decorator"))
+    .parse_next(input)
+}
+
+fn include_decorator(input: &mut &str) -> ModalResult<String> {
+    preceded(
+        "@include",
+        cut_err(delimited(
+            ('(', space0),
+            quoted_string.context(StrContext::Label("include path")),
+            (space0, ')'),
+        )),
+    )
+    .context(StrContext::Label("@include decorator"))
+    .parse_next(input)
+}
+
+fn stdin_file_decorator(input: &mut &str) -> ModalResult<String> {
+    preceded(
+        "@stdin_file",
+        cut_err(delimited(
+            ('(', space0),
+            quoted_string
+                .verify(|path: &String| !path.is_empty())
+                .context(StrContext::Label("stdin file path")),
+            (space0, ')'),
+        )),
+    )
+    .context(StrContext::Label("@stdin_file decorator"))
+    .parse_next(input)
+}
+
+fn artifact_decorator(input: &mut &str) -> ModalResult<String> {
+    preceded(
+        "@artifact",
+        cut_err(delimited(
+            ('(', space0),
+            quoted_string
+                .verify(|pattern: &String| !pattern.is_empty())
+                .context(StrContext::Label("artifact pattern")),
+            (space0, ')'),
+        )),
+    )
+    .context(StrContext::Label("@artifact decorator"))
+    .parse_next(input)
+}
+
+fn retries_decorator(input: &mut &str) -> ModalResult<Retries> {
+    preceded(
+        "@retries",
+        cut_err(delimited(
+            ('(', space0),
+            (
+                dec_uint.context(StrContext::Label("retry count")),
+                opt(preceded(
+                    (list_sep, "on", space0, '=', space0),
+                    cut_err(retry_on_list),
+                )),
+            ),
+            (space0, ')'),
+        )),
+    )
+    .map(|(count, on)| Retries {
+        count,
+        on: on.unwrap_or_default(),
+    })
+    .context(StrContext::Label("@retries decorator"))
+    .parse_next(input)
+}
+
+fn resource_decorator(input: &mut &str) -> ModalResult<Resource> {
+    preceded(
+        "@resource",
+        cut_err(delimited(
+            ('(', space0),
+            (
+                take_while(1.., |c: char| {
+                    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+                })
+                .map(str::to_string)
+                .context(StrContext::Label("resource name")),
+                opt(preceded(
+                    (list_sep, "max", space0, '=', space0),
+                    cut_err(
+                        dec_uint
+                            .verify(|max: &u32| *max > 0)
+                            .context(StrContext::Label("resource max"))
+                            .context(StrContext::Expected(StrContextValue::Description(
+                                "a positive number",
+                            ))),
+                    ),
+                )),
+            ),
+            (space0, ')'),
+        )),
+    )
+    .map(|(name, max)| Resource {
+        name,
+        max: max.unwrap_or(1),
+    })
+    .context(StrContext::Label("@resource decorator"))
+    .parse_next(input)
+}
+
+fn shells_decorator(input: &mut &str) -> ModalResult<Vec<Shell>> {
+    preceded(
+        "@shells",
+        cut_err(delimited(('(', space0), shell_list, (space0, ')'))),
+    )
+    .context(StrContext::Label("@shells decorator"))
+    .parse_next(input)
+}
+
+/// Shells separated by commas, none of them more than once.
+fn shell_list(input: &mut &str) -> ModalResult<Vec<Shell>> {
+    let mut shells = vec![shell.parse_next(input)?];
+    while opt(list_sep).parse_next(input)?.is_some() {
+        let next = peek(shell).parse_next(input)?;
+        if shells.contains(&next) {
+            return fail
+                .context(StrContext::Label(listed_twice(next)))
+                .parse_next(input);
+        }
+        shells.push(shell.parse_next(input)?);
+    }
+    Ok(shells)
+}
+
+fn shell(input: &mut &str) -> ModalResult<Shell> {
+    take_while(1.., |c: char| c.is_ascii_alphanumeric())
+        .verify_map(Shell::from_name)
+        .context(StrContext::Label("shell"))
+        .context(StrContext::Expected(StrContextValue::Description(
+            "one of bash, zsh, fish, tcsh, sh",
+        )))
+        .parse_next(input)
+}
+
+/// What's wrong with a shell that's given twice, for its parse error.
+fn listed_twice(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => "shell, bash is listed twice",
+        Shell::Zsh => "shell, zsh is listed twice",
+        Shell::Fish => "shell, fish is listed twice",
+        Shell::Tcsh => "shell, tcsh is listed twice",
+        Shell::Sh => "shell, sh is listed twice",
+    }
+}
+
+fn tags_decorator(input: &mut &str) -> ModalResult<Vec<String>> {
+    preceded(
+        "@tags",
+        cut_err(delimited(
+            ('(', space0),
+            separated(
+                1..,
+                take_while(1.., |c: char| c.is_ascii_alphanumeric() || c == '_')
+                    .map(str::to_string)
+                    .context(StrContext::Label("tag")),
+                list_sep,
+            ),
+            (space0, ')'),
+        )),
+    )
+    .context(StrContext::Label("@tags decorator"))
+    .parse_next(input)
+}
+
+fn xfail_decorator(input: &mut &str) -> ModalResult<XFail> {
+    preceded(
+        "@xfail",
+        opt(preceded(
+            '(',
+            cut_err(delimited(
+                space0,
+                quoted_string.context(StrContext::Label("xfail reason")),
+                (space0, ')'),
+            )),
+        )),
+    )
+    .map(|reason| XFail { reason })
+    .context(StrContext::Label("@xfail decorator"))
+    .parse_next(input)
+}
+
+fn skip_decorator(input: &mut &str) -> ModalResult<Skip> {
+    preceded(
+        "@skip",
+        opt(preceded(
+            '(',
+            cut_err(delimited(
+                space0,
+                quoted_string.context(StrContext::Label("skip reason")),
+                (space0, ')'),
+            )),
+        )),
+    )
+    // `@skip("")` gives no more reason than `@skip` does.
+    .map(|reason: Option<String>| Skip {
+        reason: reason.filter(|reason| !reason.is_empty()),
+    })
+    .context(StrContext::Label("@skip decorator"))
+    .parse_next(input)
+}
+
+fn exit_decorator(input: &mut &str) -> ModalResult<ExpectedExit> {
+    preceded(
+        "@exit",
+        cut_err(delimited(
+            ('(', space0),
+            alt((
+                "nonzero".value(ExpectedExit::NonZero),
+                dec_uint.map(ExpectedExit::Code),
+            ))
+            .context(StrContext::Label("exit code"))
+            .context(StrContext::Expected(StrContextValue::Description(
+                "a code from 0 to 255, or nonzero",
+            ))),
+            (space0, ')'),
+        )),
+    )
+    .context(StrContext::Label("@exit decorator"))
+    .parse_next(input)
+}
+
+fn flaky_decorator(input: &mut &str) -> ModalResult<Flaky> {
+    preceded(
+        "@flaky",
+        opt(preceded(
+            '(',
+            cut_err(delimited(
+                space0,
+                terminated(dec_uint.verify(|percent: &u8| *percent <= 100), '%')
+                    .context(StrContext::Label("failure percentage")),
+                (space0, ')'),
+            )),
+        )),
+    )
+    .map(|max_failure_percent| Flaky {
+        max_failure_percent,
+    })
+    .context(StrContext::Label("@flaky decorator"))
+    .parse_next(input)
+}
+
+fn bench_decorator(input: &mut &str) -> ModalResult<Bench> {
+    preceded(
+        "@bench",
+        opt(preceded(
+            '(',
+            cut_err(delimited(
+                space0,
+                separated(1.., bench_setting, list_sep),
+                (space0, ')'),
+            )),
+        )),
+    )
+    .map(|settings: Option<Vec<BenchSetting>>| {
+        let mut bench = Bench::default();
+        for setting in settings.unwrap_or_default() {
+            match setting {
+                BenchSetting::Iterations(iterations) => bench.iterations = iterations,
+                BenchSetting::Warmup(warmup) => bench.warmup = warmup,
+            }
+        }
+        bench
+    })
+    .context(StrContext::Label("@bench decorator"))
+    .parse_next(input)
+}
+
+/// One of the `key = value` settings of a `@bench`.
+enum BenchSetting {
+    Iterations(u32),
+    Warmup(u32),
+}
+
+fn bench_setting(input: &mut &str) -> ModalResult<BenchSetting> {
+    alt((
+        preceded(
+            ("iterations", space0, '=', space0),
+            cut_err(
+                dec_uint
+                    .verify(|iterations: &u32| *iterations > 0)
+                    .context(StrContext::Label("iteration count"))
+                    .context(StrContext::Expected(StrContextValue::Description(
+                        "a positive number",
+                    ))),
+            ),
+        )
+        .map(BenchSetting::Iterations),
+        preceded(
+            ("warmup", space0, '=', space0),
+            cut_err(dec_uint.context(StrContext::Label("warmup count"))),
+        )
+        .map(BenchSetting::Warmup),
+    ))
+    .context(StrContext::Expected(StrContextValue::StringLiteral(
+        "iterations",
+    )))
+    .context(StrContext::Expected(StrContextValue::StringLiteral(
+        "warmup",
+    )))
+    .parse_next(input)
+}
+
+fn retry_on_list(input: &mut &str) -> ModalResult<Vec<RetryOn>> {
+    delimited(
+        ('[', space0),
+        separated(1.., retry_on, list_sep),
+        (space0, ']'),
+    )
+    .parse_next(input)
+}
+
+fn retry_on(input: &mut &str) -> ModalResult<RetryOn> {
+    alt((
+        "timeout".value(RetryOn::Timeout),
+        dec_int.map(RetryOn::ExitCode),
+    ))
+    .context(StrContext::Expected(StrContextValue::StringLiteral(
+        "timeout",
+    )))
+    .context(StrContext::Expected(StrContextValue::Description(
+        "an exit code",
+    )))
+    .parse_next(input)
+}
+
+/// Whether `path` is relative and can't lead out of the directory it's
+/// relative to.
+fn is_contained(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+fn env_pair(input: &mut &str) -> ModalResult<(String, String)> {
+    separated_pair(env_key.map(str::to_string), '=', env_value).parse_next(input)
+}
+
+fn env_key<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
+    take_while(1.., |c: char| c.is_ascii_alphanumeric() || c == '_')
+        .context(StrContext::Label("environment variable name"))
+        .parse_next(input)
+}
+
+fn env_value(input: &mut &str) -> ModalResult<String> {
+    alt((
+        quoted_string,
+        take_till(0.., [',', ')', '\r', '\n']).map(|value: &str| value.trim_end().to_string()),
+    ))
+    .parse_next(input)
+}
+
+/// A double-quoted string in which `\"` and `\\` are the only escapes.
+fn quoted_string(input: &mut &str) -> ModalResult<String> {
+    delimited(
+        '"',
+        repeat(
+            0..,
+            alt((
+                preceded('\\', one_of(['"', '\\'])),
+                none_of(['"', '\\', '\n']),
+            )),
+        )
+        .fold(String::new, |mut s, c| {
+            s.push(c);
+            s
+        }),
+        cut_err('"'),
+    )
+    .parse_next(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_test_file, Error};
+
+    /// The only test in `input`.
+    fn only_test(input: &str) -> Test {
+        let mut file = parse_test_file(input).unwrap();
+        assert_eq!(file.tests.len(), 1);
+        file.tests.remove(0)
+    }
+
+    /// What the parse error for `input` says was expected.
+    fn parse_error(input: &str) -> String {
+        match parse_test_file(input) {
+            Err(Error::Parse { message, .. }) => message,
+            Ok(file) => panic!("parsed {file:?}"),
+        }
+    }
+
+    #[test]
+    fn retries_can_be_limited_to_some_failures() {
+        let test = only_test("@retries(3, on=[timeout, 75])\n@test a {\n    true\n}\n");
+        assert_eq!(
+            test.retries,
+            Some(Retries {
+                count: 3,
+                on: vec![RetryOn::Timeout, RetryOn::ExitCode(75)],
+            })
+        );
+        let test = only_test("@retries( 2 ,on = [ timeout ] )\n@test a {\n    true\n}\n");
+        assert_eq!(test.retries.unwrap().on, [RetryOn::Timeout]);
+        let test = only_test("@retries(1)\n@test a {\n    true\n}\n");
+        assert_eq!(test.retries.unwrap().on, []);
+    }
+
+    #[test]
+    fn retry_specs_must_be_timeouts_or_exit_codes() {
+        let message = parse_error("@retries(3, on=[sometimes])\n@test a {\n    true\n}\n");
+        assert!(message.contains("timeout"), "{message}");
+        let message = parse_error("@retries(3, on=[])\n@test a {\n    true\n}\n");
+        assert!(message.contains("timeout"), "{message}");
+    }
+
+    #[test]
+    fn test_for_is_several_tests_with_one_body() {
+        let input =
+            "@timeout(5s)\n@test_for(bash, zsh ,fish) {\n    run \"$BISHIN_TEST_NAME\"\n}\n";
+        let file = parse_test_file(input).unwrap();
+        let names: Vec<_> = file.tests.iter().map(|test| test.name.as_str()).collect();
+        assert_eq!(names, ["bash", "zsh", "fish"]);
+        for test in &file.tests {
+            assert_eq!(test.body, "    run \"$BISHIN_TEST_NAME\"\n");
+            assert_eq!(test.body_line, 3);
+            assert_eq!(
+                test.timeout,
+                Some(Interpolated::Value(Duration::from_secs(5)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_needs_a_name() {
+        parse_error("@test_for() {\n    true\n}\n");
+        parse_error("@test_for(a,) {\n    true\n}\n");
+    }
+
+    #[test]
+    fn xfail_has_an_optional_reason() {
+        let test = only_test("@xfail\n@test a {\n    false\n}\n");
+        assert_eq!(test.xfail, Some(XFail { reason: None }));
+        let test = only_test("@xfail( \"bug #12\" )\n@test a {\n    false\n}\n");
+        assert_eq!(
+            test.xfail,
+            Some(XFail {
+                reason: Some("bug #12".to_string())
+            })
+        );
+        assert_eq!(only_test("@test a {\n    true\n}\n").xfail, None);
+        assert!(parse_error("@xfail(bug)\n@test a {\n    false\n}\n").contains("xfail reason"));
+    }
+
+    #[test]
+    fn flaky_has_an_optional_failure_percentage() {
+        let test = only_test("@flaky\n@test a {\n    true\n}\n");
+        assert_eq!(
+            test.flaky,
+            Some(Flaky {
+                max_failure_percent: None
+            })
+        );
+        let test = only_test("@flaky( 20% )\n@test a {\n    true\n}\n");
+        assert_eq!(test.flaky.unwrap().max_failure_percent, Some(20));
+        let test = only_test("@test a {\n    true\n}\n");
+        assert_eq!(test.flaky, None);
+    }
+
+    #[test]
+    fn flaky_percentages_are_at_most_a_hundred_percent() {
+        for input in ["@flaky(120%)", "@flaky(20)", "@flaky(%)"] {
+            let message = parse_error(&format!("{input}\n@test a {{\n    true\n}}\n"));
+            assert!(message.contains("failure percentage"), "{input}: {message}");
+        }
+    }
+
+    #[test]
+    fn bench_settings_are_optional() {
+        let test = only_test("@bench\n@test a {\n    true\n}\n");
+        assert_eq!(test.bench, Some(Bench::default()));
+        assert_eq!(test.bench.unwrap().iterations, Bench::DEFAULT_ITERATIONS);
+        let test = only_test("@bench(iterations = 20, warmup = 3)\n@test a {\n    true\n}\n");
+        assert_eq!(
+            test.bench,
+            Some(Bench {
+                iterations: 20,
+                warmup: 3
+            })
+        );
+        let test = only_test("@bench( warmup=1 )\n@test a {\n    true\n}\n");
+        assert_eq!(test.bench.unwrap().warmup, 1);
+    }
+
+    #[test]
+    fn benches_run_at_least_once() {
+        let message = parse_error("@bench(iterations = 0)\n@test a {\n    true\n}\n");
+        assert!(message.contains("iteration count"), "{message}");
+        let message = parse_error("@bench(runs = 3)\n@test a {\n    true\n}\n");
+        assert!(message.contains("iterations"), "{message}");
+    }
+
+    #[test]
+    fn artifacts_are_listed_in_order() {
+        let test =
+            only_test("@artifact(\"logs/*.log\")\n@artifact( \"out/\" )\n@test a {\n    true\n}\n");
+        assert_eq!(test.artifacts, ["logs/*.log", "out/"]);
+        let message = parse_error("@artifact(\"\")\n@test a {\n    true\n}\n");
+        assert!(message.contains("artifact pattern"), "{message}");
+    }
+
+    #[test]
+    fn stdin_file_takes_a_path() {
+        let test = only_test("@stdin_file( \"data/input.txt\" )\n@test a {\n    cat\n}\n");
+        assert_eq!(test.stdin_file.as_deref(), Some("data/input.txt"));
+        let message = parse_error("@stdin_file(input.txt)\n@test a {\n    cat\n}\n");
+        assert!(message.contains("stdin file path"), "{message}");
+    }
+
+    #[test]
+    fn resources_have_a_name_and_a_max() {
+        let test = only_test("@resource(db, max=2)\n@resource(license)\n@test a {\n    true\n}\n");
+        assert_eq!(
+            test.resources,
+            [
+                Resource {
+                    name: "db".to_string(),
+                    max: 2
+                },
+                Resource {
+                    name: "license".to_string(),
+                    max: 1
+                },
+            ]
+        );
+        // Naming one again replaces it.
+        let test =
+            only_test("@resource(db, max=2)\n@resource( db ,max = 3 )\n@test a {\n    true\n}\n");
+        assert_eq!(test.resources.len(), 1);
+        assert_eq!(test.resources[0].max, 3);
+    }
+
+    #[test]
+    fn resources_have_at_least_one_slot() {
+        let message = parse_error("@resource(db, max=0)\n@test a {\n    true\n}\n");
+        assert!(message.contains("resource max"), "{message}");
+        let message = parse_error("@resource()\n@test a {\n    true\n}\n");
+        assert!(message.contains("resource name"), "{message}");
+    }
+
+    #[test]
+    fn shells_are_listed_in_order() {
+        let test = only_test("@shells(bash, fish,zsh)\n@test a {\n    true\n}\n");
+        assert_eq!(
+            test.shells,
+            Some(vec![Shell::Bash, Shell::Fish, Shell::Zsh])
+        );
+        let test = only_test("@shells( sh )\n@tags(slow)\n@test a {\n    true\n}\n");
+        assert_eq!(test.shells, Some(vec![Shell::Sh]));
+        assert!(only_test("@test a {\n    true\n}\n").shells.is_none());
+    }
+
+    #[test]
+    fn shells_listed_twice_are_named_where_they_are() {
+        let input = "@shells(bash, fish, bash)\n@test a {\n    true\n}\n";
+        match parse_test_file(input) {
+            Err(Error::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => {
+                assert_eq!((line, column), (1, 21));
+                assert!(message.contains("bash is listed twice"), "{message}");
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+        let message = parse_error("@shells(tcsh, tcsh)\n@test a {\n    true\n}\n");
+        assert!(message.contains("tcsh is listed twice"), "{message}");
+    }
+
+    #[test]
+    fn shells_must_be_known_and_listed() {
+        for input in [
+            "@shells(powershell)",
+            "@shells()",
+            "@shells(bash,)",
+            "@shells",
+        ] {
+            let message = parse_error(&format!("{input}\n@test a {{\n    true\n}}\n"));
+            assert!(message.contains("shell"), "{input}: {message}");
+        }
+    }
+
+    #[test]
+    fn timeouts_take_each_unit() {
+        for (value, timeout) in [
+            ("250ms", Duration::from_millis(250)),
+            ("30s", Duration::from_secs(30)),
+            ("2m", Duration::from_secs(120)),
+            ("1h", Duration::from_secs(3600)),
+        ] {
+            let test = only_test(&format!("@timeout( {value} )\n@test a {{\n    true\n}}\n"));
+            assert_eq!(test.timeout, Some(Interpolated::Value(timeout)), "{value}");
+        }
+        let test = only_test("@timeout(${SLOW})\n@test a {\n    true\n}\n");
+        assert_eq!(
+            test.timeout,
+            Some(Interpolated::Template("${SLOW}".to_string()))
+        );
+        assert_eq!(only_test("@test a {\n    true\n}\n").timeout, None);
+    }
+
+    #[test]
+    fn malformed_and_zero_timeouts_are_errors_at_the_value() {
+        for value in ["abc", "0s", "0ms", "-1s", "1.5s", "30", "5d"] {
+            let input = format!("@timeout({value})\n@test a {{\n    true\n}}\n");
+            match parse_test_file(&input) {
+                Err(Error::Parse {
+                    line,
+                    column,
+                    message,
+                    ..
+                }) => {
+                    assert_eq!((line, column), (1, 10), "{value}");
+                    assert!(message.contains("@timeout decorator"), "{value}: {message}");
+                }
+                other => panic!("{value}: expected a parse error, got {other:?}"),
+            }
+        }
+        parse_error("@timeout()\n@test a {\n    true\n}\n");
+        let message = parse_error("@timeout(abc)\n@test a {\n    true\n}\n");
+        assert!(
+            message.contains("invalid duration \"abc\", expected a whole number"),
+            "{message}"
+        );
+        let message = parse_error("@timeout(0s)\n@test a {\n    true\n}\n");
+        assert!(message.contains("must be longer than zero"), "{message}");
+    }
+
+    #[test]
+    fn tty_is_a_decorator_of_its_own() {
+        assert!(only_test("@tty\n@test a {\n    [ -t 1 ]\n}\n").tty);
+        assert!(only_test("@tags(ui)\n@tty\n@test a {\n    true\n}\n").tty);
+        assert!(!only_test("@test a {\n    true\n}\n").tty);
+        parse_error("@tty(yes)\n@test a {\n    true\n}\n");
+    }
+
+    #[test]
+    fn shells_only_belong_to_the_test_below_them() {
+        let file = parse_test_file(
+            "@shells(bash, zsh)\n@test both {\n    true\n}\n\n@test default {\n    true\n}\n",
+        )
+        .unwrap();
+        assert_eq!(file.tests[0].shells, Some(vec![Shell::Bash, Shell::Zsh]));
+        assert_eq!(file.tests[1].shells, None);
+    }
+
+    #[test]
+    fn env_pairs_are_comma_separated() {
+        let test = only_test(
+            "@env(KEY=VALUE, OTHER=thing ,URL=a=b=c)\n@env(EMPTY=)\n@test a {\n    true\n}\n",
+        );
+        assert_eq!(
+            test.env,
+            HashMap::from([
+                ("KEY".to_string(), "VALUE".to_string()),
+                ("OTHER".to_string(), "thing".to_string()),
+                ("URL".to_string(), "a=b=c".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ])
+        );
+        assert!(only_test("@test a {\n    true\n}\n").env.is_empty());
+    }
+
+    #[test]
+    fn quoted_env_values_keep_their_spaces_and_commas() {
+        let test = only_test(
+            "@env(MSG=\"hello world\", LIST=\"a, b)\", ESCAPED=\"say \\\"hi\\\" \\\\ bye\")\n@test a {\n    true\n}\n",
+        );
+        assert_eq!(test.env["MSG"], "hello world");
+        assert_eq!(test.env["LIST"], "a, b)");
+        assert_eq!(test.env["ESCAPED"], "say \"hi\" \\ bye");
+    }
+
+    #[test]
+    fn env_decorators_need_names_and_values() {
+        for input in [
+            "@env()",
+            "@env(KEY)",
+            "@env(=value)",
+            "@env(MY-KEY=1)",
+            "@env(MSG=\"unclosed)",
+            "@env(A=1,)",
+        ] {
+            let message = parse_error(&format!("{input}\n@test a {{\n    true\n}}\n"));
+            assert!(message.starts_with("invalid "), "{input}: {message}");
+        }
+    }
+
+    #[test]
+    fn tags_are_deduplicated_in_order() {
+        let test =
+            only_test("@tags(slow, network,slow)\n@tags(db_2, network)\n@test a {\n    true\n}\n");
+        assert_eq!(test.tags, ["slow", "network", "db_2"]);
+        assert!(only_test("@test a {\n    true\n}\n").tags.is_empty());
+    }
+
+    #[test]
+    fn tags_must_be_separated_by_commas_and_named_like_tests() {
+        match parse_test_file("@tags(foo bar)\n@test a {\n    true\n}\n") {
+            Err(Error::Parse {
+                line,
+                column,
+                snippet,
+                ..
+            }) => {
+                // At the tag with no comma before it.
+                assert_eq!((line, column), (1, 11));
+                assert_eq!(snippet, "@tags(foo bar)");
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+        for input in ["@tags()", "@tags(a-b)", "@tags(a,)", "@tags(caf\u{e9})"] {
+            let message = parse_error(&format!("{input}\n@test a {{\n    true\n}}\n"));
+            assert!(message.starts_with("invalid "), "{input}: {message}");
+        }
+    }
+
+    #[test]
+    fn skip_has_an_optional_reason() {
+        let test = only_test("@skip(\"flaky on CI\")\n@test a {\n    false\n}\n");
+        assert_eq!(
+            test.skip,
+            Some(Skip {
+                reason: Some("flaky on CI".to_string())
+            })
+        );
+        let test = only_test("@skip( \"\" )\n@test a {\n    false\n}\n");
+        assert_eq!(test.skip, Some(Skip { reason: None }));
+        let test = only_test("@skip\n@test a {\n    false\n}\n");
+        assert_eq!(test.skip, Some(Skip { reason: None }));
+        assert_eq!(only_test("@test a {\n    true\n}\n").skip, None);
+    }
+
+    #[test]
+    fn skip_reasons_are_quoted() {
+        for input in ["@skip(flaky)", "@skip()", "@skip(\"unclosed)"] {
+            let message = parse_error(&format!("{input}\n@test a {{\n    true\n}}\n"));
+            assert!(message.contains("skip reason"), "{input}: {message}");
+        }
+    }
+
+    #[test]
+    fn comments_around_tests_are_left_out() {
+        let file = parse_test_file(
+            "# Tests of the resolver.\n\n  # indented\n\t# tabbed\n@test a {\n    true\n}\n# between\n@tags(slow)\n# after a decorator\n@test b {\n    true\n}\n# at the end",
+        )
+        .unwrap();
+        let names: Vec<_> = file.tests.iter().map(|test| test.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(file.tests[1].tags, ["slow"]);
+        assert_eq!(file.tests[0].body, "    true\n");
+    }
+
+    #[test]
+    fn comments_in_bodies_are_kept() {
+        let test = only_test("@test a {\n    # why\n    true # inline\n}\n");
+        assert_eq!(test.body, "    # why\n    true # inline\n");
+    }
+
+    #[test]
+    fn files_of_only_comments_have_no_tests() {
+        for input in ["# nothing yet\n", "# one\n  # two\n\n", "#", ""] {
+            assert!(
+                parse_test_file(input).unwrap().tests.is_empty(),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn nothing_may_follow_a_closing_brace() {
+        match parse_test_file("@test a {\n    true\n} # done\n") {
+            Err(Error::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => {
+                assert_eq!((line, column), (3, 2));
+                assert!(
+                    message.contains("comments go on lines of their own"),
+                    "{message}"
+                );
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn exit_decorators_take_a_code_or_nonzero() {
+        let test = only_test("@exit(3)\n@test a {\n    exit 3\n}\n");
+        assert_eq!(test.expected_exit, Some(ExpectedExit::Code(3)));
+        let test = only_test("@exit( nonzero )\n@test a {\n    false\n}\n");
+        assert_eq!(test.expected_exit, Some(ExpectedExit::NonZero));
+        let test = only_test("@exit(0)\n@test a {\n    true\n}\n");
+        assert_eq!(test.expected_exit, Some(ExpectedExit::Code(0)));
+        assert_eq!(only_test("@test a {\n    true\n}\n").expected_exit, None);
+    }
+
+    #[test]
+    fn exit_codes_are_bytes() {
+        for input in ["@exit(256)", "@exit(-1)", "@exit(any)", "@exit()"] {
+            let message = parse_error(&format!("{input}\n@test a {{\n    true\n}}\n"));
+            assert!(
+                message.contains("a code from 0 to 255, or nonzero"),
+                "{input}: {message}"
+            );
+        }
+    }
+
+    #[test]
+    fn closing_braces_may_be_indented_less_than_the_body() {
+        for input in [
+            "@test a {\n        true\n    }\n",
+            "@test a {\n    true\n  }\n",
+            "@test a {\n\t\ttrue\n\t}\n",
+            "    @test a {\n        true\n    }\n",
+        ] {
+            let test = only_test(input);
+            assert_eq!(test.body.trim(), "true", "{input:?}");
+        }
+        let file =
+            parse_test_file("@test a {\n        true\n    }\n@test b {\n    false\n}\n").unwrap();
+        assert_eq!(file.tests.len(), 2);
+        assert_eq!(file.tests[1].body, "    false\n");
+    }
+
+    #[test]
+    fn braces_within_the_body_are_left_to_it() {
+        let body = "    f() {\n        echo \"}\"\n    }\n    { echo a; } | cat\n    f\n";
+        let test = only_test(&format!("@test a {{\n{body}}}\n"));
+        assert_eq!(test.body, body);
+        // A brace as indented as the body is still part of it.
+        parse_error("@test a {\n    true\n    }\n");
+    }
+
+    #[test]
+    fn files_have_a_setup_and_teardown_around_their_tests() {
+        for (setup, teardown) in [("setup", "teardown"), ("@setup", "@teardown")] {
+            let input = format!(
+                "# Fixtures for the tests below.\n{setup} {{\n    mkdir data\n}}\n\n@test first {{\n    ls data\n}}\n\n@test second {{\n    true\n}}\n\n{teardown} {{\n    rm -r data\n}}\n"
+            );
+            let file = parse_test_file(&input).unwrap();
+            assert_eq!(file.setup.as_deref(), Some("    mkdir data\n"), "{setup}");
+            assert_eq!(
+                file.teardown.as_deref(),
+                Some("    rm -r data\n"),
+                "{teardown}"
+            );
+            let names: Vec<_> = file.tests.iter().map(|test| test.name.as_str()).collect();
+            assert_eq!(names, ["first", "second"]);
+        }
+        let file = parse_test_file("teardown{\n}\n@test a {\n    true\n}\n").unwrap();
+        assert_eq!((file.setup, file.teardown.as_deref()), (None, Some("")));
+        let file = parse_test_file("@test a {\n    true\n}\n").unwrap();
+        assert_eq!((file.setup, file.teardown), (None, None));
+    }
+
+    #[test]
+    fn files_have_at_most_one_of_each_block() {
+        for (input, second) in [
+            ("setup {\n}\n@setup {\n}\n", 3),
+            ("@teardown {\n}\n@test a {\n    true\n}\nteardown {\n}\n", 6),
+        ] {
+            match parse_test_file(input) {
+                Err(Error::Parse { line, message, .. }) => {
+                    assert_eq!(line, second, "{input:?}");
+                    assert!(message.contains("can only have one"), "{message}");
+                }
+                other => panic!("expected a parse error, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn crlf_files_parse_like_lf_ones() {
+        let lf = "# tests\n@tags(slow)\n@timeout(5s)\n@test a {\n    echo a\n\n    echo b\n}\n\nsetup {\n    mkdir x\n}\n@test b {\n    true\n  }\n";
+        let crlf = lf.replace('\n', "\r\n");
+        let (lf, crlf) = (
+            parse_test_file(lf).unwrap(),
+            parse_test_file(&crlf).unwrap(),
+        );
+        assert_eq!(crlf.tests.len(), 2);
+        assert_eq!(crlf.tests[0].body, "    echo a\n\n    echo b\n");
+        assert_eq!(crlf.tests[0].tags, ["slow"]);
+        assert_eq!(crlf.setup.as_deref(), Some("    mkdir x\n"));
+        assert_eq!(crlf, lf);
+    }
+
+    #[test]
+    fn files_may_mix_crlf_and_lf() {
+        let file = parse_test_file(
+            "@env(A=1)\r\n@test a {\n    echo a\r\n    echo b\n}\r\n@test b {\r\n    true\r\n}\n",
+        )
+        .unwrap();
+        assert_eq!(file.tests[0].env["A"], "1");
+        assert_eq!(file.tests[0].body, "    echo a\n    echo b\n");
+        assert_eq!(file.tests[1].body, "    true\n");
+        assert_eq!(file.tests[1].body_line, 7);
+    }
+
+    #[test]
+    fn carriage_returns_that_end_no_line_are_kept() {
+        let test = only_test("@test a {\r\n    printf 'a\rb'\r\n}\r\n");
+        assert_eq!(test.body, "    printf 'a\rb'\n");
+        let file = parse_test_file("# a\rb\r\n@test a {\r\n    true\r\n}\r\n").unwrap();
+        assert_eq!(file.tests.len(), 1);
+    }
+
+    #[test]
+    fn the_last_closing_brace_needs_no_line_ending() {
+        let test = only_test("@test a {\n    true\n}");
+        assert_eq!(test.body, "    true\n");
+        let file = parse_test_file("@test a {\r\n    true\r\n}\r\nteardown {\r\n}").unwrap();
+        assert_eq!(file.teardown.as_deref(), Some(""));
+        let file = parse_test_file("@test a {\n    true\n}\n# done").unwrap();
+        assert_eq!(file.tests.len(), 1);
+    }
+
+    #[test]
+    fn bodies_from_files_need_no_line_ending() {
+        let file = parse_test_file("@test a {\n    true\n}\n@test b = \"b.sh\"").unwrap();
+        assert_eq!(file.tests[1].body_file.as_deref(), Some("b.sh"));
+    }
+
+    #[test]
+    fn bodies_cut_off_by_the_end_of_the_file_still_need_a_brace() {
+        let message = parse_error("@test a {\n    true");
+        assert_eq!(message, "expected `}`");
+        let message = parse_error("@test a {\n    true\n    }");
+        assert_eq!(message, "expected `}`");
+    }
+}
@@ -0,0 +1,81 @@
+//! The shells that test scripts can be run with.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Shell {
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+    Tcsh,
+    Sh,
+}
+
+impl Shell {
+    pub const ALL: [Shell; 5] = [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Tcsh, Shell::Sh];
+
+    /// The name of the shell's program, which is also how it's written in
+    /// test files and the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Tcsh => "tcsh",
+            Shell::Sh => "sh",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shell| shell.name() == name)
+    }
+
+    /// Whether the shell traces commands with `set -x`, prefixed by `$PS4`.
+    pub fn has_xtrace(self) -> bool {
+        matches!(self, Shell::Bash | Shell::Zsh | Shell::Sh)
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown shell {0:?}, expected one of bash, zsh, fish, tcsh, sh")]
+pub struct UnknownShell(pub String);
+
+impl FromStr for Shell {
+    type Err = UnknownShell;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::from_name(name).ok_or_else(|| UnknownShell(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shells_are_read_back_from_their_names() {
+        for shell in Shell::ALL {
+            assert_eq!(shell.name().parse(), Ok(shell));
+            assert_eq!(shell.to_string(), shell.name());
+        }
+        assert_eq!(Shell::default(), Shell::Bash);
+    }
+
+    #[test]
+    fn unknown_shells_are_errors_that_list_the_known_ones() {
+        let err = "Bash".parse::<Shell>().unwrap_err();
+        assert_eq!(err, UnknownShell("Bash".to_string()));
+        assert_eq!(
+            err.to_string(),
+            "unknown shell \"Bash\", expected one of bash, zsh, fish, tcsh, sh"
+        );
+    }
+}
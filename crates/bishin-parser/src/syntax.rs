@@ -0,0 +1,489 @@
+//! A lossless syntax tree of a test file, for tools such as editors that need
+//! to know where each part of a test is rather than what it means.
+//!
+//! Every byte of the file is in exactly one token, whitespace and line
+//! endings included, so the tokens put back together are the file:
+//!
+//! ```
+//! let input = "@timeout(5s)\n@test quick {\n    true\n}\n";
+//! let tree = bishin_parser::syntax::parse(input)?;
+//! assert_eq!(tree.root().text(), input);
+//!
+//! let test = tree.test_at(input.find("true").unwrap()).unwrap();
+//! let names: Vec<_> = test.test_names().map(|name| name.text()).collect();
+//! assert_eq!(names, ["quick"]);
+//! # Ok::<(), bishin_parser::Error>(())
+//! ```
+//!
+//! [`parse_test_file`](crate::parse_test_file) reads the tests out of this
+//! same tree.
+
+use std::fmt;
+use std::ops::Range;
+
+use winnow::Parser;
+
+use crate::{parser, Error};
+
+/// What a node or token of the tree is.
+///
+/// New kinds may be added as the syntax grows, but existing ones keep their
+/// meaning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SyntaxKind {
+    /// The whole file: its tests, its setup and teardown blocks, and the
+    /// trivia between them.
+    File,
+    /// A test's decorators, header and body, from the first decorator to the
+    /// line ending after the closing brace or script path.
+    Test,
+    /// A decorator such as `@timeout(5s)`, without its line ending.
+    Decorator,
+    /// `@test name` or `@test_for(first, second)`.
+    Header,
+    /// The lines between the braces of a test or block.
+    Body,
+    /// A file's `setup { ... }` or `teardown { ... }`, up to the line ending
+    /// after the closing brace.
+    Block,
+
+    /// Spaces and tabs.
+    Whitespace,
+    /// `\n` or `\r\n`.
+    Newline,
+    /// A line of a file outside its tests that starts with `#`, after any
+    /// spaces and tabs, without its line ending.
+    Comment,
+    /// The name of a decorator with its `@`, e.g. `@timeout`.
+    DecoratorName,
+    /// The parenthesized arguments of a decorator, e.g. `(5s)`.
+    DecoratorArgs,
+    /// `@test` or `@test_for`.
+    TestKeyword,
+    /// `setup` or `teardown`, or either with an `@`, at the start of a block.
+    BlockKeyword,
+    /// The name of a test in its header.
+    TestName,
+    LParen,
+    RParen,
+    Comma,
+    /// The `=` of `@test name = "path"`.
+    Equals,
+    /// The quoted path of `@test name = "path"`.
+    BodyPath,
+    OpenBrace,
+    CloseBrace,
+    /// A line of a body without its line ending, which is never empty.
+    BodyLine,
+}
+
+impl SyntaxKind {
+    /// Whether this is the kind of a node rather than a token.
+    pub fn is_node(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::File
+                | SyntaxKind::Test
+                | SyntaxKind::Decorator
+                | SyntaxKind::Header
+                | SyntaxKind::Body
+                | SyntaxKind::Block
+        )
+    }
+
+    /// Whether tokens of this kind only separate the others.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Whitespace | SyntaxKind::Newline | SyntaxKind::Comment
+        )
+    }
+}
+
+/// The tree of a test file, borrowing the file's text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree<'a> {
+    root: SyntaxNode<'a>,
+}
+
+/// A node of the tree, which covers its children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxNode<'a> {
+    kind: SyntaxKind,
+    span: Range<usize>,
+    children: Vec<SyntaxElement<'a>>,
+}
+
+/// A leaf of the tree, holding a piece of the file's text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxToken<'a> {
+    kind: SyntaxKind,
+    span: Range<usize>,
+    text: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(SyntaxToken<'a>),
+}
+
+/// Parse the contents of a test file into its tree.
+pub fn parse(input: &str) -> Result<SyntaxTree<'_>, Error> {
+    let mut root = parser::file.parse(input).map_err(|e| {
+        let message = match e.inner().to_string() {
+            message if message.is_empty() => "unexpected text".to_string(),
+            message => message,
+        };
+        Error::parse(input, e.offset(), message)
+    })?;
+    root.place(0);
+    Ok(SyntaxTree { root })
+}
+
+impl<'a> SyntaxTree<'a> {
+    pub fn root(&self) -> &SyntaxNode<'a> {
+        &self.root
+    }
+
+    /// The `Test` nodes, in the order they're in the file.
+    pub fn tests(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.root
+            .child_nodes()
+            .filter(|node| node.kind == SyntaxKind::Test)
+    }
+
+    /// The test that the byte at `offset` is part of, if any.
+    pub fn test_at(&self, offset: usize) -> Option<&SyntaxNode<'a>> {
+        self.tests().find(|test| test.span.contains(&offset))
+    }
+
+    /// The nodes that the byte at `offset` is in, from the root down.
+    pub fn nodes_at(&self, offset: usize) -> Vec<&SyntaxNode<'a>> {
+        let mut nodes = Vec::new();
+        let mut node = Some(&self.root).filter(|root| root.span.contains(&offset));
+        while let Some(current) = node {
+            nodes.push(current);
+            node = current
+                .child_nodes()
+                .find(|child| child.span.contains(&offset));
+        }
+        nodes
+    }
+
+    /// The token holding the byte at `offset`, if it's in the file.
+    pub fn token_at(&self, offset: usize) -> Option<&SyntaxToken<'a>> {
+        self.root
+            .tokens()
+            .find(|token| token.span.contains(&offset))
+    }
+}
+
+impl<'a> SyntaxNode<'a> {
+    pub(crate) fn new(kind: SyntaxKind, children: Vec<SyntaxElement<'a>>) -> Self {
+        Self {
+            kind,
+            span: 0..0,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    /// Where the node is in the file, in bytes.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn children(&self) -> &[SyntaxElement<'a>] {
+        &self.children
+    }
+
+    /// The children that are nodes.
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Every token under the node, in the order they're in the file.
+    pub fn tokens(&self) -> Tokens<'_, 'a> {
+        Tokens {
+            stack: vec![self.children.iter()],
+        }
+    }
+
+    /// The names declared by the header under the node, which for a `Test`
+    /// node are the tests it declares.
+    pub fn test_names(&self) -> impl Iterator<Item = &SyntaxToken<'a>> {
+        self.child_nodes()
+            .filter(|node| node.kind == SyntaxKind::Header)
+            .flat_map(|header| header.tokens())
+            .filter(|token| token.kind == SyntaxKind::TestName)
+    }
+
+    /// The part of the file the node covers.
+    pub fn text(&self) -> String {
+        self.tokens().map(|token| token.text).collect()
+    }
+
+    /// Give the node and everything under it their spans, the node starting
+    /// at `start`, and return where it ends.
+    fn place(&mut self, start: usize) -> usize {
+        let mut end = start;
+        for child in &mut self.children {
+            end = match child {
+                SyntaxElement::Node(node) => node.place(end),
+                SyntaxElement::Token(token) => {
+                    token.span = end..end + token.text.len();
+                    token.span.end
+                }
+            };
+        }
+        self.span = start..end;
+        end
+    }
+}
+
+impl fmt::Display for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.tokens().try_for_each(|token| f.write_str(token.text))
+    }
+}
+
+impl<'a> SyntaxToken<'a> {
+    pub(crate) fn new(kind: SyntaxKind, text: &'a str) -> Self {
+        Self {
+            kind,
+            span: 0..0,
+            text,
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    /// Where the token is in the file, in bytes.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+}
+
+/// The tokens under a node, see [`SyntaxNode::tokens`].
+pub struct Tokens<'t, 'a> {
+    stack: Vec<std::slice::Iter<'t, SyntaxElement<'a>>>,
+}
+
+impl<'t, 'a> Iterator for Tokens<'t, 'a> {
+    type Item = &'t SyntaxToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(SyntaxElement::Token(token)) => return Some(token),
+                Some(SyntaxElement::Node(node)) => self.stack.push(node.children.iter()),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small xorshift generator, so the random files are the same on each
+    /// run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+            choices[self.below(choices.len())]
+        }
+    }
+
+    const DECORATORS: [&str; 8] = [
+        "@timeout(5s)",
+        "@tags(slow, net)",
+        "@retries(2, on=[timeout])",
+        "@flaky",
+        "@bench(iterations = 3)",
+        "@xfail(\"bug #12\")",
+        "@exit(3)",
+        "@tty",
+    ];
+    const BODY_LINES: [&str; 6] = [
+        "true",
+        "echo \"{ not a brace }\"",
+        "if true; then",
+        "# a comment in a body",
+        "\tprintf '%s' \"$BISHIN_TMPDIR\"",
+        "",
+    ];
+    const TRIVIA: [&str; 4] = ["", "# a comment", "  # indented", "   "];
+
+    /// A random test file that parses, with either line ending throughout.
+    fn random_file(rng: &mut Rng) -> String {
+        let newline = rng.pick(&["\n", "\r\n"]);
+        let mut file = String::new();
+        let mut blocks = 0;
+        for item in 0..rng.below(5) {
+            for _ in 0..rng.below(3) {
+                file.push_str(rng.pick(&TRIVIA));
+                file.push_str(newline);
+            }
+            match rng.below(6) {
+                // A file has at most one of each block.
+                0 if blocks < 2 => {
+                    let keyword = match blocks {
+                        0 => rng.pick(&["setup", "@setup"]),
+                        _ => rng.pick(&["teardown", "@teardown"]),
+                    };
+                    blocks += 1;
+                    file.push_str(&format!("{keyword} {{{newline}    true{newline}}}"));
+                }
+                1 => file.push_str(&format!("@test t{item} = \"bodies/t{item}.sh\"")),
+                kind => {
+                    for _ in 0..rng.below(3) {
+                        file.push_str(rng.pick(&DECORATORS));
+                        file.push_str(rng.pick(&["", " ", "\t"]));
+                        file.push_str(newline);
+                    }
+                    match kind {
+                        2 => file.push_str(&format!("@test_for(a{item}, b{item})")),
+                        _ => file.push_str(&format!("@test t{item}")),
+                    }
+                    file.push_str(rng.pick(&[" {", "{", "  {"]));
+                    file.push_str(newline);
+                    let indent = rng.pick(&["  ", "    ", "\t"]);
+                    for _ in 0..rng.below(4) {
+                        let line = rng.pick(&BODY_LINES);
+                        if !line.is_empty() {
+                            file.push_str(indent);
+                            file.push_str(line);
+                        }
+                        file.push_str(newline);
+                    }
+                    // A `}` indented less than the body closes it too.
+                    match indent {
+                        "    " => file.push_str(rng.pick(&["}", "  }"])),
+                        _ => file.push('}'),
+                    }
+                }
+            }
+            file.push_str(newline);
+        }
+        file
+    }
+
+    /// That the tokens of `tree` cover `input` in order with no gaps, and
+    /// each node covers its children.
+    fn assert_lossless(tree: &SyntaxTree, input: &str) {
+        assert_eq!(tree.root().text(), input);
+        assert_eq!(tree.root().to_string(), input);
+        assert_eq!(tree.root().span(), 0..input.len());
+        let mut end = 0;
+        for token in tree.root().tokens() {
+            assert_eq!(token.span().start, end, "{input:?}");
+            assert_eq!(&input[token.span()], token.text(), "{input:?}");
+            assert!(!token.kind().is_node());
+            end = token.span().end;
+        }
+        assert_eq!(end, input.len());
+        let mut nodes = vec![tree.root()];
+        while let Some(node) = nodes.pop() {
+            assert!(node.kind().is_node());
+            assert_eq!(&input[node.span()], node.text(), "{input:?}");
+            nodes.extend(node.child_nodes());
+        }
+    }
+
+    #[test]
+    fn random_files_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let input = random_file(&mut rng);
+            let tree = parse(&input).unwrap_or_else(|err| panic!("{input:?}: {err}"));
+            assert_lossless(&tree, &input);
+            let names = tree.tests().flat_map(|test| test.test_names()).count();
+            assert_eq!(
+                crate::parse_test_file(&input).unwrap().tests.len(),
+                names,
+                "{input:?}"
+            );
+            for test in tree.tests() {
+                for offset in test.span() {
+                    assert_eq!(tree.test_at(offset), Some(test));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn damaged_files_round_trip_whenever_they_parse() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let junk = ["{", "}", "@", "(", "\n", "\r", " ", "#", "x", "é", "\""];
+        for _ in 0..500 {
+            let mut input = random_file(&mut rng);
+            for _ in 0..1 + rng.below(3) {
+                let mut at = rng.below(input.len() + 1);
+                while !input.is_char_boundary(at) {
+                    at -= 1;
+                }
+                let end = (at + rng.below(3)).min(input.len());
+                let end = (end..=input.len())
+                    .find(|end| input.is_char_boundary(*end))
+                    .unwrap();
+                input.replace_range(at..end, rng.pick(&junk));
+            }
+            if let Ok(tree) = parse(&input) {
+                assert_lossless(&tree, &input);
+            }
+        }
+    }
+
+    #[test]
+    fn nodes_and_tokens_at_an_offset() {
+        let input = "# top\n@timeout(5s)\n@test a {\n    true\n}\n";
+        let tree = parse(input).unwrap();
+        let offset = input.find("5s").unwrap();
+        let kinds: Vec<_> = tree
+            .nodes_at(offset)
+            .iter()
+            .map(|node| node.kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            [SyntaxKind::File, SyntaxKind::Test, SyntaxKind::Decorator]
+        );
+        let token = tree.token_at(offset).unwrap();
+        assert_eq!(
+            (token.kind(), token.text()),
+            (SyntaxKind::DecoratorArgs, "(5s)")
+        );
+        let comment = tree.token_at(0).unwrap();
+        assert_eq!(comment.kind(), SyntaxKind::Comment);
+        assert!(comment.kind().is_trivia());
+        assert_eq!(tree.test_at(0), None);
+        assert_eq!(tree.token_at(input.len()), None);
+        assert!(tree.nodes_at(input.len()).is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+//! Helpers for the tests of the other bishin crates, which depend on this
+//! one only as a dev-dependency.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A directory of a test's own, removed with everything in it when dropped.
+#[derive(Debug)]
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ScratchDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A new, empty directory of its own for a test to write files to, which
+/// lasts as long as what's returned.
+pub fn scratch_dir() -> ScratchDir {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "bishin-test-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    ScratchDir(dir)
+}
+
+/// Write each of `files`, a path relative to `dir` and its contents, creating
+/// the directories they're in.
+pub fn write_files(dir: &Path, files: &[(&str, &str)]) {
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scratch_dirs_are_removed_when_dropped() {
+        let dir = scratch_dir();
+        write_files(&dir, &[("a/b.txt", "b")]);
+        assert_eq!(std::fs::read_to_string(dir.join("a/b.txt")).unwrap(), "b");
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn scratch_dirs_are_distinct() {
+        let (a, b) = (scratch_dir(), scratch_dir());
+        assert_ne!(a.path(), b.path());
+        assert!(a.is_dir() && b.is_dir());
+    }
+}